@@ -1,17 +1,15 @@
-use network_protocol::NetworkCommand;
+use network_protocol::{Authentication, NetworkCommand, RequestId};
+
+use crate::transport::ServerAddress;
 
 /// Parameters to connect to a server.
 #[derive(Debug)]
 pub struct ConnectParams {
-    /// Host name of the server.
-    pub host: String,
-
-    /// Port number of the server. If no port is given, the [`default
-    /// port`](network_protocol::DEFAULT_LISTENER_PORT) is used.
-    pub port: Option<u16>,
+    /// Where the server is. Its scheme picks the transport; see [`ServerAddress`].
+    pub address: ServerAddress,
 
-    /// Initial username the user wishes to use for the session.
-    pub initial_username: String,
+    /// How to identify to the server: as a guest with an initial username, or with an account.
+    pub authentication: Authentication,
 }
 
 /// A command from the UI to the client backend.
@@ -28,6 +26,14 @@ pub enum ClientCommand {
 
     /// Commands which pass on to the network.
     NetworkCommand(NetworkCommand),
+
+    /// Like [`ClientCommand::NetworkCommand`], but tagged with a request ID. The outcome is
+    /// reported back with either a [`ClientEvent::CommandAck`](crate::client_event::ClientEvent::CommandAck)
+    /// or an error carrying the same ID.
+    Request {
+        request_id: RequestId,
+        command: NetworkCommand,
+    },
 }
 
 impl ClientCommand {
@@ -38,6 +44,7 @@ impl ClientCommand {
             ClientCommand::Disconnect => "Disconnect",
             ClientCommand::Quit => "Quit",
             ClientCommand::NetworkCommand(_) => "NetworkCommand",
+            ClientCommand::Request { .. } => "Request",
         }
     }
 }
@@ -1,15 +1,20 @@
 pub use network_protocol::ReceivedMessage;
 
+use std::collections::BTreeSet;
 use std::io;
-use std::net::SocketAddr;
 use std::result::Result as StdResult;
+use std::time::Duration;
 
 use thiserror::Error;
 
 use network_protocol::{
-    ChannelId, ChannelSync, ErrorEvent, NetworkEvent, UserId, UserInfo, UserSync,
+    Capability, ChannelId, ChannelInfo, ChannelMember, ChannelMembers, ChannelSync, CommandAck,
+    ErrorEvent, HistoryPage, MessageDeleted, MessageEdited, NetworkEvent, RequestId, Resync,
+    ServerNotice, ServerShuttingDown, UserBanned, UserId, UserInfo, UserKicked, UserSync,
 };
 
+use crate::transport::ServerAddress;
+
 /// An error arising in the client backend while processing a `ClientCommand`.
 #[derive(Debug, Error)]
 pub enum Error {
@@ -17,6 +22,19 @@ pub enum Error {
     /// while attempting to communicate with the server.
     #[error("I/O error: {0}")]
     Io(#[from] io::Error),
+
+    /// The server speaks a protocol version this client does not support.
+    #[error("the server speaks protocol version {0}, which this client does not support")]
+    IncompatibleVersion(u32),
+
+    /// A [`ClientCommand::Request`](crate::client_command::ClientCommand::Request) could not be
+    /// sent to the server. Unlike other errors, this only concerns the one request.
+    #[error("could not send request: {source}")]
+    RequestNotSent {
+        request_id: RequestId,
+        #[source]
+        source: io::Error,
+    },
 }
 
 /// Struct holding initial information about the server connection.
@@ -24,7 +42,16 @@ pub enum Error {
 pub struct InitialSync {
     pub your_id: UserId,
     pub default_channel_id: Option<ChannelId>,
-    pub server_addr: SocketAddr,
+    pub server_addr: ServerAddress,
+    /// Whether the server restored the session we had before the connection dropped, rather than
+    /// starting a new one.
+    pub resumed: bool,
+    /// Optional features both the server and this client support. Frontends should hide features
+    /// that aren't in here.
+    pub capabilities: BTreeSet<Capability>,
+    /// Longest message contents the server accepts, in characters, if it said. Frontends should
+    /// warn before sending anything longer.
+    pub max_message_length: Option<usize>,
 }
 
 /// A specialized `Result` type for carrying `ClientEvent`s to the frontend.
@@ -44,9 +71,21 @@ pub enum ClientEvent {
     /// Disconnected from the server.
     Disconnected,
 
-    /// Server was shut down while connected.
+    /// Server was shut down while connected, or the connection dropped and could not be
+    /// re-established.
     ServerShutDown,
 
+    /// The connection dropped, and the backend is about to make the given reconnect attempt,
+    /// counting from 1.
+    Reconnecting { attempt: u32 },
+
+    /// An automatic reconnect succeeded. An [`InitialSync`](ClientEvent::InitialSync) for the new
+    /// connection follows.
+    Reconnected,
+
+    /// Round-trip time of the latest ping to the server.
+    Latency(Duration),
+
     /// Bulk state update for the channel list.
     ChannelSync(ChannelSync),
 
@@ -62,11 +101,63 @@ pub enum ClientEvent {
     /// A user's information was updated.
     UserInfoUpdated(UserInfo),
 
+    /// A new channel was created.
+    ChannelAdded(ChannelInfo),
+
+    /// A channel was deleted.
+    ChannelRemoved(ChannelId),
+
+    /// A channel was renamed.
+    ChannelChangedName(ChannelInfo),
+
     /// A new message was received.
     ReceivedMessage(ReceivedMessage),
 
-    /// An error occurred on the server.
+    /// An error occurred on the server. If it carries a request ID, the request with that ID
+    /// failed.
     ErrorEvent(ErrorEvent),
+
+    /// The request with the given ID succeeded.
+    CommandAck(CommandAck),
+
+    /// A page of older messages, in response to a history fetch.
+    HistoryPage(HistoryPage),
+
+    /// A message was edited by its sender.
+    MessageEdited(MessageEdited),
+
+    /// A message was deleted by its sender.
+    MessageDeleted(MessageDeleted),
+
+    /// The full member list of a channel you joined.
+    ChannelMembers(ChannelMembers),
+
+    /// A user joined a channel you are a member of.
+    ChannelMemberJoined(ChannelMember),
+
+    /// A user left a channel you are a member of. If the user is you, you left the channel.
+    ChannelMemberLeft(ChannelMember),
+
+    /// The server dropped events because we fell behind. The backend already asked for what was
+    /// lost, which arrives as the usual sync and history events.
+    Resync(Resync),
+
+    /// An announcement from the server's operators.
+    ServerNotice(ServerNotice),
+
+    /// A user was kicked off the server. If the user is you, the connection closes and the
+    /// backend doesn't reconnect.
+    UserKicked(UserKicked),
+
+    /// A user was banned from the server. If the user is you, the connection closes and the
+    /// backend doesn't reconnect.
+    UserBanned(UserBanned),
+
+    /// The server is about to shut down, most likely to restart. The connection closes once the
+    /// grace period is over, after which the backend reconnects if its reconnect policy allows.
+    /// Otherwise, a [`Disconnected`](ClientEvent::Disconnected) follows, rather than a
+    /// [`ServerShutDown`](ClientEvent::ServerShutDown).
+    ServerShuttingDown(ServerShuttingDown),
 }
 
 impl ClientEvent {
@@ -76,13 +167,31 @@ impl ClientEvent {
             ClientEvent::InitialSync(_) => "InitialSync",
             ClientEvent::Disconnected => "Disconnected",
             ClientEvent::ServerShutDown => "ServerShutDown",
+            ClientEvent::Reconnecting { .. } => "Reconnecting",
+            ClientEvent::Reconnected => "Reconnected",
+            ClientEvent::Latency(_) => "Latency",
             ClientEvent::ChannelSync(_) => "ChannelSync",
             ClientEvent::UserSync(_) => "UserSync",
             ClientEvent::UserJoined(_) => "UserJoined",
             ClientEvent::UserLeft(_) => "UserLeft",
             ClientEvent::UserInfoUpdated(_) => "UserInfoUpdated",
+            ClientEvent::ChannelAdded(_) => "ChannelAdded",
+            ClientEvent::ChannelRemoved(_) => "ChannelRemoved",
+            ClientEvent::ChannelChangedName(_) => "ChannelChangedName",
             ClientEvent::ReceivedMessage(_) => "ReceivedMessage",
             ClientEvent::ErrorEvent(_) => "ErrorEvent",
+            ClientEvent::CommandAck(_) => "CommandAck",
+            ClientEvent::HistoryPage(_) => "HistoryPage",
+            ClientEvent::MessageEdited(_) => "MessageEdited",
+            ClientEvent::MessageDeleted(_) => "MessageDeleted",
+            ClientEvent::ChannelMembers(_) => "ChannelMembers",
+            ClientEvent::ChannelMemberJoined(_) => "ChannelMemberJoined",
+            ClientEvent::ChannelMemberLeft(_) => "ChannelMemberLeft",
+            ClientEvent::Resync(_) => "Resync",
+            ClientEvent::ServerNotice(_) => "ServerNotice",
+            ClientEvent::UserKicked(_) => "UserKicked",
+            ClientEvent::UserBanned(_) => "UserBanned",
+            ClientEvent::ServerShuttingDown(_) => "ServerShuttingDown",
         }
     }
 }
@@ -96,6 +205,7 @@ impl ClientEvent {
 /// Invalid variants are:
 /// * [`NetworkEvent::ServerHello`]: `InitialSync` carries some information from this variant, but
 ///   additional information is needed. This should only be sent once, when starting a connection.
+/// * [`NetworkEvent::Ping`] and [`NetworkEvent::Pong`]: Keepalives are handled by the backend.
 impl TryFrom<NetworkEvent> for ClientEvent {
     type Error = ();
 
@@ -107,9 +217,26 @@ impl TryFrom<NetworkEvent> for ClientEvent {
             NetworkEvent::UserLeft(user_id) => Self::UserLeft(user_id),
             NetworkEvent::ReceivedMessage(message) => Self::ReceivedMessage(message),
             NetworkEvent::UserInfoUpdated(info) => Self::UserInfoUpdated(info),
+            NetworkEvent::ChannelAdded(info) => Self::ChannelAdded(info),
+            NetworkEvent::ChannelRemoved(channel_id) => Self::ChannelRemoved(channel_id),
+            NetworkEvent::ChannelChangedName(info) => Self::ChannelChangedName(info),
             NetworkEvent::ErrorEvent(error) => Self::ErrorEvent(error),
-
-            NetworkEvent::ServerHello(_) => Err(())?,
+            NetworkEvent::CommandAck(ack) => Self::CommandAck(ack),
+            NetworkEvent::HistoryPage(page) => Self::HistoryPage(page),
+            NetworkEvent::MessageEdited(edited) => Self::MessageEdited(edited),
+            NetworkEvent::MessageDeleted(deleted) => Self::MessageDeleted(deleted),
+            NetworkEvent::ChannelMembers(members) => Self::ChannelMembers(members),
+            NetworkEvent::ChannelMemberJoined(member) => Self::ChannelMemberJoined(member),
+            NetworkEvent::ChannelMemberLeft(member) => Self::ChannelMemberLeft(member),
+            NetworkEvent::Resync(resync) => Self::Resync(resync),
+            NetworkEvent::ServerNotice(notice) => Self::ServerNotice(notice),
+            NetworkEvent::UserKicked(kicked) => Self::UserKicked(kicked),
+            NetworkEvent::UserBanned(banned) => Self::UserBanned(banned),
+            NetworkEvent::ServerShuttingDown(shutdown) => Self::ServerShuttingDown(shutdown),
+
+            NetworkEvent::ServerHello(_) | NetworkEvent::Ping(_) | NetworkEvent::Pong(_) => {
+                Err(())?
+            }
         })
     }
 }
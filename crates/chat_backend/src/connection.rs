@@ -1,70 +1,52 @@
 use std::io;
-use std::net::{SocketAddr, ToSocketAddrs};
 
 use futures::{SinkExt, StreamExt};
-use rustls::pki_types::ServerName;
-use tokio::io::AsyncWriteExt;
-use tokio::net::TcpStream;
-use tokio_rustls::{TlsConnector, client::TlsStream};
-use tokio_util::codec::Framed;
+use tokio_rustls::TlsConnector;
 
-use network_protocol::codecs::ClientCodec;
-use network_protocol::{NetworkCommand, NetworkEvent};
+use network_protocol::{CommandRequest, NetworkEvent};
+
+use crate::transport::{self, ServerAddress, Transport};
 
 /// A connection to a chat server.
 #[derive(Debug)]
 pub struct Connection {
-    stream: Framed<TlsStream<TcpStream>, ClientCodec>,
-    addr: SocketAddr,
+    stream: Box<dyn Transport>,
+    address: ServerAddress,
 }
 
 impl Connection {
-    /// Create a new `Connection` to the server at `host:port`.
+    /// Create a new `Connection` to the server at `address`, over the transport its scheme calls
+    /// for.
     ///
-    /// If no port is provided, the [default port](network_protocol::DEFAULT_LISTENER_PORT) is used.
+    /// Frames longer than `max_frame_length` bytes are rejected in both directions.
     ///
     /// Returns an error if the connection failed for any reason (invalid address, connection
     /// refused, etc.).
     pub async fn connect(
-        host: &str,
-        port: Option<u16>,
+        address: &ServerAddress,
         tls_connector: &TlsConnector,
+        max_frame_length: usize,
     ) -> io::Result<Self> {
-        let port = port.unwrap_or(network_protocol::DEFAULT_LISTENER_PORT);
-
-        let domain = ServerName::try_from(host.to_owned()).map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::InvalidInput,
-                format!("invalid TLS host '{host}': {e}"),
-            )
-        })?;
-
-        let addr = (host, port).to_socket_addrs()?.next().ok_or_else(|| {
-            io::Error::new(
-                io::ErrorKind::InvalidInput,
-                format!("socket address {host} with port {port} did not resolve"),
-            )
-        })?;
-
-        let stream = TcpStream::connect(addr).await?;
-        let stream = tls_connector.connect(domain, stream).await?;
-        let stream = Framed::new(stream, ClientCodec);
+        let stream = transport::connect(address, tls_connector, max_frame_length).await?;
 
-        Ok(Self { stream, addr })
+        Ok(Self {
+            stream,
+            address: address.clone(),
+        })
     }
 
     /// Consume the `Connection` and attempt a clean disconnect.
     ///
     /// Returns an error if the disconnect could not be done cleanly.
-    pub async fn disconnect(self) -> io::Result<()> {
-        self.stream.into_inner().shutdown().await
+    pub async fn disconnect(mut self) -> io::Result<()> {
+        self.stream.close().await
     }
 
-    /// Send a command to the connected server.
+    /// Send a command to the connected server, optionally tagged with a request ID.
     ///
     /// Returns an error if the connection closed.
-    pub async fn send_command(&mut self, command: NetworkCommand) -> io::Result<()> {
-        self.stream.send(command).await
+    pub async fn send_command(&mut self, command: impl Into<CommandRequest>) -> io::Result<()> {
+        self.stream.send(command.into()).await
     }
 
     /// Listen for an event from the connected server.
@@ -75,7 +57,7 @@ impl Connection {
         self.stream.next().await
     }
 
-    pub fn addr(&self) -> SocketAddr {
-        self.addr
+    pub fn address(&self) -> &ServerAddress {
+        &self.address
     }
 }
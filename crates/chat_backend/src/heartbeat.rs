@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use network_protocol::{Ping, Pong};
+
+/// How often to ping the server, and how long to wait for it to answer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeartbeatConfig {
+    /// Seconds between pings. Zero disables the heartbeat (default: 15)
+    pub interval_secs: u64,
+    /// Seconds to wait for an answer before giving up on the connection (default: 10)
+    pub timeout_secs: u64,
+}
+
+/// Heartbeat state for one connection. Pings the server periodically, to measure the round-trip
+/// latency and to notice when the connection silently died.
+#[derive(Debug)]
+pub struct Heartbeat {
+    interval: Duration,
+    timeout: Duration,
+    next_nonce: u64,
+    /// Nonce of the ping awaiting an answer, and when it was sent.
+    outstanding: Option<(u64, Instant)>,
+    /// When to send the next ping, or give up on the outstanding one.
+    deadline: Instant,
+}
+
+impl Heartbeat {
+    /// Create a `Heartbeat` that sends its first ping right away. Returns `None` if the heartbeat
+    /// is disabled.
+    pub fn new(config: &HeartbeatConfig) -> Option<Self> {
+        if config.interval_secs == 0 {
+            return None;
+        }
+
+        Some(Self {
+            interval: Duration::from_secs(config.interval_secs),
+            timeout: Duration::from_secs(config.timeout_secs),
+            next_nonce: 0,
+            outstanding: None,
+            deadline: Instant::now(),
+        })
+    }
+
+    /// Get when [`Self::tick`] should be called next.
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// Advance the heartbeat once its deadline passed. Returns the [`Ping`] to send, or `None` if
+    /// the last ping went unanswered for too long.
+    pub fn tick(&mut self) -> Option<Ping> {
+        if self.outstanding.is_some() {
+            return None;
+        }
+
+        let now = Instant::now();
+        let nonce = self.next_nonce;
+        self.next_nonce = self.next_nonce.wrapping_add(1);
+        self.outstanding = Some((nonce, now));
+        self.deadline = now + self.timeout;
+
+        Some(Ping { nonce })
+    }
+
+    /// Handle a [`Pong`] from the server. Returns the round-trip time if it answers the
+    /// outstanding ping.
+    pub fn receive_pong(&mut self, pong: Pong) -> Option<Duration> {
+        let (nonce, sent_at) = self.outstanding?;
+        if pong.nonce != nonce {
+            return None;
+        }
+
+        self.outstanding = None;
+        self.deadline = sent_at + self.interval;
+
+        Some(sent_at.elapsed())
+    }
+}
@@ -1,14 +1,19 @@
 pub mod client_command;
 pub mod client_event;
 mod connection;
+mod heartbeat;
+mod reconnect;
+pub mod transport;
 
 /// Convenience re-export of types from [`network_protocol`].
 pub mod network_protocol {
     pub use network_protocol::*;
 }
 
+use std::collections::BTreeSet;
 use std::fs::{create_dir_all, write};
 use std::io;
+use std::mem;
 use std::ops::ControlFlow;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
@@ -20,26 +25,32 @@ use figment::{
 use rustls::{
     RootCertStore,
     pki_types::{
-        CertificateDer,
+        CertificateDer, PrivateKeyDer,
         pem::{self, PemObject},
     },
 };
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::time::{Instant, sleep_until};
 use tokio_rustls::TlsConnector;
 
 use client_command::{ClientCommand, ConnectParams};
 use client_event::{ClientEvent, InitialSync};
 use connection::Connection;
+use heartbeat::{Heartbeat, HeartbeatConfig};
 use network_protocol::{
-    ClientHello, FetchChannels, FetchUsers, NetworkCommand, NetworkEvent, ServerHello,
+    Authentication, Capability, ClientHello, CommandRequest, ErrorEvent, FetchChannels,
+    FetchHistory, FetchUsers, HistoryDestination, NetworkCommand, NetworkEvent, PROTOCOL_VERSION,
+    Pong, ResumeToken, Resync, ServerHello, UserBanned, UserId, UserKicked,
 };
+use reconnect::ReconnectPolicy;
 use shared_utils::{
     files::{NamedProjectDirs, TildeRelativePathBuf},
     first_match,
 };
 use tracing::{debug, error, info, instrument, warn};
+use transport::ServerAddress;
 
 const DEFAULT_CONFIG: &str = include_str!("../data/config.toml");
 
@@ -54,12 +65,21 @@ pub enum InitError {
     #[error("Config resolution failed: {0}")]
     ConfigResolutionFailed(#[source] Box<figment::Error>),
 
-    /// Reading a certificate file from [`Config::additional_root_ca_paths`] failed.
+    /// Reading a certificate file from [`Config::additional_root_ca_paths`] or
+    /// [`Config::client_cert_path`] failed.
     #[error("Reading certificate file '{path}' failed: {source}")]
     CertFileReadFailed { path: PathBuf, source: pem::Error },
 
+    /// Reading the private key file from [`Config::client_key_path`] failed.
+    #[error("Reading private key file '{path}' failed: {source}")]
+    KeyFileReadFailed { path: PathBuf, source: pem::Error },
+
+    /// Only one of [`Config::client_cert_path`] and [`Config::client_key_path`] was set.
+    #[error("Client certificate and key paths must be set together")]
+    IncompleteClientCert,
+
     /// A certificate file from [`Config::additional_root_ca_paths`] could not be added to the main
-    /// [`rustls::RootCertStore`].
+    /// [`rustls::RootCertStore`], or the client certificate and key don't form a valid pair.
     #[error("Certificate validation failed: {0}")]
     CertValidationFailed(#[from] rustls::Error),
 
@@ -74,6 +94,30 @@ impl From<figment::Error> for InitError {
     }
 }
 
+/// An error while connecting to a server.
+#[derive(Debug, Error)]
+enum ConnectError {
+    /// The connection could not be established, or broke during the handshake.
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    /// The server refused our Hello, for example because the password was wrong.
+    #[error("server rejected Hello: {0}")]
+    Rejected(ErrorEvent),
+
+    /// The server sent something other than its Hello.
+    #[error("missed server Hello, got unexpected event: {0}")]
+    UnexpectedEvent(&'static str),
+
+    /// The server closed the connection during the handshake.
+    #[error("connection closed unexpectedly")]
+    Closed,
+
+    /// The server speaks a protocol version we don't support.
+    #[error("server speaks unsupported protocol version {0}")]
+    IncompatibleVersion(u32),
+}
+
 #[derive(Debug)]
 struct DefaultPaths {
     config: PathBuf,
@@ -100,6 +144,16 @@ struct Config {
     include_webpki_roots: bool,
     /// Paths to additional root certificates (default: empty)
     additional_root_ca_paths: Vec<TildeRelativePathBuf>,
+    /// Path to a certificate to authenticate to servers with (default: none)
+    client_cert_path: Option<TildeRelativePathBuf>,
+    /// Path to the private key for `client_cert_path` (default: none)
+    client_key_path: Option<TildeRelativePathBuf>,
+    /// When and how often to reconnect after losing the connection to a server
+    reconnect: ReconnectPolicy,
+    /// How often to ping the server to check on the connection
+    heartbeat: HeartbeatConfig,
+    /// Longest frame to accept from a server, in bytes
+    max_frame_length: usize,
 }
 
 /// Contains channels through which to send `ClientCommand`s to the backend and from which to
@@ -112,6 +166,23 @@ pub struct BackendHandle {
     pub event_rx: Receiver<client_event::Result>,
 }
 
+/// A session on a server that we may resume if the connection drops, by presenting its token when
+/// reconnecting.
+#[derive(Debug)]
+struct ResumableSession {
+    address: ServerAddress,
+    token: ResumeToken,
+}
+
+/// An automatic reconnect in progress.
+#[derive(Debug)]
+struct Reconnect {
+    /// The upcoming attempt, counting from 1.
+    attempt: u32,
+    /// When to make the attempt.
+    at: Instant,
+}
+
 /// The backend for the chat client. Frontends communicate with this via tokio channels by sending
 /// `ClientCommand`s and receiving `ClientEvent`s.
 ///
@@ -119,7 +190,29 @@ pub struct BackendHandle {
 /// For more information, see the documentation for those respective functions.
 pub struct ChatBackend {
     tls_connector: TlsConnector,
+    max_frame_length: usize,
     connection: Option<Connection>,
+    /// The last session the server let us resume. Cleared when we disconnect on purpose.
+    resumable_session: Option<ResumableSession>,
+    /// Parameters of the last successful connection, to reconnect with. Cleared when we disconnect
+    /// on purpose.
+    last_params: Option<ConnectParams>,
+    reconnect_policy: ReconnectPolicy,
+    /// The automatic reconnect in progress, if any.
+    reconnect: Option<Reconnect>,
+    heartbeat_config: HeartbeatConfig,
+    /// Heartbeat for the current connection, if enabled and supported by the server.
+    heartbeat: Option<Heartbeat>,
+    /// Optional features both the current server and the backend support.
+    server_capabilities: BTreeSet<Capability>,
+    /// Our user ID on the current server.
+    our_id: Option<UserId>,
+    /// Whether we were kicked or banned from the current server, so losing the connection is
+    /// expected and shouldn't be recovered from.
+    removed: bool,
+    /// Whether the current server announced it's shutting down, so losing the connection is
+    /// expected.
+    server_shutting_down: bool,
     cmd_rx: Receiver<ClientCommand>,
     event_tx: Sender<client_event::Result>,
 }
@@ -190,9 +283,37 @@ impl ChatBackend {
             root_cert_store.add(cert)?;
         }
 
-        let tls_config = rustls::ClientConfig::builder()
-            .with_root_certificates(root_cert_store)
-            .with_no_client_auth();
+        let tls_config = rustls::ClientConfig::builder().with_root_certificates(root_cert_store);
+
+        let tls_config = match (config.client_cert_path, config.client_key_path) {
+            (Some(cert_path), Some(key_path)) => {
+                debug!(
+                    cert_path = %cert_path.original().display(),
+                    key_path = %key_path.original().display(),
+                    "Loading client certificate"
+                );
+
+                let certs = CertificateDer::pem_file_iter(cert_path.resolved()?)
+                    .and_then(Iterator::collect::<Result<Vec<_>, _>>)
+                    .map_err(|e| InitError::CertFileReadFailed {
+                        path: cert_path.original().to_owned(),
+                        source: e,
+                    })?;
+
+                let key = PrivateKeyDer::from_pem_file(key_path.resolved()?).map_err(|e| {
+                    InitError::KeyFileReadFailed {
+                        path: key_path.original().to_owned(),
+                        source: e,
+                    }
+                })?;
+
+                tls_config.with_client_auth_cert(certs, key)?
+            }
+
+            (None, None) => tls_config.with_no_client_auth(),
+
+            _ => return Err(InitError::IncompleteClientCert),
+        };
 
         let tls_connector = TlsConnector::from(Arc::new(tls_config));
 
@@ -203,7 +324,18 @@ impl ChatBackend {
 
         let backend = Self {
             tls_connector,
+            max_frame_length: config.max_frame_length,
             connection: None,
+            resumable_session: None,
+            last_params: None,
+            our_id: None,
+            removed: false,
+            server_shutting_down: false,
+            reconnect_policy: config.reconnect,
+            reconnect: None,
+            heartbeat_config: config.heartbeat,
+            heartbeat: None,
+            server_capabilities: BTreeSet::new(),
             cmd_rx,
             event_tx,
         };
@@ -241,6 +373,12 @@ impl ChatBackend {
     /// the channels' blocking methods when sending to/receiving from the backend.
     pub async fn run(mut self) {
         'backend: loop {
+            let heartbeat_deadline = self
+                .heartbeat
+                .as_ref()
+                .filter(|_| self.connection.is_some())
+                .map(Heartbeat::deadline);
+
             tokio::select! {
                 // This structure is a bit odd, but it's necessary. We need this `select!` arm to
                 // listen for an event only if we're connected to the server. If we aren't
@@ -255,8 +393,7 @@ impl ChatBackend {
                     // If the server event is None, the server disconnected from us.
                     let Some(event) = event else {
                         info!("Server disconnected unexpectedly");
-                        self.send_ui_event(ClientEvent::ServerShutDown).await;
-                        self.connection = None;
+                        self.handle_connection_lost(None).await;
                         continue 'backend;
                     };
 
@@ -267,13 +404,28 @@ impl ChatBackend {
 
                         Err(e) => {
                             warn!(error = %e, "Error reading event from server");
-                            self.send_ui_error(client_event::Error::Io(e)).await;
-                            self.connection = None;
+                            self.handle_connection_lost(Some(e)).await;
                             continue 'backend;
                         }
                     }
                 }
 
+                // Same trick again: the heartbeat only runs while connected.
+                () = async {
+                    match heartbeat_deadline {
+                        Some(deadline) => sleep_until(deadline).await,
+                        None => std::future::pending().await,
+                    }
+                } => self.heartbeat_tick().await,
+
+                // Same trick as above: if no reconnect is in progress, this never resolves.
+                () = async {
+                    match &self.reconnect {
+                        Some(reconnect) => sleep_until(reconnect.at).await,
+                        None => std::future::pending().await,
+                    }
+                } => self.try_reconnect().await,
+
                 command = self.cmd_rx.recv() => {
                     let Some(command) = command else {
                         self.handle_ui_crash();
@@ -303,12 +455,13 @@ impl ChatBackend {
         match command {
             ClientCommand::Connect(params) => {
                 info!(
-                    host = %params.host,
-                    port = ?params.port,
-                    initial_username = %params.initial_username,
+                    address = %params.address,
+                    authentication = ?params.authentication,
                     "Command received: connecting to server"
                 );
 
+                // Connecting on purpose supersedes any automatic reconnect.
+                self.reconnect = None;
                 self.connect(params).await;
             }
 
@@ -325,6 +478,17 @@ impl ChatBackend {
             }
 
             ClientCommand::NetworkCommand(net_cmd) => self.send_network_command(net_cmd).await,
+
+            ClientCommand::Request {
+                request_id,
+                command,
+            } => {
+                self.send_request(CommandRequest {
+                    request_id: Some(request_id),
+                    command,
+                })
+                .await;
+            }
         }
 
         ControlFlow::Continue(())
@@ -333,6 +497,56 @@ impl ChatBackend {
     /// Handle a `NetworkEvent` coming from the server.
     #[instrument(skip_all, fields(event = %event.name()))]
     async fn handle_event(&mut self, event: NetworkEvent) {
+        // Keepalives are handled right here, the UI only hears about the latency.
+        match event {
+            NetworkEvent::Ping(ping) => {
+                self.send_network_command(NetworkCommand::Pong(Pong { nonce: ping.nonce }))
+                    .await;
+                return;
+            }
+
+            NetworkEvent::Pong(pong) => {
+                if let Some(latency) = self
+                    .heartbeat
+                    .as_mut()
+                    .and_then(|heartbeat| heartbeat.receive_pong(pong))
+                {
+                    debug!(?latency, "Server answered ping");
+                    self.send_ui_event(ClientEvent::Latency(latency)).await;
+                }
+                return;
+            }
+
+            // The UI still hears about it, but the refetches are already on their way by then.
+            NetworkEvent::Resync(ref resync) => self.refetch_lost_state(resync).await,
+
+            // The server closes the connection next. Neither resuming nor logging in again would
+            // be welcome.
+            NetworkEvent::UserKicked(UserKicked { user_id, .. })
+            | NetworkEvent::UserBanned(UserBanned { user_id, .. })
+                if self.our_id == Some(user_id) =>
+            {
+                info!("We were removed from the server");
+                self.removed = true;
+                self.resumable_session = None;
+                self.last_params = None;
+            }
+
+            // Our session ends with the server, but it may well come back, so reconnecting is
+            // still worth a try.
+            NetworkEvent::ServerShuttingDown(ref shutdown) => {
+                info!(
+                    reason = %shutdown.reason,
+                    grace_period = ?shutdown.grace_period,
+                    "Server is shutting down"
+                );
+                self.server_shutting_down = true;
+                self.resumable_session = None;
+            }
+
+            _ => {}
+        }
+
         #[allow(clippy::single_match_else)]
         let event: ClientEvent = match event.try_into() {
             Ok(event) => event,
@@ -346,86 +560,165 @@ impl ChatBackend {
         self.send_ui_event(event).await;
     }
 
+    /// Ask the server again for the state that events dropped by a [`Resync`] would have changed:
+    /// the channel and user lists for global events, and the newest history of each channel that
+    /// lost messages.
+    async fn refetch_lost_state(&mut self, resync: &Resync) {
+        warn!(global = resync.global, channel_ids = ?resync.channel_ids, "Fell behind the server, refetching lost state");
+
+        if resync.global {
+            self.send_network_command(NetworkCommand::FetchChannels(FetchChannels))
+                .await;
+            self.send_network_command(NetworkCommand::FetchUsers(FetchUsers))
+                .await;
+        }
+
+        if !self.server_capabilities.contains(&Capability::History) {
+            return;
+        }
+
+        for &channel_id in &resync.channel_ids {
+            // A limit of zero gets the server's largest page.
+            let fetch = FetchHistory {
+                destination: HistoryDestination::Channel(channel_id),
+                before: None,
+                limit: 0,
+            };
+
+            self.send_network_command(NetworkCommand::FetchHistory(fetch))
+                .await;
+        }
+    }
+
     /// Attempt to connect to the server at using the given `ConnectParams`. The UI will be notified
     /// about whether the connection is successful or not.
-    #[instrument(skip_all, fields(
-        host = %params.host,
-        port = ?params.port,
-    ))]
     async fn connect(&mut self, params: ConnectParams) {
+        match self.establish_connection(&params).await {
+            Ok(initial_sync) => {
+                self.last_params = Some(Self::params_for_reconnect(params));
+                self.send_ui_event(ClientEvent::InitialSync(initial_sync))
+                    .await;
+            }
+
+            Err(ConnectError::Io(e)) => self.send_ui_error(e.into()).await,
+
+            // The server closes the connection right after rejecting us, so all that's left is to
+            // tell the user why.
+            Err(ConnectError::Rejected(error)) => {
+                self.send_ui_event(ClientEvent::ErrorEvent(error)).await;
+            }
+
+            Err(ConnectError::IncompatibleVersion(version)) => {
+                self.send_ui_error(client_event::Error::IncompatibleVersion(version))
+                    .await;
+            }
+
+            Err(ConnectError::UnexpectedEvent(_) | ConnectError::Closed) => {}
+        }
+    }
+
+    /// Connect to a server, perform the handshake, and request the initial state. On success, the
+    /// new connection replaces the current one, if any.
+    ///
+    /// # Errors
+    /// Returns a [`ConnectError`] if any step of the handshake fails.
+    #[instrument(skip_all, err(level = tracing::Level::WARN), fields(
+        address = %params.address,
+    ))]
+    async fn establish_connection(
+        &mut self,
+        params: &ConnectParams,
+    ) -> Result<InitialSync, ConnectError> {
         let mut connection =
-            match Connection::connect(&params.host, params.port, &self.tls_connector).await {
-                Ok(conn) => conn,
-                Err(e) => {
-                    warn!(error = %e, "Failed to establish TCP+TLS connection to server");
-                    self.send_ui_error(e.into()).await;
-                    return;
-                }
-            };
+            Connection::connect(&params.address, &self.tls_connector, self.max_frame_length)
+                .await?;
+
+        debug!("Established connection to server");
 
-        debug!("Established TCP+TLS connection to server");
+        // The server only knows our token if we talked to it before.
+        let resume_token = self
+            .resumable_session
+            .take()
+            .filter(|session| session.address == params.address)
+            .map(|session| session.token);
 
         let client_hello = ClientHello {
-            requested_name: params.initial_username,
+            authentication: params.authentication.clone(),
+            resume_token,
+            protocol_version: PROTOCOL_VERSION,
+            // The backend itself supports everything, it's up to the frontend what it exposes.
+            capabilities: Capability::ALL.into(),
         };
 
-        if let Err(e) = connection
+        connection
             .send_command(NetworkCommand::ClientHello(client_hello))
-            .await
-        {
-            warn!(error = %e, "Failed to send Hello to server");
-        }
+            .await?;
 
         // We expect the server to send its Hello immediately after we send ours. Otherwise, we
         // cannot establish necessary basic state.
         let ServerHello {
             your_id,
             default_channel_id,
+            resume_token,
+            resume_grace_period,
+            resumed,
+            protocol_version,
+            capabilities,
+            max_message_length,
+            max_frame_length,
         } = match connection.receive_event().await {
             Some(Ok(NetworkEvent::ServerHello(hello))) => hello,
+            Some(Ok(NetworkEvent::ErrorEvent(error))) => Err(ConnectError::Rejected(error))?,
+            Some(Ok(other)) => Err(ConnectError::UnexpectedEvent(other.name()))?,
+            Some(Err(e)) => Err(e)?,
+            None => Err(ConnectError::Closed)?,
+        };
+        debug!(
+            our_id = %your_id,
+            resumed,
+            protocol_version,
+            ?capabilities,
+            ?max_message_length,
+            ?max_frame_length,
+            "Received server Hello"
+        );
+
+        if !network_protocol::is_compatible_version(protocol_version) {
+            return Err(ConnectError::IncompatibleVersion(protocol_version));
+        }
 
-            Some(Ok(other)) => {
-                warn!(
-                    ?other,
-                    "Failed to connect to server - missed server Hello, got unexpected command"
-                );
-                return;
-            }
-
-            Some(Err(e)) => {
-                warn!(error = %e, "Failed to connect to server - IO error");
-                return;
-            }
-
-            None => {
-                warn!("Failed to connect to server - connection closed unexpectedly");
-                return;
+        self.resumable_session = resume_token.map(|token| {
+            debug!(?resume_grace_period, "Server allows resuming this session");
+            ResumableSession {
+                address: params.address.clone(),
+                token,
             }
-        };
-        debug!(our_id = %your_id, "Received server Hello");
+        });
 
         // Fetch the channel list and initial user list. Currently, we treat this as a full,
         // automatic state dump. In future versions, this may be paginated and done lazily to
         // minimize network traffic.
-        if let Err(e) = connection
+        connection
             .send_command(NetworkCommand::FetchChannels(FetchChannels))
-            .await
-        {
-            warn!(error = %e, "Failed to connect to server - could not fetch channels");
-            return;
-        }
-
-        if let Err(e) = connection
+            .await?;
+        connection
             .send_command(NetworkCommand::FetchUsers(FetchUsers))
-            .await
-        {
-            warn!(error = %e, "Failed to connect to server - could not fetch users");
-            return;
-        }
+            .await?;
 
         debug!("Fetched channels and users");
 
-        let server_addr = connection.addr();
+        self.heartbeat = if capabilities.contains(&Capability::Keepalive) {
+            Heartbeat::new(&self.heartbeat_config)
+        } else {
+            None
+        };
+
+        self.server_capabilities.clone_from(&capabilities);
+        self.our_id = Some(your_id);
+        self.removed = false;
+        self.server_shutting_down = false;
+
+        let server_addr = connection.address().clone();
 
         self.connection = Some(connection);
         // At this point, the connection has succeeded. While we may immediately experience a UI
@@ -433,19 +726,155 @@ impl ChatBackend {
         // is done.
         debug!("Connection succeeded");
 
-        self.send_ui_event(ClientEvent::InitialSync(InitialSync {
+        Ok(InitialSync {
             your_id,
             default_channel_id,
             server_addr,
-        }))
-        .await;
+            resumed,
+            capabilities,
+            max_message_length,
+        })
+    }
+
+    /// Turn the parameters of a successful connection into parameters to reconnect with. Once an
+    /// account is registered, reconnecting has to log into it instead.
+    fn params_for_reconnect(params: ConnectParams) -> ConnectParams {
+        let authentication = match params.authentication {
+            Authentication::Register(credentials) => Authentication::Login(credentials),
+            other => other,
+        };
+
+        ConnectParams {
+            authentication,
+            ..params
+        }
+    }
+
+    /// Ping the server, or give up on the connection if it didn't answer the last ping in time.
+    async fn heartbeat_tick(&mut self) {
+        let Some(heartbeat) = &mut self.heartbeat else {
+            return;
+        };
+
+        if let Some(ping) = heartbeat.tick() {
+            self.send_network_command(NetworkCommand::Ping(ping)).await;
+        } else {
+            warn!("Server did not answer ping in time, assuming the connection is dead");
+            let error = io::Error::new(io::ErrorKind::TimedOut, "server stopped answering pings");
+            self.handle_connection_lost(Some(error)).await;
+        }
+    }
+
+    /// Drop the lost connection, then either start reconnecting or tell the UI what happened.
+    async fn handle_connection_lost(&mut self, error: Option<io::Error>) {
+        self.connection = None;
+
+        // The UI already heard why from the server.
+        if mem::take(&mut self.removed) {
+            self.send_ui_event(ClientEvent::Disconnected).await;
+            return;
+        }
+
+        let shutdown_announced = mem::take(&mut self.server_shutting_down);
+
+        if self.start_reconnecting().await {
+            return;
+        }
+
+        match error {
+            // The UI already heard the server was going away, so this is no surprise.
+            _ if shutdown_announced => self.send_ui_event(ClientEvent::Disconnected).await,
+            Some(e) => self.send_ui_error(client_event::Error::Io(e)).await,
+            None => self.send_ui_event(ClientEvent::ServerShutDown).await,
+        }
+    }
+
+    /// Start reconnecting after the connection dropped, if the [`ReconnectPolicy`] allows it.
+    /// Returns whether a reconnect was started.
+    async fn start_reconnecting(&mut self) -> bool {
+        if !self.reconnect_policy.enabled
+            || self.reconnect_policy.max_attempts == 0
+            || self.last_params.is_none()
+        {
+            self.last_params = None;
+            return false;
+        }
+
+        self.schedule_reconnect(1).await;
+        true
+    }
+
+    /// Schedule a reconnect attempt after the delay the [`ReconnectPolicy`] gives for it.
+    async fn schedule_reconnect(&mut self, attempt: u32) {
+        let delay = self.reconnect_policy.delay(attempt);
+        info!(attempt, ?delay, "Scheduling reconnect attempt");
+
+        self.reconnect = Some(Reconnect {
+            attempt,
+            at: Instant::now() + delay,
+        });
+        self.send_ui_event(ClientEvent::Reconnecting { attempt })
+            .await;
+    }
+
+    /// Make the scheduled reconnect attempt. If it fails, the next attempt is scheduled, until the
+    /// [`ReconnectPolicy`] runs out of attempts.
+    #[instrument(skip_all, fields(attempt = self.reconnect.as_ref().map(|r| r.attempt)))]
+    async fn try_reconnect(&mut self) {
+        let (Some(Reconnect { attempt, .. }), Some(params)) =
+            (self.reconnect.take(), self.last_params.take())
+        else {
+            return;
+        };
+
+        match self.establish_connection(&params).await {
+            Ok(initial_sync) => {
+                info!(resumed = initial_sync.resumed, "Reconnected to server");
+                self.last_params = Some(params);
+                self.send_ui_event(ClientEvent::Reconnected).await;
+                self.send_ui_event(ClientEvent::InitialSync(initial_sync))
+                    .await;
+            }
+
+            // Trying again won't change the server's mind.
+            Err(ConnectError::Rejected(error)) => {
+                self.send_ui_event(ClientEvent::ErrorEvent(error)).await;
+                self.send_ui_event(ClientEvent::ServerShutDown).await;
+            }
+
+            // The server was replaced with one we can't talk to.
+            Err(ConnectError::IncompatibleVersion(version)) => {
+                self.send_ui_error(client_event::Error::IncompatibleVersion(version))
+                    .await;
+            }
+
+            Err(_) if attempt < self.reconnect_policy.max_attempts => {
+                self.last_params = Some(params);
+                self.schedule_reconnect(attempt + 1).await;
+            }
+
+            Err(_) => {
+                warn!("Giving up on reconnecting");
+                self.send_ui_event(ClientEvent::ServerShutDown).await;
+            }
+        }
     }
 
     /// Disconnect from the server.
     #[instrument(skip_all, fields(
-        connection_address = ?self.connection.as_ref().map(Connection::addr),
+        connection_address = ?self.connection.as_ref().map(|connection| connection.address().to_string()),
     ))]
     async fn disconnect(&mut self) {
+        // Disconnecting cleanly ends the session on the server, so there is nothing to resume.
+        self.resumable_session = None;
+        self.last_params = None;
+
+        // Giving up on a reconnect counts as disconnecting, as far as the UI is concerned.
+        if self.reconnect.take().is_some() {
+            self.send_ui_event(ClientEvent::Disconnected).await;
+            return;
+        }
+
         let Some(connection) = self.connection.take() else {
             // Disconnecting while already disconnected is a NOP
             return;
@@ -462,18 +891,34 @@ impl ChatBackend {
     }
 
     /// Send a `NetworkCommand` to the server. The UI will be notified if this fails.
-    #[instrument(skip_all, fields(command = %command.name()))]
     async fn send_network_command(&mut self, command: NetworkCommand) {
+        self.send_request(command.into()).await;
+    }
+
+    /// Send a `CommandRequest` to the server. The UI will be notified if this fails, about the
+    /// request in particular if it carries a request ID.
+    #[instrument(skip_all, fields(command = %request.command.name(), request_id = ?request.request_id))]
+    async fn send_request(&mut self, request: CommandRequest) {
+        // Failures of a tagged request are reported as such, so the UI can tell which one failed.
+        let request_id = request.request_id;
+        let to_ui_error = |e: io::Error| match request_id {
+            Some(request_id) => client_event::Error::RequestNotSent {
+                request_id,
+                source: e,
+            },
+            None => e.into(),
+        };
+
         let Some(connection) = &mut self.connection else {
             warn!("Tried to send a command, but there's no active connection");
             let error = io::Error::from(io::ErrorKind::NotConnected);
-            self.send_ui_error(error.into()).await;
+            self.send_ui_error(to_ui_error(error)).await;
             return;
         };
 
-        if let Err(e) = connection.send_command(command).await {
+        if let Err(e) = connection.send_command(request).await {
             warn!(error = %e, "Failed to send command to server");
-            self.send_ui_error(e.into()).await;
+            self.send_ui_error(to_ui_error(e)).await;
         }
     }
 
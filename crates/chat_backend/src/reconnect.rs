@@ -0,0 +1,34 @@
+use std::time::Duration;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// When and how often to reconnect after the connection to a server drops.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReconnectPolicy {
+    /// Whether to reconnect automatically at all (default: true)
+    pub enabled: bool,
+    /// How many attempts to make before giving up (default: 10)
+    pub max_attempts: u32,
+    /// Delay before the first attempt, in milliseconds. Doubles with every attempt after that
+    /// (default: 500)
+    pub initial_delay_ms: u64,
+    /// Upper bound for the delay between attempts, in milliseconds (default: 30000)
+    pub max_delay_ms: u64,
+}
+
+impl ReconnectPolicy {
+    /// Get how long to wait before the given attempt, counting from 1. The delay grows
+    /// exponentially, and is randomly shortened by up to half, so that clients that lost their
+    /// connection at the same time don't all reconnect at once.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(u64::BITS - 1);
+        let delay_ms = self
+            .initial_delay_ms
+            .saturating_mul(1 << exponent)
+            .min(self.max_delay_ms);
+
+        let jittered_ms = rand::thread_rng().gen_range(delay_ms / 2..=delay_ms);
+        Duration::from_millis(jittered_ms)
+    }
+}
@@ -0,0 +1,568 @@
+//! The transports the backend can reach a server over, and the addresses that select them.
+//!
+//! An address is written like a URL, and its scheme picks the transport:
+//! * `tls://host[:port]`: length-prefixed frames over TLS, the server's main listener. An address
+//!   without a scheme is taken as a host name for this transport.
+//! * `ws://host[:port][/path]` and `wss://host[:port][/path]`: one frame per binary WebSocket
+//!   message, without or with TLS, e.g. through an HTTP reverse proxy.
+//! * `unix://path`: length-prefixed frames over a Unix domain socket, without TLS.
+//!
+//! IPv6 addresses need brackets when they're followed by a port, like `tls://[::1]:12345`.
+//!
+//! In-memory connections to a server in the same process have no written form. Create them with
+//! [`memory_listener`].
+
+use std::{
+    fmt::{self, Debug, Display},
+    io,
+    path::PathBuf,
+    pin::Pin,
+    str::FromStr,
+    task::{Context, Poll, ready},
+};
+
+use futures::{Sink, Stream};
+use network_protocol::{CommandRequest, NetworkEvent, codecs::ClientCodec};
+use rustls::pki_types::ServerName;
+use thiserror::Error;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, DuplexStream},
+    net::TcpStream,
+    sync::mpsc,
+};
+use tokio_rustls::TlsConnector;
+use tokio_tungstenite::{
+    WebSocketStream,
+    tungstenite::{self, Message, protocol::WebSocketConfig},
+};
+use tokio_util::codec::Framed;
+
+/// Default port for `ws://` addresses, as for any other WebSocket URL.
+const DEFAULT_WS_PORT: u16 = 80;
+
+/// Default port for `wss://` addresses, as for any other WebSocket URL.
+const DEFAULT_WSS_PORT: u16 = 443;
+
+/// Capacity of each direction of an in-memory connection, in bytes.
+const MEMORY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// A server's commands and events, whatever transport carries them.
+pub trait Transport:
+    Stream<Item = io::Result<NetworkEvent>>
+    + Sink<CommandRequest, Error = io::Error>
+    + Debug
+    + Send
+    + Unpin
+{
+}
+
+impl<T> Transport for T where
+    T: Stream<Item = io::Result<NetworkEvent>>
+        + Sink<CommandRequest, Error = io::Error>
+        + Debug
+        + Send
+        + Unpin
+{
+}
+
+/// Where a server is, and which transport reaches it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerAddress {
+    /// Length-prefixed frames over TLS over TCP. If no port is given, the [default
+    /// port](network_protocol::DEFAULT_LISTENER_PORT) is used.
+    Tls { host: String, port: Option<u16> },
+
+    /// One frame per binary WebSocket message. If no port is given, the usual port for WebSocket
+    /// URLs is used: 443 with TLS, 80 without.
+    WebSocket {
+        tls: bool,
+        host: String,
+        port: Option<u16>,
+        path: String,
+    },
+
+    /// Length-prefixed frames over a Unix domain socket, without TLS.
+    Unix(PathBuf),
+
+    /// Length-prefixed frames over an in-memory pipe to a server in the same process.
+    Memory(MemoryDialer),
+}
+
+/// Errors from parsing a [`ServerAddress`].
+#[derive(Debug, Error)]
+pub enum AddressError {
+    #[error("unknown scheme '{0}://', expected tls://, ws://, wss:// or unix://")]
+    UnknownScheme(String),
+
+    #[error("address has no host")]
+    MissingHost,
+
+    #[error("address has no socket path")]
+    MissingPath,
+
+    #[error("invalid port '{0}'")]
+    InvalidPort(String),
+
+    #[error("IPv6 address is missing its closing bracket")]
+    UnclosedBracket,
+
+    #[error("IPv6 address '{0}' needs brackets, like [::1]")]
+    UnbracketedIpv6(String),
+
+    #[error("{0} addresses don't have a port")]
+    NoPort(&'static str),
+}
+
+impl ServerAddress {
+    /// Replace the address's port.
+    ///
+    /// # Errors
+    /// Returns an error if the transport doesn't use ports.
+    pub fn with_port(self, port: u16) -> Result<Self, AddressError> {
+        match self {
+            Self::Tls { host, .. } => Ok(Self::Tls {
+                host,
+                port: Some(port),
+            }),
+
+            Self::WebSocket {
+                tls, host, path, ..
+            } => Ok(Self::WebSocket {
+                tls,
+                host,
+                port: Some(port),
+                path,
+            }),
+
+            Self::Unix(_) => Err(AddressError::NoPort("unix://")),
+            Self::Memory(_) => Err(AddressError::NoPort("In-memory")),
+        }
+    }
+}
+
+impl FromStr for ServerAddress {
+    type Err = AddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((scheme, rest)) = s.split_once("://") else {
+            if s.is_empty() {
+                return Err(AddressError::MissingHost);
+            }
+
+            return Ok(Self::Tls {
+                host: s.to_owned(),
+                port: None,
+            });
+        };
+
+        match scheme {
+            "tls" => {
+                let (host, port) = parse_authority(rest)?;
+                Ok(Self::Tls { host, port })
+            }
+
+            "ws" | "wss" => {
+                let (authority, path) = match rest.find('/') {
+                    Some(index) => rest.split_at(index),
+                    None => (rest, "/"),
+                };
+                let (host, port) = parse_authority(authority)?;
+
+                Ok(Self::WebSocket {
+                    tls: scheme == "wss",
+                    host,
+                    port,
+                    path: path.to_owned(),
+                })
+            }
+
+            "unix" if rest.is_empty() => Err(AddressError::MissingPath),
+            "unix" => Ok(Self::Unix(PathBuf::from(rest))),
+
+            other => Err(AddressError::UnknownScheme(other.to_owned())),
+        }
+    }
+}
+
+/// Split `host[:port]` or `[ipv6][:port]` into its parts.
+fn parse_authority(authority: &str) -> Result<(String, Option<u16>), AddressError> {
+    let (host, port) = if let Some(rest) = authority.strip_prefix('[') {
+        let (host, rest) = rest.split_once(']').ok_or(AddressError::UnclosedBracket)?;
+
+        match rest.strip_prefix(':') {
+            Some(port) => (host, Some(port)),
+            None if rest.is_empty() => (host, None),
+            None => return Err(AddressError::InvalidPort(rest.to_owned())),
+        }
+    } else {
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        };
+
+        // Otherwise, the last group of an IPv6 address would be taken for the port.
+        if host.contains(':') {
+            return Err(AddressError::UnbracketedIpv6(authority.to_owned()));
+        }
+
+        (host, port)
+    };
+
+    if host.is_empty() {
+        return Err(AddressError::MissingHost);
+    }
+
+    let port = port
+        .map(|port| {
+            port.parse()
+                .map_err(|_| AddressError::InvalidPort(port.to_owned()))
+        })
+        .transpose()?;
+
+    Ok((host.to_owned(), port))
+}
+
+impl Display for ServerAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let write_authority = |f: &mut fmt::Formatter<'_>, host: &str, port: Option<u16>| {
+            if host.contains(':') {
+                write!(f, "[{host}]")?;
+            } else {
+                write!(f, "{host}")?;
+            }
+
+            match port {
+                Some(port) => write!(f, ":{port}"),
+                None => Ok(()),
+            }
+        };
+
+        match self {
+            Self::Tls { host, port } => {
+                write!(f, "tls://")?;
+                write_authority(f, host, *port)
+            }
+
+            Self::WebSocket {
+                tls,
+                host,
+                port,
+                path,
+            } => {
+                write!(f, "{}://", if *tls { "wss" } else { "ws" })?;
+                write_authority(f, host, *port)?;
+                write!(f, "{path}")
+            }
+
+            Self::Unix(path) => write!(f, "unix://{}", path.display()),
+            Self::Memory(_) => write!(f, "memory://"),
+        }
+    }
+}
+
+/// Opens in-memory connections to a server in the same process, e.g. to test the backend against
+/// it. Created by [`memory_listener`].
+#[derive(Debug, Clone)]
+pub struct MemoryDialer {
+    tx: mpsc::UnboundedSender<DuplexStream>,
+}
+
+impl PartialEq for MemoryDialer {
+    fn eq(&self, other: &Self) -> bool {
+        self.tx.same_channel(&other.tx)
+    }
+}
+
+impl Eq for MemoryDialer {}
+
+impl MemoryDialer {
+    fn dial(&self) -> io::Result<DuplexStream> {
+        let (client, server) = tokio::io::duplex(MEMORY_BUFFER_SIZE);
+
+        self.tx.send(server).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                "in-memory listener is gone",
+            )
+        })?;
+
+        Ok(client)
+    }
+}
+
+/// The server's end of in-memory connections. Each stream it accepts speaks length-prefixed
+/// frames, as on the server's main listener, without TLS.
+#[derive(Debug)]
+pub struct MemoryListener {
+    rx: mpsc::UnboundedReceiver<DuplexStream>,
+}
+
+impl MemoryListener {
+    /// Wait for the next connection. Returns `None` once every [`MemoryDialer`] is gone.
+    pub async fn accept(&mut self) -> Option<DuplexStream> {
+        self.rx.recv().await
+    }
+}
+
+/// Create a listener for in-memory connections, and the dialer to connect to it with
+/// [`ServerAddress::Memory`].
+#[must_use]
+pub fn memory_listener() -> (MemoryDialer, MemoryListener) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    (MemoryDialer { tx }, MemoryListener { rx })
+}
+
+/// Connect to a server over the transport its address calls for. Frames longer than
+/// `max_frame_length` bytes are rejected in both directions.
+///
+/// Returns an error if the connection or any handshake failed.
+pub(crate) async fn connect(
+    address: &ServerAddress,
+    tls_connector: &TlsConnector,
+    max_frame_length: usize,
+) -> io::Result<Box<dyn Transport>> {
+    let codec = ClientCodec::new(max_frame_length);
+
+    match address {
+        ServerAddress::Tls { host, port } => {
+            let port = port.unwrap_or(network_protocol::DEFAULT_LISTENER_PORT);
+
+            let stream = TcpStream::connect((host.as_str(), port)).await?;
+            let stream = tls_connector.connect(server_name(host)?, stream).await?;
+
+            Ok(Box::new(Framed::new(stream, codec)))
+        }
+
+        ServerAddress::WebSocket {
+            tls,
+            host,
+            port,
+            path: _,
+        } => {
+            let default_port = if *tls {
+                DEFAULT_WSS_PORT
+            } else {
+                DEFAULT_WS_PORT
+            };
+            let port = port.unwrap_or(default_port);
+            let url = address.to_string();
+
+            let stream = TcpStream::connect((host.as_str(), port)).await?;
+
+            if *tls {
+                let stream = tls_connector.connect(server_name(host)?, stream).await?;
+                websocket(stream, &url, codec).await
+            } else {
+                websocket(stream, &url, codec).await
+            }
+        }
+
+        #[cfg(unix)]
+        ServerAddress::Unix(path) => {
+            let stream = tokio::net::UnixStream::connect(path).await?;
+            Ok(Box::new(Framed::new(stream, codec)))
+        }
+
+        #[cfg(not(unix))]
+        ServerAddress::Unix(_) => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Unix domain sockets aren't supported on this platform",
+        )),
+
+        ServerAddress::Memory(dialer) => Ok(Box::new(Framed::new(dialer.dial()?, codec))),
+    }
+}
+
+fn server_name(host: &str) -> io::Result<ServerName<'static>> {
+    ServerName::try_from(host.to_owned()).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid TLS host '{host}': {e}"),
+        )
+    })
+}
+
+/// Perform the WebSocket handshake on an established stream.
+async fn websocket<S>(stream: S, url: &str, codec: ClientCodec) -> io::Result<Box<dyn Transport>>
+where
+    S: AsyncRead + AsyncWrite + Debug + Send + Unpin + 'static,
+{
+    let config = WebSocketConfig::default()
+        .max_message_size(Some(codec.max_frame_length()))
+        .max_frame_size(Some(codec.max_frame_length()));
+
+    let (inner, _response) = tokio_tungstenite::client_async_with_config(url, stream, Some(config))
+        .await
+        .map_err(into_io_error)?;
+
+    Ok(Box::new(WebSocketServerStream { inner, codec }))
+}
+
+/// A WebSocket connection carrying one frame per binary message.
+#[derive(Debug)]
+struct WebSocketServerStream<S> {
+    inner: WebSocketStream<S>,
+    codec: ClientCodec,
+}
+
+impl<S> Stream for WebSocketServerStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    type Item = io::Result<NetworkEvent>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let message = match ready!(Pin::new(&mut self.inner).poll_next(cx)) {
+                Some(Ok(message)) => message,
+                Some(Err(e)) => return Poll::Ready(Some(Err(into_io_error(e)))),
+                None => return Poll::Ready(None),
+            };
+
+            match message {
+                Message::Binary(data) => {
+                    return Poll::Ready(Some(self.codec.decode_message(&data)));
+                }
+
+                Message::Text(_) => {
+                    return Poll::Ready(Some(Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "expected a binary message, got a text message",
+                    ))));
+                }
+
+                // Pings are answered by the WebSocket layer, and the stream ends after a close.
+                Message::Ping(_) | Message::Pong(_) | Message::Close(_) | Message::Frame(_) => {}
+            }
+        }
+    }
+}
+
+impl<S> Sink<CommandRequest> for WebSocketServerStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    type Error = io::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner)
+            .poll_ready(cx)
+            .map_err(into_io_error)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: CommandRequest) -> Result<(), Self::Error> {
+        let data = self.codec.encode_message(item)?;
+
+        Pin::new(&mut self.inner)
+            .start_send(Message::Binary(data.into()))
+            .map_err(into_io_error)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner)
+            .poll_flush(cx)
+            .map_err(into_io_error)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner)
+            .poll_close(cx)
+            .map_err(into_io_error)
+    }
+}
+
+fn into_io_error(error: tungstenite::Error) -> io::Error {
+    match error {
+        tungstenite::Error::Io(e) => e,
+        e => io::Error::other(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(address: &str) -> Result<ServerAddress, AddressError> {
+        address.parse()
+    }
+
+    #[test]
+    fn parses_addresses() {
+        assert_eq!(
+            parse("localhost").unwrap(),
+            ServerAddress::Tls {
+                host: "localhost".to_owned(),
+                port: None,
+            }
+        );
+
+        assert_eq!(
+            parse("tls://[::1]:12345").unwrap(),
+            ServerAddress::Tls {
+                host: "::1".to_owned(),
+                port: Some(12345),
+            }
+        );
+
+        assert_eq!(
+            parse("wss://example.com").unwrap(),
+            ServerAddress::WebSocket {
+                tls: true,
+                host: "example.com".to_owned(),
+                port: None,
+                path: "/".to_owned(),
+            }
+        );
+
+        assert_eq!(
+            parse("unix:///run/chat.sock").unwrap(),
+            ServerAddress::Unix(PathBuf::from("/run/chat.sock"))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_addresses() {
+        assert!(matches!(parse(""), Err(AddressError::MissingHost)));
+        assert!(matches!(parse("tls://:1"), Err(AddressError::MissingHost)));
+        assert!(matches!(parse("unix://"), Err(AddressError::MissingPath)));
+        assert!(matches!(
+            parse("ftp://host"),
+            Err(AddressError::UnknownScheme(_))
+        ));
+        assert!(matches!(
+            parse("tls://host:port"),
+            Err(AddressError::InvalidPort(_))
+        ));
+        assert!(matches!(
+            parse("tls://[::1"),
+            Err(AddressError::UnclosedBracket)
+        ));
+        assert!(matches!(
+            parse("tls://[::1]x"),
+            Err(AddressError::InvalidPort(_))
+        ));
+        assert!(matches!(
+            parse("tls://::1"),
+            Err(AddressError::UnbracketedIpv6(_))
+        ));
+        assert!(matches!(
+            parse("ws://::1:80/"),
+            Err(AddressError::UnbracketedIpv6(_))
+        ));
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for address in [
+            "tls://localhost",
+            "tls://localhost:12345",
+            "tls://[::1]:12345",
+            "tls://[2001:db8::1]",
+            "ws://127.0.0.1:8080/chat",
+            "wss://example.com/",
+            "unix:///run/chat.sock",
+        ] {
+            assert_eq!(parse(address).unwrap().to_string(), address);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Drives the backend against a stand-in server over an in-memory connection.
+
+use std::{collections::BTreeSet, io::Write, time::Duration};
+
+use chat_backend::{
+    ChatBackend,
+    client_command::{ClientCommand, ConnectParams},
+    client_event::ClientEvent,
+    network_protocol::{
+        Authentication, NetworkCommand, NetworkEvent, PROTOCOL_VERSION, SendDestination,
+        SendMessage, ServerHello, UserId, codecs::ServerCodec,
+    },
+    transport::{ServerAddress, memory_listener},
+};
+use futures::{SinkExt, StreamExt};
+use tokio::time::timeout;
+use tokio_util::codec::Framed;
+use uuid::Uuid;
+
+/// How long to wait for anything before failing the test.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[tokio::test]
+async fn backend_talks_to_in_memory_server() {
+    let mut config = tempfile::NamedTempFile::new().unwrap();
+    writeln!(config, "include_webpki_roots = false").unwrap();
+
+    let (backend, mut handle) = ChatBackend::new(Some(config.path().to_owned())).unwrap();
+    tokio::spawn(backend.run());
+
+    let (dialer, mut listener) = memory_listener();
+    let your_id = UserId(Uuid::from_u128(1));
+
+    handle
+        .cmd_tx
+        .send(ClientCommand::Connect(ConnectParams {
+            address: ServerAddress::Memory(dialer),
+            authentication: Authentication::Guest {
+                requested_name: "tester".to_owned(),
+            },
+        }))
+        .await
+        .unwrap();
+
+    let stream = timeout(TIMEOUT, listener.accept()).await.unwrap().unwrap();
+    let mut server = Framed::new(stream, ServerCodec::default());
+
+    let hello = timeout(TIMEOUT, server.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert!(matches!(hello.command, NetworkCommand::ClientHello(_)));
+
+    server
+        .send(NetworkEvent::ServerHello(ServerHello {
+            your_id,
+            default_channel_id: None,
+            resume_token: None,
+            resume_grace_period: Duration::ZERO,
+            resumed: false,
+            protocol_version: PROTOCOL_VERSION,
+            capabilities: BTreeSet::new(),
+            max_message_length: None,
+            max_frame_length: None,
+        }))
+        .await
+        .unwrap();
+
+    let event = timeout(TIMEOUT, handle.event_rx.recv()).await.unwrap();
+    match event {
+        Some(Ok(ClientEvent::InitialSync(sync))) => assert_eq!(sync.your_id, your_id),
+        other => panic!("expected the initial sync, got {other:?}"),
+    }
+
+    handle
+        .cmd_tx
+        .send(ClientCommand::NetworkCommand(NetworkCommand::SendMessage(
+            SendMessage {
+                contents: "hello".to_owned(),
+                destination: SendDestination::User(your_id),
+            },
+        )))
+        .await
+        .unwrap();
+
+    // The backend may fetch the server's state first.
+    loop {
+        let request = timeout(TIMEOUT, server.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+
+        if let NetworkCommand::SendMessage(message) = request.command {
+            assert_eq!(message.contents, "hello");
+            break;
+        }
+    }
+
+    handle.cmd_tx.send(ClientCommand::Quit).await.unwrap();
+}
@@ -0,0 +1,309 @@
+pub mod protocol;
+
+use std::{
+    net::IpAddr,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, bail};
+use clap::{Args, Subcommand};
+use figment::{
+    Figment,
+    providers::{Format, Serialized, Toml},
+};
+use network_protocol::Role;
+use serde::{Deserialize, Serialize};
+use shared_utils::{files::TildeRelativePathBuf, first_match};
+
+use protocol::{AdminRequest, AdminResponse};
+
+use crate::{DEFAULT_CONFIG, DefaultPaths, ENV_VAR_PREFIX};
+
+#[derive(Debug, Args, Serialize, Deserialize)]
+pub struct AdminArgs {
+    /// Path to the server's admin socket
+    /// If not provided, it is read from the server's config file.
+    #[arg(long, value_name = "PATH")]
+    socket: Option<PathBuf>,
+
+    /// Path to the TOML config file for the server
+    #[arg(long, value_name = "PATH")]
+    config_file: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: AdminCommand,
+}
+
+#[derive(Debug, Subcommand, Serialize, Deserialize)]
+pub enum AdminCommand {
+    /// List the users on the server
+    Users,
+
+    /// Disconnect a user
+    Kick {
+        /// Name or ID of the user
+        user: String,
+
+        /// Reason shown to the user and everyone else
+        #[arg(long, default_value = "")]
+        reason: String,
+    },
+
+    /// Ban a connected user, and disconnect them
+    Ban {
+        /// Name or ID of the user
+        user: String,
+
+        /// Reason shown to the user and everyone else
+        #[arg(long, default_value = "")]
+        reason: String,
+
+        /// How long the ban lasts, in seconds. Bans until unbanned if not given
+        #[arg(long, value_name = "SECONDS")]
+        duration: Option<u64>,
+
+        /// Ban the address the user is connected from, rather than their account. Guests can
+        /// only be banned this way
+        #[arg(long)]
+        address: bool,
+    },
+
+    /// Ban an address, whether or not anyone is connected from it
+    BanAddress {
+        /// The IP address to ban
+        address: IpAddr,
+
+        /// Reason shown to users connected from the address
+        #[arg(long, default_value = "")]
+        reason: String,
+
+        /// How long the ban lasts, in seconds. Bans until unbanned if not given
+        #[arg(long, value_name = "SECONDS")]
+        duration: Option<u64>,
+    },
+
+    /// Lift a ban
+    Unban {
+        /// A banned IP address, or the name or ID of a banned account
+        target: String,
+    },
+
+    /// List the bans on the server
+    Bans,
+
+    /// Change the role of an account
+    SetRole {
+        /// Name or ID of the account
+        user: String,
+
+        /// The new role: member, moderator or owner
+        #[arg(value_parser = parse_role)]
+        role: Role,
+    },
+
+    /// Show a notice to every connected user
+    Notice {
+        /// The notice to show
+        message: String,
+    },
+
+    /// Create a channel
+    CreateChannel {
+        /// Name of the new channel
+        name: String,
+    },
+
+    /// Rename a channel
+    RenameChannel {
+        /// Name or ID of the channel
+        channel: String,
+
+        /// New name of the channel
+        name: String,
+    },
+}
+
+impl From<AdminCommand> for AdminRequest {
+    fn from(command: AdminCommand) -> Self {
+        match command {
+            AdminCommand::Users => Self::ListUsers,
+            AdminCommand::Kick { user, reason } => Self::Kick { user, reason },
+            AdminCommand::Ban {
+                user,
+                reason,
+                duration,
+                address,
+            } => Self::Ban {
+                user,
+                reason,
+                duration_secs: duration,
+                by_address: address,
+            },
+            AdminCommand::BanAddress {
+                address,
+                reason,
+                duration,
+            } => Self::BanAddress {
+                address,
+                reason,
+                duration_secs: duration,
+            },
+            AdminCommand::Unban { target } => Self::Unban { target },
+            AdminCommand::Bans => Self::ListBans,
+            AdminCommand::SetRole { user, role } => Self::SetRole { user, role },
+            AdminCommand::Notice { message } => Self::Notice { message },
+            AdminCommand::CreateChannel { name } => Self::CreateChannel { name },
+            AdminCommand::RenameChannel { channel, name } => Self::RenameChannel { channel, name },
+        }
+    }
+}
+
+fn parse_role(role: &str) -> Result<Role, String> {
+    role.parse().map_err(|()| {
+        format!(
+            "expected one of: {}",
+            Role::ALL.map(Role::as_str).join(", ")
+        )
+    })
+}
+
+/// The part of the server's configuration the admin command needs.
+#[derive(Debug, Deserialize)]
+struct AdminSocketConfig {
+    socket_path: TildeRelativePathBuf,
+}
+
+pub async fn main(default_paths: Option<DefaultPaths>, args: AdminArgs) -> anyhow::Result<()> {
+    let socket_path = match args.socket {
+        Some(path) => path,
+        None => socket_path_from_config(default_paths.as_ref(), args.config_file)?,
+    };
+
+    match send_request(&socket_path, &args.command.into()).await? {
+        AdminResponse::Users { users } => {
+            println!("{:<36}  {:<20}  {:<9}  ADDRESS", "ID", "NAME", "ROLE");
+            for user in users {
+                let addr = user.addr.unwrap_or_else(|| "(disconnected)".to_owned());
+                println!(
+                    "{:<36}  {:<20}  {:<9}  {addr}",
+                    user.id.0.to_string(),
+                    user.name,
+                    user.role.as_str()
+                );
+            }
+        }
+
+        AdminResponse::Bans { bans } => {
+            println!("{:<50}  {:<12}  REASON", "TARGET", "ENDS IN");
+            for ban in bans {
+                let ends_in = ban
+                    .expires_at
+                    .map_or_else(|| "never".to_owned(), format_remaining);
+                println!("{:<50}  {ends_in:<12}  {}", ban.target, ban.reason);
+            }
+        }
+
+        AdminResponse::Channel { channel } => {
+            println!("Channel {}: {}", u64::from(channel.id), channel.name);
+        }
+
+        AdminResponse::Done => println!("Done"),
+
+        AdminResponse::Error { message } => bail!("Server refused request: {message}"),
+    }
+
+    Ok(())
+}
+
+/// Format the time left until a point in time, to the minute.
+fn format_remaining(until: SystemTime) -> String {
+    let minutes = until
+        .duration_since(SystemTime::now())
+        .unwrap_or(Duration::ZERO)
+        .as_secs()
+        .div_ceil(60);
+
+    format!("{}h {:02}m", minutes / 60, minutes % 60)
+}
+
+/// Send a single request over the admin socket and wait for the server's answer.
+#[cfg(unix)]
+async fn send_request(
+    socket_path: &std::path::Path,
+    request: &AdminRequest,
+) -> anyhow::Result<AdminResponse> {
+    use futures::{SinkExt, StreamExt};
+    use tokio::net::UnixStream;
+    use tokio_util::codec::{Framed, LinesCodec};
+
+    let stream = UnixStream::connect(socket_path).await.with_context(|| {
+        format!(
+            "Connecting to admin socket '{}'. Is the server running?",
+            socket_path.display()
+        )
+    })?;
+    let mut lines = Framed::new(
+        stream,
+        LinesCodec::new_with_max_length(protocol::MAX_LINE_LENGTH),
+    );
+
+    let request = serde_json::to_string(request).context("Serializing request")?;
+    lines.send(request).await.context("Sending request")?;
+
+    let response = lines
+        .next()
+        .await
+        .context("Server closed the admin connection without answering")?
+        .context("Reading response")?;
+
+    serde_json::from_str(&response).context("Parsing response")
+}
+
+#[cfg(not(unix))]
+#[allow(clippy::unused_async)]
+async fn send_request(
+    _socket_path: &std::path::Path,
+    _request: &AdminRequest,
+) -> anyhow::Result<AdminResponse> {
+    bail!("The admin socket is only supported on Unix platforms")
+}
+
+/// Resolve the admin socket path the same way the server does.
+fn socket_path_from_config(
+    default_paths: Option<&DefaultPaths>,
+    config_file: Option<PathBuf>,
+) -> anyhow::Result<PathBuf> {
+    let env_conf_path = std::env::var(format!("{ENV_VAR_PREFIX}CONFIG_FILE"))
+        .ok()
+        .map(PathBuf::from);
+
+    let config_path = first_match! {
+        Some(path) = config_file => path,
+        Some(path) = env_conf_path => path,
+        Some(defaults) = default_paths => defaults.config.clone(),
+    };
+
+    let mut figment = Figment::new().merge(Toml::string(DEFAULT_CONFIG));
+
+    if let Some(path) = &config_path {
+        figment = figment.merge(Toml::file(path));
+    }
+
+    if let Some(defaults) = default_paths {
+        figment = figment.merge(Serialized::default(
+            "admin.socket_path",
+            &defaults.admin_socket,
+        ));
+    }
+
+    let config: AdminSocketConfig = figment
+        .focus("admin")
+        .extract()
+        .context("Resolving admin socket path")?;
+
+    config
+        .socket_path
+        .resolved()
+        .context("Resolving admin socket path")
+}
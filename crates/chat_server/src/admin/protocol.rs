@@ -0,0 +1,107 @@
+//! Messages exchanged over the admin socket. Each message is a single line of JSON.
+
+use std::{net::IpAddr, time::SystemTime};
+
+use network_protocol::{ChannelInfo, Role, UserId};
+use serde::{Deserialize, Serialize};
+
+/// Longest line either side of the admin socket accepts, in bytes.
+pub const MAX_LINE_LENGTH: usize = 64 * 1024;
+
+/// A request from an admin client to the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum AdminRequest {
+    /// List every user on the server.
+    ListUsers,
+
+    /// Kick a user, given by name or ID.
+    Kick {
+        user: String,
+        #[serde(default)]
+        reason: String,
+    },
+
+    /// Ban a connected user, given by name or ID, by their account or by their address.
+    Ban {
+        user: String,
+        #[serde(default)]
+        reason: String,
+        /// How long the ban lasts. `None` bans until unbanned.
+        duration_secs: Option<u64>,
+        #[serde(default)]
+        by_address: bool,
+    },
+
+    /// Ban an address, whether or not anyone is connected from it.
+    BanAddress {
+        address: IpAddr,
+        #[serde(default)]
+        reason: String,
+        /// How long the ban lasts. `None` bans until unbanned.
+        duration_secs: Option<u64>,
+    },
+
+    /// Lift a ban on an address, or on an account given by name or ID.
+    Unban { target: String },
+
+    /// List every ban that hasn't ended.
+    ListBans,
+
+    /// Change the role of an account, given by name or ID.
+    SetRole { user: String, role: Role },
+
+    /// Show a notice to every connected user.
+    Notice { message: String },
+
+    /// Create a channel.
+    CreateChannel { name: String },
+
+    /// Rename a channel, given by name or ID.
+    RenameChannel { channel: String, name: String },
+}
+
+/// A user, as listed over the admin socket.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectedUser {
+    pub id: UserId,
+    pub name: String,
+    pub role: Role,
+
+    /// Where the user's connection comes from: an address, or a local socket. `None` while the
+    /// user is held for session resumption.
+    pub addr: Option<String>,
+}
+
+/// A ban, as listed over the admin socket.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListedBan {
+    /// What is banned, like `account <ID>` or `address <IP>`.
+    pub target: String,
+    pub reason: String,
+    pub banned_by: Option<UserId>,
+    pub created_at: SystemTime,
+
+    /// When the ban ends. `None` if it lasts until it's lifted.
+    pub expires_at: Option<SystemTime>,
+}
+
+/// The server's answer to an [`AdminRequest`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "response", rename_all = "snake_case")]
+pub enum AdminResponse {
+    /// The users on the server.
+    Users { users: Vec<ConnectedUser> },
+
+    /// The bans on the server.
+    Bans { bans: Vec<ListedBan> },
+
+    /// The channel that was created or renamed.
+    Channel { channel: ChannelInfo },
+
+    /// The request succeeded, and there is nothing else to say.
+    Done,
+
+    /// The request failed.
+    Error { message: String },
+}
@@ -1,4 +1,5 @@
 mod ca_certs;
+mod client_certs;
 mod config;
 mod pki;
 mod server_certs;
@@ -11,6 +12,7 @@ use clap::Subcommand;
 use serde::{Deserialize, Serialize};
 
 use ca_certs::{InitCACertsArgs, init_ca_certs};
+use client_certs::{InitClientCertsArgs, init_client_certs};
 use config::{InitConfigArgs, init_config};
 use server_certs::{InitServerCertsArgs, init_server_certs};
 use tempfile::NamedTempFile;
@@ -32,6 +34,9 @@ pub enum InitMode {
 
     /// Initialize a CA-signed private key and certificate for TLS
     ServerCerts(InitServerCertsArgs),
+
+    /// Initialize a CA-signed private key and certificate for a client to authenticate with
+    ClientCerts(InitClientCertsArgs),
 }
 
 #[derive(Debug)]
@@ -90,5 +95,6 @@ pub fn main(default_paths: Option<DefaultPaths>, mode: InitMode) -> anyhow::Resu
         InitMode::CaCerts(args) => init_ca_certs(default_paths, args),
         InitMode::Pki(args) => init_pki(default_paths, args),
         InitMode::ServerCerts(args) => init_server_certs(default_paths, args),
+        InitMode::ClientCerts(args) => init_client_certs(default_paths, args),
     }
 }
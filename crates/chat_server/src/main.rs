@@ -1,3 +1,4 @@
+mod admin;
 mod init;
 mod run;
 
@@ -6,6 +7,7 @@ use std::path::PathBuf;
 use clap::{Parser, Subcommand};
 use serde::{Deserialize, Serialize};
 
+use admin::AdminArgs;
 use init::InitMode;
 use run::RunArgs;
 use shared_utils::files::NamedProjectDirs;
@@ -29,6 +31,9 @@ enum Commands {
 
     /// Start the server
     Run(RunArgs),
+
+    /// Manage a running server through its admin socket
+    Admin(AdminArgs),
 }
 
 /// Collection of relevant paths for the server to read or initialize important files.
@@ -39,7 +44,11 @@ struct DefaultPaths {
     ca_key: PathBuf,
     server_cert: PathBuf,
     server_key: PathBuf,
+    client_certs_dir: PathBuf,
     log_dir: PathBuf,
+    history_db: PathBuf,
+    admin_socket: PathBuf,
+    unix_socket: PathBuf,
 }
 
 impl DefaultPaths {
@@ -51,7 +60,11 @@ impl DefaultPaths {
     /// `ca_key`: `NamedProjectDirs::data_dir()/tls/ca/key.pem`
     /// `server_cert`: `NamedProjectDirs::data_dir()/tls/server/certificate.pem`
     /// `server_key`: `NamedProjectDirs::data_dir()/tls/server/key.pem`
+    /// `client_certs_dir`: `NamedProjectDirs::data_dir()/tls/clients`
     /// `log_file`: `NamedProjectDirs::state_dir()/server.log`
+    /// `history_db`: `NamedProjectDirs::data_dir()/history.sqlite3`
+    /// `admin_socket`: `NamedProjectDirs::state_dir()/admin.sock`
+    /// `unix_socket`: `NamedProjectDirs::state_dir()/chat.sock`
     fn defaults(component: impl Into<PathBuf>) -> Option<Self> {
         let base = NamedProjectDirs::new(component)?;
 
@@ -65,15 +78,26 @@ impl DefaultPaths {
         let server_cert = server_cert_dir.join("certificate.pem");
         let server_key = server_cert_dir.join("key.pem");
 
+        let client_certs_dir = base.data_dir().join("tls").join("clients");
+
         let log_dir = base.state_dir().to_owned();
 
+        let history_db = base.data_dir().join("history.sqlite3");
+
+        let admin_socket = base.state_dir().join("admin.sock");
+        let unix_socket = base.state_dir().join("chat.sock");
+
         Some(Self {
             config,
             ca_cert,
             ca_key,
             server_cert,
             server_key,
+            client_certs_dir,
             log_dir,
+            history_db,
+            admin_socket,
+            unix_socket,
         })
     }
 }
@@ -87,5 +111,6 @@ async fn main() -> anyhow::Result<()> {
     match global_args.command {
         Commands::Run(args) => run::main(default_paths, args).await,
         Commands::Init(mode) => init::main(default_paths, mode),
+        Commands::Admin(args) => admin::main(default_paths, args).await,
     }
 }
@@ -0,0 +1,363 @@
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::{SinkExt, StreamExt};
+use network_protocol::{ChannelId, NetworkEvent, Role, ServerNotice, UserId};
+use tokio::net::UnixStream;
+use tokio_util::codec::{Framed, LinesCodec};
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::{debug, info, instrument, warn};
+
+use crate::admin::protocol::{
+    AdminRequest, AdminResponse, ConnectedUser, ListedBan, MAX_LINE_LENGTH,
+};
+use crate::run::ServerState;
+use crate::run::storage::{BanTarget, StorageError};
+use crate::run::unix_socket;
+
+/// A task struct serving admin clients on a Unix domain socket. Only the user the server runs as
+/// may connect to it.
+pub struct AdminSocket {
+    /// Server state - users, channels, etc.
+    server_state: Arc<ServerState>,
+
+    /// Cancellation token for the main task to signal for shutdown.
+    cancellation_token: CancellationToken,
+
+    /// Task tracker for the main task to join all tasks on shutdown.
+    task_tracker: TaskTracker,
+
+    /// Path of the socket.
+    path: PathBuf,
+}
+
+impl AdminSocket {
+    /// Create a new `AdminSocket`.
+    pub fn new(
+        server_state: Arc<ServerState>,
+        cancellation_token: CancellationToken,
+        task_tracker: TaskTracker,
+        path: PathBuf,
+    ) -> Self {
+        Self {
+            server_state,
+            cancellation_token,
+            task_tracker,
+            path,
+        }
+    }
+
+    /// Start an initialized `AdminSocket`. This should be spawned as a [`tokio`] task.
+    #[instrument(skip_all, fields(path = %self.path.display()), parent = None, err)]
+    pub async fn start(self) -> io::Result<()> {
+        // Only the user the server runs as may connect.
+        let listener = unix_socket::bind(&self.path, 0o600).await?;
+
+        info!("Admin socket bound and accepting connections");
+
+        loop {
+            tokio::select! {
+                conn = listener.accept() => match conn {
+                    Ok((stream, _addr)) => {
+                        debug!("Accepted admin connection");
+                        self.task_tracker.spawn(serve_admin_client(
+                            self.server_state.clone(),
+                            stream,
+                            self.cancellation_token.clone(),
+                        ));
+                    }
+
+                    Err(e) => {
+                        warn!(error = %e, "Failed to accept admin connection");
+                    }
+                },
+
+                () = self.cancellation_token.cancelled() => {
+                    info!("Admin socket received cancellation signal, shutting down...");
+                    break;
+                }
+            }
+        }
+
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!(error = %e, "Failed to remove admin socket");
+        }
+
+        Ok(())
+    }
+}
+
+/// Answer requests from one admin client until it disconnects.
+#[instrument(skip_all, parent = None)]
+async fn serve_admin_client(
+    server_state: Arc<ServerState>,
+    stream: UnixStream,
+    cancellation_token: CancellationToken,
+) {
+    let mut lines = Framed::new(stream, LinesCodec::new_with_max_length(MAX_LINE_LENGTH));
+
+    loop {
+        let line = tokio::select! {
+            line = lines.next() => line,
+            () = cancellation_token.cancelled() => break,
+        };
+
+        let line = match line {
+            Some(Ok(line)) => line,
+            Some(Err(e)) => {
+                warn!(error = %e, "Error reading from admin client");
+                break;
+            }
+            None => break,
+        };
+
+        let response = match serde_json::from_str::<AdminRequest>(&line) {
+            Ok(request) => handle_request(&server_state, request).await,
+            Err(e) => AdminResponse::Error {
+                message: format!("invalid request: {e}"),
+            },
+        };
+
+        let response =
+            serde_json::to_string(&response).expect("Admin responses always serialize to JSON");
+
+        if let Err(e) = lines.send(response).await {
+            warn!(error = %e, "Error writing to admin client");
+            break;
+        }
+    }
+
+    debug!("Admin client disconnected");
+}
+
+async fn handle_request(server_state: &ServerState, request: AdminRequest) -> AdminResponse {
+    info!(?request, "Handling admin request");
+
+    match request {
+        AdminRequest::ListUsers => {
+            let mut users: Vec<ConnectedUser> = server_state
+                .get_all_user_connections()
+                .await
+                .into_iter()
+                .map(|(info, addr)| ConnectedUser {
+                    id: info.id,
+                    name: info.name,
+                    role: info.role,
+                    addr: addr.map(|addr| addr.to_string()),
+                })
+                .collect();
+            users.sort_by(|a, b| a.name.cmp(&b.name));
+
+            AdminResponse::Users { users }
+        }
+
+        AdminRequest::Kick { user, reason } => kick(server_state, &user, reason).await,
+
+        AdminRequest::Ban {
+            user,
+            reason,
+            duration_secs,
+            by_address,
+        } => {
+            let duration = duration_secs.map(Duration::from_secs);
+            ban(server_state, &user, reason, duration, by_address).await
+        }
+
+        AdminRequest::BanAddress {
+            address,
+            reason,
+            duration_secs,
+        } => {
+            let duration = duration_secs.map(Duration::from_secs);
+            ban_address(server_state, address, reason, duration).await
+        }
+
+        AdminRequest::Unban { target } => unban(server_state, &target).await,
+
+        AdminRequest::ListBans => list_bans(server_state).await,
+
+        AdminRequest::SetRole { user, role } => set_role(server_state, &user, role).await,
+
+        AdminRequest::Notice { message } => {
+            server_state.send_global_event(NetworkEvent::ServerNotice(ServerNotice { message }));
+            AdminResponse::Done
+        }
+
+        AdminRequest::CreateChannel { name } => match server_state.create_channel(name, None).await
+        {
+            Ok(channel) => AdminResponse::Channel { channel },
+            Err(e) => error_response(e),
+        },
+
+        AdminRequest::RenameChannel { channel, name } => {
+            let Some(id) = find_channel(server_state, &channel).await else {
+                return error_response(format!("no channel '{channel}'"));
+            };
+
+            match server_state.rename_channel(id, name, None).await {
+                Ok(channel) => AdminResponse::Channel { channel },
+                Err(e) => error_response(e),
+            }
+        }
+    }
+}
+
+/// Disconnect a user, given by name or ID.
+async fn kick(server_state: &ServerState, user: &str, reason: String) -> AdminResponse {
+    let Some(id) = find_user(server_state, user).await else {
+        return error_response(format!("no user '{user}'"));
+    };
+
+    match server_state.kick_user(id, None, reason).await {
+        Ok(()) => AdminResponse::Done,
+        Err(e) => error_response(e),
+    }
+}
+
+/// Ban a connected user, given by name or ID, by their account or by their address.
+async fn ban(
+    server_state: &ServerState,
+    user: &str,
+    reason: String,
+    duration: Option<Duration>,
+    by_address: bool,
+) -> AdminResponse {
+    let Some(id) = find_user(server_state, user).await else {
+        return error_response(format!("no user '{user}'"));
+    };
+
+    match server_state
+        .ban_user(id, None, reason, duration, by_address)
+        .await
+    {
+        Ok(ban) => {
+            info!(target = %ban.target, "Banned user");
+            AdminResponse::Done
+        }
+        Err(e) => error_response(e),
+    }
+}
+
+/// Ban an address, whether or not anyone is connected from it.
+async fn ban_address(
+    server_state: &ServerState,
+    address: IpAddr,
+    reason: String,
+    duration: Option<Duration>,
+) -> AdminResponse {
+    match server_state.ban_address(address, reason, duration).await {
+        Ok(ban) => {
+            info!(target = %ban.target, "Banned address");
+            AdminResponse::Done
+        }
+        Err(e) => error_response(e),
+    }
+}
+
+/// List every ban that hasn't ended, oldest first.
+async fn list_bans(server_state: &ServerState) -> AdminResponse {
+    let mut bans: Vec<ListedBan> = server_state
+        .list_bans()
+        .await
+        .into_iter()
+        .map(|ban| ListedBan {
+            target: ban.target.to_string(),
+            reason: ban.reason,
+            banned_by: ban.banned_by,
+            created_at: ban.created_at,
+            expires_at: ban.expires_at,
+        })
+        .collect();
+    bans.sort_by_key(|ban| ban.created_at);
+
+    AdminResponse::Bans { bans }
+}
+
+/// Change the role of an account, given by name or ID.
+async fn set_role(server_state: &ServerState, user: &str, role: Role) -> AdminResponse {
+    let id = match find_account(server_state, user).await {
+        Ok(Some(id)) => id,
+        Ok(None) => return error_response(format!("no account '{user}'")),
+        Err(e) => return error_response(e),
+    };
+
+    match server_state.set_role(id, role).await {
+        Ok(()) => AdminResponse::Done,
+        Err(e) => error_response(e),
+    }
+}
+
+/// Lift the ban on an address, or on an account given by name or ID.
+async fn unban(server_state: &ServerState, target: &str) -> AdminResponse {
+    let target = if let Ok(ip) = target.parse::<IpAddr>() {
+        BanTarget::address(ip)
+    } else {
+        match find_account(server_state, target).await {
+            Ok(Some(id)) => BanTarget::Account(id),
+            Ok(None) => return error_response(format!("no address or account '{target}'")),
+            Err(e) => return error_response(e),
+        }
+    };
+
+    match server_state.unban(target).await {
+        Ok(true) => AdminResponse::Done,
+        Ok(false) => error_response(format!("{target} is not banned")),
+        Err(e) => error_response(e),
+    }
+}
+
+fn error_response(error: impl ToString) -> AdminResponse {
+    AdminResponse::Error {
+        message: error.to_string(),
+    }
+}
+
+/// Find a user by ID, or by name, ignoring case.
+async fn find_user(server_state: &ServerState, user: &str) -> Option<UserId> {
+    let users = server_state.get_all_user_info().await;
+
+    if let Ok(id) = user.parse::<UserId>()
+        && users.iter().any(|info| info.id == id)
+    {
+        return Some(id);
+    }
+
+    users
+        .into_iter()
+        .find(|info| info.name.eq_ignore_ascii_case(user))
+        .map(|info| info.id)
+}
+
+/// Find an account by ID, or by name, ignoring case, whether or not anyone is logged into it.
+/// Since account users keep their account's ID, any well-formed ID is taken as is.
+async fn find_account(
+    server_state: &ServerState,
+    user: &str,
+) -> Result<Option<UserId>, StorageError> {
+    if let Ok(id) = user.parse::<UserId>() {
+        return Ok(Some(id));
+    }
+
+    server_state.find_account(user).await
+}
+
+/// Find a channel by ID, or by name, ignoring case.
+async fn find_channel(server_state: &ServerState, channel: &str) -> Option<ChannelId> {
+    let channels = server_state.get_all_channel_info().await;
+
+    if let Ok(id) = channel.parse::<ChannelId>()
+        && channels.iter().any(|info| info.id == id)
+    {
+        return Some(id);
+    }
+
+    channels
+        .into_iter()
+        .find(|info| info.name.eq_ignore_ascii_case(channel))
+        .map(|info| info.id)
+}
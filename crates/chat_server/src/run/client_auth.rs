@@ -0,0 +1,115 @@
+use std::sync::Arc;
+
+use anyhow::{Context, anyhow};
+use rustls::{
+    RootCertStore,
+    pki_types::{CertificateDer, pem::PemObject},
+    server::{WebPkiClientVerifier, danger::ClientCertVerifier},
+};
+use serde::{Deserialize, Serialize};
+use shared_utils::files::TildeRelativePathBuf;
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+/// Whether clients must present a TLS certificate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientAuthMode {
+    /// Don't ask clients for certificates.
+    None,
+
+    /// Verify a client certificate if the client presents one, but also accept clients without
+    /// one.
+    Optional,
+
+    /// Reject clients that don't present a valid certificate.
+    Required,
+}
+
+/// Configuration for client certificate authentication.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientAuthConfig {
+    /// Whether clients must present a certificate.
+    pub mode: ClientAuthMode,
+
+    /// Path to the CA certificate that client certificates must be signed by.
+    pub ca_cert_path: TildeRelativePathBuf,
+}
+
+impl ClientAuthConfig {
+    /// Build the verifier for client certificates, or `None` if client authentication is
+    /// disabled.
+    ///
+    /// # Errors
+    /// Returns an error if the CA certificate could not be loaded, or the verifier could not be
+    /// built from it.
+    pub fn verifier(&self) -> anyhow::Result<Option<Arc<dyn ClientCertVerifier>>> {
+        if self.mode == ClientAuthMode::None {
+            return Ok(None);
+        }
+
+        let ca_path_err_display = self.ca_cert_path.original().display();
+        let ca_cert_path = self
+            .ca_cert_path
+            .resolved()
+            .context("Resolving client CA certificate path")?;
+
+        let mut roots = RootCertStore::empty();
+        for cert in CertificateDer::pem_file_iter(&ca_cert_path).with_context(|| {
+            format!("Opening client CA certificate file '{ca_path_err_display}'")
+        })? {
+            let cert = cert.with_context(|| {
+                format!("Reading client CA certificate file '{ca_path_err_display}'")
+            })?;
+            roots
+                .add(cert)
+                .context("Configuring client authentication: bad CA certificate")?;
+        }
+
+        let builder = WebPkiClientVerifier::builder(Arc::new(roots));
+        let builder = if self.mode == ClientAuthMode::Optional {
+            builder.allow_unauthenticated()
+        } else {
+            builder
+        };
+
+        Ok(Some(builder.build().context(
+            "Configuring client authentication: could not build verifier",
+        )?))
+    }
+}
+
+/// The identity in a verified client certificate.
+#[derive(Debug, Clone)]
+pub struct CertificateIdentity {
+    /// The certificate's full subject, in RFC 4514 form. This is what identifies the client.
+    pub subject: String,
+
+    /// The subject's common name. New accounts created from the certificate use this as their
+    /// username.
+    pub common_name: String,
+}
+
+impl CertificateIdentity {
+    /// Extract the identity from a DER-encoded certificate. The certificate must already have been
+    /// verified.
+    ///
+    /// # Errors
+    /// Returns an error if the certificate could not be parsed, or has no common name.
+    pub fn from_der(der: &CertificateDer<'_>) -> anyhow::Result<Self> {
+        let (_, cert) = X509Certificate::from_der(der).context("Parsing client certificate")?;
+        let subject = cert.subject();
+
+        let common_name = subject
+            .iter_common_name()
+            .next()
+            .ok_or_else(|| anyhow!("client certificate has no common name"))?
+            .as_str()
+            .context("Reading client certificate common name")?
+            .to_owned();
+
+        Ok(Self {
+            subject: subject.to_string(),
+            common_name,
+        })
+    }
+}
@@ -1,21 +1,38 @@
 use std::sync::Arc;
 
 use network_protocol::{NetworkEvent, UserId};
-use tracing::warn;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
 
 use crate::run::server_state::{ServerState, UserError, UserToken};
 
-/// RAII guard that automatically unregisters a user when dropped.
+/// RAII guard that automatically unregisters a user when dropped. If the connection dropped
+/// unexpectedly, the user is instead held for the server's resume grace period, so they can resume
+/// their session.
 #[derive(Debug)]
 pub struct ConnectionGuard {
     token: Option<UserToken>,
+
+    /// Channel for events coming from elsewhere on the server. Owned by the guard, so events keep
+    /// queueing up while the user is held.
+    event_rx: Option<mpsc::Receiver<NetworkEvent>>,
+
+    /// Whether the user should be held when the guard drops, rather than removed.
+    resumable: bool,
+
     server_state: Arc<ServerState>,
 }
 
 impl ConnectionGuard {
-    pub fn new(token: UserToken, server_state: Arc<ServerState>) -> Self {
+    pub fn new(
+        token: UserToken,
+        event_rx: mpsc::Receiver<NetworkEvent>,
+        server_state: Arc<ServerState>,
+    ) -> Self {
         Self {
             token: Some(token),
+            event_rx: Some(event_rx),
+            resumable: false,
             server_state,
         }
     }
@@ -29,6 +46,18 @@ impl ConnectionGuard {
     pub fn id(&self) -> UserId {
         self.token().id()
     }
+
+    pub fn event_rx(&mut self) -> &mut mpsc::Receiver<NetworkEvent> {
+        self.event_rx
+            .as_mut()
+            .expect("Event receiver is always present while running")
+    }
+
+    /// Set whether the user should be held for resumption when the guard drops. Only users who
+    /// were given a resume token can be held.
+    pub fn set_resumable(&mut self, resumable: bool) {
+        self.resumable = resumable;
+    }
 }
 
 impl Drop for ConnectionGuard {
@@ -37,11 +66,26 @@ impl Drop for ConnectionGuard {
             .token
             .take()
             .expect("Token is always present while running");
+        let event_rx = self
+            .event_rx
+            .take()
+            .expect("Event receiver is always present while running");
 
         let id = token.id();
+        let resumable = self.resumable;
         let server_state = self.server_state.clone();
 
         tokio::spawn(async move {
+            if resumable && let Some(detachment) = server_state.detach_user(&token, event_rx).await
+            {
+                let grace_period = server_state.resume_grace_period();
+                debug!(%id, ?grace_period, "Holding user for session resumption");
+
+                tokio::time::sleep(grace_period).await;
+                server_state.expire_detached_user(id, detachment).await;
+                return;
+            }
+
             match server_state.remove_user(token).await {
                 Err(UserError::YourIdNotFound) => {
                     warn!(%id, "State mismatch detected while disconnecting: ID not found");
@@ -1,30 +1,56 @@
 mod guard;
 
-use std::{net::SocketAddr, sync::Arc};
+use std::{
+    collections::{BTreeSet, HashSet},
+    fmt::Debug,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 use anyhow::{Context, bail};
-use futures::{
-    SinkExt, StreamExt,
-    stream::{SelectAll, select_all},
-};
+use futures::{SinkExt, StreamExt};
 use guard::ConnectionGuard;
 use network_protocol::{
-    ChannelSync, NetworkCommand, NetworkEvent, ReceiveDestination, ReceivedMessage,
-    SendDestination, SendMessage, ServerHello, UpdateInfo, UserSync, codecs::ServerCodec,
+    BanUser, Capability, ChannelId, ChannelInfo, ChannelMember, ChannelMembers, ChannelSync,
+    CommandAck, CommandRequest, DeleteMessage, EditMessage, ErrorEvent, ErrorKind, FetchHistory,
+    HistoryDestination, HistoryPage, KickUser, MIN_PROTOCOL_VERSION, MessageId, MuteUser,
+    NetworkCommand, NetworkEvent, PROTOCOL_VERSION, Ping, Pong, ReceiveDestination, RequestId,
+    Resync, SendDestination, SendMessage, ServerHello, UnmuteUser, UpdateInfo, UserBanned,
+    UserKicked, UserSync,
 };
 use tokio::{
-    io::AsyncWriteExt,
-    net::TcpStream,
-    sync::{broadcast, mpsc},
+    io::{AsyncRead, AsyncWrite},
+    sync::{broadcast, mpsc, watch},
+    time::{Instant, sleep_until},
+};
+use tokio_stream::{
+    StreamMap,
+    wrappers::{BroadcastStream, errors::BroadcastStreamRecvError},
 };
-use tokio_rustls::{TlsAcceptor, server::TlsStream};
-use tokio_stream::wrappers::{BroadcastStream, errors::BroadcastStreamRecvError};
-use tokio_util::{codec::Framed, sync::CancellationToken};
+use tokio_util::sync::CancellationToken;
 use tracing::{Level, debug, info, instrument, warn};
+use uuid::Uuid;
 
-use crate::run::ServerState;
+use crate::run::{
+    ServerState,
+    client_auth::CertificateIdentity,
+    rate_limit::{RateLimiter, Verdict},
+    server_state::{ChannelError, Settings},
+    storage::StoredMessage,
+    transport::{Accepted, ClientAddr, ClientStream, Transport},
+};
 
-type ClientStream = Framed<TlsStream<TcpStream>, ServerCodec>;
+/// Outcome of a successful application-level handshake.
+#[derive(Debug)]
+struct Handshake {
+    guard: ConnectionGuard,
+
+    /// Whether the user resumed an earlier session.
+    resumed: bool,
+
+    /// Optional protocol features both the server and the client support.
+    capabilities: BTreeSet<Capability>,
+}
 
 /// A connection task responsible for talking to one client.
 #[derive(Debug)]
@@ -33,97 +59,162 @@ pub struct Connection {
     server_state: Arc<ServerState>,
 
     /// Stream of commands coming from the client, or sending back to the client.
-    client_stream: ClientStream,
+    client_stream: Box<dyn ClientStream>,
 
-    /// Address of the client associated with this connection.
-    client_addr: SocketAddr,
+    /// Where the client associated with this connection connected from.
+    client_addr: ClientAddr,
 
     /// Channel for events broadcast to all users on the server.
     global_event_rx: broadcast::Receiver<NetworkEvent>,
 
-    /// Channel for events coming from elsewhere on the server. Typically outbound towards the
-    /// client.
-    event_rx: mpsc::Receiver<NetworkEvent>,
+    /// Optional protocol features both the server and the client support.
+    capabilities: BTreeSet<Capability>,
 
-    /// Unified receiver stream for all channels on the server.
-    channels: SelectAll<BroadcastStream<NetworkEvent>>,
+    /// Request ID of the command being handled, until the command either fails or is acknowledged.
+    current_request: Option<RequestId>,
+
+    /// Limits how fast the client may send commands.
+    rate_limiter: RateLimiter,
+
+    /// Tells us when the server's settings were reloaded, so the rate limits can follow.
+    settings_rx: watch::Receiver<Settings>,
+
+    /// Unified receiver stream for all channels the user joined, keyed by channel ID.
+    channels: StreamMap<ChannelId, BroadcastStream<NetworkEvent>>,
+
+    /// Whether the client was told it lost global events, and hasn't refetched the user or
+    /// channel list since. Until it does, further losses aren't worth another [`Resync`].
+    global_resync_pending: bool,
+
+    /// Channels the client was told it lost messages in, and hasn't refetched the history of
+    /// since.
+    channel_resyncs_pending: HashSet<ChannelId>,
 
     /// Cancellation token for the main task to signal for shutdown.
     cancellation_token: CancellationToken,
 
+    /// Cancelled when the user is kicked off the server.
+    kick_token: CancellationToken,
+
     /// RAII guard to ensure the `Connection` unregisters from the `server_state` when it drops.
+    /// Also owns the channel for events coming from elsewhere on the server, which are typically
+    /// outbound towards the client.
     guard: ConnectionGuard,
 }
 
 impl Connection {
-    /// Open the connection with the client. This starts with a TLS handshake, then the main
-    /// communication loop. This should be spawned as a separate [`tokio`] task using
+    /// Open the connection with the client. This starts with the transport's handshakes, then the
+    /// main communication loop. This should be spawned as a separate [`tokio`] task using
     /// [`tokio::spawn`].
     ///
     /// Note that this function is completely self-contained. It is responsible for both
     /// initializing and running the `Connection` task. This is because the typical `new()` ->
     /// `run()` pattern involves the parent `Listener` in the handshake resolution, which both slows
     /// it down and potentially allows DDOS attacks.
+    ///
+    /// A `trusted_identity` lets the client log in as if it presented a certificate with that
+    /// identity, e.g. for trusted local users. A certificate the client does present takes
+    /// precedence.
     #[instrument(skip_all, parent = None, fields(%client_addr))]
-    pub async fn start(
+    pub async fn start<S>(
         server_state: Arc<ServerState>,
-        tls_acceptor: TlsAcceptor,
-        client_stream: TcpStream,
-        client_addr: SocketAddr,
+        transport: Transport,
+        client_stream: S,
+        client_addr: ClientAddr,
+        trusted_identity: Option<CertificateIdentity>,
         cancellation_token: CancellationToken,
-    ) {
+    ) where
+        S: AsyncRead + AsyncWrite + Debug + Send + Unpin + 'static,
+    {
         debug!("New client connection starting");
 
-        let client_stream = match tls_acceptor.accept(client_stream).await {
-            Ok(stream) => stream,
+        let Accepted {
+            stream: mut client_stream,
+            certificate,
+        } = match transport
+            .accept(client_stream, server_state.max_frame_length())
+            .await
+        {
+            Ok(accepted) => accepted,
             Err(e) => {
-                warn!(error = %e, "TLS handshake failed");
+                warn!("{e:#}");
                 return;
             }
         };
-        let mut client_stream = Framed::new(client_stream, ServerCodec);
-        debug!("Client completed TLS handshake");
+        let certificate = certificate.or(trusted_identity);
 
         // We want to finish the ClientHello -> ServerHello handshake before anything else.
         // NOTE: For now, if the handshake fails for any reason, we just abort the connection
-        // entirely. This keeps the implementation far simpler, at the cost of potentially repeating
-        // the TLS handshake. If this becomes a problem later, we'll fix it later.
-        let (event_rx, guard) =
-            match Self::handshake_client(&mut client_stream, server_state.clone()).await {
-                Ok(output) => output,
-                Err(e) => {
-                    warn!(error = %e, "Client handshake failed");
-                    return;
-                }
-            };
+        // entirely, after telling the client why if we can. This keeps the implementation far
+        // simpler, at the cost of potentially repeating the TLS handshake. If this becomes a
+        // problem later, we'll fix it later.
+        let Handshake {
+            guard,
+            resumed,
+            capabilities,
+        } = match Self::handshake_client(&mut client_stream, server_state.clone(), certificate)
+            .await
+        {
+            Ok(output) => output,
+            Err(e) => {
+                warn!(error = %e, "Client handshake failed");
+                return;
+            }
+        };
         debug!("Client completed application-level handshake");
 
-        // Subscribe to all the server's channels
-        let channels: select_all::SelectAll<_> = server_state
-            .subscribe_to_channels()
+        let Some(kick_token) = server_state
+            .attach_connection(guard.token(), client_addr)
             .await
-            .into_iter()
-            .map(BroadcastStream::from)
-            .collect();
+        else {
+            warn!("User was removed right after the handshake");
+            return;
+        };
 
         // Subscribe to the global broadcast channel. We do this AFTER sending the join notification
         // because the client doesn't need to be reminded that they connected (they already know
         // that).
         let global_event_rx = server_state.subscribe_to_global();
+        let default_channel_id = server_state.default_channel_id();
+
+        // A resumed user is still a member of the channels they were in, so we listen to them
+        // again. Everyone else starts out in the default channel. Other channels must be joined
+        // explicitly.
+        let channels_to_join = if resumed {
+            server_state.get_joined_channels(guard.token()).await
+        } else {
+            default_channel_id.into_iter().collect()
+        };
+
+        let settings_rx = server_state.watch_settings();
+        let rate_limiter = RateLimiter::new(&settings_rx.borrow().rate_limits);
 
-        let connection = Self {
+        let mut connection = Self {
             server_state,
             client_stream,
             client_addr,
             global_event_rx,
-            event_rx,
-            channels,
+            capabilities,
+            current_request: None,
+            rate_limiter,
+            settings_rx,
+            channels: StreamMap::new(),
+            global_resync_pending: false,
+            channel_resyncs_pending: HashSet::new(),
             cancellation_token,
+            kick_token,
             guard,
         };
 
         let user_id = connection.guard.id();
-        info!(%user_id, "Starting connection");
+        info!(%user_id, resumed, "Starting connection");
+
+        for id in channels_to_join {
+            if let Err(e) = connection.join_channel(id).await {
+                warn!(error = %e, %user_id, channel_id = %id, "Failed to join channel");
+                return;
+            }
+        }
 
         if let Err(e) = connection.run().await {
             warn!(
@@ -139,45 +230,110 @@ impl Connection {
     /// Perform the application-level handshake.
     #[instrument(skip_all, err(level = Level::WARN))]
     async fn handshake_client(
-        client_stream: &mut ClientStream,
+        client_stream: &mut Box<dyn ClientStream>,
         server_state: Arc<ServerState>,
-    ) -> anyhow::Result<(mpsc::Receiver<NetworkEvent>, ConnectionGuard)> {
+        certificate: Option<CertificateIdentity>,
+    ) -> anyhow::Result<Handshake> {
         let hello = match client_stream.next().await {
-            Some(Ok(NetworkCommand::ClientHello(hello))) => hello,
-            Some(Ok(other)) => bail!("unexpected command: {other:?}"),
+            Some(Ok(CommandRequest {
+                command: NetworkCommand::ClientHello(hello),
+                ..
+            })) => hello,
+            Some(Ok(other)) => bail!("unexpected command: {:?}", other.command),
             Some(Err(e)) => bail!("IO error: {e}"),
             None => bail!("client stream closed unexpectedly"),
         };
         debug!(?hello, "Received client hello");
 
+        // Nothing after the hellos can be relied on if the client doesn't speak our protocol.
+        if !network_protocol::is_compatible_version(hello.protocol_version) {
+            let message = format!(
+                "the client speaks protocol version {}, but the server only supports versions {} to {}",
+                hello.protocol_version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+            );
+
+            // We're giving up on the connection anyways, so a failure to send this is moot.
+            let _: Result<_, _> = client_stream
+                .send(NetworkEvent::ErrorEvent(ErrorEvent::new(
+                    ErrorKind::IncompatibleVersion,
+                    message.clone(),
+                )))
+                .await;
+
+            bail!(message);
+        }
+
+        // Only features both sides support may be used.
+        let capabilities: BTreeSet<_> = server_state
+            .capabilities()
+            .intersection(&hello.capabilities)
+            .copied()
+            .collect();
+        debug!(?capabilities, "Negotiated capabilities");
+
         let default_channel_id = server_state.default_channel_id();
 
-        let (event_tx, event_rx) = mpsc::channel::<NetworkEvent>(128); // TODO: Buffer size
+        // A valid resume token takes precedence. If it's unknown or expired, the client falls back
+        // to authenticating as usual.
+        let resumed_session = match &hello.resume_token {
+            Some(resume_token) => server_state.resume_user(resume_token).await,
+            None => None,
+        };
 
-        let new_user_result = server_state
-            .handle_new_user(
-                hello.requested_name,
-                server_state.max_username_length(),
-                event_tx,
-            )
-            .await;
+        let (user_token, event_rx, resumed) = if let Some((user_token, event_rx)) = resumed_session
+        {
+            debug!(user_id = %user_token.id(), "User resumed their session");
+            (user_token, event_rx, true)
+        } else {
+            let (event_tx, event_rx) =
+                mpsc::channel::<NetworkEvent>(server_state.event_queues().user_capacity);
+
+            let new_user_result = server_state
+                .handle_new_user(
+                    hello.authentication,
+                    certificate,
+                    server_state.max_username_length(),
+                    event_tx,
+                )
+                .await;
 
-        let user_token = match new_user_result {
-            Ok(token) => token,
-            Err(e) => return Err(anyhow::anyhow!(e)),
+            let user_token = match new_user_result {
+                Ok(token) => token,
+                Err(e) => {
+                    let message = e.to_string();
+
+                    // We're giving up on the connection anyways, so a failure to send this is moot.
+                    let _: Result<_, _> =
+                        client_stream.send(NetworkEvent::ErrorEvent(e.into())).await;
+
+                    bail!(message);
+                }
+            };
+            debug!(user_id = %user_token.id(), "User successfully authenticated, user token created");
+
+            (user_token, event_rx, false)
         };
-        debug!(user_id = %user_token.id(), "Username successfully registered, user token created");
 
         // It's important that we create the guard before any more fallible operations, since
         // `handle_new_user` touched persistent state.
         #[allow(clippy::used_underscore_binding)]
-        let guard = ConnectionGuard::new(user_token, server_state);
+        let mut guard = ConnectionGuard::new(user_token, event_rx, server_state.clone());
+
+        // Every connection gets a fresh token, so a token is only ever good for one resumption.
+        let resume_token = server_state.issue_resume_token(guard.token()).await?;
 
         // Send Hello to the client.
         if let Err(e) = client_stream
             .send(NetworkEvent::ServerHello(ServerHello {
                 your_id: guard.id(),
                 default_channel_id,
+                resume_token,
+                resume_grace_period: server_state.resume_grace_period(),
+                resumed,
+                protocol_version: PROTOCOL_VERSION,
+                capabilities: capabilities.clone(),
+                max_message_length: Some(server_state.max_message_length()),
+                max_frame_length: Some(server_state.max_frame_length()),
             }))
             .await
         {
@@ -185,7 +341,14 @@ impl Connection {
         }
         debug!("Server hello sent successfully");
 
-        Ok((event_rx, guard))
+        // The client has its token now, so from here on, a dropped connection can be resumed.
+        guard.set_resumable(true);
+
+        Ok(Handshake {
+            guard,
+            resumed,
+            capabilities,
+        })
     }
 
     /// Internal helper to actually run the connection task. Why make `Connection` a struct at all,
@@ -202,30 +365,60 @@ impl Connection {
         ),
     )]
     async fn run(mut self) -> anyhow::Result<()> {
+        // A connection that ends right after a read error was cut off, rather than closed by the
+        // client.
+        let mut read_failed = false;
+
+        // Clients that support keepalives are pinged after half the idle timeout of silence, and
+        // disconnected if they stay silent for all of it. Half-open connections would otherwise
+        // linger forever.
+        let idle_timeout = self.server_state.idle_timeout();
+        let keepalive =
+            self.capabilities.contains(&Capability::Keepalive) && !idle_timeout.is_zero();
+        let mut last_heard = Instant::now();
+        let mut pinged = false;
+        let mut next_nonce = 0;
+
         'connection: loop {
             tokio::select! {
                 // Commands from the client.
                 network_cmd = self.client_stream.next() => {
                     let Some(res) = network_cmd else {
-                        info!("Client disconnected");
+                        if read_failed {
+                            info!("Client connection dropped");
+                        } else {
+                            // The client said goodbye, so it won't be back to resume.
+                            info!("Client disconnected");
+                            self.guard.set_resumable(false);
+                        }
                         return Ok(());
                     };
 
                     match res {
-                        Ok(cmd) => self.handle_command(cmd).await?,
+                        Ok(request) => {
+                            read_failed = false;
+                            last_heard = Instant::now();
+                            pinged = false;
+                            self.handle_request(request).await?;
+                        }
 
                         Err(e) => {
                             warn!(error = %e, "Error reading command from client");
+                            read_failed = true;
                         }
                     }
                 },
 
                 // Global events.
                 event = self.global_event_rx.recv() => match event {
+                    // Our own removal is sent once we notice the kick, so it isn't sent twice.
+                    Ok(NetworkEvent::UserKicked(UserKicked { user_id, .. }) | NetworkEvent::UserBanned(UserBanned { user_id, .. }))
+                        if user_id == self.guard.id() => {}
+
                     Ok(event) => self.send_event_to_client(event).await?,
 
                     Err(broadcast::error::RecvError::Lagged(skipped)) => {
-                        bail!("Client lagged by {skipped} global messages. Forcing disconnect.");
+                        self.handle_lag(skipped, None).await?;
                     }
 
                     Err(broadcast::error::RecvError::Closed) => {
@@ -234,33 +427,59 @@ impl Connection {
                 },
 
                 // Direct messages.
-                direct_msg = self.event_rx.recv() => match direct_msg {
+                direct_msg = self.guard.event_rx().recv() => match direct_msg {
                     Some(msg) => self.send_event_to_client(msg).await?,
                     None => {
                         unreachable!("Sender side of our MPSC channel only closes when we unregister ourselves from the server");
                     }
                 },
 
-                // Channel messages.
-                Some(result) = self.channels.next() => {
+                // Channel messages. Streams of removed channels end once their broadcast sender is
+                // dropped, and `StreamMap` drops them on its own.
+                Some((channel_id, result)) = self.channels.next() => {
                     match result {
                         Ok(msg) => self.send_event_to_client(msg).await?,
 
                         Err(BroadcastStreamRecvError::Lagged(skipped)) => {
-                            bail!("Client lagged by {skipped} global messages. Forcing disconnect.");
+                            self.handle_lag(skipped, Some(channel_id)).await?;
                         }
                     }
                 }
 
+                // Keepalive.
+                () = sleep_until(last_heard + if pinged { idle_timeout } else { idle_timeout / 2 }), if keepalive => {
+                    if pinged {
+                        bail!("Client was silent for {idle_timeout:?}. Forcing disconnect.");
+                    }
+
+                    self.send_event_to_client(NetworkEvent::Ping(Ping { nonce: next_nonce })).await?;
+                    next_nonce += 1;
+                    pinged = true;
+                }
+
+                // Kicked or banned by a moderator or an administrator.
+                () = self.kick_token.cancelled() => {
+                    info!("User was kicked, disconnecting...");
+                    // Kicked users have to connect again, rather than resume.
+                    self.guard.set_resumable(false);
+
+                    self.send_removal().await?;
+
+                    if let Err(e) = self.client_stream.close().await {
+                        warn!(error = %e, "Failed to shut down cleanly");
+                    }
+
+                    break 'connection;
+                }
+
                 // Cancellation signal.
                 () = self.cancellation_token.cancelled() => {
                     info!("Received cancellation signal, disconnecting...");
+                    // The server is going away, so there is nothing to resume.
+                    self.guard.set_resumable(false);
 
-                    if let Err(e) = self.client_stream.flush().await {
-                        warn!(error = %e, "Could not flush client stream on shutdown");
-                    }
-
-                    if let Err(e) = self.client_stream.into_inner().shutdown().await {
+                    // Closing flushes whatever is still buffered first.
+                    if let Err(e) = self.client_stream.close().await {
                         warn!(error = %e, "Failed to shut down cleanly");
                     }
 
@@ -272,22 +491,134 @@ impl Connection {
         Ok(())
     }
 
+    /// Deal with the client falling behind the global events, or a channel's if `channel_id` is
+    /// set. Clients that can resync are told what they lost, the rest are disconnected.
+    async fn handle_lag(
+        &mut self,
+        skipped: u64,
+        channel_id: Option<ChannelId>,
+    ) -> anyhow::Result<()> {
+        // The capability is only negotiated if the lag policy allows resyncing.
+        if !self.capabilities.contains(&Capability::Resync) {
+            match channel_id {
+                Some(id) => {
+                    bail!("Client lagged by {skipped} messages in {id}. Forcing disconnect.")
+                }
+                None => bail!("Client lagged by {skipped} global messages. Forcing disconnect."),
+            }
+        }
+
+        // A client that is still catching up would only refetch the same state again.
+        let newly_lost = match channel_id {
+            Some(id) => self.channel_resyncs_pending.insert(id),
+            None => !std::mem::replace(&mut self.global_resync_pending, true),
+        };
+
+        if !newly_lost {
+            debug!(
+                skipped,
+                ?channel_id,
+                "Client lagged behind again before resyncing"
+            );
+            return Ok(());
+        }
+
+        warn!(skipped, ?channel_id, "Client lagged behind, resyncing");
+
+        let resync = Resync {
+            global: channel_id.is_none(),
+            channel_ids: channel_id.into_iter().collect(),
+        };
+        self.send_event_to_client(NetworkEvent::Resync(resync))
+            .await?;
+
+        // Membership changes may have been lost too, but unlike messages, the current member list
+        // is cheap to just send again.
+        if let Some(id) = channel_id {
+            let members = ChannelMembers {
+                channel_id: id,
+                members: self.server_state.get_channel_members(id).await,
+            };
+            self.send_event_to_client(NetworkEvent::ChannelMembers(members))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Handle a command from the client, then acknowledge it if it carries a request ID and
+    /// succeeded.
+    async fn handle_request(&mut self, request: CommandRequest) -> anyhow::Result<()> {
+        self.current_request = request.request_id;
+
+        // The rate limits may have been reloaded since the last command.
+        if self.settings_rx.has_changed().unwrap_or(false) {
+            self.rate_limiter
+                .reconfigure(&self.settings_rx.borrow_and_update().rate_limits);
+        }
+
+        match self.rate_limiter.check(&request.command) {
+            Verdict::Allow => {}
+
+            Verdict::Reject { retry_after } => {
+                debug!(command = %request.command.name(), ?retry_after, "Client is rate limited");
+                return self.send_rate_limited(retry_after).await;
+            }
+
+            Verdict::Disconnect => {
+                // Abusers shouldn't get to pick up where they left off.
+                self.guard.set_resumable(false);
+                self.send_rate_limited(Duration::ZERO).await?;
+                bail!("Client kept exceeding rate limits. Forcing disconnect.");
+            }
+        }
+
+        self.handle_command(request.command).await?;
+
+        // Errors take the request ID with them, so if it's still here, nothing failed.
+        if let Some(request_id) = self.current_request.take() {
+            self.send_event_to_client(NetworkEvent::CommandAck(CommandAck { request_id }))
+                .await?;
+        }
+
+        Ok(())
+    }
+
     async fn handle_command(&mut self, command: NetworkCommand) -> anyhow::Result<()> {
         match command {
             NetworkCommand::ClientHello(_) => {
                 warn!("Received second client hello while already connected");
+                self.send_error(ErrorEvent::new(
+                    ErrorKind::PermissionDenied,
+                    "already connected",
+                ))
+                .await?;
             }
 
+            NetworkCommand::Ping(Ping { nonce }) => {
+                self.send_event_to_client(NetworkEvent::Pong(Pong { nonce }))
+                    .await?;
+            }
+
+            // Hearing from the client at all is what counts, which the main loop already noted.
+            NetworkCommand::Pong(_) => {}
+
             NetworkCommand::FetchChannels(_fetch) => {
                 debug!("Client requested channel sync");
+                self.global_resync_pending = false;
                 self.send_event_to_client(NetworkEvent::ChannelSync(ChannelSync {
                     channels: self.server_state.get_all_channel_info().await,
+                    joined: self
+                        .server_state
+                        .get_joined_channels(self.guard.token())
+                        .await,
                 }))
                 .await?;
             }
 
             NetworkCommand::FetchUsers(_fetch) => {
                 debug!("Client requested user sync");
+                self.global_resync_pending = false;
                 self.send_event_to_client(NetworkEvent::UserSync(UserSync {
                     users: self.server_state.get_all_user_info().await,
                 }))
@@ -303,6 +634,120 @@ impl Connection {
                 debug!(?info, "Client requested to update info");
                 self.update_info(info).await?;
             }
+
+            NetworkCommand::FetchHistory(fetch) => {
+                debug!(?fetch, "Client requested history");
+                if let HistoryDestination::Channel(id) = fetch.destination {
+                    self.channel_resyncs_pending.remove(&id);
+                }
+                self.fetch_history(fetch).await?;
+            }
+
+            NetworkCommand::EditMessage(edit) => {
+                debug!(message_id = %edit.message_id, "Client requested to edit message");
+                self.edit_message(edit).await?;
+            }
+
+            NetworkCommand::DeleteMessage(delete) => {
+                debug!(message_id = %delete.message_id, "Client requested to delete message");
+                self.delete_message(delete).await?;
+            }
+
+            NetworkCommand::CreateChannel(create) => {
+                debug!(name = %create.name, "Client requested to create channel");
+                self.create_channel(create.name).await?;
+            }
+
+            NetworkCommand::RenameChannel(rename) => {
+                debug!(channel_id = %rename.channel_id, new_name = %rename.new_name, "Client requested to rename channel");
+                let result = self
+                    .server_state
+                    .rename_channel(rename.channel_id, rename.new_name, Some(self.guard.token()))
+                    .await;
+                let _: Option<_> = self.report_channel_result(result, "rename").await?;
+            }
+
+            NetworkCommand::DeleteChannel(delete) => {
+                debug!(channel_id = %delete.channel_id, "Client requested to delete channel");
+                let result = self
+                    .server_state
+                    .remove_channel(delete.channel_id, Some(self.guard.token()))
+                    .await;
+                let _: Option<_> = self.report_channel_result(result, "delete").await?;
+            }
+
+            NetworkCommand::JoinChannel(join) => {
+                debug!(channel_id = %join.channel_id, "Client requested to join channel");
+
+                if let Err(e) = self.join_channel(join.channel_id).await {
+                    warn!(error = %e, "Failed to join channel");
+                    self.send_error(e).await?;
+                }
+            }
+
+            NetworkCommand::LeaveChannel(leave) => {
+                debug!(channel_id = %leave.channel_id, "Client requested to leave channel");
+                self.leave_channel(leave.channel_id).await?;
+            }
+
+            NetworkCommand::KickUser(_)
+            | NetworkCommand::MuteUser(_)
+            | NetworkCommand::UnmuteUser(_)
+            | NetworkCommand::BanUser(_) => self.moderate(command).await?,
+        }
+
+        Ok(())
+    }
+
+    /// Kick, mute, unmute or ban another user on this user's behalf.
+    ///
+    /// # Panics
+    /// Panics if the command is not a moderation command.
+    async fn moderate(&mut self, command: NetworkCommand) -> anyhow::Result<()> {
+        let command_name = command.name();
+        let by = Some(self.guard.token());
+
+        let result = match command {
+            NetworkCommand::KickUser(KickUser { user_id, reason }) => {
+                self.server_state.kick_user(user_id, by, reason).await
+            }
+
+            NetworkCommand::MuteUser(MuteUser {
+                channel_id,
+                user_id,
+                duration,
+            }) => {
+                self.server_state
+                    .mute_user(channel_id, user_id, by, duration)
+                    .await
+            }
+
+            NetworkCommand::UnmuteUser(UnmuteUser {
+                channel_id,
+                user_id,
+            }) => self.server_state.unmute_user(channel_id, user_id, by).await,
+
+            NetworkCommand::BanUser(BanUser {
+                user_id,
+                reason,
+                duration,
+                by_address,
+            }) => self
+                .server_state
+                .ban_user(user_id, by, reason, duration, by_address)
+                .await
+                .map(|ban| info!(target = %ban.target, "Client banned user")),
+
+            _ => panic!("moderate called with {command_name}, which is not a moderation command"),
+        };
+
+        match result {
+            Ok(()) => info!(command = %command_name, "Client moderated a user"),
+
+            Err(e) => {
+                warn!(command = %command_name, error = %e, "Failed to moderate user");
+                self.send_error(e).await?;
+            }
         }
 
         Ok(())
@@ -316,43 +761,46 @@ impl Connection {
             contents,
         } = message;
 
+        if !self.check_message_length(&contents).await? {
+            return Ok(());
+        }
+
+        let sender_id = self.guard.id();
+        let destination = match destination {
+            SendDestination::Channel(channel_id) => ReceiveDestination::Channel(channel_id),
+            SendDestination::User(user_id) => ReceiveDestination::User(user_id),
+        };
+
+        let message = StoredMessage {
+            id: MessageId(Uuid::now_v7()),
+            sender_id,
+            destination,
+            contents,
+            timestamp: SystemTime::now(),
+            edited_at: None,
+        };
+
         match destination {
-            SendDestination::Channel(channel_id) => {
-                let event = NetworkEvent::ReceivedMessage(ReceivedMessage {
-                    contents,
-                    sender_id: self.guard.id(),
-                    destination: ReceiveDestination::Channel(channel_id),
-                });
-
-                if let Err(e) = self
-                    .server_state
-                    .send_event_to_channel(channel_id, event)
-                    .await
-                {
+            ReceiveDestination::Channel(_) => {
+                if let Err(e) = self.server_state.post_channel_message(message).await {
                     warn!(error = %e, "Failed to send message to target channel");
+                    self.send_error(e).await?;
                 }
             }
 
-            SendDestination::User(target_user_id) => {
-                let event = NetworkEvent::ReceivedMessage(ReceivedMessage {
-                    contents,
-                    sender_id: self.guard.id(),
-                    destination: ReceiveDestination::User(target_user_id),
-                });
+            ReceiveDestination::User(target_user_id) => {
+                let event = NetworkEvent::ReceivedMessage(message.clone().into());
 
-                if let Err(e) = self
-                    .server_state
-                    .send_event_to_user(target_user_id, event.clone())
-                    .await
-                {
+                if let Err(e) = self.server_state.post_direct_message(message).await {
                     warn!(error = %e, "Failed to send message to target user");
+                    return self.send_error(e).await;
                 }
 
                 // We send back to the sender as well to include them in the loopback, such that
                 // they can render their own message in the correct order relative to other messages.
                 // However, if the sender is sending to themselves (a "note to self"), this would
                 // result in a double send. As such, we filter that case out.
-                if target_user_id != self.guard.id() {
+                if target_user_id != sender_id {
                     self.send_event_to_client(event).await?;
                 }
             }
@@ -361,6 +809,218 @@ impl Connection {
         Ok(())
     }
 
+    /// Send a page of history to the client.
+    #[instrument(skip_all, fields(destination = ?fetch.destination))]
+    async fn fetch_history(&mut self, fetch: FetchHistory) -> anyhow::Result<()> {
+        let FetchHistory {
+            destination,
+            before,
+            limit,
+        } = fetch;
+
+        let max_page_size = self.server_state.max_history_page_size();
+        let page_size = match usize::try_from(limit) {
+            Ok(0) | Err(_) => max_page_size,
+            Ok(limit) => limit.min(max_page_size),
+        };
+
+        // We fetch one extra message to find out whether there is any older history left.
+        let history: Result<_, ErrorEvent> = match destination {
+            HistoryDestination::Channel(channel_id) => self
+                .server_state
+                .channel_history(channel_id, before, page_size + 1)
+                .await
+                .map_err(Into::into),
+
+            HistoryDestination::User(user_id) => self
+                .server_state
+                .direct_history(self.guard.id(), user_id, before, page_size + 1)
+                .await
+                .map_err(Into::into),
+        };
+
+        let mut messages = match history {
+            Ok(messages) => messages,
+            Err(e) => {
+                warn!(error = %e.message, "Failed to fetch history");
+                return self.send_error(e).await;
+            }
+        };
+
+        // Messages are in chronological order, so the extra one is at the front.
+        let next_before = if messages.len() > page_size {
+            messages.remove(0);
+            messages.first().map(|message| message.id)
+        } else {
+            None
+        };
+
+        self.send_event_to_client(NetworkEvent::HistoryPage(HistoryPage {
+            destination,
+            messages: messages.into_iter().map(Into::into).collect(),
+            next_before,
+        }))
+        .await
+    }
+
+    /// Edit a message we sent.
+    #[instrument(skip_all, fields(message_id = %edit.message_id))]
+    async fn edit_message(&mut self, edit: EditMessage) -> anyhow::Result<()> {
+        if !self.check_message_length(&edit.contents).await? {
+            return Ok(());
+        }
+
+        let result = self
+            .server_state
+            .edit_message(self.guard.token(), edit.message_id, edit.contents)
+            .await;
+
+        match result {
+            Ok(edited) => {
+                // Same loopback rules as sending a direct message.
+                if let ReceiveDestination::User(recipient_id) = edited.destination
+                    && recipient_id != self.guard.id()
+                {
+                    self.send_event_to_client(NetworkEvent::MessageEdited(edited))
+                        .await?;
+                }
+            }
+
+            Err(e) => {
+                warn!(error = %e, "Failed to edit message");
+                self.send_error(e).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Delete a message we sent.
+    #[instrument(skip_all, fields(message_id = %delete.message_id))]
+    async fn delete_message(&mut self, delete: DeleteMessage) -> anyhow::Result<()> {
+        let result = self
+            .server_state
+            .delete_message(self.guard.token(), delete.message_id)
+            .await;
+
+        match result {
+            Ok(deleted) => {
+                // Same loopback rules as sending a direct message.
+                if let ReceiveDestination::User(recipient_id) = deleted.destination
+                    && recipient_id != self.guard.id()
+                {
+                    self.send_event_to_client(NetworkEvent::MessageDeleted(deleted))
+                        .await?;
+                }
+            }
+
+            Err(e) => {
+                warn!(error = %e, "Failed to delete message");
+                self.send_error(e).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Log the outcome of a channel management command, and tell the client if it failed. On
+    /// success, the client hears about the change through the global event like everyone else.
+    async fn report_channel_result(
+        &mut self,
+        result: Result<ChannelInfo, ChannelError>,
+        operation: &str,
+    ) -> anyhow::Result<Option<ChannelInfo>> {
+        match result {
+            Ok(info) => {
+                info!(operation, channel_id = %info.id, channel_name = %info.name, "Channel command succeeded");
+                Ok(Some(info))
+            }
+
+            Err(e) => {
+                warn!(operation, error = %e, "Channel command failed");
+                self.send_error(e).await?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Tell the client why it was kicked off the server.
+    async fn send_removal(&mut self) -> anyhow::Result<()> {
+        match self.server_state.take_removal(self.guard.token()).await {
+            Some(removal) => self.send_event_to_client(removal).await,
+
+            None => {
+                self.send_error(ErrorEvent::new(
+                    ErrorKind::PermissionDenied,
+                    "you were kicked from the server",
+                ))
+                .await
+            }
+        }
+    }
+
+    /// Create a channel, then join it.
+    async fn create_channel(&mut self, name: String) -> anyhow::Result<()> {
+        let result = self
+            .server_state
+            .create_channel(name, Some(self.guard.token()))
+            .await;
+
+        // Whoever creates a channel presumably wants to be in it.
+        if let Some(info) = self.report_channel_result(result, "create").await?
+            && let Err(e) = self.join_channel(info.id).await
+        {
+            warn!(error = %e, "Failed to join created channel");
+            self.send_error(e).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Join a channel, start listening to it, and send its member list to the client.
+    ///
+    /// # Errors
+    /// Returns a [`ChannelError`] if the channel could not be joined.
+    async fn join_channel(&mut self, id: ChannelId) -> Result<(), ChannelError> {
+        let (rx, members) = self
+            .server_state
+            .join_channel(self.guard.token(), id)
+            .await?;
+
+        // Joining twice just replaces the old subscription, so messages are never duplicated.
+        self.channels.insert(id, BroadcastStream::new(rx));
+        debug!(channel_id = %id, "Joined channel");
+
+        // If this fails, the client is gone, and the main loop finds out on its next read.
+        let _: anyhow::Result<()> = self
+            .send_event_to_client(NetworkEvent::ChannelMembers(members))
+            .await;
+
+        Ok(())
+    }
+
+    /// Leave a channel, and stop listening to it.
+    async fn leave_channel(&mut self, id: ChannelId) -> anyhow::Result<()> {
+        // We stop listening first, so we don't get our own departure twice.
+        self.channels.remove(&id);
+
+        if let Err(e) = self
+            .server_state
+            .leave_channel(self.guard.token(), id)
+            .await
+        {
+            warn!(error = %e, "Failed to leave channel");
+            return self.send_error(e).await;
+        }
+
+        debug!(channel_id = %id, "Left channel");
+        self.send_event_to_client(NetworkEvent::ChannelMemberLeft(ChannelMember {
+            channel_id: id,
+            user_id: self.guard.id(),
+        }))
+        .await
+    }
+
     /// Update our user info.
     #[instrument(skip_all, fields(
         new_username = ?new_info.name,
@@ -378,13 +1038,56 @@ impl Connection {
         if let Err(e) = update_result {
             warn!(error = %e, "Failed to update user info");
 
-            self.send_event_to_client(NetworkEvent::ErrorEvent(e.into()))
-                .await?;
+            self.send_error(e).await?;
         }
 
         Ok(())
     }
 
+    /// Tell the client that the command being handled failed. The error carries the command's
+    /// request ID, if it has one.
+    async fn send_error(&mut self, error: impl Into<ErrorEvent>) -> anyhow::Result<()> {
+        let mut error = error.into();
+        error.request_id = self.current_request.take();
+
+        self.send_event_to_client(NetworkEvent::ErrorEvent(error))
+            .await
+    }
+
+    /// Check message contents against the maximum message length. If they're too long, tell the
+    /// client so and return `false`.
+    async fn check_message_length(&mut self, contents: &str) -> anyhow::Result<bool> {
+        let max_length = self.server_state.max_message_length();
+        let length = contents.chars().count();
+
+        if length <= max_length {
+            return Ok(true);
+        }
+
+        debug!(length, max_length, "Client sent a message that is too long");
+        self.send_error(ErrorEvent::new(
+            ErrorKind::MessageTooLong,
+            format!("messages may be at most {max_length} characters long"),
+        ))
+        .await?;
+
+        Ok(false)
+    }
+
+    /// Tell the client that the command being handled was rejected for exceeding the rate limits.
+    async fn send_rate_limited(&mut self, retry_after: Duration) -> anyhow::Result<()> {
+        let mut error = ErrorEvent::new(
+            ErrorKind::RateLimited,
+            format!(
+                "too many commands, try again in {:.1}s",
+                retry_after.as_secs_f64()
+            ),
+        );
+        error.retry_after = Some(retry_after);
+
+        self.send_error(error).await
+    }
+
     /// Send an event to the client associated with this `Connection`.
     async fn send_event_to_client(&mut self, event: NetworkEvent) -> anyhow::Result<()> {
         let event_name = event.name();
@@ -0,0 +1,54 @@
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+/// What to do with a client that falls so far behind that events meant for it are dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LagPolicy {
+    /// Disconnect the client. It may reconnect and start over.
+    Disconnect,
+
+    /// Tell the client which events were lost, so it can refetch what it missed. Clients that
+    /// don't support this are disconnected instead.
+    Resync,
+}
+
+/// Sizes of the queues events wait in until each client's connection gets to them, and what to
+/// do when a client falls behind.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventQueueConfig {
+    /// How many server-wide events, like users and channels coming and going, are kept for
+    /// clients that fall behind.
+    pub global_capacity: usize,
+
+    /// How many events of each channel are kept for clients that fall behind.
+    pub channel_capacity: usize,
+
+    /// How many events for a single user, like direct messages, are queued. Senders wait for room
+    /// in the queue, so nothing is lost. Also bounds what is kept for users whose connection
+    /// dropped.
+    pub user_capacity: usize,
+
+    /// What to do with clients that fall behind the global or a channel's events.
+    pub lag_policy: LagPolicy,
+}
+
+impl EventQueueConfig {
+    /// Check that the queue sizes make sense.
+    ///
+    /// # Errors
+    /// Returns an error if any queue has no room at all.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (name, capacity) in [
+            ("global_capacity", self.global_capacity),
+            ("channel_capacity", self.channel_capacity),
+            ("user_capacity", self.user_capacity),
+        ] {
+            if capacity == 0 {
+                bail!("event_queues.{name} must be at least 1");
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,33 +1,59 @@
 use std::io;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
-use tokio::net::TcpListener;
-use tokio_rustls::TlsAcceptor;
+use socket2::{Domain, Socket, Type};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
 use tokio_util::sync::CancellationToken;
 use tokio_util::task::TaskTracker;
 use tracing::{debug, info, instrument, warn};
 
 use crate::run::ServerState;
 
-use super::connection::Connection;
+use super::{
+    connection::Connection,
+    proxy_protocol::{self, ProxyProtocolConfig},
+    transport::{ClientAddr, Transport},
+};
+
+/// How long a trusted proxy gets to send the PROXY protocol header of a new connection.
+const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A TCP listener for the server to run.
+#[derive(Clone)]
+pub struct Endpoint {
+    pub address: SocketAddr,
+
+    /// Whether a listener on an IPv6 address only accepts IPv6 clients.
+    pub ipv6_only: bool,
+
+    /// How clients on the listener connect.
+    pub transport: Transport,
+
+    /// Proxies that tell the listener who their clients are with a PROXY protocol header, if the
+    /// listener reads them.
+    pub proxy_protocol: Option<ProxyProtocolConfig>,
+}
 
 /// A task struct designed to listen for new client connections.
 pub struct Listener {
     /// Server state - users, channels, etc.
     server_state: Arc<ServerState>,
 
-    /// Cancellation token for the main task to signal for shutdown.
+    /// Cancellation token for the main task to signal for shutdown. Passed on to connections.
     cancellation_token: CancellationToken,
 
-    /// Task tracker for the main task to join all tasks on shutdown.
-    task_tracker: TaskTracker,
+    /// Cancelled when the server starts shutting down, to stop accepting new connections while the
+    /// existing ones drain.
+    drain_token: CancellationToken,
 
-    /// Wrapper around a [`ClientConfig`](rustls::ClientConfig) for TLS handshakes.
-    tls_acceptor: TlsAcceptor,
+    /// Task tracker for the main task to join all connections on shutdown.
+    task_tracker: TaskTracker,
 
-    /// The address on which to bind the listener.
-    bind_address: SocketAddr,
+    /// Where the listener binds, and how its clients connect.
+    endpoint: Endpoint,
 }
 
 impl Listener {
@@ -35,27 +61,30 @@ impl Listener {
     pub fn new(
         server_state: Arc<ServerState>,
         cancellation_token: CancellationToken,
+        drain_token: CancellationToken,
         task_tracker: TaskTracker,
-        tls_acceptor: TlsAcceptor,
-        bind_address: SocketAddr,
+        endpoint: Endpoint,
     ) -> Self {
         Self {
             server_state,
             cancellation_token,
+            drain_token,
             task_tracker,
-            tls_acceptor,
-            bind_address,
+            endpoint,
         }
     }
 
     /// Start an initialized `Listener`. This should be spawned as a [`tokio`] task: `tokio::spawn(listener)`.
-    #[instrument(skip_all, fields(address = %self.bind_address), parent = None)]
+    #[instrument(
+        skip_all,
+        fields(address = %self.endpoint.address, protocol = ?self.endpoint.transport.protocol),
+        parent = None,
+        err,
+    )]
     pub async fn start(self) -> io::Result<()> {
-        let address = self.bind_address;
-
-        let listener = TcpListener::bind(address).await?;
+        let listener = bind(self.endpoint.address, self.endpoint.ipv6_only)?;
 
-        info!("TCP listener bound and accepting connections");
+        info!("Listener bound and accepting connections");
 
         loop {
             tokio::select! {
@@ -63,13 +92,31 @@ impl Listener {
                     Ok((stream, peer_addr)) => {
                         debug!(%peer_addr, "Accepted incoming TCP connection");
 
-                        self.task_tracker.spawn(Connection::start(
-                            self.server_state.clone(),
-                            self.tls_acceptor.clone(),
-                            stream,
-                            peer_addr,
-                            self.cancellation_token.clone(),
-                        ));
+                        // Banned addresses don't even get a TLS handshake. Account bans can only be
+                        // checked once the client says who it is.
+                        if let Some(ban) = self.server_state.address_ban(peer_addr.ip()).await {
+                            info!(%peer_addr, reason = %ban.reason, "Dropping connection from banned address");
+                            continue;
+                        }
+
+                        if self.is_trusted_proxy(peer_addr) {
+                            self.task_tracker.spawn(start_proxied(
+                                self.server_state.clone(),
+                                self.endpoint.transport.clone(),
+                                stream,
+                                peer_addr,
+                                self.cancellation_token.clone(),
+                            ));
+                        } else {
+                            self.task_tracker.spawn(Connection::start(
+                                self.server_state.clone(),
+                                self.endpoint.transport.clone(),
+                                stream,
+                                ClientAddr::Tcp(peer_addr),
+                                None,
+                                self.cancellation_token.clone(),
+                            ));
+                        }
                     }
 
                     Err(e) => {
@@ -78,7 +125,8 @@ impl Listener {
                     }
                 },
 
-                () = self.cancellation_token.cancelled() => {
+                // Dropping the listener refuses any further connections.
+                () = self.drain_token.cancelled() => {
                     info!("Listener task received cancellation signal, shutting down...");
                     break;
                 }
@@ -87,4 +135,81 @@ impl Listener {
 
         Ok(())
     }
+
+    /// Whether a peer is a proxy the listener reads PROXY protocol headers from.
+    fn is_trusted_proxy(&self, peer_addr: SocketAddr) -> bool {
+        self.endpoint
+            .proxy_protocol
+            .as_ref()
+            .is_some_and(|config| config.trusts(peer_addr.ip()))
+    }
+}
+
+/// Read the PROXY protocol header a trusted proxy sends ahead of its client's traffic, then handle
+/// the connection as coming from that client. Its TLS handshake only starts after the header.
+#[instrument(skip_all, parent = None, fields(%proxy_addr))]
+async fn start_proxied(
+    server_state: Arc<ServerState>,
+    transport: Transport,
+    mut stream: TcpStream,
+    proxy_addr: SocketAddr,
+    cancellation_token: CancellationToken,
+) {
+    let client_addr = match timeout(
+        PROXY_HEADER_TIMEOUT,
+        proxy_protocol::read_header(&mut stream),
+    )
+    .await
+    {
+        Ok(Ok(Some(client_addr))) => client_addr,
+
+        // The proxy connected on its own behalf, e.g. for a health check.
+        Ok(Ok(None)) => proxy_addr,
+
+        Ok(Err(e)) => {
+            warn!(error = %e, "Failed to read PROXY protocol header");
+            return;
+        }
+
+        Err(_) => {
+            warn!("Timed out waiting for PROXY protocol header");
+            return;
+        }
+    };
+    debug!(%client_addr, "Read PROXY protocol header");
+
+    if let Some(ban) = server_state.address_ban(client_addr.ip()).await {
+        info!(%client_addr, reason = %ban.reason, "Dropping proxied connection from banned address");
+        return;
+    }
+
+    Connection::start(
+        server_state,
+        transport,
+        stream,
+        ClientAddr::Tcp(client_addr),
+        None,
+        cancellation_token,
+    )
+    .await;
+}
+
+/// Bind a TCP listener. Unlike [`TcpListener::bind`], this decides whether a listener on an IPv6
+/// address also accepts IPv4 clients, rather than leaving it to the platform.
+fn bind(address: SocketAddr, ipv6_only: bool) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(address), Type::STREAM, None)?;
+
+    if address.is_ipv6() {
+        socket.set_only_v6(ipv6_only)?;
+    }
+
+    // Like `TcpListener::bind`, so a restarted server can bind while old connections linger.
+    #[cfg(not(windows))]
+    socket.set_reuse_address(true)?;
+
+    socket.set_nonblocking(true)?;
+    socket.bind(&address.into())?;
+    socket.listen(1024)?;
+
+    TcpListener::from_std(socket.into())
 }
@@ -0,0 +1,71 @@
+//! Log output, which may be reconfigured while the server runs.
+
+use anyhow::Context;
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{RollingFileAppender, Rotation},
+};
+use tracing_subscriber::{Layer, Registry, layer::SubscriberExt, reload, util::SubscriberInitExt};
+
+use super::Config;
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// The installed log layers, and what's needed to replace them.
+pub struct Logging {
+    handle: reload::Handle<Vec<BoxedLayer>, Registry>,
+
+    /// Keeps the background file writer alive. Dropping it flushes the log file.
+    file_guard: Option<WorkerGuard>,
+}
+
+impl Logging {
+    /// Install the global subscriber, logging where the config says to.
+    pub fn init(config: &Config) -> anyhow::Result<Self> {
+        let (layers, file_guard) = build_layers(config)?;
+        let (layer, handle) = reload::Layer::new(layers);
+
+        tracing_subscriber::registry().with(layer).init();
+
+        Ok(Self { handle, file_guard })
+    }
+
+    /// Log where the new config says to. If the new log file can't be opened, logging stays as it
+    /// was.
+    pub fn reload(&mut self, config: &Config) -> anyhow::Result<()> {
+        let (layers, file_guard) = build_layers(config)?;
+        self.handle.reload(layers).context("Replacing log layers")?;
+
+        // Dropping the old guard flushes whatever the old file writer still had buffered.
+        self.file_guard = file_guard;
+        Ok(())
+    }
+}
+
+fn build_layers(config: &Config) -> anyhow::Result<(Vec<BoxedLayer>, Option<WorkerGuard>)> {
+    let mut layers = Vec::new();
+    let mut file_guard = None;
+
+    if config.log_to_stdout {
+        layers.push(tracing_subscriber::fmt::layer().boxed());
+    }
+
+    if config.log_to_file {
+        let appender = RollingFileAppender::builder()
+            .rotation(Rotation::DAILY)
+            .filename_prefix("server.log")
+            .build(&config.log_dir)
+            .with_context(|| format!("Opening log directory '{}'", config.log_dir.display()))?;
+        let (appender, guard) = tracing_appender::non_blocking(appender);
+
+        layers.push(
+            tracing_subscriber::fmt::layer()
+                .with_writer(appender)
+                .with_ansi(false)
+                .boxed(),
+        );
+        file_guard = Some(guard);
+    }
+
+    Ok((layers, file_guard))
+}
@@ -1,38 +1,71 @@
+#[cfg(unix)]
+mod admin_socket;
+mod client_auth;
 mod connection;
+mod event_queues;
 mod listener;
+mod logging;
+mod proxy_protocol;
+mod rate_limit;
+mod reload;
 mod server_state;
+mod storage;
+mod tls;
+mod transport;
+#[cfg(unix)]
+mod unix_listener;
+#[cfg(unix)]
+mod unix_socket;
 
 use std::{
+    collections::{BTreeSet, HashMap, HashSet},
     net::{IpAddr, SocketAddr},
     path::PathBuf,
     sync::Arc,
+    time::{Duration, SystemTime},
 };
 
-use anyhow::{Context, bail};
+use anyhow::{Context, bail, ensure};
 use clap::Args;
 use figment::{
     Figment,
     providers::{Env, Format, Serialized, Toml},
 };
-use network_protocol::{ChannelInfo, NetworkEvent, UserInfo};
-use rustls::{
-    ServerConfig,
-    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+use network_protocol::{
+    Capability, ChannelId, ChannelInfo, NetworkEvent, ResumeToken, ServerShuttingDown, UserId,
+    UserInfo,
 };
+use rustls::{ServerConfig, server::danger::ClientCertVerifier};
 use serde::{Deserialize, Serialize};
 use shared_utils::{files::TildeRelativePathBuf, first_match};
-use tokio::sync::{broadcast, mpsc};
+use tokio::{
+    sync::{broadcast, mpsc},
+    time::sleep,
+};
 use tokio_rustls::TlsAcceptor;
 use tokio_util::{sync::CancellationToken, task::TaskTracker};
 
-use listener::Listener;
-use server_state::ServerState;
-use tracing::{debug, info, instrument};
-use tracing_appender::{non_blocking::WorkerGuard, rolling};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+#[cfg(unix)]
+use admin_socket::AdminSocket;
+use client_auth::{ClientAuthConfig, ClientAuthMode};
+use event_queues::{EventQueueConfig, LagPolicy};
+use listener::{Endpoint, Listener};
+use logging::Logging;
+use proxy_protocol::ProxyProtocolConfig;
+use rate_limit::RateLimitConfig;
+use server_state::{ServerState, Settings};
+use storage::{StorageConfig, Stores};
+use tls::ReloadableCertificate;
+use tracing::{debug, error, info, instrument, warn};
+use transport::{ClientAddr, Protocol, Transport};
+#[cfg(unix)]
+use unix_listener::{TrustedUser, UnixSocketListener};
 
 use crate::{DEFAULT_CONFIG, DefaultPaths, ENV_VAR_PREFIX};
 
+/// Room a frame needs besides the contents of the message it carries, in bytes.
+const MESSAGE_FRAME_OVERHEAD: usize = 1024;
+
 #[derive(Debug, Args, Serialize, Deserialize)]
 pub struct RunArgs {
     /// Path to the TOML config file for the server
@@ -89,6 +122,13 @@ struct Config {
     /// Port the listener task binds to.
     listener_port: u16,
 
+    /// PROXY protocol headers to read on the main listener, if it's behind a proxy that sends them.
+    #[serde(default)]
+    proxy_protocol: Option<ProxyProtocolConfig>,
+
+    /// Listeners to run besides the main one.
+    listeners: Vec<ListenerConfig>,
+
     /// Path to the TLS certificate file.
     tls_cert_path: TildeRelativePathBuf,
 
@@ -98,6 +138,32 @@ struct Config {
     /// Maximum allowed length of users' display names.
     max_username_length: usize,
 
+    /// Maximum number of messages the server returns in a single page of history.
+    max_history_page_size: usize,
+
+    /// Maximum allowed length of channel names.
+    max_channel_name_length: usize,
+
+    /// Maximum allowed length of message contents, in characters.
+    max_message_length: usize,
+
+    /// Maximum length of a single frame from or to a client, in bytes.
+    max_frame_length: usize,
+
+    /// Whether users may connect as guests, without an account.
+    allow_guests: bool,
+
+    /// Minimum allowed length of account passwords.
+    min_password_length: usize,
+
+    /// How many seconds to hold a user's session after their connection drops, so they can resume
+    /// it. Zero disables session resumption.
+    resume_grace_period_secs: u64,
+
+    /// How many seconds a client may stay silent before it is disconnected. Zero disables the
+    /// timeout.
+    idle_timeout_secs: u64,
+
     /// Whether to write logs to standard output.
     log_to_stdout: bool,
 
@@ -109,6 +175,253 @@ struct Config {
 
     /// List of all the channels on the server. Includes channels' IDs and names.
     channels: Vec<ChannelInfo>,
+
+    /// Message history and account storage configuration.
+    storage: StorageConfig,
+
+    /// Client certificate authentication configuration.
+    client_auth: ClientAuthConfig,
+
+    /// Per-connection rate limits.
+    rate_limit: RateLimitConfig,
+
+    /// Event queue sizes, and what to do with clients that fall behind.
+    event_queues: EventQueueConfig,
+
+    /// Admin socket configuration.
+    admin: AdminConfig,
+
+    /// What happens when the server is told to stop.
+    shutdown: ShutdownConfig,
+
+    /// WebSocket listener configuration.
+    websocket: WebSocketConfig,
+
+    /// Unix domain socket listener configuration.
+    unix_socket: UnixSocketConfig,
+}
+
+impl Config {
+    /// Check the settings that can't be checked while parsing.
+    fn validate(&self) -> anyhow::Result<()> {
+        self.rate_limit
+            .validate()
+            .context("Invalid rate limit configuration")?;
+
+        self.event_queues
+            .validate()
+            .context("Invalid event queue configuration")?;
+
+        ensure!(
+            !(self.websocket.enabled
+                && !self.websocket.tls
+                && self.client_auth.mode == ClientAuthMode::Required),
+            "client_auth.mode is \"required\", but clients of the WebSocket listener can't present \
+             certificates without TLS",
+        );
+
+        if let Some(proxy_protocol) = &self.proxy_protocol {
+            proxy_protocol
+                .validate()
+                .context("Invalid PROXY protocol configuration")?;
+        }
+
+        if let Some(proxy_protocol) = &self.websocket.proxy_protocol {
+            proxy_protocol
+                .validate()
+                .context("Invalid PROXY protocol configuration of the WebSocket listener")?;
+        }
+
+        for listener in &self.listeners {
+            ensure!(
+                listener.tls_cert_path.is_some() == listener.tls_key_path.is_some(),
+                "The listener on {} must set both tls_cert_path and tls_key_path, or neither",
+                listener.address(),
+            );
+
+            if let Some(proxy_protocol) = &listener.proxy_protocol {
+                proxy_protocol.validate().with_context(|| {
+                    format!(
+                        "Invalid PROXY protocol configuration of the listener on {}",
+                        listener.address()
+                    )
+                })?;
+            }
+        }
+
+        ensure!(
+            self.unix_socket.mode <= 0o777,
+            "unix_socket.mode ({:o}) isn't a valid file mode",
+            self.unix_socket.mode,
+        );
+
+        // Characters take up to 4 bytes in UTF-8, and the frame needs some room besides the
+        // contents.
+        ensure!(
+            self.max_frame_length >= self.max_message_length * 4 + MESSAGE_FRAME_OVERHEAD,
+            "max_frame_length ({}) is too small for messages of max_message_length ({}) characters",
+            self.max_frame_length,
+            self.max_message_length,
+        );
+
+        Ok(())
+    }
+
+    /// Get the optional protocol features the server supports with this configuration.
+    fn capabilities(&self) -> BTreeSet<Capability> {
+        let mut capabilities = BTreeSet::from([
+            Capability::History,
+            Capability::MessageEditing,
+            Capability::ChannelManagement,
+            Capability::Accounts,
+            Capability::Keepalive,
+            Capability::CommandAcks,
+            Capability::Moderation,
+        ]);
+
+        if self.client_auth.mode != ClientAuthMode::None || self.unix_socket.trusts_anyone() {
+            capabilities.insert(Capability::CertificateLogin);
+        }
+
+        if self.resume_grace_period_secs > 0 {
+            capabilities.insert(Capability::SessionResumption);
+        }
+
+        if self.event_queues.lag_policy == LagPolicy::Resync {
+            capabilities.insert(Capability::Resync);
+        }
+
+        capabilities
+    }
+}
+
+/// Configuration for a listener besides the main one.
+#[derive(Debug, Serialize, Deserialize)]
+struct ListenerConfig {
+    /// Host address the listener binds to.
+    ip: IpAddr,
+
+    /// Port the listener binds to.
+    port: u16,
+
+    /// Whether a listener on an IPv6 address only accepts IPv6 clients. By default, one on `::`
+    /// accepts IPv4 clients as well, so it can't share its port with a listener on `0.0.0.0`.
+    #[serde(default)]
+    ipv6_only: bool,
+
+    /// Path to a TLS certificate file to present instead of the main one.
+    tls_cert_path: Option<TildeRelativePathBuf>,
+
+    /// Path to the private key file associated with `tls_cert_path`.
+    tls_key_path: Option<TildeRelativePathBuf>,
+
+    /// PROXY protocol headers to read, if the listener is behind a proxy that sends them.
+    #[serde(default)]
+    proxy_protocol: Option<ProxyProtocolConfig>,
+}
+
+impl ListenerConfig {
+    fn address(&self) -> SocketAddr {
+        SocketAddr::new(self.ip, self.port)
+    }
+
+    /// Get the paths of the certificate and key the listener presents instead of the main ones, if
+    /// it has its own.
+    fn tls_override(&self) -> Option<(&TildeRelativePathBuf, &TildeRelativePathBuf)> {
+        self.tls_cert_path.as_ref().zip(self.tls_key_path.as_ref())
+    }
+}
+
+/// Configuration for the local admin socket.
+#[derive(Debug, Serialize, Deserialize)]
+struct AdminConfig {
+    /// Whether to open the admin socket at all. Only supported on Unix platforms.
+    enabled: bool,
+
+    /// Path of the admin socket.
+    socket_path: TildeRelativePathBuf,
+}
+
+/// What happens when the server is told to stop.
+#[derive(Debug, Serialize, Deserialize)]
+struct ShutdownConfig {
+    /// How many seconds connected clients get to finish up before their connections are closed.
+    /// Zero closes them right away.
+    grace_period_secs: u64,
+
+    /// What clients are told about why the server is shutting down.
+    reason: String,
+}
+
+/// Configuration for the optional WebSocket listener, which runs alongside the main one.
+#[derive(Debug, Serialize, Deserialize)]
+struct WebSocketConfig {
+    /// Whether to run the WebSocket listener at all.
+    enabled: bool,
+
+    /// Host address the WebSocket listener binds to.
+    listener_ip: IpAddr,
+
+    /// Port the WebSocket listener binds to.
+    listener_port: u16,
+
+    /// Whether clients connect with TLS, using the same certificate as the main listener. Off when
+    /// a reverse proxy in front of the server terminates TLS.
+    tls: bool,
+
+    /// PROXY protocol headers to read, if the listener is behind a proxy that sends them.
+    #[serde(default)]
+    proxy_protocol: Option<ProxyProtocolConfig>,
+}
+
+impl WebSocketConfig {
+    /// Get the WebSocket listener, if it's enabled.
+    fn endpoint(&self, tls_acceptor: &TlsAcceptor) -> Option<Endpoint> {
+        if !self.enabled {
+            return None;
+        }
+
+        let address = SocketAddr::new(self.listener_ip, self.listener_port);
+        debug!(%address, tls = self.tls, "Resolved WebSocket bind address");
+
+        Some(Endpoint {
+            address,
+            ipv6_only: false,
+            transport: Transport {
+                protocol: Protocol::WebSocket,
+                tls_acceptor: self.tls.then(|| tls_acceptor.clone()),
+            },
+            proxy_protocol: self.proxy_protocol.clone(),
+        })
+    }
+}
+
+/// Configuration for the optional Unix domain socket listener, for clients on the same machine.
+#[derive(Debug, Serialize, Deserialize)]
+struct UnixSocketConfig {
+    /// Whether to listen on the socket at all. Only supported on Unix platforms.
+    enabled: bool,
+
+    /// Path of the socket.
+    path: TildeRelativePathBuf,
+
+    /// Permissions of the socket file, which decide which local users may connect.
+    mode: u32,
+
+    /// Local users who may log in over the socket without a password or certificate.
+    #[cfg(unix)]
+    trusted_users: Vec<TrustedUser>,
+}
+
+impl UnixSocketConfig {
+    /// Whether any local user may log in without a password or certificate.
+    fn trusts_anyone(&self) -> bool {
+        #[cfg(unix)]
+        return self.enabled && !self.trusted_users.is_empty();
+
+        #[cfg(not(unix))]
+        false
+    }
 }
 
 /// Represents a connected user.
@@ -116,6 +429,27 @@ struct Config {
 struct User {
     pub info: UserInfo,
     pub sender: mpsc::Sender<NetworkEvent>,
+
+    /// Channels the user joined. The user only receives messages from, and may only send messages
+    /// to, these channels.
+    pub joined_channels: HashSet<ChannelId>,
+
+    /// Whether the user is logged into an account, rather than connected as a guest.
+    pub registered: bool,
+
+    /// Token the user may present to resume their session if their connection drops.
+    pub resume_token: Option<ResumeToken>,
+
+    /// Where the connection the user is on comes from. `None` while the user is held for
+    /// resumption.
+    pub addr: Option<ClientAddr>,
+
+    /// Cancelled to kick the user. Their connection watches it and closes.
+    pub kick_token: CancellationToken,
+
+    /// Why the user was removed from the server, set right before `kick_token` is cancelled so
+    /// their connection can pass it on before closing.
+    pub removal: Option<NetworkEvent>,
 }
 
 /// Represents a channel.
@@ -123,183 +457,513 @@ struct User {
 struct Channel {
     pub info: ChannelInfo,
     pub broadcast: broadcast::Sender<NetworkEvent>,
+
+    /// Users muted in the channel, and when their mute ends. `None` mutes until unmuted.
+    pub muted: HashMap<UserId, Option<SystemTime>>,
 }
 
 /// A chat server. To start the server, first initialize it with `new()`. Then, call `run()`.
 struct ChatServer {
-    bind_address: SocketAddr,
-    tls_acceptor: TlsAcceptor,
+    /// Every TCP listener to run: the main one, the others from the config, then the WebSocket one.
+    endpoints: Vec<Endpoint>,
     server_state: Arc<ServerState>,
     task_tracker: TaskTracker,
+
+    /// Path to serve the admin socket on, if it's enabled.
+    admin_socket_path: Option<PathBuf>,
+
+    /// Path of the Unix socket listener, if it's enabled.
+    unix_socket_path: Option<PathBuf>,
+
+    /// The configuration currently in effect.
+    config: Config,
+
+    /// Where the configuration came from, so it can be read again.
+    config_source: ConfigSource,
+
+    /// The certificate the TLS acceptor presents. Reloading it doesn't affect existing connections.
+    certificate: Arc<ReloadableCertificate>,
+
+    /// Certificates listeners present instead of the main one.
+    listener_certificates: Vec<ListenerCertificate>,
+}
+
+/// A certificate a listener presents instead of the main one, and where it's loaded from.
+struct ListenerCertificate {
+    address: SocketAddr,
+    cert_path: TildeRelativePathBuf,
+    key_path: TildeRelativePathBuf,
+    certificate: Arc<ReloadableCertificate>,
 }
 
 impl ChatServer {
     #[instrument(skip_all, err)]
     // TODO: `async fn new` is an antipattern. This whole function is getting bloated in general;
     // refactor the whole thing (and make it synchronous).
-    async fn new(config: Config) -> anyhow::Result<Self> {
-        let bind_address = SocketAddr::new(config.listener_ip, config.listener_port);
-        debug!(ip = %config.listener_ip, port = %config.listener_port, "Resolved bind address");
-
-        let cert_path_err_display = config.tls_cert_path.original().display();
-        let tls_cert_path = &config
-            .tls_cert_path
-            .resolved()
-            .context("Resolving TLS key path")?;
-        let certs = CertificateDer::pem_file_iter(tls_cert_path)
-            .with_context(|| format!("Opening TLS certificate file '{cert_path_err_display}'"))?
-            .collect::<Result<Vec<_>, _>>()
-            .with_context(|| format!("Reading TLS certificate file '{cert_path_err_display}'"))?;
-
-        let key_path_err_display = config.tls_key_path.original().display();
-        let tls_key_path = &config
-            .tls_key_path
-            .resolved()
-            .context("Resolving TLS key path")?;
-        let key = PrivateKeyDer::from_pem_file(tls_key_path)
-            .with_context(|| format!("Reading TLS key file '{key_path_err_display}'"))?;
-
-        debug!(
-            cert_path = %tls_cert_path.display(),
-            key_path = %tls_key_path.display(),
-            "Loaded TLS keypair"
-        );
-
-        let tls_config = ServerConfig::builder()
-            .with_no_client_auth()
-            .with_single_cert(certs, key)
-            .context("Configuring TLS: bad certificate or key")?;
+    async fn new(config: Config, config_source: ConfigSource) -> anyhow::Result<Self> {
+        config.validate()?;
+
+        let certificate = Arc::new(ReloadableCertificate::load(
+            ServerConfig::builder().crypto_provider().clone(),
+            &config.tls_cert_path,
+            &config.tls_key_path,
+        )?);
+
+        let verifier = config.client_auth.verifier()?;
+        if verifier.is_some() {
+            debug!(mode = ?config.client_auth.mode, "Client certificate authentication enabled");
+        }
 
-        let tls_acceptor = TlsAcceptor::from(Arc::new(tls_config));
+        let (endpoints, listener_certificates) =
+            Self::endpoints(&config, verifier, certificate.clone())?;
+
+        let admin_socket_path = config
+            .admin
+            .enabled
+            .then(|| config.admin.socket_path.resolved())
+            .transpose()
+            .context("Resolving admin socket path")?;
+
+        let unix_socket_path = config
+            .unix_socket
+            .enabled
+            .then(|| config.unix_socket.path.resolved())
+            .transpose()
+            .context("Resolving Unix socket path")?;
+
+        let stores = storage::open(&config.storage).with_context(|| {
+            format!(
+                "Opening storage at '{}'",
+                config.storage.path.original().display()
+            )
+        })?;
+        info!(backend = ?config.storage.backend, "Opened storage");
 
         let default_channel_id = config.channels.first().map(|inner| inner.id);
+
         let server_state = Arc::new(ServerState::new(
             default_channel_id,
-            config.max_username_length,
+            Settings {
+                max_username_length: config.max_username_length,
+                max_history_page_size: config.max_history_page_size,
+                max_channel_name_length: config.max_channel_name_length,
+                max_message_length: config.max_message_length,
+                max_frame_length: config.max_frame_length,
+                min_password_length: config.min_password_length,
+                allow_guests: config.allow_guests,
+                resume_grace_period: Duration::from_secs(config.resume_grace_period_secs),
+                idle_timeout: Duration::from_secs(config.idle_timeout_secs),
+                capabilities: config.capabilities(),
+                rate_limits: config.rate_limit.clone(),
+                event_queues: config.event_queues.clone(),
+            },
+            stores.clone(),
         ));
 
-        for channel_info in config.channels {
-            let (tx, _rx) = broadcast::channel(128); // TODO: Buffer size
+        Self::load_bans(&stores, &server_state).await?;
 
-            debug!(
-                channel_id = %channel_info.id,
-                channel_name = %channel_info.name,
-                "Registering channel"
-            );
-
-            if let Err(e) = server_state
-                .add_channel(channel_info.id, channel_info.name, tx)
-                .await
-            {
-                bail!("Failed to initialize channels - {e}");
-            }
-        }
+        Self::load_channels(&config, &stores, &server_state).await?;
 
         info!("Initialized server state");
         Ok(Self {
-            bind_address,
-            tls_acceptor,
+            endpoints,
             server_state,
             task_tracker: TaskTracker::new(),
+            admin_socket_path,
+            unix_socket_path,
+            config,
+            config_source,
+            certificate,
+            listener_certificates,
         })
     }
 
+    /// Work out every TCP listener to run, and load the certificates of the listeners that have
+    /// their own. Those get their own TLS acceptor, while the rest share the main one.
+    fn endpoints(
+        config: &Config,
+        verifier: Option<Arc<dyn ClientCertVerifier>>,
+        certificate: Arc<ReloadableCertificate>,
+    ) -> anyhow::Result<(Vec<Endpoint>, Vec<ListenerCertificate>)> {
+        let tls_acceptor = build_tls_acceptor(verifier.clone(), certificate);
+
+        let main = Endpoint {
+            address: SocketAddr::new(config.listener_ip, config.listener_port),
+            ipv6_only: false,
+            transport: Transport {
+                protocol: Protocol::Raw,
+                tls_acceptor: Some(tls_acceptor.clone()),
+            },
+            proxy_protocol: config.proxy_protocol.clone(),
+        };
+        debug!(address = %main.address, "Resolved bind address");
+
+        let mut endpoints = vec![main];
+        let mut listener_certificates = Vec::new();
+
+        for listener in &config.listeners {
+            let address = listener.address();
+
+            let listener_tls_acceptor = if let Some((cert_path, key_path)) = listener.tls_override()
+            {
+                let certificate = Arc::new(
+                    ReloadableCertificate::load(
+                        ServerConfig::builder().crypto_provider().clone(),
+                        cert_path,
+                        key_path,
+                    )
+                    .with_context(|| {
+                        format!("Loading the TLS certificate of the listener on {address}")
+                    })?,
+                );
+
+                listener_certificates.push(ListenerCertificate {
+                    address,
+                    cert_path: cert_path.clone(),
+                    key_path: key_path.clone(),
+                    certificate: certificate.clone(),
+                });
+
+                build_tls_acceptor(verifier.clone(), certificate)
+            } else {
+                tls_acceptor.clone()
+            };
+
+            debug!(
+                %address,
+                ipv6_only = listener.ipv6_only,
+                own_certificate = listener.tls_override().is_some(),
+                "Resolved listener bind address"
+            );
+
+            endpoints.push(Endpoint {
+                address,
+                ipv6_only: listener.ipv6_only,
+                transport: Transport {
+                    protocol: Protocol::Raw,
+                    tls_acceptor: Some(listener_tls_acceptor),
+                },
+                proxy_protocol: listener.proxy_protocol.clone(),
+            });
+        }
+
+        endpoints.extend(config.websocket.endpoint(&tls_acceptor));
+
+        Ok((endpoints, listener_certificates))
+    }
+
+    /// Register the stored channels, then apply whatever changed in the config's channel list since
+    /// the server last ran.
+    async fn load_channels(
+        config: &Config,
+        stores: &Stores,
+        server_state: &ServerState,
+    ) -> anyhow::Result<()> {
+        // Channels created at runtime must not pick up the history of a channel that is unknown to
+        // the channel store, e.g. one removed from the config before channels were stored.
+        if let Some(id) = stores
+            .messages
+            .max_channel_id()
+            .context("Reading channel IDs from message store")?
+        {
+            server_state.reserve_channel_id(id);
+        }
+
+        let channels = stores.channels.channels().context("Reading channels")?;
+        debug!(stored = channels.len(), "Loaded channels");
+
+        if let Err(e) = server_state.restore_channels(channels).await {
+            bail!("Failed to initialize channels - {e}");
+        }
+
+        let configured = stores
+            .channels
+            .configured_channels()
+            .context("Reading configured channels")?;
+        reload::apply_channel_changes(server_state, &configured, &config.channels).await;
+
+        // The default channel may have been deleted while it wasn't the default.
+        if let Some(default) = config.channels.first()
+            && server_state.get_channel_info(default.id).await.is_none()
+        {
+            warn!(channel_id = %default.id, "The default channel was deleted, adding it back");
+
+            if let Err(e) = server_state.add_new_channel(default).await {
+                bail!("Failed to add the default channel - {e}");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Forget bans that ended while the server was down, then hand the rest to the server state.
+    async fn load_bans(stores: &Stores, server_state: &ServerState) -> anyhow::Result<()> {
+        let expired = stores
+            .bans
+            .remove_expired_bans(SystemTime::now())
+            .context("Removing expired bans")?;
+        let bans = stores.bans.bans().context("Reading bans")?;
+        debug!(active = bans.len(), expired, "Loaded bans");
+
+        server_state.restore_bans(bans).await;
+        Ok(())
+    }
+
     #[instrument(skip_all, err)]
-    async fn run(self) -> anyhow::Result<()> {
+    async fn run(mut self, mut logging: Logging) -> anyhow::Result<()> {
         let cancellation_token = CancellationToken::new();
 
-        let listener = Listener::new(
-            self.server_state.clone(),
-            cancellation_token.clone(),
-            self.task_tracker.clone(),
-            self.tls_acceptor.clone(),
-            self.bind_address,
-        );
+        // Cancelled first on shutdown, to stop accepting connections while the existing ones drain.
+        let drain_token = cancellation_token.child_token();
+        let connection_tracker = TaskTracker::new();
+
+        for endpoint in &self.endpoints {
+            let listener = Listener::new(
+                self.server_state.clone(),
+                cancellation_token.clone(),
+                drain_token.clone(),
+                connection_tracker.clone(),
+                endpoint.clone(),
+            );
+
+            self.task_tracker.spawn(listener.start());
+        }
+
+        #[cfg(unix)]
+        if let Some(path) = self.admin_socket_path.take() {
+            let admin_socket = AdminSocket::new(
+                self.server_state.clone(),
+                cancellation_token.clone(),
+                self.task_tracker.clone(),
+                path,
+            );
 
-        self.task_tracker.spawn(listener.start());
+            self.task_tracker.spawn(admin_socket.start());
+        }
+
+        #[cfg(not(unix))]
+        if self.admin_socket_path.is_some() {
+            tracing::warn!("The admin socket is only supported on Unix platforms; not starting it");
+        }
 
-        tokio::signal::ctrl_c()
-            .await
-            .context("Failed to listen for 'Ctrl-C' signal")?;
+        #[cfg(unix)]
+        if let Some(path) = self.unix_socket_path.take() {
+            let unix_listener = UnixSocketListener::new(
+                self.server_state.clone(),
+                cancellation_token.clone(),
+                drain_token.clone(),
+                connection_tracker.clone(),
+                path,
+                self.config.unix_socket.mode,
+                self.config.unix_socket.trusted_users.clone(),
+            );
 
-        info!("Interruption signal received, shutting down...");
+            self.task_tracker.spawn(unix_listener.start());
+        }
+
+        #[cfg(not(unix))]
+        if self.unix_socket_path.is_some() {
+            tracing::warn!(
+                "The Unix socket listener is only supported on Unix platforms; not starting it"
+            );
+        }
+
+        #[cfg(unix)]
+        let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+            .context("Failed to listen for 'SIGHUP' signal")?;
+
+        let shutdown = shutdown_signal();
+        tokio::pin!(shutdown);
+
+        loop {
+            // Only Unix platforms have a signal for reloading the configuration.
+            #[cfg(unix)]
+            let reload_signal = hangup.recv();
+            #[cfg(not(unix))]
+            let reload_signal = std::future::pending::<Option<()>>();
+
+            tokio::select! {
+                result = &mut shutdown => {
+                    result?;
+                    break;
+                }
+
+                _ = reload_signal => {
+                    info!("Hangup signal received, reloading configuration...");
+
+                    if let Err(e) = self.reload(&mut logging).await {
+                        error!("Failed to reload configuration, keeping the current one: {e:#}");
+                    }
+                }
+            }
+        }
+
+        info!("Shutdown signal received, shutting down...");
+        self.drain(&drain_token, &connection_tracker).await;
         cancellation_token.cancel();
 
         self.task_tracker.close();
+        connection_tracker.close();
 
         debug!("Waiting for tasks to finish...");
         self.task_tracker.wait().await;
+        connection_tracker.wait().await;
 
         info!("Server shut down gracefully");
         Ok(())
     }
-}
 
-pub async fn main(default_paths: Option<DefaultPaths>, args: RunArgs) -> anyhow::Result<()> {
-    let env_conf_path = std::env::var(format!("{ENV_VAR_PREFIX}CONFIG_FILE"))
-        .ok()
-        .map(PathBuf::from);
-
-    let config_path = first_match! {
-        Some(path) = &args.config_file => path.clone(),
-        Some(path) = env_conf_path => path,
-        Some(defaults) = &default_paths => defaults.config.clone(),
-    };
+    /// Tell every client that the server is shutting down, stop accepting connections, then give
+    /// the connected clients the grace period to finish up. Returns early once every connection
+    /// closes, or if another shutdown signal arrives.
+    async fn drain(&self, drain_token: &CancellationToken, connection_tracker: &TaskTracker) {
+        let grace_period = Duration::from_secs(self.config.shutdown.grace_period_secs);
 
-    let mut figment = Figment::new().merge(Toml::string(DEFAULT_CONFIG));
+        self.server_state
+            .send_global_event(NetworkEvent::ServerShuttingDown(ServerShuttingDown {
+                reason: self.config.shutdown.reason.clone(),
+                grace_period,
+            }));
 
-    if let Some(path) = &config_path {
-        figment = figment.merge(Toml::file(path));
-    }
+        drain_token.cancel();
+        connection_tracker.close();
+
+        if grace_period.is_zero() {
+            return;
+        }
+
+        info!(?grace_period, "Waiting for clients to disconnect...");
 
-    if let Some(defaults) = &default_paths {
-        figment = figment.merge(Serialized::default("tls_cert_path", &defaults.server_cert));
-        figment = figment.merge(Serialized::default("tls_key_path", &defaults.server_key));
-        figment = figment.merge(Serialized::default("log_dir", &defaults.log_dir));
+        tokio::select! {
+            () = connection_tracker.wait() => debug!("Every client disconnected"),
+            () = sleep(grace_period) => debug!("Grace period over"),
+
+            result = shutdown_signal() => match result {
+                Ok(()) => info!("Shutdown signal received again, closing connections now"),
+                Err(e) => error!("{e:#}"),
+            },
+        }
     }
+}
 
-    let config: Config = figment
-        .merge(Env::prefixed(ENV_VAR_PREFIX))
-        .merge(Serialized::defaults(args))
-        .extract()
-        .context("Resolving configuration")?;
+/// Build a TLS acceptor presenting a certificate, and verifying client certificates if there's a
+/// verifier.
+fn build_tls_acceptor(
+    verifier: Option<Arc<dyn ClientCertVerifier>>,
+    certificate: Arc<ReloadableCertificate>,
+) -> TlsAcceptor {
+    let builder = ServerConfig::builder();
+
+    let tls_config = match verifier {
+        Some(verifier) => builder.with_client_cert_verifier(verifier),
+        None => builder.with_no_client_auth(),
+    }
+    .with_cert_resolver(certificate);
 
-    let _log_file_guard = init_logging(&config);
+    TlsAcceptor::from(Arc::new(tls_config))
+}
 
-    debug!(config_path = ?config_path, "Configuration resolved");
+/// Wait for a signal to shut down: Ctrl-C, or also `SIGTERM` on Unix platforms.
+async fn shutdown_signal() -> anyhow::Result<()> {
+    #[cfg(unix)]
+    {
+        let mut terminate =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+                .context("Failed to listen for 'SIGTERM' signal")?;
+
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => {
+                result.context("Failed to listen for 'Ctrl-C' signal")
+            }
 
-    if config.log_to_file {
-        info!(log_dir = %config.log_dir.display(), "Background file logging enabled");
+            _ = terminate.recv() => Ok(()),
+        }
     }
 
-    info!("Starting server");
-    ChatServer::new(config)
-        .await
-        .context("Initializing server")?
-        .run()
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c()
         .await
+        .context("Failed to listen for 'Ctrl-C' signal")
 }
 
-fn init_logging(config: &Config) -> Option<WorkerGuard> {
-    let stdout_layer = config.log_to_stdout.then(tracing_subscriber::fmt::layer);
+/// Everything the configuration is resolved from, besides the environment and the config file
+/// itself.
+#[derive(Debug)]
+struct ConfigSource {
+    default_paths: Option<DefaultPaths>,
+    args: RunArgs,
+}
+
+impl ConfigSource {
+    /// Resolve the configuration from the defaults, the config file, the environment and the
+    /// command line arguments, in increasing order of priority.
+    ///
+    /// Returns the configuration, and the path of the config file if there is one.
+    fn resolve(&self) -> anyhow::Result<(Config, Option<PathBuf>)> {
+        let env_conf_path = std::env::var(format!("{ENV_VAR_PREFIX}CONFIG_FILE"))
+            .ok()
+            .map(PathBuf::from);
+
+        let config_path = first_match! {
+            Some(path) = &self.args.config_file => path.clone(),
+            Some(path) = env_conf_path => path,
+            Some(defaults) = &self.default_paths => defaults.config.clone(),
+        };
+
+        let mut figment = Figment::new().merge(Toml::string(DEFAULT_CONFIG));
+
+        if let Some(path) = &config_path {
+            figment = figment.merge(Toml::file(path));
+        }
 
-    let (file_layer, file_guard) = if config.log_to_file {
-        let appender = rolling::daily(&config.log_dir, "server.log");
-        let (appender, guard) = tracing_appender::non_blocking(appender);
+        if let Some(defaults) = &self.default_paths {
+            figment = figment.merge(Serialized::default("tls_cert_path", &defaults.server_cert));
+            figment = figment.merge(Serialized::default("tls_key_path", &defaults.server_key));
+            figment = figment.merge(Serialized::default("log_dir", &defaults.log_dir));
+            figment = figment.merge(Serialized::default("storage.path", &defaults.history_db));
+            figment = figment.merge(Serialized::default(
+                "admin.socket_path",
+                &defaults.admin_socket,
+            ));
+            figment = figment.merge(Serialized::default(
+                "unix_socket.path",
+                &defaults.unix_socket,
+            ));
+            figment = figment.merge(Serialized::default(
+                "client_auth.ca_cert_path",
+                &defaults.ca_cert,
+            ));
+        }
 
-        let layer = tracing_subscriber::fmt::layer()
-            .with_writer(appender)
-            .with_ansi(false);
+        let config = figment
+            .merge(Env::prefixed(ENV_VAR_PREFIX))
+            .merge(Serialized::defaults(&self.args))
+            .extract()
+            .context("Resolving configuration")?;
 
-        (Some(layer), Some(guard))
-    } else {
-        (None, None)
+        Ok((config, config_path))
+    }
+}
+
+pub async fn main(default_paths: Option<DefaultPaths>, args: RunArgs) -> anyhow::Result<()> {
+    let config_source = ConfigSource {
+        default_paths,
+        args,
     };
+    let (config, config_path) = config_source.resolve()?;
+
+    let logging = Logging::init(&config).context("Setting up logging")?;
 
-    tracing_subscriber::registry()
-        .with(stdout_layer)
-        .with(file_layer)
-        .init();
+    debug!(config_path = ?config_path, "Configuration resolved");
+
+    if config.log_to_file {
+        info!(log_dir = %config.log_dir.display(), "Background file logging enabled");
+    }
 
-    file_guard
+    info!("Starting server");
+    ChatServer::new(config, config_source)
+        .await
+        .context("Initializing server")?
+        .run(logging)
+        .await
 }
@@ -0,0 +1,377 @@
+//! The PROXY protocol, versions 1 and 2, with which a proxy in front of the server tells it who
+//! the client on the other end of a connection is. See
+//! <https://www.haproxy.org/download/3.0/doc/proxy-protocol.txt>.
+
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str,
+};
+
+use anyhow::ensure;
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Signature every version 2 header starts with.
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Longest a version 1 header may be, including the line break.
+const V1_MAX_LENGTH: usize = 107;
+
+/// Length of the IPv4 addresses and ports in a version 2 header.
+const V2_INET_ADDRESSES_LENGTH: usize = 12;
+
+/// Length of the IPv6 addresses and ports in a version 2 header.
+const V2_INET6_ADDRESSES_LENGTH: usize = 36;
+
+/// Configuration for reading PROXY protocol headers on a listener.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProxyProtocolConfig {
+    /// Networks of the proxies that tell the server who their clients are. Connections from
+    /// anywhere else are taken to come straight from the client, and no header is read.
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+impl ProxyProtocolConfig {
+    /// Check the settings that can't be checked while parsing.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        ensure!(
+            !self.trusted_proxies.is_empty(),
+            "trusted_proxies is empty, so no proxy may send a header",
+        );
+
+        Ok(())
+    }
+
+    /// Whether a peer is a trusted proxy.
+    pub fn trusts(&self, ip: IpAddr) -> bool {
+        // IPv4 peers of a listener on "::" show up as IPv4-mapped IPv6 addresses.
+        let ip = ip.to_canonical();
+        self.trusted_proxies
+            .iter()
+            .any(|network| network.contains(&ip))
+    }
+}
+
+/// Error arising from reading a PROXY protocol header.
+#[derive(Debug, Error)]
+pub enum ProxyHeaderError {
+    /// The connection failed or closed before the whole header arrived.
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    /// The connection doesn't start with a PROXY protocol header.
+    #[error("no PROXY protocol header")]
+    Missing,
+
+    /// The header is malformed, or uses a version or command this server doesn't understand.
+    #[error("malformed PROXY protocol header: {0}")]
+    Malformed(&'static str),
+}
+
+/// Read the PROXY protocol header at the start of a connection, leaving whatever follows it in the
+/// stream. Returns the address of the client the proxy connected for, or `None` if the header
+/// doesn't say, e.g. because the proxy connected on its own behalf for a health check.
+///
+/// # Errors
+/// Returns an error if the connection doesn't start with a valid header.
+pub async fn read_header<S>(stream: &mut S) -> Result<Option<SocketAddr>, ProxyHeaderError>
+where
+    S: AsyncRead + Unpin,
+{
+    // Headers of either version are at least this long, so this never reads past one.
+    let mut start = [0; V2_SIGNATURE.len()];
+    stream.read_exact(&mut start).await?;
+
+    if start == V2_SIGNATURE {
+        read_v2(stream).await
+    } else if start.starts_with(b"PROXY ") {
+        read_v1(stream, &start).await
+    } else {
+        Err(ProxyHeaderError::Missing)
+    }
+}
+
+/// Read the rest of a version 1 header, a line like `PROXY TCP4 <src> <dst> <src port> <dst port>`.
+async fn read_v1<S>(stream: &mut S, start: &[u8]) -> Result<Option<SocketAddr>, ProxyHeaderError>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut line = start.to_vec();
+
+    // One byte at a time, so nothing after the line break is consumed.
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LENGTH {
+            return Err(ProxyHeaderError::Malformed("line too long"));
+        }
+
+        line.push(stream.read_u8().await?);
+    }
+
+    let line = str::from_utf8(&line[..line.len() - 2])
+        .map_err(|_| ProxyHeaderError::Malformed("not ASCII"))?;
+    let fields: Vec<_> = line.split(' ').collect();
+
+    let (ip, port) = match fields.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => return Ok(None),
+        ["PROXY", "TCP4", source, _, port, _] => (source.parse::<Ipv4Addr>().map(IpAddr::V4), port),
+        ["PROXY", "TCP6", source, _, port, _] => (source.parse::<Ipv6Addr>().map(IpAddr::V6), port),
+        _ => return Err(ProxyHeaderError::Malformed("unexpected fields")),
+    };
+
+    let ip = ip.map_err(|_| ProxyHeaderError::Malformed("invalid source address"))?;
+    let port = port
+        .parse()
+        .map_err(|_| ProxyHeaderError::Malformed("invalid source port"))?;
+
+    Ok(Some(SocketAddr::new(ip, port)))
+}
+
+/// Read the rest of a binary version 2 header, after the signature.
+async fn read_v2<S>(stream: &mut S) -> Result<Option<SocketAddr>, ProxyHeaderError>
+where
+    S: AsyncRead + Unpin,
+{
+    let [version_command, family, length @ ..] = {
+        let mut fixed = [0; 4];
+        stream.read_exact(&mut fixed).await?;
+        fixed
+    };
+    let length = usize::from(u16::from_be_bytes(length));
+
+    if version_command >> 4 != 2 {
+        return Err(ProxyHeaderError::Malformed("unsupported version"));
+    }
+
+    match version_command & 0x0F {
+        // LOCAL: the proxy connected on its own behalf.
+        0x0 => {
+            skip(stream, length).await?;
+            return Ok(None);
+        }
+        // PROXY: the proxy connected for a client.
+        0x1 => {}
+        _ => return Err(ProxyHeaderError::Malformed("unsupported command")),
+    }
+
+    let addresses_length = match family >> 4 {
+        0x1 => V2_INET_ADDRESSES_LENGTH,
+        0x2 => V2_INET6_ADDRESSES_LENGTH,
+        // Unspecified, or Unix socket addresses, which don't tell clients apart.
+        _ => {
+            skip(stream, length).await?;
+            return Ok(None);
+        }
+    };
+
+    if length < addresses_length {
+        return Err(ProxyHeaderError::Malformed("addresses too short"));
+    }
+
+    let mut addresses = [0; V2_INET6_ADDRESSES_LENGTH];
+    let addresses = &mut addresses[..addresses_length];
+    stream.read_exact(addresses).await?;
+
+    // Optional extensions we don't need.
+    skip(stream, length - addresses_length).await?;
+
+    let address = match family >> 4 {
+        0x1 => source_address::<4>(addresses)
+            .map(|(ip, port)| SocketAddr::new(Ipv4Addr::from(ip).into(), port)),
+        _ => source_address::<16>(addresses)
+            .map(|(ip, port)| SocketAddr::new(Ipv6Addr::from(ip).into(), port)),
+    };
+
+    address
+        .map(Some)
+        .ok_or(ProxyHeaderError::Malformed("addresses too short"))
+}
+
+/// Discard the next `length` bytes of a stream, without buffering them all at once.
+async fn skip<S>(stream: &mut S, length: usize) -> Result<(), ProxyHeaderError>
+where
+    S: AsyncRead + Unpin,
+{
+    let length = u64::try_from(length).expect("usize fits in u64");
+    let skipped = tokio::io::copy(&mut stream.take(length), &mut tokio::io::sink()).await?;
+
+    if skipped < length {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+
+    Ok(())
+}
+
+/// Get the source IP address and port from the addresses in a version 2 header, laid out as source
+/// and destination IP addresses of `N` bytes each, then source and destination ports.
+fn source_address<const N: usize>(addresses: &[u8]) -> Option<([u8; N], u16)> {
+    let (ip, rest) = addresses.split_first_chunk::<N>()?;
+    let (_destination, rest) = rest.split_first_chunk::<N>()?;
+    let port = rest.first_chunk::<2>()?;
+
+    Some((*ip, u16::from_be_bytes(*port)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a version 2 header with the given version and command byte, family byte and body.
+    fn v2_header(version_command: u8, family: u8, body: &[u8]) -> Vec<u8> {
+        let length = u16::try_from(body.len()).expect("body fits in a header");
+
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend([version_command, family]);
+        header.extend(length.to_be_bytes());
+        header.extend(body);
+        header
+    }
+
+    /// Read a header from `input`, returning the result and whatever was left unread.
+    async fn read(input: &[u8]) -> (Result<Option<SocketAddr>, ProxyHeaderError>, &[u8]) {
+        let mut stream = input;
+        let result = read_header(&mut stream).await;
+        (result, stream)
+    }
+
+    #[tokio::test]
+    async fn v1_tcp4() {
+        let (result, rest) = read(b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\nhello").await;
+
+        assert_eq!(result.unwrap(), Some("192.0.2.1:56324".parse().unwrap()));
+        assert_eq!(rest, b"hello");
+    }
+
+    #[tokio::test]
+    async fn v1_tcp6() {
+        let (result, rest) = read(b"PROXY TCP6 2001:db8::1 2001:db8::2 4000 443\r\n").await;
+
+        assert_eq!(result.unwrap(), Some("[2001:db8::1]:4000".parse().unwrap()));
+        assert!(rest.is_empty());
+    }
+
+    #[tokio::test]
+    async fn v1_unknown() {
+        let (result, rest) = read(b"PROXY UNKNOWN ffff::1 ffff::2 1 2\r\nhello").await;
+
+        assert_eq!(result.unwrap(), None);
+        assert_eq!(rest, b"hello");
+    }
+
+    #[tokio::test]
+    async fn v1_line_too_long() {
+        let mut input = b"PROXY TCP4 ".to_vec();
+        input.extend([b'1'; V1_MAX_LENGTH]);
+        input.extend(b"\r\n");
+
+        let (result, _) = read(&input).await;
+
+        assert!(matches!(
+            result,
+            Err(ProxyHeaderError::Malformed("line too long"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn v1_bad_port() {
+        let (result, _) = read(b"PROXY TCP4 192.0.2.1 198.51.100.1 70000 443\r\n").await;
+
+        assert!(matches!(
+            result,
+            Err(ProxyHeaderError::Malformed("invalid source port"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn v2_local() {
+        let mut input = v2_header(0x20, 0x00, &[1, 2, 3]);
+        input.extend(b"hello");
+
+        let (result, rest) = read(&input).await;
+
+        assert_eq!(result.unwrap(), None);
+        assert_eq!(rest, b"hello");
+    }
+
+    #[tokio::test]
+    async fn v2_ipv4_with_extensions() {
+        let mut body = vec![192, 0, 2, 1, 198, 51, 100, 1];
+        body.extend(56324_u16.to_be_bytes());
+        body.extend(443_u16.to_be_bytes());
+        // A no-op extension, with two bytes of padding.
+        body.extend([0x04, 0x00, 0x02, 0xAB, 0xCD]);
+
+        let mut input = v2_header(0x21, 0x11, &body);
+        input.extend(b"hello");
+
+        let (result, rest) = read(&input).await;
+
+        assert_eq!(result.unwrap(), Some("192.0.2.1:56324".parse().unwrap()));
+        assert_eq!(rest, b"hello");
+    }
+
+    #[tokio::test]
+    async fn v2_ipv6_with_extensions() {
+        let source: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        let destination: Ipv6Addr = "2001:db8::2".parse().unwrap();
+
+        let mut body = source.octets().to_vec();
+        body.extend(destination.octets());
+        body.extend(4000_u16.to_be_bytes());
+        body.extend(443_u16.to_be_bytes());
+        body.extend([0x04, 0x00, 0x01, 0x00]);
+
+        let mut input = v2_header(0x21, 0x21, &body);
+        input.extend(b"hello");
+
+        let (result, rest) = read(&input).await;
+
+        assert_eq!(result.unwrap(), Some("[2001:db8::1]:4000".parse().unwrap()));
+        assert_eq!(rest, b"hello");
+    }
+
+    #[tokio::test]
+    async fn v2_addresses_too_short() {
+        let input = v2_header(0x21, 0x11, &[192, 0, 2, 1, 198, 51]);
+
+        let (result, _) = read(&input).await;
+
+        assert!(matches!(
+            result,
+            Err(ProxyHeaderError::Malformed("addresses too short"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn v2_unsupported_version() {
+        let input = v2_header(0x11, 0x11, &[0; 12]);
+
+        let (result, _) = read(&input).await;
+
+        assert!(matches!(
+            result,
+            Err(ProxyHeaderError::Malformed("unsupported version"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn v2_unsupported_command() {
+        let input = v2_header(0x22, 0x11, &[0; 12]);
+
+        let (result, _) = read(&input).await;
+
+        assert!(matches!(
+            result,
+            Err(ProxyHeaderError::Malformed("unsupported command"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn missing_header() {
+        let (result, _) = read(b"GET / HTTP/1.1\r\n\r\n").await;
+
+        assert!(matches!(result, Err(ProxyHeaderError::Missing)));
+    }
+}
@@ -0,0 +1,387 @@
+use std::time::Duration;
+
+use anyhow::bail;
+use network_protocol::NetworkCommand;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+/// Limits for one kind of command. Each command takes a token from a bucket holding up to `burst`
+/// tokens, which refills at `per_second` tokens a second.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BucketConfig {
+    /// How many commands a client may send in a quick burst. Zero removes the limit.
+    pub burst: u32,
+
+    /// How many commands a client may send per second in the long run.
+    pub per_second: f64,
+}
+
+/// Per-connection rate limits, to keep a single client from flooding the server and everyone on
+/// it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    /// Whether commands are rate limited at all.
+    pub enabled: bool,
+
+    /// Limits for sending, editing and deleting messages.
+    pub messages: BucketConfig,
+
+    /// Limits for changing your name, creating, renaming and deleting channels, and moderating
+    /// other users.
+    pub renames: BucketConfig,
+
+    /// Limits for fetching channels, users and history, joining and leaving channels, and pings.
+    /// Clients join a channel whenever it's opened, so these must allow browsing channels quickly.
+    pub fetches: BucketConfig,
+
+    /// How many commands over the limits a client may send within `violation_window_secs`
+    /// before it is disconnected. Zero never disconnects clients.
+    pub max_violations: u32,
+
+    /// Length of the window in which violations are counted, in seconds.
+    pub violation_window_secs: u64,
+}
+
+impl RateLimitConfig {
+    /// Check that the limits make sense.
+    ///
+    /// # Errors
+    /// Returns an error if a bucket with a limit doesn't refill at a positive, finite rate.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (name, bucket) in [
+            ("messages", self.messages),
+            ("renames", self.renames),
+            ("fetches", self.fetches),
+        ] {
+            if bucket.burst > 0 && !(bucket.per_second.is_finite() && bucket.per_second > 0.0) {
+                bail!("rate_limit.{name}.per_second must be a positive number");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// What to do with a command, according to a [`RateLimiter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Handle the command.
+    Allow,
+
+    /// Reject the command. The client may try again after the given time.
+    Reject { retry_after: Duration },
+
+    /// Reject the command, and disconnect the client for exceeding the limits too often.
+    Disconnect,
+}
+
+/// A token bucket. Tokens are fractional, so the bucket refills smoothly.
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    refill_per_second: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Create a full bucket. Returns `None` if the bucket has no limit.
+    fn new(config: BucketConfig, now: Instant) -> Option<Self> {
+        if config.burst == 0 {
+            return None;
+        }
+
+        let capacity = f64::from(config.burst);
+
+        Some(Self {
+            capacity,
+            refill_per_second: config.per_second,
+            tokens: capacity,
+            last_refill: now,
+        })
+    }
+
+    /// Take a token. If the bucket is empty, returns how long until the next token.
+    fn take(&mut self, now: Instant) -> Result<(), Duration> {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_second).min(self.capacity);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            // Very slow refill rates take longer than a Duration can hold.
+            Err(
+                Duration::try_from_secs_f64((1.0 - self.tokens) / self.refill_per_second)
+                    .unwrap_or(Duration::MAX),
+            )
+        }
+    }
+}
+
+/// Rate limiter for the commands of one connection.
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// The limits this rate limiter was created with.
+    config: RateLimitConfig,
+    messages: Option<TokenBucket>,
+    renames: Option<TokenBucket>,
+    fetches: Option<TokenBucket>,
+    max_violations: u32,
+    violation_window: Duration,
+    violations: u32,
+    window_start: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        let now = Instant::now();
+        let bucket = |bucket_config| {
+            if config.enabled {
+                TokenBucket::new(bucket_config, now)
+            } else {
+                None
+            }
+        };
+
+        Self {
+            config: config.clone(),
+            messages: bucket(config.messages),
+            renames: bucket(config.renames),
+            fetches: bucket(config.fetches),
+            max_violations: config.max_violations,
+            violation_window: Duration::from_secs(config.violation_window_secs),
+            violations: 0,
+            window_start: now,
+        }
+    }
+
+    /// Switch to new limits, if they differ from the current ones. The buckets start over full, and
+    /// past violations are forgotten.
+    pub fn reconfigure(&mut self, config: &RateLimitConfig) {
+        if *config != self.config {
+            *self = Self::new(config);
+        }
+    }
+
+    /// Decide whether a command may be handled, taking a token if so.
+    pub fn check(&mut self, command: &NetworkCommand) -> Verdict {
+        let now = Instant::now();
+
+        let bucket = match command {
+            NetworkCommand::SendMessage(_)
+            | NetworkCommand::EditMessage(_)
+            | NetworkCommand::DeleteMessage(_) => &mut self.messages,
+
+            NetworkCommand::UpdateInfo(_)
+            | NetworkCommand::CreateChannel(_)
+            | NetworkCommand::RenameChannel(_)
+            | NetworkCommand::DeleteChannel(_)
+            | NetworkCommand::KickUser(_)
+            | NetworkCommand::MuteUser(_)
+            | NetworkCommand::UnmuteUser(_)
+            | NetworkCommand::BanUser(_) => &mut self.renames,
+
+            NetworkCommand::FetchChannels(_)
+            | NetworkCommand::FetchUsers(_)
+            | NetworkCommand::FetchHistory(_)
+            | NetworkCommand::JoinChannel(_)
+            | NetworkCommand::LeaveChannel(_)
+            | NetworkCommand::Ping(_) => &mut self.fetches,
+
+            // Pongs answer our own pings, and a second hello is rejected anyways.
+            NetworkCommand::Pong(_) | NetworkCommand::ClientHello(_) => return Verdict::Allow,
+        };
+
+        let Some(bucket) = bucket else {
+            return Verdict::Allow;
+        };
+
+        match bucket.take(now) {
+            Ok(()) => Verdict::Allow,
+            Err(retry_after) => self.record_violation(now, retry_after),
+        }
+    }
+
+    /// Count a command over the limits, and decide whether the client has had enough chances.
+    fn record_violation(&mut self, now: Instant, retry_after: Duration) -> Verdict {
+        if now.duration_since(self.window_start) >= self.violation_window {
+            self.window_start = now;
+            self.violations = 0;
+        }
+
+        self.violations += 1;
+
+        if self.max_violations > 0 && self.violations > self.max_violations {
+            Verdict::Disconnect
+        } else {
+            Verdict::Reject { retry_after }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use network_protocol::{Ping, Pong};
+
+    use super::*;
+
+    fn config(fetches: BucketConfig, max_violations: u32) -> RateLimitConfig {
+        let unlimited = BucketConfig {
+            burst: 0,
+            per_second: 0.0,
+        };
+
+        RateLimitConfig {
+            enabled: true,
+            messages: unlimited,
+            renames: unlimited,
+            fetches,
+            max_violations,
+            violation_window_secs: 10,
+        }
+    }
+
+    fn ping() -> NetworkCommand {
+        NetworkCommand::Ping(Ping { nonce: 0 })
+    }
+
+    #[test]
+    fn bucket_allows_burst_then_refills() {
+        let now = Instant::now();
+        let config = BucketConfig {
+            burst: 2,
+            per_second: 4.0,
+        };
+        let mut bucket = TokenBucket::new(config, now).unwrap();
+
+        assert_eq!(bucket.take(now), Ok(()));
+        assert_eq!(bucket.take(now), Ok(()));
+        assert_eq!(bucket.take(now), Err(Duration::from_millis(250)));
+
+        assert_eq!(bucket.take(now + Duration::from_millis(250)), Ok(()));
+        assert!(bucket.take(now + Duration::from_millis(250)).is_err());
+    }
+
+    #[test]
+    fn bucket_holds_at_most_burst() {
+        let now = Instant::now();
+        let config = BucketConfig {
+            burst: 2,
+            per_second: 1.0,
+        };
+        let mut bucket = TokenBucket::new(config, now).unwrap();
+
+        let later = now + Duration::from_secs(30);
+        assert_eq!(bucket.take(later), Ok(()));
+        assert_eq!(bucket.take(later), Ok(()));
+        assert!(bucket.take(later).is_err());
+    }
+
+    #[test]
+    fn bucket_without_limit() {
+        let config = BucketConfig {
+            burst: 0,
+            per_second: 1.0,
+        };
+
+        assert!(TokenBucket::new(config, Instant::now()).is_none());
+    }
+
+    #[test]
+    fn slow_refill_saturates_retry_after() {
+        let now = Instant::now();
+        let config = BucketConfig {
+            burst: 1,
+            per_second: 1e-20,
+        };
+        let mut bucket = TokenBucket::new(config, now).unwrap();
+
+        assert_eq!(bucket.take(now), Ok(()));
+        assert_eq!(bucket.take(now), Err(Duration::MAX));
+    }
+
+    #[test]
+    fn disconnects_after_max_violations() {
+        let mut limiter = RateLimiter::new(&config(
+            BucketConfig {
+                burst: 1,
+                per_second: 1e-3,
+            },
+            2,
+        ));
+
+        assert_eq!(limiter.check(&ping()), Verdict::Allow);
+        assert!(matches!(limiter.check(&ping()), Verdict::Reject { .. }));
+        assert!(matches!(limiter.check(&ping()), Verdict::Reject { .. }));
+        assert_eq!(limiter.check(&ping()), Verdict::Disconnect);
+
+        // Commands outside of any bucket are never limited.
+        assert_eq!(
+            limiter.check(&NetworkCommand::Pong(Pong { nonce: 0 })),
+            Verdict::Allow
+        );
+    }
+
+    #[test]
+    fn violations_expire_with_window() {
+        let mut limiter = RateLimiter::new(&config(
+            BucketConfig {
+                burst: 1,
+                per_second: 1.0,
+            },
+            1,
+        ));
+        let start = limiter.window_start;
+        let retry_after = Duration::from_secs(1);
+
+        assert!(matches!(
+            limiter.record_violation(start, retry_after),
+            Verdict::Reject { .. }
+        ));
+
+        let later = start + Duration::from_secs(10);
+        assert!(matches!(
+            limiter.record_violation(later, retry_after),
+            Verdict::Reject { .. }
+        ));
+        assert_eq!(
+            limiter.record_violation(later, retry_after),
+            Verdict::Disconnect
+        );
+    }
+
+    #[test]
+    fn zero_max_violations_never_disconnects() {
+        let mut limiter = RateLimiter::new(&config(
+            BucketConfig {
+                burst: 1,
+                per_second: 1e-3,
+            },
+            0,
+        ));
+
+        assert_eq!(limiter.check(&ping()), Verdict::Allow);
+        for _ in 0..100 {
+            assert!(matches!(limiter.check(&ping()), Verdict::Reject { .. }));
+        }
+    }
+
+    #[test]
+    fn disabled_allows_everything() {
+        let mut config = config(
+            BucketConfig {
+                burst: 1,
+                per_second: 1e-3,
+            },
+            1,
+        );
+        config.enabled = false;
+        let mut limiter = RateLimiter::new(&config);
+
+        for _ in 0..100 {
+            assert_eq!(limiter.check(&ping()), Verdict::Allow);
+        }
+    }
+}
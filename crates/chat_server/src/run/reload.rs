@@ -0,0 +1,232 @@
+//! Reloading the configuration while the server runs, on `SIGHUP`.
+
+use anyhow::{Context, bail};
+use network_protocol::ChannelInfo;
+use serde_json::Value;
+use tracing::{error, info, instrument, warn};
+
+use super::{ChatServer, Config, logging::Logging, server_state::ServerState};
+
+/// Top-level settings that take effect when the configuration is reloaded. Changes to any other
+/// setting only take effect after a restart.
+const RELOADABLE: &[&str] = &[
+    "channels",
+    "max_username_length",
+    "rate_limit",
+    "log_to_stdout",
+    "log_to_file",
+    "log_dir",
+    "tls_cert_path",
+    "tls_key_path",
+    "shutdown",
+];
+
+/// Settings that decide where logs go.
+const LOGGING: &[&str] = &["log_to_stdout", "log_to_file", "log_dir"];
+
+impl ChatServer {
+    /// Read the configuration again, and apply the settings that can change while the server runs.
+    /// Changes to other settings are logged, and left for the next restart.
+    ///
+    /// The TLS certificates and keys, including those of listeners with their own, are always
+    /// reloaded, since the files may have been replaced under the same paths. Existing connections
+    /// keep the certificate they were established with.
+    ///
+    /// # Errors
+    /// Returns an error if the new configuration can't be read or is invalid. Nothing is applied in
+    /// that case.
+    #[instrument(skip_all)]
+    pub(super) async fn reload(&mut self, logging: &mut Logging) -> anyhow::Result<()> {
+        let (new, config_path) = self.config_source.resolve()?;
+        new.validate()?;
+        info!(config_path = ?config_path, "Configuration re-read");
+
+        let changed = changed_settings(&self.config, &new)?;
+        let is_changed = |setting: &str| changed.iter().any(|changed| changed == setting);
+
+        for setting in changed
+            .iter()
+            .filter(|setting| !RELOADABLE.contains(&setting.as_str()))
+        {
+            warn!(
+                setting,
+                "Setting changed, but only takes effect after a restart"
+            );
+        }
+
+        if LOGGING.iter().any(|setting| is_changed(setting)) {
+            match logging.reload(&new) {
+                Ok(()) => {
+                    self.config.log_to_stdout = new.log_to_stdout;
+                    self.config.log_to_file = new.log_to_file;
+                    self.config.log_dir.clone_from(&new.log_dir);
+
+                    info!(
+                        log_to_stdout = new.log_to_stdout,
+                        log_to_file = new.log_to_file,
+                        log_dir = %new.log_dir.display(),
+                        "Applied new log settings"
+                    );
+                }
+
+                Err(e) => error!("Failed to apply log settings, keeping the current ones: {e:#}"),
+            }
+        }
+
+        if is_changed("channels") {
+            self.reload_channels(&new.channels).await;
+            self.config.channels.clone_from(&new.channels);
+        }
+
+        if is_changed("max_username_length") || is_changed("rate_limit") {
+            self.server_state.update_settings(|settings| {
+                settings.max_username_length = new.max_username_length;
+                settings.rate_limits.clone_from(&new.rate_limit);
+            });
+
+            self.config.max_username_length = new.max_username_length;
+            self.config.rate_limit.clone_from(&new.rate_limit);
+
+            info!(
+                max_username_length = new.max_username_length,
+                rate_limit = ?new.rate_limit,
+                "Applied new limits"
+            );
+        }
+
+        if is_changed("shutdown") {
+            info!(shutdown = ?new.shutdown, "Applied new shutdown settings");
+            self.config.shutdown = new.shutdown;
+        }
+
+        match self
+            .certificate
+            .reload(&new.tls_cert_path, &new.tls_key_path)
+        {
+            Ok(()) => {
+                self.config.tls_cert_path = new.tls_cert_path;
+                self.config.tls_key_path = new.tls_key_path;
+                info!("Reloaded TLS certificate");
+            }
+
+            Err(e) => error!("Failed to reload TLS certificate, keeping the current one: {e:#}"),
+        }
+
+        for listener in &self.listener_certificates {
+            match listener
+                .certificate
+                .reload(&listener.cert_path, &listener.key_path)
+            {
+                Ok(()) => info!(address = %listener.address, "Reloaded listener TLS certificate"),
+
+                Err(e) => error!(
+                    address = %listener.address,
+                    "Failed to reload listener TLS certificate, keeping the current one: {e:#}"
+                ),
+            }
+        }
+
+        if changed.is_empty() {
+            info!("No settings changed");
+        }
+
+        Ok(())
+    }
+
+    /// Bring the server's channels in line with a new channel list from the config.
+    async fn reload_channels(&self, new: &[ChannelInfo]) {
+        let old = &self.config.channels;
+
+        if old.first().map(|channel| channel.id) != new.first().map(|channel| channel.id) {
+            warn!("The default channel changed, but only takes effect after a restart");
+        }
+
+        apply_channel_changes(&self.server_state, old, new).await;
+    }
+}
+
+/// Apply the differences between the config's old and new channel lists, then persist the new one.
+/// Only the differences are applied, so channels created, renamed or deleted while the server runs
+/// stay that way unless the config changes them too. Channels that fail to change are logged and
+/// skipped.
+pub(super) async fn apply_channel_changes(
+    server_state: &ServerState,
+    old: &[ChannelInfo],
+    new: &[ChannelInfo],
+) {
+    for channel in old
+        .iter()
+        .filter(|channel| !new.iter().any(|new_channel| new_channel.id == channel.id))
+    {
+        match server_state.remove_channel(channel.id, None).await {
+            Ok(removed) => info!(
+                channel_id = %removed.id,
+                channel_name = %removed.name,
+                "Removed channel"
+            ),
+
+            Err(e) => warn!(channel_id = %channel.id, "Failed to remove channel: {e}"),
+        }
+    }
+
+    for channel in new {
+        // Compare against the old config rather than the server, so runtime renames are kept.
+        let old_name = match old.iter().find(|old_channel| old_channel.id == channel.id) {
+            Some(old_channel) => Some(old_channel.name.clone()),
+            None => server_state
+                .get_channel_info(channel.id)
+                .await
+                .map(|current| current.name),
+        };
+
+        match old_name {
+            Some(old_name) if old_name == channel.name => {}
+
+            Some(old_name) => match server_state
+                .rename_channel(channel.id, channel.name.clone(), None)
+                .await
+            {
+                Ok(renamed) => info!(
+                    channel_id = %renamed.id,
+                    %old_name,
+                    new_name = %renamed.name,
+                    "Renamed channel"
+                ),
+
+                Err(e) => warn!(channel_id = %channel.id, "Failed to rename channel: {e}"),
+            },
+
+            None => match server_state.add_new_channel(channel).await {
+                Ok(()) => info!(
+                    channel_id = %channel.id,
+                    channel_name = %channel.name,
+                    "Added channel"
+                ),
+
+                Err(e) => warn!(channel_id = %channel.id, "Failed to add channel: {e}"),
+            },
+        }
+    }
+
+    if let Err(e) = server_state.save_configured_channels(new.to_vec()).await {
+        error!("Failed to save the configured channels: {e}");
+    }
+}
+
+/// List the top-level settings that differ between two configurations.
+fn changed_settings(old: &Config, new: &Config) -> anyhow::Result<Vec<String>> {
+    let to_map = |config: &Config| match serde_json::to_value(config) {
+        Ok(Value::Object(map)) => Ok(map),
+        Ok(_) => bail!("Configuration isn't a map"),
+        Err(e) => Err(e).context("Comparing configurations"),
+    };
+
+    let old = to_map(old)?;
+    let new = to_map(new)?;
+
+    Ok(new
+        .into_iter()
+        .filter(|(setting, value)| old.get(setting) != Some(value))
+        .map(|(setting, _)| setting)
+        .collect())
+}
@@ -1,15 +1,50 @@
+use std::{
+    collections::BTreeSet,
+    net::IpAddr,
+    panic,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime},
+};
+
 use network_protocol::{
-    ChannelId, ChannelInfo, ErrorEvent, ErrorKind, NetworkEvent, UpdateInfo, UserId, UserInfo,
+    Authentication, Capability, ChannelId, ChannelInfo, ChannelMember, ChannelMembers, Credentials,
+    ErrorEvent, ErrorKind, MessageDeleted, MessageEdited, MessageId, NetworkEvent,
+    ReceiveDestination, ResumeToken, Role, UpdateInfo, UserBanned, UserId, UserInfo, UserKicked,
 };
 use scc::{HashMap, HashSet};
 use shared_utils::strings::StringExt;
 use thiserror::Error;
-use tokio::sync::{broadcast, mpsc};
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio_util::sync::CancellationToken;
+
+use argon2::{
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+    password_hash::{
+        self, SaltString,
+        rand_core::{OsRng, RngCore},
+    },
+};
 
-use crate::run::{Channel, User};
+use crate::run::{
+    Channel, User,
+    client_auth::CertificateIdentity,
+    event_queues::EventQueueConfig,
+    rate_limit::RateLimitConfig,
+    storage::{
+        Account, AccountStore, Ban, BanStore, BanTarget, ChannelStore, Conversation, MessageStore,
+        StorageError, StoredChannel, StoredMessage, Stores,
+    },
+    transport::ClientAddr,
+};
 
 const ALLOWED_NON_ALPHANUMERIC_CHARACTERS: [char; 2] = ['_', '-'];
 
+/// Number of random bytes in a resume token.
+const RESUME_TOKEN_LENGTH: usize = 32;
+
 /// Error when handling a username.
 #[derive(Debug, Clone, Error)]
 pub enum UserNameError {
@@ -33,7 +68,7 @@ pub enum UserNameError {
 }
 
 /// Error when managing users on the server.
-#[derive(Debug, Clone, Error)]
+#[derive(Debug, Error)]
 pub enum UserError {
     /// Error when updating a user's name.
     #[error("username error: {0}")]
@@ -46,37 +81,84 @@ pub enum UserError {
     /// Your own user ID is no longer known to the server. This indicates a fatal state mismatch.
     #[error("fatal state mismatch, your ID was not found on the server")]
     YourIdNotFound,
+
+    /// The username or password was wrong. We deliberately don't say which.
+    #[error("invalid username or password")]
+    AuthenticationFailed,
+
+    /// The server only accepts users with an account.
+    #[error("guests are not allowed on this server, log in or register an account")]
+    GuestsNotAllowed,
+
+    /// The password given when registering is too short.
+    #[error("passwords must be at least {0} characters long")]
+    PasswordTooShort(usize),
+
+    /// The user asked to log in with a client certificate, but didn't present a usable one.
+    #[error("no usable client certificate was presented")]
+    NoCertificate,
+
+    /// Someone is already logged into the account.
+    #[error("this account is already logged in")]
+    AlreadyLoggedIn,
+
+    /// A password could not be hashed, or a stored hash could not be parsed.
+    #[error("password hashing error: {0}")]
+    PasswordHash(#[from] password_hash::Error),
+
+    /// The user's account is banned.
+    #[error("you are banned from this server: {}", describe_ban(.0))]
+    Banned(Ban),
+
+    /// A direct message or account could not be read or persisted.
+    #[error("storage error: {0}")]
+    Storage(#[from] StorageError),
 }
 
 impl From<UserError> for ErrorEvent {
     fn from(value: UserError) -> Self {
         match value {
-            UserError::Name(e @ UserNameError::AlreadyTaken(_)) => Self {
-                kind: ErrorKind::NameTaken,
-                message: e.to_string(),
-            },
+            UserError::Name(e @ UserNameError::AlreadyTaken(_)) => {
+                Self::new(ErrorKind::NameTaken, e.to_string())
+            }
 
             // All other UserNameError variants are handled the same way
-            UserError::Name(other) => Self {
-                kind: ErrorKind::InvalidName,
-                message: other.to_string(),
-            },
+            UserError::Name(other) => Self::new(ErrorKind::InvalidName, other.to_string()),
+
+            e @ UserError::TargetNotFound(_) => Self::new(ErrorKind::TargetNotFound, e.to_string()),
 
-            e @ UserError::TargetNotFound(_) => Self {
-                kind: ErrorKind::TargetNotFound,
-                message: e.to_string(),
-            },
+            e @ (UserError::AuthenticationFailed
+            | UserError::GuestsNotAllowed
+            | UserError::PasswordTooShort(_)
+            | UserError::NoCertificate
+            | UserError::AlreadyLoggedIn) => {
+                Self::new(ErrorKind::AuthenticationFailed, e.to_string())
+            }
+
+            e @ UserError::Banned(_) => Self::new(ErrorKind::Banned, e.to_string()),
 
-            e @ UserError::YourIdNotFound => Self {
-                kind: ErrorKind::ServerError,
-                message: e.to_string(),
-            },
+            e
+            @ (UserError::YourIdNotFound | UserError::PasswordHash(_) | UserError::Storage(_)) => {
+                Self::new(ErrorKind::ServerError, e.to_string())
+            }
         }
     }
 }
 
-/// Error when managing channels on the server.
+/// Error when handling a channel name.
 #[derive(Debug, Clone, Error)]
+pub enum ChannelNameError {
+    /// The channel name is empty.
+    #[error("channel names cannot be empty")]
+    Empty,
+
+    /// The channel name is too long.
+    #[error("channel names cannot be longer than {0} characters")]
+    TooLong(usize),
+}
+
+/// Error when managing channels on the server.
+#[derive(Debug, Error)]
 pub enum ChannelError {
     /// Attempted to add a channel ID that already exists.
     #[error("duplicate channel ID: {0}")]
@@ -85,6 +167,176 @@ pub enum ChannelError {
     /// Attempted to access a channel ID that does not exist.
     #[error("channel does not exist: {0}")]
     DoesNotExist(ChannelId),
+
+    /// Error when naming a channel.
+    #[error("channel name error: {0}")]
+    Name(#[from] ChannelNameError),
+
+    /// The default channel cannot be removed, since every new user is pointed at it.
+    #[error("the default channel cannot be removed")]
+    IsDefault,
+
+    /// The user is not a member of the channel.
+    #[error("you are not a member of channel {0}")]
+    NotMember(ChannelId),
+
+    /// The user was muted in the channel by a moderator.
+    #[error("you are muted in channel {0}")]
+    Muted(ChannelId),
+
+    /// Your own user ID is no longer known to the server. This indicates a fatal state mismatch.
+    #[error("fatal state mismatch, your ID was not found on the server")]
+    YourIdNotFound,
+
+    /// The channel's history could not be read or written.
+    #[error("storage error: {0}")]
+    Storage(#[from] StorageError),
+
+    /// The user may not create, rename or delete channels.
+    #[error(transparent)]
+    Moderation(#[from] ModerationError),
+}
+
+impl From<ChannelError> for ErrorEvent {
+    fn from(value: ChannelError) -> Self {
+        match value {
+            ChannelError::Moderation(e) => e.into(),
+
+            e @ ChannelError::DoesNotExist(_) => {
+                Self::new(ErrorKind::TargetNotFound, e.to_string())
+            }
+
+            ChannelError::Name(e) => Self::new(ErrorKind::InvalidChannelName, e.to_string()),
+
+            e @ (ChannelError::IsDefault | ChannelError::NotMember(_) | ChannelError::Muted(_)) => {
+                Self::new(ErrorKind::PermissionDenied, e.to_string())
+            }
+
+            e @ (ChannelError::AlreadyExists(_)
+            | ChannelError::YourIdNotFound
+            | ChannelError::Storage(_)) => Self::new(ErrorKind::ServerError, e.to_string()),
+        }
+    }
+}
+
+/// Error when modifying an existing chat message.
+#[derive(Debug, Error)]
+pub enum MessageError {
+    /// The message ID given is not associated with a known message.
+    #[error("message does not exist: {0}")]
+    DoesNotExist(MessageId),
+
+    /// Only the original sender of a message may modify it.
+    #[error("only the sender of a message may modify it")]
+    NotSender,
+
+    /// The message could not be read or written.
+    #[error("storage error: {0}")]
+    Storage(#[from] StorageError),
+}
+
+impl From<MessageError> for ErrorEvent {
+    fn from(value: MessageError) -> Self {
+        match value {
+            e @ MessageError::DoesNotExist(_) => {
+                Self::new(ErrorKind::TargetNotFound, e.to_string())
+            }
+
+            e @ MessageError::NotSender => Self::new(ErrorKind::PermissionDenied, e.to_string()),
+
+            e @ MessageError::Storage(_) => Self::new(ErrorKind::ServerError, e.to_string()),
+        }
+    }
+}
+
+/// Error when kicking, muting, banning, or changing the role of a user.
+#[derive(Debug, Error)]
+pub enum ModerationError {
+    /// The user ID given is not associated with a known user.
+    #[error("user ID '{0}' does not exist")]
+    TargetNotFound(UserId),
+
+    /// The channel ID given is not associated with a known channel.
+    #[error("channel does not exist: {0}")]
+    ChannelNotFound(ChannelId),
+
+    /// Only moderators and owners may moderate other users.
+    #[error("only moderators may do that")]
+    NotModerator,
+
+    /// Moderators may only moderate users with a lower role than their own.
+    #[error("you may only moderate users with a lower role than yours")]
+    Outranked,
+
+    /// Guests get a new ID every time they connect, so banning their ID would do nothing.
+    #[error("guests can only be banned by address")]
+    GuestBan,
+
+    /// The user's connection dropped, and they are only held for session resumption.
+    #[error("the user is not connected, so their address is unknown")]
+    NoAddress,
+
+    /// The user is connected over a local socket, which has no IP address.
+    #[error("the user is connected over a local socket, which has no address to ban")]
+    LocalConnection,
+
+    /// Roles are stored with accounts, which guests don't have.
+    #[error("only users with an account can be given a role")]
+    GuestRole,
+
+    /// Your own user ID is no longer known to the server. This indicates a fatal state mismatch.
+    #[error("fatal state mismatch, your ID was not found on the server")]
+    YourIdNotFound,
+
+    /// A ban or role could not be persisted.
+    #[error("storage error: {0}")]
+    Storage(#[from] StorageError),
+}
+
+impl From<ModerationError> for ErrorEvent {
+    fn from(value: ModerationError) -> Self {
+        match value {
+            e @ (ModerationError::TargetNotFound(_) | ModerationError::ChannelNotFound(_)) => {
+                Self::new(ErrorKind::TargetNotFound, e.to_string())
+            }
+
+            e @ (ModerationError::NotModerator
+            | ModerationError::Outranked
+            | ModerationError::GuestBan
+            | ModerationError::NoAddress
+            | ModerationError::LocalConnection
+            | ModerationError::GuestRole) => Self::new(ErrorKind::PermissionDenied, e.to_string()),
+
+            e @ (ModerationError::YourIdNotFound | ModerationError::Storage(_)) => {
+                Self::new(ErrorKind::ServerError, e.to_string())
+            }
+        }
+    }
+}
+
+/// Describe a ban's reason and how long it has left, for the banned user.
+fn describe_ban(ban: &Ban) -> String {
+    let reason = if ban.reason.is_empty() {
+        "no reason given"
+    } else {
+        &ban.reason
+    };
+
+    match ban.expires_at {
+        None => format!("{reason} (permanent)"),
+
+        Some(expires_at) => {
+            let remaining = expires_at
+                .duration_since(SystemTime::now())
+                .unwrap_or_default();
+
+            // Rounded up, so a ban with seconds left doesn't claim to be over.
+            format!(
+                "{reason} (ends in {} minutes)",
+                remaining.as_secs().div_ceil(60)
+            )
+        }
+    }
 }
 
 /// Unique token representing a specific user. This wraps the user's `UserId`, but can't be forged
@@ -102,14 +354,69 @@ impl UserToken {
     }
 }
 
+/// Tunable limits and policies for the [`ServerState`].
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// Maximum allowed length of users' display names.
+    pub max_username_length: usize,
+
+    /// Maximum number of messages returned in a single page of history.
+    pub max_history_page_size: usize,
+
+    /// Maximum allowed length of channel names.
+    pub max_channel_name_length: usize,
+
+    /// Maximum allowed length of message contents, in characters.
+    pub max_message_length: usize,
+
+    /// Maximum length of a single frame from or to a client, in bytes.
+    pub max_frame_length: usize,
+
+    /// Minimum allowed length of account passwords.
+    pub min_password_length: usize,
+
+    /// Whether users may connect without an account.
+    pub allow_guests: bool,
+
+    /// How long to hold a user's session after their connection drops, so they can resume it.
+    /// Zero disables session resumption.
+    pub resume_grace_period: Duration,
+
+    /// How long a client that supports keepalives may stay silent before it is disconnected. Zero
+    /// disables the timeout.
+    pub idle_timeout: Duration,
+
+    /// Optional protocol features the server supports.
+    pub capabilities: BTreeSet<Capability>,
+
+    /// Limits on how fast each connection may send commands.
+    pub rate_limits: RateLimitConfig,
+
+    /// Event queue sizes, and what to do with clients that fall behind.
+    pub event_queues: EventQueueConfig,
+}
+
+/// A user whose connection dropped, held so they may resume their session.
+#[derive(Debug)]
+struct DetachedUser {
+    /// Receiving end of the user's event channel. Events sent to the user queue up here until they
+    /// resume.
+    event_rx: mpsc::Receiver<NetworkEvent>,
+
+    /// Identifies this detachment, so an expiring grace period can't remove a user who resumed and
+    /// then dropped again.
+    detachment: u64,
+}
+
 /// State shared between all tasks.
 #[derive(Debug)]
 pub struct ServerState {
     /// The default channel's ID.
     default_channel_id: Option<ChannelId>,
 
-    /// Maximum allowed length of users' display names.
-    max_username_length: usize,
+    /// Limits and policies. Some of them may change while the server runs, so connections can
+    /// watch them.
+    settings: watch::Sender<Settings>,
 
     /// Broadcast sender to send an event to all connected clients.
     global_broadcast: broadcast::Sender<NetworkEvent>,
@@ -117,27 +424,67 @@ pub struct ServerState {
     /// Map from channel IDs to channels.
     channels: HashMap<ChannelId, Channel>,
 
+    /// The ID the next created channel will get. Always above every channel ID that was ever added
+    /// or has history, so that IDs are never reused.
+    next_channel_id: AtomicU64,
+
     /// Map from user IDs to users.
     users: HashMap<UserId, User>,
 
     /// Set of all connected users' names. Used for fast, atomic lookups to enforce username
     /// uniqueness.
     taken_names: HashSet<String>,
+
+    /// Map from resume tokens to the users they belong to. Each user has at most one token.
+    resume_tokens: HashMap<ResumeToken, UserId>,
+
+    /// Users whose connection dropped, but who may still resume their session.
+    detached_users: HashMap<UserId, DetachedUser>,
+
+    /// The ID the next detachment will get.
+    next_detachment: AtomicU64,
+
+    /// Persistent storage for message history.
+    message_store: Arc<dyn MessageStore>,
+
+    /// Persistent storage for user accounts.
+    account_store: Arc<dyn AccountStore>,
+
+    /// Persistent storage for bans.
+    ban_store: Arc<dyn BanStore>,
+
+    /// Persistent storage for channels.
+    channel_store: Arc<dyn ChannelStore>,
+
+    /// Every ban in the ban store that hasn't ended, so connections can be checked without
+    /// touching storage. Ended bans are dropped when they're next looked up.
+    bans: HashMap<BanTarget, Ban>,
 }
 
 impl ServerState {
     /// Initialize a `ServerState` instance.
-    pub fn new(default_channel_id: Option<ChannelId>, max_username_length: usize) -> Self {
+    pub fn new(default_channel_id: Option<ChannelId>, settings: Settings, stores: Stores) -> Self {
         const CHANNEL_INIT_CAPACITY: usize = 64;
         const USER_INIT_CAPACITY: usize = 4096;
 
+        let (global_broadcast, _rx) = broadcast::channel(settings.event_queues.global_capacity);
+
         Self {
             default_channel_id,
-            max_username_length,
-            global_broadcast: broadcast::channel(128).0, // TODO: Buffer size
+            settings: watch::Sender::new(settings),
+            global_broadcast,
             channels: HashMap::with_capacity(CHANNEL_INIT_CAPACITY),
+            next_channel_id: AtomicU64::new(1),
             users: HashMap::with_capacity(USER_INIT_CAPACITY),
             taken_names: HashSet::with_capacity(USER_INIT_CAPACITY),
+            resume_tokens: HashMap::with_capacity(USER_INIT_CAPACITY),
+            detached_users: HashMap::new(),
+            next_detachment: AtomicU64::new(0),
+            message_store: stores.messages,
+            account_store: stores.accounts,
+            ban_store: stores.bans,
+            channel_store: stores.channels,
+            bans: HashMap::new(),
         }
     }
 
@@ -148,7 +495,54 @@ impl ServerState {
 
     /// Get the maximum allowed username length.
     pub fn max_username_length(&self) -> usize {
-        self.max_username_length
+        self.settings.borrow().max_username_length
+    }
+
+    /// Get the maximum allowed length of message contents, in characters.
+    pub fn max_message_length(&self) -> usize {
+        self.settings.borrow().max_message_length
+    }
+
+    /// Get the maximum length of a single frame from or to a client, in bytes.
+    pub fn max_frame_length(&self) -> usize {
+        self.settings.borrow().max_frame_length
+    }
+
+    /// Get the maximum number of messages returned in a single page of history.
+    pub fn max_history_page_size(&self) -> usize {
+        self.settings.borrow().max_history_page_size
+    }
+
+    /// Get how long a user's session is held after their connection drops. Zero if session
+    /// resumption is disabled.
+    pub fn resume_grace_period(&self) -> Duration {
+        self.settings.borrow().resume_grace_period
+    }
+
+    /// Get how long a client that supports keepalives may stay silent before it is disconnected.
+    /// Zero if clients are never timed out.
+    pub fn idle_timeout(&self) -> Duration {
+        self.settings.borrow().idle_timeout
+    }
+
+    /// Get the event queue sizes, and what to do with clients that fall behind.
+    pub fn event_queues(&self) -> EventQueueConfig {
+        self.settings.borrow().event_queues.clone()
+    }
+
+    /// Get the optional protocol features the server supports.
+    pub fn capabilities(&self) -> BTreeSet<Capability> {
+        self.settings.borrow().capabilities.clone()
+    }
+
+    /// Change the settings while the server runs. Connections watching the settings are told.
+    pub fn update_settings(&self, update: impl FnOnce(&mut Settings)) {
+        self.settings.send_modify(update);
+    }
+
+    /// Watch the settings, to notice when they change while the server runs.
+    pub fn watch_settings(&self) -> watch::Receiver<Settings> {
+        self.settings.subscribe()
     }
 
     /// Send an event to all active users.
@@ -166,7 +560,6 @@ impl ServerState {
 
     /// Get a channel's [`ChannelInfo`] by its ID, if the ID is associated with a channel on the
     /// server.
-    #[expect(dead_code)]
     pub async fn get_channel_info(&self, id: ChannelId) -> Option<ChannelInfo> {
         self.channels
             .read_async(&id, |_, channel| channel.info.clone())
@@ -202,155 +595,1577 @@ impl ServerState {
         // receivers, but we don't actually care if nobody gets this message. As such, we ignore
         // the Result.
         self.channels
-            .read_async(&target_id, |_, value| value.broadcast.send(event))
+            .read_async(&target_id, |_, value| {
+                let _ignored_result = value.broadcast.send(event);
+            })
             .await
-            .map(|_ignored_result| ())
             .ok_or(ChannelError::DoesNotExist(target_id))
     }
 
-    /// Add a new channel to the server.
+    /// Persist a chat message to a channel's history, then broadcast it to the channel.
     ///
-    /// It is the server administrator's responsibility to ensure that each channel has a unique ID.
-    /// Channels may have duplicate names.
+    /// The message is only broadcast if it was persisted successfully, so that the history never
+    /// disagrees with what connected users saw.
     ///
     /// # Errors
-    /// Returns [`ChannelError`] if a called with an ID that is already present.
-    pub async fn add_channel(
-        &self,
-        id: ChannelId,
-        name: String,
-        event_tx: broadcast::Sender<NetworkEvent>,
-    ) -> Result<(), ChannelError> {
-        let channel_info = ChannelInfo { id, name };
-
-        let channel = Channel {
-            info: channel_info,
-            broadcast: event_tx,
+    /// * [`ChannelError::DoesNotExist`] if the target channel ID was not found.
+    /// * [`ChannelError::NotMember`] if the sender did not join the channel.
+    /// * [`ChannelError::Muted`] if the sender is muted in the channel.
+    /// * [`ChannelError::Storage`] if the message could not be persisted.
+    ///
+    /// # Panics
+    /// Panics if the message is not addressed to a channel.
+    pub async fn post_channel_message(&self, message: StoredMessage) -> Result<(), ChannelError> {
+        let ReceiveDestination::Channel(channel_id) = message.destination else {
+            panic!("post_channel_message called with a direct message");
         };
 
-        self.channels
-            .insert_async(id, channel)
+        let muted_until = self
+            .channels
+            .read_async(&channel_id, |_, channel| {
+                channel.muted.get(&message.sender_id).copied()
+            })
             .await
-            .map_err(|_| ChannelError::AlreadyExists(id))
-    }
-
-    /// Subscribe to all channels on the server. Returns a [`Vec`] of [`broadcast::Receiver`]s for
-    /// every channel.
-    pub async fn subscribe_to_channels(&self) -> Vec<broadcast::Receiver<NetworkEvent>> {
-        let mut res = Vec::with_capacity(self.channels.len());
+            .ok_or(ChannelError::DoesNotExist(channel_id))?;
 
-        self.channels
-            .iter_async(|_, value| {
-                res.push(value.broadcast.subscribe());
-                true
+        if !self
+            .users
+            .read_async(&message.sender_id, |_, user| {
+                user.joined_channels.contains(&channel_id)
             })
-            .await;
-
-        res
-    }
-
-    /// Get a user's [`UserInfo`] by their ID, if the ID is associated with a user on the server.
-    #[expect(dead_code)]
-    pub async fn get_user_info(&self, id: UserId) -> Option<UserInfo> {
-        self.users
-            .read_async(&id, |_, value| value.info.clone())
             .await
-    }
+            .unwrap_or(false)
+        {
+            return Err(ChannelError::NotMember(channel_id));
+        }
 
-    /// Get the [`UserInfo`] of every user on the server. If there are no users, returns an empty
-    /// [`Vec`].
-    pub async fn get_all_user_info(&self) -> Vec<UserInfo> {
-        let mut res = Vec::with_capacity(self.users.len());
+        if let Some(until) = muted_until
+            && until.is_none_or(|until| until > SystemTime::now())
+        {
+            return Err(ChannelError::Muted(channel_id));
+        }
 
-        self.users
-            .iter_async(|_, value| {
-                res.push(value.info.clone());
-                true
-            })
-            .await;
+        let to_store = message.clone();
+        self.with_store(move |store| store.append(&to_store))
+            .await?;
 
-        res
+        self.send_event_to_channel(channel_id, NetworkEvent::ReceivedMessage(message.into()))
+            .await
     }
 
-    /// Send a [`NetworkEvent`] to a client with the given ID, if that ID is associated with a user
-    /// on the server.
+    /// Get up to `limit` messages from a channel's history, in chronological order. See
+    /// [`MessageStore::history`].
     ///
     /// # Errors
-    /// Returns [`UserError::TargetNotFound`] if the target ID was not found.
-    pub async fn send_event_to_user(
+    /// * [`ChannelError::DoesNotExist`] if the channel ID was not found.
+    /// * [`ChannelError::Storage`] if the history could not be read.
+    pub async fn channel_history(
         &self,
-        target_id: UserId,
-        event: NetworkEvent,
-    ) -> Result<(), UserError> {
-        let sender = self
-            .users
-            .read_async(&target_id, |_, value| value.sender.clone())
-            .await
-            .ok_or(UserError::TargetNotFound(target_id))?;
+        channel_id: ChannelId,
+        before: Option<MessageId>,
+        limit: usize,
+    ) -> Result<Vec<StoredMessage>, ChannelError> {
+        if !self.channels.contains_async(&channel_id).await {
+            return Err(ChannelError::DoesNotExist(channel_id));
+        }
 
-        // The only failure condition for sending through this channel is if the channel is closed,
-        // but that can only happen if the target user disconnected, which we don't really care
-        // about. As such, we ignore this Result.
-        let _: Result<_, _> = sender.send(event).await;
-        Ok(())
+        let conversation = Conversation::Channel(channel_id);
+        Ok(self
+            .with_store(move |store| store.history(conversation, before, limit))
+            .await?)
     }
 
-    /// Register a new (ID, name) user pair. This will:
-    /// 1. Ensure the name is not empty, and does not exceed the maximum length.
-    /// 2. Ensure the name contains no invalid characters.
-    /// 3. Ensure the name is not already registered (case-insensitive).
-    /// 4. Register the name.
+    /// Persist a direct message, then deliver it to its recipient.
+    ///
+    /// Like [`Self::post_channel_message`], the message is only delivered if it was persisted
+    /// successfully. Delivering the loopback copy to the sender is left to the caller.
     ///
     /// # Errors
-    /// Returns a [`NameRegistrationError`] if name registration fails.
-    pub async fn handle_new_user(
-        &self,
-        mut name: String,
-        max_username_length: usize,
-        event_tx: mpsc::Sender<NetworkEvent>,
-    ) -> Result<UserToken, UserError> {
-        name.fast_trim();
+    /// * [`UserError::TargetNotFound`] if the recipient is not connected.
+    /// * [`UserError::Storage`] if the message could not be persisted.
+    ///
+    /// # Panics
+    /// Panics if the message is not addressed to a user.
+    pub async fn post_direct_message(&self, message: StoredMessage) -> Result<(), UserError> {
+        let ReceiveDestination::User(recipient_id) = message.destination else {
+            panic!("post_direct_message called with a channel message");
+        };
 
-        Self::validate_username(&name, max_username_length)?;
-        let normalized_name = Self::normalize_username(&name);
+        if !self.users.contains_async(&recipient_id).await {
+            return Err(UserError::TargetNotFound(recipient_id));
+        }
 
-        if self
-            .taken_names
-            .insert_async(normalized_name)
+        let to_store = message.clone();
+        self.with_store(move |store| store.append(&to_store))
+            .await?;
+
+        self.send_event_to_user(recipient_id, NetworkEvent::ReceivedMessage(message.into()))
             .await
-            .is_err()
-        {
-            return Err(UserError::Name(UserNameError::AlreadyTaken(name.clone())));
-        }
+    }
 
-        let user_id = UserId(uuid::Uuid::now_v7());
+    /// Get up to `limit` direct messages between two users, in chronological order. See
+    /// [`MessageStore::history`].
+    ///
+    /// # Errors
+    /// Returns [`UserError::Storage`] if the history could not be read.
+    pub async fn direct_history(
+        &self,
+        user: UserId,
+        other: UserId,
+        before: Option<MessageId>,
+        limit: usize,
+    ) -> Result<Vec<StoredMessage>, UserError> {
+        let conversation = Conversation::direct(user, other);
+        Ok(self
+            .with_store(move |store| store.history(conversation, before, limit))
+            .await?)
+    }
 
-        let user_info = UserInfo { id: user_id, name };
+    /// Replace the contents of a message, then notify everyone who can see it.
+    ///
+    /// Delivering the event to the editor's own client is left to the caller for direct messages,
+    /// like with [`Self::post_direct_message`].
+    ///
+    /// # Errors
+    /// * [`MessageError::DoesNotExist`] if the message ID was not found.
+    /// * [`MessageError::NotSender`] if the user is not the message's sender.
+    /// * [`MessageError::Storage`] if the message could not be updated.
+    pub async fn edit_message(
+        &self,
+        token: &UserToken,
+        id: MessageId,
+        contents: String,
+    ) -> Result<MessageEdited, MessageError> {
+        let message = self.get_own_message(token, id).await?;
+        let edited_at = SystemTime::now();
+
+        let new_contents = contents.clone();
+        if !self
+            .with_store(move |store| store.edit(id, &new_contents, edited_at))
+            .await?
+        {
+            return Err(MessageError::DoesNotExist(id));
+        }
 
-        let user = User {
-            info: user_info.clone(),
-            sender: event_tx,
+        let edited = MessageEdited {
+            message_id: id,
+            sender_id: token.id(),
+            destination: message.destination,
+            contents,
+            edited_at,
         };
 
-        self.users.insert_async(user_id, user).await.expect(
-            "This error would indicate a UUID collision, which we can assume to be impossible",
-        );
-
-        self.send_global_event(NetworkEvent::UserJoined(user_info));
+        self.deliver_message_event(
+            message.destination,
+            NetworkEvent::MessageEdited(edited.clone()),
+        )
+        .await;
 
-        Ok(UserToken(user_id))
+        Ok(edited)
     }
 
-    /// Update a user's information with the given [`UpdateInfo`]. `Some` fields will be updated,
-    /// while `None` fields will be unmodified. The update operation is atomic - if any updates fail
-    /// (for example, if a username is invalid), the entire update will fail.
-    pub async fn update_user_info(
+    /// Delete a message, then notify everyone who could see it.
+    ///
+    /// Delivering the event to the deleter's own client is left to the caller for direct messages,
+    /// like with [`Self::post_direct_message`].
+    ///
+    /// # Errors
+    /// * [`MessageError::DoesNotExist`] if the message ID was not found.
+    /// * [`MessageError::NotSender`] if the user is not the message's sender.
+    /// * [`MessageError::Storage`] if the message could not be deleted.
+    pub async fn delete_message(
         &self,
         token: &UserToken,
-        new_info: UpdateInfo,
-        max_username_length: usize,
-    ) -> Result<(), UserError> {
-        /// Drop guard for name operations. To make operations on the `taken_names` set atomic, we
+        id: MessageId,
+    ) -> Result<MessageDeleted, MessageError> {
+        let message = self.get_own_message(token, id).await?;
+
+        if !self.with_store(move |store| store.delete(id)).await? {
+            return Err(MessageError::DoesNotExist(id));
+        }
+
+        let deleted = MessageDeleted {
+            message_id: id,
+            sender_id: token.id(),
+            destination: message.destination,
+        };
+
+        self.deliver_message_event(
+            message.destination,
+            NetworkEvent::MessageDeleted(deleted.clone()),
+        )
+        .await;
+
+        Ok(deleted)
+    }
+
+    /// Get a message from the store, ensuring it was sent by the user the token belongs to.
+    async fn get_own_message(
+        &self,
+        token: &UserToken,
+        id: MessageId,
+    ) -> Result<StoredMessage, MessageError> {
+        let message = self
+            .with_store(move |store| store.get(id))
+            .await?
+            .ok_or(MessageError::DoesNotExist(id))?;
+
+        if message.sender_id != token.id() {
+            return Err(MessageError::NotSender);
+        }
+
+        Ok(message)
+    }
+
+    /// Send an event about an existing message to the channel or user it was sent to.
+    async fn deliver_message_event(&self, destination: ReceiveDestination, event: NetworkEvent) {
+        // The channel may have been removed, or the recipient may have disconnected, since the
+        // message was sent. Either way, there is nobody left to notify.
+        match destination {
+            ReceiveDestination::Channel(channel_id) => {
+                let _: Result<_, _> = self.send_event_to_channel(channel_id, event).await;
+            }
+
+            ReceiveDestination::User(recipient_id) => {
+                let _: Result<_, _> = self.send_event_to_user(recipient_id, event).await;
+            }
+        }
+    }
+
+    /// Run a blocking operation against the message store on tokio's blocking thread pool.
+    async fn with_store<T, F>(&self, operation: F) -> Result<T, StorageError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&dyn MessageStore) -> Result<T, StorageError> + Send + 'static,
+    {
+        let store = self.message_store.clone();
+        Self::spawn_blocking(move || operation(store.as_ref())).await
+    }
+
+    /// Run a blocking operation against the account store on tokio's blocking thread pool. Since
+    /// password hashing is just as blocking, account operations may do that here as well.
+    async fn with_accounts<T, F>(&self, operation: F) -> Result<T, UserError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&dyn AccountStore) -> Result<T, UserError> + Send + 'static,
+    {
+        let store = self.account_store.clone();
+        Self::spawn_blocking(move || operation(store.as_ref())).await
+    }
+
+    /// Run a blocking operation against the ban store on tokio's blocking thread pool.
+    async fn with_bans<T, F>(&self, operation: F) -> Result<T, StorageError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&dyn BanStore) -> Result<T, StorageError> + Send + 'static,
+    {
+        let store = self.ban_store.clone();
+        Self::spawn_blocking(move || operation(store.as_ref())).await
+    }
+
+    /// Run a blocking operation against the channel store on tokio's blocking thread pool.
+    async fn with_channels<T, F>(&self, operation: F) -> Result<T, StorageError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&dyn ChannelStore) -> Result<T, StorageError> + Send + 'static,
+    {
+        let store = self.channel_store.clone();
+        Self::spawn_blocking(move || operation(store.as_ref())).await
+    }
+
+    /// Run a blocking operation on tokio's blocking thread pool, propagating panics.
+    async fn spawn_blocking<T, E, F>(operation: F) -> Result<T, E>
+    where
+        T: Send + 'static,
+        E: From<StorageError> + Send + 'static,
+        F: FnOnce() -> Result<T, E> + Send + 'static,
+    {
+        match tokio::task::spawn_blocking(operation).await {
+            Ok(result) => result,
+            Err(e) if e.is_panic() => panic::resume_unwind(e.into_panic()),
+            Err(_) => Err(StorageError::Cancelled.into()),
+        }
+    }
+
+    /// Add a new channel to the server.
+    ///
+    /// It is the server administrator's responsibility to ensure that each channel has a unique ID.
+    /// Channels may have duplicate names.
+    ///
+    /// # Errors
+    /// Returns [`ChannelError`] if a called with an ID that is already present.
+    pub async fn add_channel(
+        &self,
+        id: ChannelId,
+        name: String,
+        event_tx: broadcast::Sender<NetworkEvent>,
+    ) -> Result<(), ChannelError> {
+        let channel_info = ChannelInfo { id, name };
+
+        let channel = Channel {
+            info: channel_info,
+            broadcast: event_tx,
+            muted: std::collections::HashMap::new(),
+        };
+
+        self.channels
+            .insert_async(id, channel)
+            .await
+            .map_err(|_| ChannelError::AlreadyExists(id))?;
+
+        self.reserve_channel_id(id);
+        Ok(())
+    }
+
+    /// Ensure channels created later never get the given ID, or any ID below it.
+    pub fn reserve_channel_id(&self, id: ChannelId) {
+        self.next_channel_id
+            .fetch_max(u64::from(id).saturating_add(1), Ordering::Relaxed);
+    }
+
+    /// Create a new channel with a server-assigned ID on behalf of `by`, then announce it to every
+    /// user. `None` stands for the server's administrators.
+    ///
+    /// # Errors
+    /// * [`ChannelError::Moderation`] if `by` is not a moderator.
+    /// * [`ChannelError::Name`] if the name is invalid.
+    pub async fn create_channel(
+        &self,
+        mut name: String,
+        by: Option<&UserToken>,
+    ) -> Result<ChannelInfo, ChannelError> {
+        self.check_is_moderator(by).await?;
+
+        name.fast_trim();
+        Self::validate_channel_name(&name, self.settings.borrow().max_channel_name_length)?;
+
+        let id = ChannelId::try_from(self.next_channel_id.fetch_add(1, Ordering::Relaxed))
+            .expect("ChannelId conversion from u64 is infallible");
+        let channel_info = ChannelInfo { id, name };
+
+        self.add_new_channel(&channel_info).await?;
+        Ok(channel_info)
+    }
+
+    /// Persist a channel, add it to the server, then announce it to every user. A deleted channel
+    /// with the same ID is brought back, without its history.
+    ///
+    /// # Errors
+    /// * [`ChannelError::AlreadyExists`] if a channel with the same ID is already on the server.
+    /// * [`ChannelError::Storage`] if the channel could not be persisted.
+    pub async fn add_new_channel(&self, channel_info: &ChannelInfo) -> Result<(), ChannelError> {
+        if self.channels.contains_async(&channel_info.id).await {
+            return Err(ChannelError::AlreadyExists(channel_info.id));
+        }
+
+        let (id, name) = (channel_info.id, channel_info.name.clone());
+        self.with_channels(move |store| store.save_channel(id, &name))
+            .await?;
+
+        let (tx, _rx) = broadcast::channel(self.settings.borrow().event_queues.channel_capacity);
+        self.add_channel(channel_info.id, channel_info.name.clone(), tx)
+            .await?;
+
+        self.send_global_event(NetworkEvent::ChannelAdded(channel_info.clone()));
+        Ok(())
+    }
+
+    /// Change a channel's display name on behalf of `by`, then announce the change to every user.
+    /// `None` stands for the server's administrators and its configuration.
+    ///
+    /// # Errors
+    /// * [`ChannelError::Moderation`] if `by` is not a moderator.
+    /// * [`ChannelError::Name`] if the new name is invalid.
+    /// * [`ChannelError::DoesNotExist`] if the channel ID was not found.
+    pub async fn rename_channel(
+        &self,
+        id: ChannelId,
+        mut new_name: String,
+        by: Option<&UserToken>,
+    ) -> Result<ChannelInfo, ChannelError> {
+        self.check_is_moderator(by).await?;
+
+        new_name.fast_trim();
+        Self::validate_channel_name(&new_name, self.settings.borrow().max_channel_name_length)?;
+
+        if !self.channels.contains_async(&id).await {
+            return Err(ChannelError::DoesNotExist(id));
+        }
+
+        let name = new_name.clone();
+        self.with_channels(move |store| {
+            // Channels the store doesn't know yet are saved under their new name.
+            if !store.rename_channel(id, &name)? {
+                store.save_channel(id, &name)?;
+            }
+            Ok(())
+        })
+        .await?;
+
+        let channel_info = self
+            .channels
+            .update_async(&id, |_, channel| {
+                channel.info.name = new_name;
+                channel.info.clone()
+            })
+            .await
+            .ok_or(ChannelError::DoesNotExist(id))?;
+
+        self.send_global_event(NetworkEvent::ChannelChangedName(channel_info.clone()));
+
+        Ok(channel_info)
+    }
+
+    /// Remove a channel from the server on behalf of `by`, then announce the removal to every user.
+    /// `None` stands for the server's administrators and its configuration.
+    ///
+    /// Dropping the channel's broadcast sender closes every subscription to it, so connections stop
+    /// listening to the channel on their own. The channel's history is deleted, and its ID is never
+    /// reused.
+    ///
+    /// # Errors
+    /// * [`ChannelError::Moderation`] if `by` is not a moderator.
+    /// * [`ChannelError::IsDefault`] if the channel is the default channel.
+    /// * [`ChannelError::DoesNotExist`] if the channel ID was not found.
+    pub async fn remove_channel(
+        &self,
+        id: ChannelId,
+        by: Option<&UserToken>,
+    ) -> Result<ChannelInfo, ChannelError> {
+        self.check_is_moderator(by).await?;
+
+        if self.default_channel_id == Some(id) {
+            return Err(ChannelError::IsDefault);
+        }
+
+        if !self.channels.contains_async(&id).await {
+            return Err(ChannelError::DoesNotExist(id));
+        }
+
+        self.with_channels(move |store| {
+            // A channel the store doesn't know yet is saved first, so it stays deleted.
+            if !store.delete_channel(id)? {
+                store.save_channel(id, "")?;
+                store.delete_channel(id)?;
+            }
+            Ok(())
+        })
+        .await?;
+
+        let (_, channel) = self
+            .channels
+            .remove_async(&id)
+            .await
+            .ok_or(ChannelError::DoesNotExist(id))?;
+
+        self.users
+            .retain_async(|_, user| {
+                user.joined_channels.remove(&id);
+                true
+            })
+            .await;
+
+        self.send_global_event(NetworkEvent::ChannelRemoved(id));
+
+        Ok(channel.info)
+    }
+
+    /// Add a user to a channel's members, then tell the channel's members about it. Joining a
+    /// channel you already joined is allowed, and only resends the member list.
+    ///
+    /// Returns a subscription to the channel, and the channel's full member list.
+    ///
+    /// # Errors
+    /// * [`ChannelError::DoesNotExist`] if the channel ID was not found.
+    /// * [`ChannelError::YourIdNotFound`] if the user is no longer known to the server.
+    pub async fn join_channel(
+        &self,
+        token: &UserToken,
+        id: ChannelId,
+    ) -> Result<(broadcast::Receiver<NetworkEvent>, ChannelMembers), ChannelError> {
+        // We subscribe before announcing the join, so the new member hears everything after it.
+        let rx = self
+            .channels
+            .read_async(&id, |_, channel| channel.broadcast.subscribe())
+            .await
+            .ok_or(ChannelError::DoesNotExist(id))?;
+
+        let newly_joined = self
+            .users
+            .update_async(&token.id(), |_, user| user.joined_channels.insert(id))
+            .await
+            .ok_or(ChannelError::YourIdNotFound)?;
+
+        if newly_joined {
+            let member = ChannelMember {
+                channel_id: id,
+                user_id: token.id(),
+            };
+
+            // The channel may have been removed in the meantime, in which case nobody is left to
+            // tell.
+            let _: Result<_, _> = self
+                .send_event_to_channel(id, NetworkEvent::ChannelMemberJoined(member))
+                .await;
+        }
+
+        let members = ChannelMembers {
+            channel_id: id,
+            members: self.get_channel_members(id).await,
+        };
+
+        Ok((rx, members))
+    }
+
+    /// Remove a user from a channel's members, then tell the remaining members about it.
+    ///
+    /// # Errors
+    /// * [`ChannelError::DoesNotExist`] if the channel ID was not found.
+    /// * [`ChannelError::NotMember`] if the user did not join the channel.
+    /// * [`ChannelError::YourIdNotFound`] if the user is no longer known to the server.
+    pub async fn leave_channel(
+        &self,
+        token: &UserToken,
+        id: ChannelId,
+    ) -> Result<(), ChannelError> {
+        if !self.channels.contains_async(&id).await {
+            return Err(ChannelError::DoesNotExist(id));
+        }
+
+        let was_member = self
+            .users
+            .update_async(&token.id(), |_, user| user.joined_channels.remove(&id))
+            .await
+            .ok_or(ChannelError::YourIdNotFound)?;
+
+        if !was_member {
+            return Err(ChannelError::NotMember(id));
+        }
+
+        let member = ChannelMember {
+            channel_id: id,
+            user_id: token.id(),
+        };
+
+        let _: Result<_, _> = self
+            .send_event_to_channel(id, NetworkEvent::ChannelMemberLeft(member))
+            .await;
+
+        Ok(())
+    }
+
+    /// Get the IDs of every user that joined a channel.
+    pub async fn get_channel_members(&self, id: ChannelId) -> Vec<UserId> {
+        let mut res = Vec::new();
+
+        self.users
+            .iter_async(|user_id, user| {
+                if user.joined_channels.contains(&id) {
+                    res.push(*user_id);
+                }
+                true
+            })
+            .await;
+
+        res
+    }
+
+    /// Get the IDs of every channel a user joined. If the user is not known, returns an empty
+    /// [`Vec`].
+    pub async fn get_joined_channels(&self, token: &UserToken) -> Vec<ChannelId> {
+        let joined = self
+            .users
+            .read_async(&token.id(), |_, user| user.joined_channels.clone())
+            .await
+            .unwrap_or_default();
+
+        // A channel removed while the user was joining it may linger in their set.
+        let mut res = Vec::with_capacity(joined.len());
+        for id in joined {
+            if self.channels.contains_async(&id).await {
+                res.push(id);
+            }
+        }
+
+        res
+    }
+
+    /// Get a user's [`UserInfo`] by their ID, if the ID is associated with a user on the server.
+    #[expect(dead_code)]
+    pub async fn get_user_info(&self, id: UserId) -> Option<UserInfo> {
+        self.users
+            .read_async(&id, |_, value| value.info.clone())
+            .await
+    }
+
+    /// Get the [`UserInfo`] of every user on the server. If there are no users, returns an empty
+    /// [`Vec`].
+    pub async fn get_all_user_info(&self) -> Vec<UserInfo> {
+        let mut res = Vec::with_capacity(self.users.len());
+
+        self.users
+            .iter_async(|_, value| {
+                res.push(value.info.clone());
+                true
+            })
+            .await;
+
+        res
+    }
+
+    /// Get the [`UserInfo`] of every user on the server, along with the address of the connection
+    /// they are on. Users held for session resumption have no address.
+    pub async fn get_all_user_connections(&self) -> Vec<(UserInfo, Option<ClientAddr>)> {
+        let mut res = Vec::with_capacity(self.users.len());
+
+        self.users
+            .iter_async(|_, value| {
+                res.push((value.info.clone(), value.addr));
+                true
+            })
+            .await;
+
+        res
+    }
+
+    /// Send a [`NetworkEvent`] to a client with the given ID, if that ID is associated with a user
+    /// on the server.
+    ///
+    /// # Errors
+    /// Returns [`UserError::TargetNotFound`] if the target ID was not found.
+    pub async fn send_event_to_user(
+        &self,
+        target_id: UserId,
+        event: NetworkEvent,
+    ) -> Result<(), UserError> {
+        let sender = self
+            .users
+            .read_async(&target_id, |_, value| value.sender.clone())
+            .await
+            .ok_or(UserError::TargetNotFound(target_id))?;
+
+        // The only failure condition for sending through this channel is if the channel is closed,
+        // but that can only happen if the target user disconnected, which we don't really care
+        // about. As such, we ignore this Result.
+        if self.detached_users.contains_async(&target_id).await {
+            // Nobody drains a detached user's queue, so waiting for room could block forever.
+            // Events that don't fit are dropped.
+            let _: Result<_, _> = sender.try_send(event);
+        } else {
+            let _: Result<_, _> = sender.send(event).await;
+        }
+        Ok(())
+    }
+
+    /// Register a new user on the server, authenticating them as requested. This will:
+    /// 1. Ensure the user may connect the way they asked to. Guests must be allowed by the server,
+    ///    new accounts need a valid name and a long enough password, and logins must match an
+    ///    existing account's password.
+    /// 2. Ensure the name is not already in use (case-insensitive), either by a connected user or
+    ///    by an account.
+    /// 3. Register the name, and the new account if one was requested.
+    ///
+    /// Guests get a new [`UserId`] every time, while account users always get their account's ID.
+    /// `certificate` is the identity in the client's verified TLS certificate, if it presented one.
+    ///
+    /// # Errors
+    /// Returns a [`UserError`] if authentication or name registration fails.
+    pub async fn handle_new_user(
+        &self,
+        authentication: Authentication,
+        certificate: Option<CertificateIdentity>,
+        max_username_length: usize,
+        event_tx: mpsc::Sender<NetworkEvent>,
+    ) -> Result<UserToken, UserError> {
+        let account = match authentication {
+            Authentication::Guest { requested_name } => {
+                let name = self
+                    .admit_guest(requested_name, max_username_length)
+                    .await?;
+                let user_info = UserInfo {
+                    id: UserId(uuid::Uuid::now_v7()),
+                    name,
+                    role: Role::Member,
+                };
+                return self.add_user(user_info, false, event_tx).await;
+            }
+
+            Authentication::Register(credentials) => {
+                self.register_account(credentials, max_username_length)
+                    .await?
+            }
+
+            Authentication::Login(credentials) => self.log_in(credentials).await?,
+
+            Authentication::Certificate => {
+                let certificate = certificate.ok_or(UserError::NoCertificate)?;
+                self.log_in_with_certificate(certificate, max_username_length)
+                    .await?
+            }
+        };
+
+        // The account's name is already claimed at this point, so it must be released again.
+        if let Some(ban) = self.ban_on(BanTarget::Account(account.id)).await {
+            self.release_name(&account.username).await;
+            return Err(UserError::Banned(ban));
+        }
+
+        let user_info = UserInfo {
+            id: account.id,
+            name: account.username,
+            role: account.role,
+        };
+        self.add_user(user_info, true, event_tx).await
+    }
+
+    /// Add an authenticated user whose name was claimed to the server, then announce them.
+    async fn add_user(
+        &self,
+        user_info: UserInfo,
+        registered: bool,
+        event_tx: mpsc::Sender<NetworkEvent>,
+    ) -> Result<UserToken, UserError> {
+        let user_id = user_info.id;
+
+        let user = User {
+            info: user_info.clone(),
+            sender: event_tx,
+            joined_channels: std::collections::HashSet::new(),
+            registered,
+            resume_token: None,
+            addr: None,
+            kick_token: CancellationToken::new(),
+            removal: None,
+        };
+
+        // For guests, this would indicate a UUID collision, which we can assume to be impossible.
+        // For accounts, the name check in `log_in` already rejects a second login, unless the
+        // account was renamed in the middle of it.
+        if self.users.insert_async(user_id, user).await.is_err() {
+            self.release_name(&user_info.name).await;
+            return Err(UserError::AlreadyLoggedIn);
+        }
+
+        self.send_global_event(NetworkEvent::UserJoined(user_info));
+
+        Ok(UserToken(user_id))
+    }
+
+    /// Issue a new resume token for a user, replacing their old one. Returns `None` if session
+    /// resumption is disabled.
+    ///
+    /// # Errors
+    /// Returns [`UserError::YourIdNotFound`] if the user is no longer on the server.
+    pub async fn issue_resume_token(
+        &self,
+        token: &UserToken,
+    ) -> Result<Option<ResumeToken>, UserError> {
+        if self.settings.borrow().resume_grace_period.is_zero() {
+            return Ok(None);
+        }
+
+        let mut bytes = vec![0; RESUME_TOKEN_LENGTH];
+        OsRng.fill_bytes(&mut bytes);
+        let resume_token = ResumeToken(bytes);
+
+        let old_token = self
+            .users
+            .update_async(&token.id(), |_, user| {
+                user.resume_token.replace(resume_token.clone())
+            })
+            .await
+            .ok_or(UserError::YourIdNotFound)?;
+
+        // Random tokens this long never collide in practice.
+        let _: Result<_, _> = self
+            .resume_tokens
+            .insert_async(resume_token.clone(), token.id())
+            .await;
+
+        if let Some(old_token) = old_token {
+            let _: Option<_> = self.resume_tokens.remove_async(&old_token).await;
+        }
+
+        Ok(Some(resume_token))
+    }
+
+    /// Hold a user whose connection dropped, instead of removing them, so they can resume their
+    /// session. Events sent to the user queue up in `event_rx` in the meantime.
+    ///
+    /// Returns the detachment's ID, to pass to [`Self::expire_detached_user`] once the grace period
+    /// ends. Returns `None` if the user can't resume, because they were never given a resume token.
+    pub async fn detach_user(
+        &self,
+        token: &UserToken,
+        event_rx: mpsc::Receiver<NetworkEvent>,
+    ) -> Option<u64> {
+        let resumable = self
+            .users
+            .update_async(&token.id(), |_, user| {
+                user.addr = None;
+                user.resume_token.is_some()
+            })
+            .await
+            .unwrap_or(false);
+
+        if !resumable {
+            return None;
+        }
+
+        let detachment = self.next_detachment.fetch_add(1, Ordering::Relaxed);
+
+        // A user can only detach from a live connection, so they can't already be detached.
+        let _: Result<_, _> = self
+            .detached_users
+            .insert_async(
+                token.id(),
+                DetachedUser {
+                    event_rx,
+                    detachment,
+                },
+            )
+            .await;
+
+        Some(detachment)
+    }
+
+    /// Note the address of the connection a user is on, and get the token that is cancelled when
+    /// the user is kicked. Returns `None` if the user is no longer known to the server.
+    pub async fn attach_connection(
+        &self,
+        token: &UserToken,
+        addr: ClientAddr,
+    ) -> Option<CancellationToken> {
+        self.users
+            .update_async(&token.id(), |_, user| {
+                user.addr = Some(addr);
+                user.kick_token.clone()
+            })
+            .await
+    }
+
+    /// Take the event explaining why a user was removed from the server, if they were kicked or
+    /// banned.
+    pub async fn take_removal(&self, token: &UserToken) -> Option<NetworkEvent> {
+        self.users
+            .update_async(&token.id(), |_, user| user.removal.take())
+            .await
+            .flatten()
+    }
+
+    /// Kick a user off the server. `by` is the moderator doing it, or `None` for the server's
+    /// administrators, who may kick anyone.
+    ///
+    /// # Errors
+    /// * [`ModerationError::TargetNotFound`] if the ID was not found.
+    /// * [`ModerationError::NotModerator`] or [`ModerationError::Outranked`] if `by` may not kick
+    ///   the user.
+    pub async fn kick_user(
+        &self,
+        id: UserId,
+        by: Option<&UserToken>,
+        reason: String,
+    ) -> Result<(), ModerationError> {
+        self.check_moderator(by, id).await?;
+
+        self.remove_from_server(
+            id,
+            NetworkEvent::UserKicked(UserKicked {
+                user_id: id,
+                by: by.map(UserToken::id),
+                reason,
+            }),
+        )
+        .await
+    }
+
+    /// Mute a user in a channel, for `duration` or until they're unmuted. `by` is the moderator
+    /// doing it, or `None` for the server's administrators.
+    ///
+    /// # Errors
+    /// * [`ModerationError::TargetNotFound`] or [`ModerationError::ChannelNotFound`] if either ID
+    ///   was not found.
+    /// * [`ModerationError::NotModerator`] or [`ModerationError::Outranked`] if `by` may not mute
+    ///   the user.
+    pub async fn mute_user(
+        &self,
+        channel_id: ChannelId,
+        id: UserId,
+        by: Option<&UserToken>,
+        duration: Option<Duration>,
+    ) -> Result<(), ModerationError> {
+        self.check_moderator(by, id).await?;
+
+        // Mutes too long to represent don't end, just like mutes without a duration.
+        let until = duration.and_then(|duration| SystemTime::now().checked_add(duration));
+
+        self.channels
+            .update_async(&channel_id, |_, channel| channel.muted.insert(id, until))
+            .await
+            .ok_or(ModerationError::ChannelNotFound(channel_id))?;
+
+        Ok(())
+    }
+
+    /// Lift a user's mute in a channel. Unmuting a user who isn't muted does nothing.
+    ///
+    /// # Errors
+    /// Same as [`Self::mute_user`].
+    pub async fn unmute_user(
+        &self,
+        channel_id: ChannelId,
+        id: UserId,
+        by: Option<&UserToken>,
+    ) -> Result<(), ModerationError> {
+        self.check_moderator(by, id).await?;
+
+        self.channels
+            .update_async(&channel_id, |_, channel| channel.muted.remove(&id))
+            .await
+            .ok_or(ModerationError::ChannelNotFound(channel_id))?;
+
+        Ok(())
+    }
+
+    /// Ban a user, either by their account or by the address they're connected from, for
+    /// `duration` or until they're unbanned. `by` is the moderator doing it, or `None` for the
+    /// server's administrators. Returns the ban that was added.
+    ///
+    /// Banning an address removes every user connected from it, not just the given one.
+    ///
+    /// # Errors
+    /// * [`ModerationError::TargetNotFound`] if the ID was not found.
+    /// * [`ModerationError::NotModerator`] or [`ModerationError::Outranked`] if `by` may not ban
+    ///   the user.
+    /// * [`ModerationError::GuestBan`] if banning a guest by account.
+    /// * [`ModerationError::NoAddress`] if banning a user held for session resumption by address.
+    /// * [`ModerationError::LocalConnection`] if banning a user on a local socket by address.
+    /// * [`ModerationError::Storage`] if the ban could not be persisted.
+    pub async fn ban_user(
+        &self,
+        id: UserId,
+        by: Option<&UserToken>,
+        reason: String,
+        duration: Option<Duration>,
+        by_address: bool,
+    ) -> Result<Ban, ModerationError> {
+        self.check_moderator(by, id).await?;
+
+        let (registered, addr) = self
+            .users
+            .read_async(&id, |_, user| (user.registered, user.addr))
+            .await
+            .ok_or(ModerationError::TargetNotFound(id))?;
+
+        let target = if by_address {
+            let addr = addr.ok_or(ModerationError::NoAddress)?;
+            BanTarget::address(addr.ip().ok_or(ModerationError::LocalConnection)?)
+        } else if registered {
+            BanTarget::Account(id)
+        } else {
+            return Err(ModerationError::GuestBan);
+        };
+
+        let ban = Self::new_ban(target, reason, by.map(UserToken::id), duration);
+        self.add_ban(ban.clone()).await?;
+
+        Ok(ban)
+    }
+
+    /// Ban an address, whether or not anyone is connected from it, for `duration` or until it's
+    /// unbanned. Returns the ban that was added.
+    ///
+    /// # Errors
+    /// Returns [`StorageError`] if the ban could not be persisted.
+    pub async fn ban_address(
+        &self,
+        ip: IpAddr,
+        reason: String,
+        duration: Option<Duration>,
+    ) -> Result<Ban, StorageError> {
+        let ban = Self::new_ban(BanTarget::address(ip), reason, None, duration);
+        self.add_ban(ban.clone()).await?;
+
+        Ok(ban)
+    }
+
+    /// Lift the ban on a target. Returns whether it was banned.
+    ///
+    /// # Errors
+    /// Returns [`StorageError`] if the ban could not be removed from storage.
+    pub async fn unban(&self, target: BanTarget) -> Result<bool, StorageError> {
+        let stored = self
+            .with_bans(move |store| store.remove_ban(target))
+            .await?;
+        let cached = self.bans.remove_async(&target).await.is_some();
+
+        Ok(stored || cached)
+    }
+
+    /// Add the channels read from the channel store when the server starts, without announcing
+    /// them. Deleted channels are skipped, but no channel created later gets their IDs.
+    ///
+    /// # Errors
+    /// Returns [`ChannelError::AlreadyExists`] if two channels have the same ID.
+    pub async fn restore_channels(&self, channels: Vec<StoredChannel>) -> Result<(), ChannelError> {
+        for channel in channels {
+            self.reserve_channel_id(channel.id);
+
+            if channel.deleted {
+                continue;
+            }
+
+            let (tx, _rx) =
+                broadcast::channel(self.settings.borrow().event_queues.channel_capacity);
+            self.add_channel(channel.id, channel.name, tx).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Persist the channel list from the config, to compare against when the server next starts.
+    ///
+    /// # Errors
+    /// Returns [`StorageError`] if the list could not be persisted.
+    pub async fn save_configured_channels(
+        &self,
+        channels: Vec<ChannelInfo>,
+    ) -> Result<(), StorageError> {
+        self.with_channels(move |store| store.set_configured_channels(&channels))
+            .await
+    }
+
+    /// Cache bans read from the ban store when the server starts. Bans that ended are skipped.
+    pub async fn restore_bans(&self, bans: Vec<Ban>) {
+        let now = SystemTime::now();
+
+        for ban in bans.into_iter().filter(|ban| !ban.is_expired(now)) {
+            self.bans.upsert_async(ban.target, ban).await;
+        }
+    }
+
+    /// Get every ban that hasn't ended.
+    pub async fn list_bans(&self) -> Vec<Ban> {
+        let now = SystemTime::now();
+        let mut res = Vec::with_capacity(self.bans.len());
+
+        self.bans
+            .iter_async(|_, ban| {
+                if !ban.is_expired(now) {
+                    res.push(ban.clone());
+                }
+                true
+            })
+            .await;
+
+        res
+    }
+
+    /// Get the ban on an address, if it's banned.
+    pub async fn address_ban(&self, ip: IpAddr) -> Option<Ban> {
+        self.ban_on(BanTarget::address(ip)).await
+    }
+
+    /// Get the ban on a target, if it's banned. A ban that ended is forgotten here.
+    async fn ban_on(&self, target: BanTarget) -> Option<Ban> {
+        let ban = self.bans.read_async(&target, |_, ban| ban.clone()).await?;
+
+        if ban.is_expired(SystemTime::now()) {
+            let _: Option<_> = self
+                .bans
+                .remove_if_async(&target, |cached| cached.is_expired(SystemTime::now()))
+                .await;
+            return None;
+        }
+
+        Some(ban)
+    }
+
+    /// Create a ban on a target, starting now.
+    fn new_ban(
+        target: BanTarget,
+        reason: String,
+        banned_by: Option<UserId>,
+        duration: Option<Duration>,
+    ) -> Ban {
+        let created_at = SystemTime::now();
+
+        Ban {
+            target,
+            reason,
+            banned_by,
+            created_at,
+            // Bans too long to represent don't end, just like bans without a duration.
+            expires_at: duration.and_then(|duration| created_at.checked_add(duration)),
+        }
+    }
+
+    /// Persist a ban and cache it, then remove every user it applies to from the server.
+    async fn add_ban(&self, ban: Ban) -> Result<(), StorageError> {
+        let to_store = ban.clone();
+        self.with_bans(move |store| store.add_ban(&to_store))
+            .await?;
+        self.bans.upsert_async(ban.target, ban.clone()).await;
+
+        let mut banned_users = Vec::new();
+        self.users
+            .iter_async(|id, user| {
+                let applies = match ban.target {
+                    BanTarget::Account(account) => user.registered && *id == account,
+                    BanTarget::Address(ip) => {
+                        user.addr
+                            .and_then(ClientAddr::ip)
+                            .map(|addr| addr.to_canonical())
+                            == Some(ip)
+                    }
+                };
+
+                if applies {
+                    banned_users.push(*id);
+                }
+                true
+            })
+            .await;
+
+        for id in banned_users {
+            let event = NetworkEvent::UserBanned(UserBanned {
+                user_id: id,
+                by: ban.banned_by,
+                reason: ban.reason.clone(),
+                expires_at: ban.expires_at,
+            });
+
+            // The user may have left in the meantime, which is fine.
+            let _: Result<_, _> = self.remove_from_server(id, event).await;
+        }
+
+        Ok(())
+    }
+
+    /// Remove a user from the server and tell everyone why, with `event`. A connected user's
+    /// connection passes the event on and closes, and a user held for session resumption is
+    /// removed right away.
+    async fn remove_from_server(
+        &self,
+        id: UserId,
+        event: NetworkEvent,
+    ) -> Result<(), ModerationError> {
+        let kick_token = self
+            .users
+            .update_async(&id, |_, user| {
+                user.removal = Some(event.clone());
+                user.kick_token.clone()
+            })
+            .await
+            .ok_or(ModerationError::TargetNotFound(id))?;
+
+        self.send_global_event(event);
+
+        // Nobody is watching a detached user's token, so they're removed here instead.
+        if !self.remove_detached_user(id, None).await {
+            kick_token.cancel();
+        }
+
+        Ok(())
+    }
+
+    /// Check that `by` is a moderator or owner, and return their role. `None` stands for the
+    /// server's administrators and its configuration, who may do anything, so no role is returned.
+    async fn check_is_moderator(
+        &self,
+        by: Option<&UserToken>,
+    ) -> Result<Option<Role>, ModerationError> {
+        let Some(by) = by else {
+            return Ok(None);
+        };
+
+        let role = self
+            .users
+            .read_async(&by.id(), |_, user| user.info.role)
+            .await
+            .ok_or(ModerationError::YourIdNotFound)?;
+
+        if role < Role::Moderator {
+            return Err(ModerationError::NotModerator);
+        }
+
+        Ok(Some(role))
+    }
+
+    /// Check that `by` may moderate the user with the given ID. Only moderators and owners may
+    /// moderate users, and only users with a lower role than theirs. `None` stands for the
+    /// server's administrators, who may moderate anyone.
+    async fn check_moderator(
+        &self,
+        by: Option<&UserToken>,
+        id: UserId,
+    ) -> Result<(), ModerationError> {
+        let target_role = self
+            .users
+            .read_async(&id, |_, user| user.info.role)
+            .await
+            .ok_or(ModerationError::TargetNotFound(id))?;
+
+        let Some(role) = self.check_is_moderator(by).await? else {
+            return Ok(());
+        };
+
+        if !role.can_moderate(target_role) {
+            return Err(ModerationError::Outranked);
+        }
+
+        Ok(())
+    }
+
+    /// Change the role of an account, then announce the change if its user is on the server.
+    ///
+    /// # Errors
+    /// * [`ModerationError::GuestRole`] if the ID belongs to a guest.
+    /// * [`ModerationError::TargetNotFound`] if the ID belongs to no account.
+    /// * [`ModerationError::Storage`] if the role could not be persisted.
+    pub async fn set_role(&self, id: UserId, role: Role) -> Result<(), ModerationError> {
+        let store = self.account_store.clone();
+        if !Self::spawn_blocking(move || store.set_role(id, role)).await? {
+            return Err(if self.users.contains_async(&id).await {
+                ModerationError::GuestRole
+            } else {
+                ModerationError::TargetNotFound(id)
+            });
+        }
+
+        if let Some(info) = self
+            .users
+            .update_async(&id, |_, user| {
+                user.info.role = role;
+                user.info.clone()
+            })
+            .await
+        {
+            self.send_global_event(NetworkEvent::UserInfoUpdated(info));
+        }
+
+        Ok(())
+    }
+
+    /// Find the ID of the account with the given name, ignoring case, whether or not anyone is
+    /// logged into it.
+    ///
+    /// # Errors
+    /// Returns [`StorageError`] if the account store could not be read.
+    pub async fn find_account(&self, name: &str) -> Result<Option<UserId>, StorageError> {
+        let normalized = Self::normalize_username(name);
+        let store = self.account_store.clone();
+
+        Self::spawn_blocking(move || store.account_by_name(&normalized))
+            .await
+            .map(|account| account.map(|account| account.id))
+    }
+
+    /// Resume the session of the detached user a resume token belongs to, handing back their
+    /// [`UserToken`] and the events queued for them. Returns `None` if the token is unknown, or its
+    /// user is still connected.
+    pub async fn resume_user(
+        &self,
+        resume_token: &ResumeToken,
+    ) -> Option<(UserToken, mpsc::Receiver<NetworkEvent>)> {
+        let id = self
+            .resume_tokens
+            .read_async(resume_token, |_, id| *id)
+            .await?;
+        let (_, detached) = self.detached_users.remove_async(&id).await?;
+
+        Some((UserToken(id), detached.event_rx))
+    }
+
+    /// Remove a detached user whose grace period ended, and tell everyone they left. Does nothing
+    /// if the user resumed their session since the given detachment.
+    pub async fn expire_detached_user(&self, id: UserId, detachment: u64) {
+        self.remove_detached_user(id, Some(detachment)).await;
+    }
+
+    /// Remove a detached user and tell everyone they left. If `detachment` is given, only that
+    /// detachment is removed. Returns whether the user was removed.
+    async fn remove_detached_user(&self, id: UserId, detachment: Option<u64>) -> bool {
+        let detached = self
+            .detached_users
+            .remove_if_async(&id, |detached| {
+                detachment.is_none_or(|detachment| detached.detachment == detachment)
+            })
+            .await
+            .is_some();
+
+        if detached && self.remove_user(UserToken(id)).await.is_ok() {
+            self.send_global_event(NetworkEvent::UserLeft(id));
+            return true;
+        }
+
+        false
+    }
+
+    /// Validate and claim a guest's requested name. Guests may not use an account's name, even if
+    /// nobody is logged into that account.
+    async fn admit_guest(
+        &self,
+        mut name: String,
+        max_username_length: usize,
+    ) -> Result<String, UserError> {
+        if !self.settings.borrow().allow_guests {
+            return Err(UserError::GuestsNotAllowed);
+        }
+
+        name.fast_trim();
+        Self::validate_username(&name, max_username_length)?;
+        self.claim_name(&name).await?;
+
+        let normalized_name = Self::normalize_username(&name);
+        let owned_by_account = self
+            .with_accounts(move |store| Ok(store.account_by_name(&normalized_name)?.is_some()))
+            .await;
+
+        match owned_by_account {
+            Ok(false) => Ok(name),
+
+            Ok(true) => {
+                self.release_name(&name).await;
+                Err(UserError::Name(UserNameError::AlreadyTaken(name)))
+            }
+
+            Err(e) => {
+                self.release_name(&name).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Validate and claim a new account's name, then create the account.
+    async fn register_account(
+        &self,
+        credentials: Credentials,
+        max_username_length: usize,
+    ) -> Result<Account, UserError> {
+        let Credentials {
+            username: mut name,
+            password,
+        } = credentials;
+
+        name.fast_trim();
+        Self::validate_username(&name, max_username_length)?;
+
+        let min_password_length = self.settings.borrow().min_password_length;
+        if password.chars().count() < min_password_length {
+            return Err(UserError::PasswordTooShort(min_password_length));
+        }
+
+        self.claim_name(&name).await?;
+
+        let normalized_name = Self::normalize_username(&name);
+        let username = name.clone();
+        let created = self
+            .with_accounts(move |store| {
+                let account = Account {
+                    id: UserId(uuid::Uuid::now_v7()),
+                    username,
+                    password_hash: Some(Self::hash_password(&password)?),
+                    certificate_subject: None,
+                    created_at: SystemTime::now(),
+                    role: Role::Member,
+                };
+
+                Ok(store
+                    .create_account(&account, &normalized_name)?
+                    .then_some(account))
+            })
+            .await;
+
+        match created {
+            Ok(Some(account)) => Ok(account),
+
+            Ok(None) => {
+                self.release_name(&name).await;
+                Err(UserError::Name(UserNameError::AlreadyTaken(name)))
+            }
+
+            Err(e) => {
+                self.release_name(&name).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Check an account's password, then claim its name.
+    async fn log_in(&self, credentials: Credentials) -> Result<Account, UserError> {
+        let Credentials {
+            username: mut name,
+            password,
+        } = credentials;
+
+        name.fast_trim();
+        let normalized_name = Self::normalize_username(&name);
+
+        let account = self
+            .with_accounts(move |store| {
+                let account = store
+                    .account_by_name(&normalized_name)?
+                    .ok_or(UserError::AuthenticationFailed)?;
+
+                Self::verify_password(&password, account.password_hash.as_deref())?;
+                Ok(account)
+            })
+            .await?;
+
+        self.claim_account_name(&account).await?;
+        Ok(account)
+    }
+
+    /// Log into the account created from a client certificate, creating it first if this is the
+    /// first time the certificate is used. New accounts are named after the certificate's common
+    /// name, and have no password.
+    async fn log_in_with_certificate(
+        &self,
+        certificate: CertificateIdentity,
+        max_username_length: usize,
+    ) -> Result<Account, UserError> {
+        let subject = certificate.subject.clone();
+        let existing = self
+            .with_accounts(move |store| Ok(store.account_by_certificate(&subject)?))
+            .await?;
+
+        if let Some(account) = existing {
+            self.claim_account_name(&account).await?;
+            return Ok(account);
+        }
+
+        let CertificateIdentity {
+            subject,
+            common_name: name,
+        } = certificate;
+
+        Self::validate_username(&name, max_username_length)?;
+        self.claim_name(&name).await?;
+
+        let normalized_name = Self::normalize_username(&name);
+        let username = name.clone();
+        let created = self
+            .with_accounts(move |store| {
+                let account = Account {
+                    id: UserId(uuid::Uuid::now_v7()),
+                    username,
+                    password_hash: None,
+                    certificate_subject: Some(subject),
+                    created_at: SystemTime::now(),
+                    role: Role::Member,
+                };
+
+                Ok(store
+                    .create_account(&account, &normalized_name)?
+                    .then_some(account))
+            })
+            .await;
+
+        match created {
+            Ok(Some(account)) => Ok(account),
+
+            // Either another account already has the name, or the same certificate was used twice
+            // at once.
+            Ok(None) => {
+                self.release_name(&name).await;
+                Err(UserError::Name(UserNameError::AlreadyTaken(name)))
+            }
+
+            Err(e) => {
+                self.release_name(&name).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Claim the name of an account that is being logged into.
+    ///
+    /// # Errors
+    /// Returns [`UserError::AlreadyLoggedIn`] if the name is taken.
+    async fn claim_account_name(&self, account: &Account) -> Result<(), UserError> {
+        let normalized_name = Self::normalize_username(&account.username);
+
+        // Account names are unique among accounts, and guests can't use them, so the name can only
+        // be taken if someone is already logged into this account.
+        if self
+            .taken_names
+            .insert_async(normalized_name.clone())
+            .await
+            .is_ok()
+        {
+            return Ok(());
+        }
+
+        // If that someone's connection dropped, they are only held in case they resume. Logging in
+        // again takes over from them.
+        if self.remove_detached_user(account.id, None).await
+            && self.taken_names.insert_async(normalized_name).await.is_ok()
+        {
+            return Ok(());
+        }
+
+        Err(UserError::AlreadyLoggedIn)
+    }
+
+    /// Atomically claim a name in [`Self::taken_names`].
+    ///
+    /// # Errors
+    /// Returns [`UserNameError::AlreadyTaken`] if a connected user already has the name.
+    async fn claim_name(&self, name: &str) -> Result<(), UserError> {
+        if self
+            .taken_names
+            .insert_async(Self::normalize_username(name))
+            .await
+            .is_err()
+        {
+            return Err(UserError::Name(UserNameError::AlreadyTaken(
+                name.to_owned(),
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Release a name claimed with [`Self::claim_name`].
+    async fn release_name(&self, name: &str) {
+        // `None` would mean the name was never claimed, which we don't care about.
+        let _: Option<_> = self
+            .taken_names
+            .remove_async(&Self::normalize_username(name))
+            .await;
+    }
+
+    /// Check a new name against the account store. Account names are reserved even while nobody
+    /// is logged into them. Account users take their account's name along with them, renaming
+    /// the account, while guests may not use it at all. Returns `false` if the name is taken.
+    async fn check_account_name(
+        &self,
+        user_id: UserId,
+        registered: bool,
+        name: String,
+        normalized_name: String,
+    ) -> Result<bool, UserError> {
+        self.with_accounts(move |store| {
+            if registered {
+                return Ok(store.rename_account(user_id, &name, &normalized_name)?);
+            }
+
+            Ok(store.account_by_name(&normalized_name)?.is_none())
+        })
+        .await
+    }
+
+    /// Update a user's information with the given [`UpdateInfo`]. `Some` fields will be updated,
+    /// while `None` fields will be unmodified. The update operation is atomic - if any updates fail
+    /// (for example, if a username is invalid), the entire update will fail.
+    pub async fn update_user_info(
+        &self,
+        token: &UserToken,
+        new_info: UpdateInfo,
+        max_username_length: usize,
+    ) -> Result<(), UserError> {
+        /// Drop guard for name operations. To make operations on the `taken_names` set atomic, we
         /// need to mutate the set. However, that mutation makes the whole function non-atomic, as
         /// we touch persistent state between checkpoints. This patches that hole.
         #[derive(Debug)]
@@ -386,9 +2201,11 @@ impl ServerState {
             committed: false,
         };
 
-        let Some(mut proposed_user_info) = self
+        let Some((mut proposed_user_info, registered)) = self
             .users
-            .read_async(&token.id(), |_, value| value.info.clone())
+            .read_async(&token.id(), |_, value| {
+                (value.info.clone(), value.registered)
+            })
             .await
         else {
             return Err(UserError::YourIdNotFound);
@@ -397,6 +2214,9 @@ impl ServerState {
         // Name to remove from `taken_names` if we update the user's name
         let mut old_name_to_remove: Option<String> = None;
 
+        // The account's name before it was renamed in the store, to restore if the update fails
+        let mut renamed_account_from: Option<String> = None;
+
         if let Some(mut new_name) = new_info.name {
             new_name.fast_trim();
 
@@ -420,12 +2240,29 @@ impl ServerState {
                     return Err(UserError::Name(UserNameError::AlreadyTaken(new_name)));
                 }
 
-                drop_guard.added_name = Some(normalized_new_name);
+                drop_guard.added_name = Some(normalized_new_name.clone());
 
                 // We defer removal until we know the entire transaction succeded
                 old_name_to_remove = Some(normalized_old_name);
             }
 
+            let name_available = self
+                .check_account_name(
+                    token.id(),
+                    registered,
+                    new_name.clone(),
+                    normalized_new_name,
+                )
+                .await?;
+
+            if !name_available {
+                return Err(UserError::Name(UserNameError::AlreadyTaken(new_name)));
+            }
+
+            if registered {
+                renamed_account_from = Some(proposed_user_info.name.clone());
+            }
+
             new_name.clone_into(&mut proposed_user_info.name);
         }
 
@@ -456,6 +2293,14 @@ impl ServerState {
             self.send_global_event(NetworkEvent::UserInfoUpdated(proposed_user_info));
             Ok(())
         } else {
+            // The user left while their account was being renamed, so the rename never took
+            // effect. Give the account its old name back.
+            if let Some(old_name) = renamed_account_from {
+                let normalized_old_name = Self::normalize_username(&old_name);
+                self.check_account_name(token.id(), true, old_name, normalized_old_name)
+                    .await?;
+            }
+
             Err(UserError::YourIdNotFound)
         }
     }
@@ -467,12 +2312,41 @@ impl ServerState {
         };
 
         let normalized_name = Self::normalize_username(&user.info.name);
-        // We don't care about this state inconsistency since we're disconnecting anyways.
+        // We don't care about these state inconsistencies since we're disconnecting anyways.
         let _: Option<_> = self.taken_names.remove_async(&normalized_name).await;
 
+        if let Some(resume_token) = user.resume_token {
+            let _: Option<_> = self.resume_tokens.remove_async(&resume_token).await;
+        }
+
         Ok(())
     }
 
+    /// Hash a password for storage, with a random salt.
+    fn hash_password(password: &str) -> Result<String, password_hash::Error> {
+        let salt = SaltString::generate(&mut OsRng);
+
+        Ok(Argon2::default()
+            .hash_password(password.as_bytes(), &salt)?
+            .to_string())
+    }
+
+    /// Check a password against a hash produced by [`Self::hash_password`].
+    ///
+    /// # Errors
+    /// Returns [`UserError::AuthenticationFailed`] if the password does not match, or the account
+    /// has no password.
+    fn verify_password(password: &str, hash: Option<&str>) -> Result<(), UserError> {
+        let hash = PasswordHash::new(hash.ok_or(UserError::AuthenticationFailed)?)?;
+
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .map_err(|e| match e {
+                password_hash::Error::Password => UserError::AuthenticationFailed,
+                other => other.into(),
+            })
+    }
+
     /// Validate a username. Validation involves:
     /// * Ensuring it is not empty.
     /// * Ensuring it does not exceed the maximum length.
@@ -498,6 +2372,23 @@ impl ServerState {
         Ok(())
     }
 
+    /// Validate a channel name. Validation involves:
+    /// * Ensuring it is not empty.
+    /// * Ensuring it does not exceed the maximum length.
+    ///
+    /// Unlike usernames, channel names may contain any character, and may be duplicated.
+    fn validate_channel_name(name: &str, max_length: usize) -> Result<(), ChannelNameError> {
+        if name.is_empty() {
+            return Err(ChannelNameError::Empty);
+        }
+
+        if name.chars().count() > max_length {
+            return Err(ChannelNameError::TooLong(max_length));
+        }
+
+        Ok(())
+    }
+
     /// Normalize a username. This is useful to enforce that usernames aren't duplicated with
     /// inconsequential differences. As such, normalized usernames should be favored in
     /// [`Self::taken_names`].
@@ -0,0 +1,325 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Mutex, MutexGuard},
+    time::SystemTime,
+};
+
+use network_protocol::{ChannelId, ChannelInfo, MessageId, Role, UserId};
+
+use super::{
+    Account, AccountStore, Ban, BanStore, BanTarget, ChannelStore, Conversation, MessageStore,
+    StorageError, StoredChannel, StoredMessage,
+};
+
+/// A [`MessageStore`], [`AccountStore`], [`BanStore`] and [`ChannelStore`] that keeps everything
+/// in memory. Nothing survives a restart, so this is mostly useful for testing and for deployments
+/// that don't want history on disk.
+#[derive(Debug)]
+pub struct MemoryStore {
+    conversations: Mutex<HashMap<Conversation, Vec<StoredMessage>>>,
+
+    /// Accounts, keyed by normalized username.
+    accounts: Mutex<HashMap<String, Account>>,
+
+    bans: Mutex<HashMap<BanTarget, Ban>>,
+
+    channels: Mutex<BTreeMap<ChannelId, StoredChannel>>,
+
+    /// The channel list the config file last gave.
+    configured_channels: Mutex<Vec<ChannelInfo>>,
+}
+
+impl MemoryStore {
+    /// Create an empty `MemoryStore`.
+    pub fn new() -> Self {
+        Self {
+            conversations: Mutex::new(HashMap::new()),
+            accounts: Mutex::new(HashMap::new()),
+            bans: Mutex::new(HashMap::new()),
+            channels: Mutex::new(BTreeMap::new()),
+            configured_channels: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<Conversation, Vec<StoredMessage>>> {
+        self.conversations
+            .lock()
+            .expect("Message store mutex poisoned")
+    }
+
+    fn lock_accounts(&self) -> MutexGuard<'_, HashMap<String, Account>> {
+        self.accounts.lock().expect("Account store mutex poisoned")
+    }
+
+    fn lock_bans(&self) -> MutexGuard<'_, HashMap<BanTarget, Ban>> {
+        self.bans.lock().expect("Ban store mutex poisoned")
+    }
+
+    fn lock_channels(&self) -> MutexGuard<'_, BTreeMap<ChannelId, StoredChannel>> {
+        self.channels.lock().expect("Channel store mutex poisoned")
+    }
+
+    /// Find the conversation and index of a message by its ID.
+    fn locate(
+        conversations: &HashMap<Conversation, Vec<StoredMessage>>,
+        id: MessageId,
+    ) -> Option<(Conversation, usize)> {
+        conversations.iter().find_map(|(conversation, messages)| {
+            messages
+                .binary_search_by_key(&id, |message| message.id)
+                .ok()
+                .map(|index| (*conversation, index))
+        })
+    }
+}
+
+impl MessageStore for MemoryStore {
+    fn append(&self, message: &StoredMessage) -> Result<(), StorageError> {
+        let mut conversations = self.lock();
+        let messages = conversations.entry(message.conversation()).or_default();
+
+        // Concurrent senders may append slightly out of order, so we keep each list sorted by ID.
+        let index = messages.partition_point(|existing| existing.id < message.id);
+        messages.insert(index, message.clone());
+
+        Ok(())
+    }
+
+    fn history(
+        &self,
+        conversation: Conversation,
+        before: Option<MessageId>,
+        limit: usize,
+    ) -> Result<Vec<StoredMessage>, StorageError> {
+        let conversations = self.lock();
+
+        let Some(messages) = conversations.get(&conversation) else {
+            return Ok(Vec::new());
+        };
+
+        // Each list is sorted by ID, so everything before the cursor is a prefix.
+        let end = match before {
+            Some(before) => messages.partition_point(|message| message.id < before),
+            None => messages.len(),
+        };
+        let start = end.saturating_sub(limit);
+
+        Ok(messages[start..end].to_vec())
+    }
+
+    fn get(&self, id: MessageId) -> Result<Option<StoredMessage>, StorageError> {
+        let conversations = self.lock();
+
+        Ok(Self::locate(&conversations, id)
+            .map(|(conversation, index)| conversations[&conversation][index].clone()))
+    }
+
+    fn edit(
+        &self,
+        id: MessageId,
+        contents: &str,
+        edited_at: SystemTime,
+    ) -> Result<bool, StorageError> {
+        let mut conversations = self.lock();
+
+        let Some((conversation, index)) = Self::locate(&conversations, id) else {
+            return Ok(false);
+        };
+
+        let message = &mut conversations
+            .get_mut(&conversation)
+            .expect("We just located the message in this conversation")[index];
+        contents.clone_into(&mut message.contents);
+        message.edited_at = Some(edited_at);
+
+        Ok(true)
+    }
+
+    fn delete(&self, id: MessageId) -> Result<bool, StorageError> {
+        let mut conversations = self.lock();
+
+        let Some((conversation, index)) = Self::locate(&conversations, id) else {
+            return Ok(false);
+        };
+
+        conversations
+            .get_mut(&conversation)
+            .expect("We just located the message in this conversation")
+            .remove(index);
+
+        Ok(true)
+    }
+
+    fn max_channel_id(&self) -> Result<Option<ChannelId>, StorageError> {
+        Ok(self
+            .lock()
+            .keys()
+            .filter_map(|conversation| match conversation {
+                Conversation::Channel(id) => Some(*id),
+                Conversation::Direct(..) => None,
+            })
+            .max())
+    }
+}
+
+impl AccountStore for MemoryStore {
+    fn create_account(
+        &self,
+        account: &Account,
+        normalized_username: &str,
+    ) -> Result<bool, StorageError> {
+        let mut accounts = self.lock_accounts();
+
+        let subject_taken = account.certificate_subject.as_ref().is_some_and(|subject| {
+            accounts
+                .values()
+                .any(|existing| existing.certificate_subject.as_ref() == Some(subject))
+        });
+
+        if subject_taken || accounts.contains_key(normalized_username) {
+            return Ok(false);
+        }
+
+        accounts.insert(normalized_username.to_owned(), account.clone());
+        Ok(true)
+    }
+
+    fn account_by_name(&self, normalized_username: &str) -> Result<Option<Account>, StorageError> {
+        Ok(self.lock_accounts().get(normalized_username).cloned())
+    }
+
+    fn account_by_certificate(&self, subject: &str) -> Result<Option<Account>, StorageError> {
+        Ok(self
+            .lock_accounts()
+            .values()
+            .find(|account| account.certificate_subject.as_deref() == Some(subject))
+            .cloned())
+    }
+
+    fn rename_account(
+        &self,
+        id: UserId,
+        username: &str,
+        normalized_username: &str,
+    ) -> Result<bool, StorageError> {
+        let mut accounts = self.lock_accounts();
+
+        if accounts
+            .get(normalized_username)
+            .is_some_and(|account| account.id != id)
+        {
+            return Ok(false);
+        }
+
+        let Some(old_key) = accounts
+            .iter()
+            .find_map(|(key, account)| (account.id == id).then(|| key.clone()))
+        else {
+            return Ok(false);
+        };
+
+        let mut account = accounts
+            .remove(&old_key)
+            .expect("We just found the account under this key");
+        username.clone_into(&mut account.username);
+        accounts.insert(normalized_username.to_owned(), account);
+
+        Ok(true)
+    }
+
+    fn set_role(&self, id: UserId, role: Role) -> Result<bool, StorageError> {
+        let mut accounts = self.lock_accounts();
+
+        let Some(account) = accounts.values_mut().find(|account| account.id == id) else {
+            return Ok(false);
+        };
+
+        account.role = role;
+        Ok(true)
+    }
+}
+
+impl BanStore for MemoryStore {
+    fn add_ban(&self, ban: &Ban) -> Result<(), StorageError> {
+        self.lock_bans().insert(ban.target, ban.clone());
+        Ok(())
+    }
+
+    fn remove_ban(&self, target: BanTarget) -> Result<bool, StorageError> {
+        Ok(self.lock_bans().remove(&target).is_some())
+    }
+
+    fn remove_expired_bans(&self, now: SystemTime) -> Result<usize, StorageError> {
+        let mut bans = self.lock_bans();
+        let before = bans.len();
+        bans.retain(|_, ban| !ban.is_expired(now));
+
+        Ok(before - bans.len())
+    }
+
+    fn bans(&self) -> Result<Vec<Ban>, StorageError> {
+        Ok(self.lock_bans().values().cloned().collect())
+    }
+}
+
+impl ChannelStore for MemoryStore {
+    fn channels(&self) -> Result<Vec<StoredChannel>, StorageError> {
+        Ok(self.lock_channels().values().cloned().collect())
+    }
+
+    fn save_channel(&self, id: ChannelId, name: &str) -> Result<(), StorageError> {
+        self.lock_channels().insert(
+            id,
+            StoredChannel {
+                id,
+                name: name.to_owned(),
+                deleted: false,
+            },
+        );
+
+        Ok(())
+    }
+
+    fn rename_channel(&self, id: ChannelId, name: &str) -> Result<bool, StorageError> {
+        let mut channels = self.lock_channels();
+
+        let Some(channel) = channels.get_mut(&id).filter(|channel| !channel.deleted) else {
+            return Ok(false);
+        };
+
+        name.clone_into(&mut channel.name);
+        Ok(true)
+    }
+
+    fn delete_channel(&self, id: ChannelId) -> Result<bool, StorageError> {
+        let mut channels = self.lock_channels();
+
+        let Some(channel) = channels.get_mut(&id).filter(|channel| !channel.deleted) else {
+            return Ok(false);
+        };
+
+        channel.deleted = true;
+        self.lock().remove(&Conversation::Channel(id));
+
+        Ok(true)
+    }
+
+    fn configured_channels(&self) -> Result<Vec<ChannelInfo>, StorageError> {
+        Ok(self
+            .configured_channels
+            .lock()
+            .expect("Channel store mutex poisoned")
+            .clone())
+    }
+
+    fn set_configured_channels(&self, channels: &[ChannelInfo]) -> Result<(), StorageError> {
+        channels.clone_into(
+            &mut self
+                .configured_channels
+                .lock()
+                .expect("Channel store mutex poisoned"),
+        );
+
+        Ok(())
+    }
+}
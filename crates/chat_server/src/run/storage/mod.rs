@@ -0,0 +1,444 @@
+mod memory;
+mod sqlite;
+
+pub use memory::MemoryStore;
+pub use sqlite::SqliteStore;
+
+use std::{
+    fmt::{self, Debug, Display, Formatter},
+    io,
+    net::IpAddr,
+    sync::Arc,
+    time::SystemTime,
+};
+
+use network_protocol::{
+    ChannelId, ChannelInfo, MessageId, ReceiveDestination, ReceivedMessage, Role, UserId,
+};
+use serde::{Deserialize, Serialize};
+use shared_utils::files::TildeRelativePathBuf;
+use thiserror::Error;
+use tracing::debug;
+
+/// Which storage backend to use for message history, accounts and bans.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// Persist history in an `SQLite` database on disk.
+    Sqlite,
+
+    /// Keep history, accounts, bans and channels in memory. All are lost when the server shuts
+    /// down.
+    Memory,
+}
+
+/// Configuration for the message history, account, ban and channel storage.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StorageConfig {
+    /// Storage backend to use.
+    pub backend: StorageBackend,
+
+    /// Path to the database file, if the backend uses one.
+    pub path: TildeRelativePathBuf,
+}
+
+/// Error arising from a [`MessageStore`], an [`AccountStore`], a [`BanStore`] or a
+/// [`ChannelStore`].
+#[derive(Debug, Error)]
+pub enum StorageError {
+    /// The underlying database returned an error.
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+
+    /// An I/O error occurred while opening the store.
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    /// A stored record could not be converted back into its domain type.
+    #[error("corrupt record: {0}")]
+    Corrupt(String),
+
+    /// The blocking storage task was cancelled before it could run. This only happens while the
+    /// runtime is shutting down.
+    #[error("storage task was cancelled")]
+    Cancelled,
+}
+
+/// A conversation whose history is tracked by a [`MessageStore`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Conversation {
+    /// Messages sent to a channel.
+    Channel(ChannelId),
+
+    /// Direct messages between two users. Use [`Conversation::direct`] to construct this, so that
+    /// both participants' views of the conversation map to the same key.
+    Direct(UserId, UserId),
+}
+
+impl Conversation {
+    /// The direct conversation between two users, regardless of who sent what.
+    pub fn direct(a: UserId, b: UserId) -> Self {
+        if a.0 <= b.0 {
+            Self::Direct(a, b)
+        } else {
+            Self::Direct(b, a)
+        }
+    }
+}
+
+/// A chat message, as persisted by a [`MessageStore`].
+#[derive(Debug, Clone)]
+pub struct StoredMessage {
+    /// Server-assigned ID of the message.
+    pub id: MessageId,
+
+    /// The sender's user ID.
+    pub sender_id: UserId,
+
+    /// Where the message was sent to.
+    pub destination: ReceiveDestination,
+
+    /// The message's content.
+    pub contents: String,
+
+    /// Server time at which the message was received.
+    pub timestamp: SystemTime,
+
+    /// Server time at which the message was last edited, if it was ever edited.
+    pub edited_at: Option<SystemTime>,
+}
+
+impl StoredMessage {
+    /// The conversation this message belongs to.
+    pub fn conversation(&self) -> Conversation {
+        match self.destination {
+            ReceiveDestination::Channel(id) => Conversation::Channel(id),
+            ReceiveDestination::User(recipient) => Conversation::direct(self.sender_id, recipient),
+        }
+    }
+}
+
+impl From<StoredMessage> for ReceivedMessage {
+    fn from(value: StoredMessage) -> Self {
+        Self {
+            id: value.id,
+            timestamp: value.timestamp,
+            edited_at: value.edited_at,
+            contents: value.contents,
+            sender_id: value.sender_id,
+            destination: value.destination,
+        }
+    }
+}
+
+/// Persistent storage for message history.
+///
+/// Implementations are synchronous and may block, so they should only be called from blocking
+/// contexts (see [`tokio::task::spawn_blocking`]).
+pub trait MessageStore: Send + Sync + Debug {
+    /// Persist a new message.
+    ///
+    /// # Errors
+    /// Returns a [`StorageError`] if the message could not be persisted.
+    fn append(&self, message: &StoredMessage) -> Result<(), StorageError>;
+
+    /// Get up to `limit` messages from a conversation, in chronological order. If `before` is
+    /// given, only messages older than that message are returned; otherwise, the newest messages
+    /// are returned.
+    ///
+    /// # Errors
+    /// Returns a [`StorageError`] if the history could not be read.
+    fn history(
+        &self,
+        conversation: Conversation,
+        before: Option<MessageId>,
+        limit: usize,
+    ) -> Result<Vec<StoredMessage>, StorageError>;
+
+    /// Get a single message by its ID, if it exists.
+    ///
+    /// # Errors
+    /// Returns a [`StorageError`] if the message could not be read.
+    fn get(&self, id: MessageId) -> Result<Option<StoredMessage>, StorageError>;
+
+    /// Replace a message's contents. Returns `false` if no message with the given ID exists.
+    ///
+    /// # Errors
+    /// Returns a [`StorageError`] if the message could not be updated.
+    fn edit(
+        &self,
+        id: MessageId,
+        contents: &str,
+        edited_at: SystemTime,
+    ) -> Result<bool, StorageError>;
+
+    /// Delete a message. Returns `false` if no message with the given ID exists.
+    ///
+    /// # Errors
+    /// Returns a [`StorageError`] if the message could not be deleted.
+    fn delete(&self, id: MessageId) -> Result<bool, StorageError>;
+
+    /// Get the highest channel ID that has any history, if there is one. New channels must be
+    /// given IDs above this, so they don't inherit an old channel's history.
+    ///
+    /// # Errors
+    /// Returns a [`StorageError`] if the history could not be read.
+    fn max_channel_id(&self) -> Result<Option<ChannelId>, StorageError>;
+}
+
+/// A user account, as persisted by an [`AccountStore`].
+#[derive(Debug, Clone)]
+pub struct Account {
+    /// The account's user ID. This stays the same across sessions.
+    pub id: UserId,
+
+    /// The account's username, which doubles as the user's display name.
+    pub username: String,
+
+    /// Argon2 hash of the account's password, in PHC string format. Accounts created from a
+    /// client certificate have no password.
+    pub password_hash: Option<String>,
+
+    /// Subject of the client certificate the account was created from, if any. Presenting a
+    /// certificate with this subject logs into the account.
+    pub certificate_subject: Option<String>,
+
+    /// Server time at which the account was created.
+    pub created_at: SystemTime,
+
+    /// What the account's user may do on the server.
+    pub role: Role,
+}
+
+/// Persistent storage for user accounts.
+///
+/// Usernames are looked up by their normalized form, which the caller provides. Two accounts may
+/// never share a normalized username.
+///
+/// Like [`MessageStore`], implementations are synchronous and may block.
+pub trait AccountStore: Send + Sync + Debug {
+    /// Persist a new account. Returns `false` if an account with the same normalized username,
+    /// or the same certificate subject, already exists.
+    ///
+    /// # Errors
+    /// Returns a [`StorageError`] if the account could not be persisted.
+    fn create_account(
+        &self,
+        account: &Account,
+        normalized_username: &str,
+    ) -> Result<bool, StorageError>;
+
+    /// Get the account with the given normalized username, if it exists.
+    ///
+    /// # Errors
+    /// Returns a [`StorageError`] if the account could not be read.
+    fn account_by_name(&self, normalized_username: &str) -> Result<Option<Account>, StorageError>;
+
+    /// Get the account created from a client certificate with the given subject, if it exists.
+    ///
+    /// # Errors
+    /// Returns a [`StorageError`] if the account could not be read.
+    fn account_by_certificate(&self, subject: &str) -> Result<Option<Account>, StorageError>;
+
+    /// Change an account's username. Returns `false` if no account has the given ID, or if
+    /// another account already has the new normalized username.
+    ///
+    /// # Errors
+    /// Returns a [`StorageError`] if the account could not be updated.
+    fn rename_account(
+        &self,
+        id: UserId,
+        username: &str,
+        normalized_username: &str,
+    ) -> Result<bool, StorageError>;
+
+    /// Change an account's role. Returns `false` if no account has the given ID.
+    ///
+    /// # Errors
+    /// Returns a [`StorageError`] if the account could not be updated.
+    fn set_role(&self, id: UserId, role: Role) -> Result<bool, StorageError>;
+}
+
+/// What a [`Ban`] keeps off the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BanTarget {
+    /// An account, no matter where it connects from.
+    Account(UserId),
+
+    /// Everyone connecting from an IP address. IPv4-mapped IPv6 addresses are stored as plain IPv4
+    /// addresses, so use [`BanTarget::address`] to construct this.
+    Address(IpAddr),
+}
+
+impl BanTarget {
+    /// A ban on the given address, in its canonical form.
+    pub fn address(ip: IpAddr) -> Self {
+        Self::Address(ip.to_canonical())
+    }
+}
+
+impl Display for BanTarget {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Account(id) => write!(f, "account {}", id.0),
+            Self::Address(ip) => write!(f, "address {ip}"),
+        }
+    }
+}
+
+/// A ban, as persisted by a [`BanStore`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ban {
+    /// What the ban keeps off the server.
+    pub target: BanTarget,
+
+    /// Why the ban was issued.
+    pub reason: String,
+
+    /// The moderator who issued the ban. `None` if the server's operators did.
+    pub banned_by: Option<UserId>,
+
+    /// Server time at which the ban was issued.
+    pub created_at: SystemTime,
+
+    /// Server time at which the ban ends. `None` if the ban is permanent.
+    pub expires_at: Option<SystemTime>,
+}
+
+impl Ban {
+    /// Whether the ban has ended by the given time.
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// Persistent storage for bans. Each target has at most one ban.
+///
+/// Like [`MessageStore`], implementations are synchronous and may block.
+pub trait BanStore: Send + Sync + Debug {
+    /// Persist a ban, replacing any existing ban on the same target.
+    ///
+    /// # Errors
+    /// Returns a [`StorageError`] if the ban could not be persisted.
+    fn add_ban(&self, ban: &Ban) -> Result<(), StorageError>;
+
+    /// Remove the ban on a target. Returns `false` if the target wasn't banned.
+    ///
+    /// # Errors
+    /// Returns a [`StorageError`] if the ban could not be removed.
+    fn remove_ban(&self, target: BanTarget) -> Result<bool, StorageError>;
+
+    /// Remove every ban that has ended by the given time. Returns how many were removed.
+    ///
+    /// # Errors
+    /// Returns a [`StorageError`] if the bans could not be removed.
+    fn remove_expired_bans(&self, now: SystemTime) -> Result<usize, StorageError>;
+
+    /// Get every ban, including ones that have ended but weren't removed yet.
+    ///
+    /// # Errors
+    /// Returns a [`StorageError`] if the bans could not be read.
+    fn bans(&self) -> Result<Vec<Ban>, StorageError>;
+}
+
+/// A channel, as persisted by a [`ChannelStore`].
+#[derive(Debug, Clone)]
+pub struct StoredChannel {
+    /// The channel's ID, which also keys its history.
+    pub id: ChannelId,
+
+    /// The channel's display name.
+    pub name: String,
+
+    /// Whether the channel was deleted. Deleted channels are kept, so their IDs are never given to
+    /// another channel, and so they stay deleted even if the config still lists them.
+    pub deleted: bool,
+}
+
+/// Persistent storage for the channels on the server, whether they came from the config or were
+/// created while the server ran.
+///
+/// Like [`MessageStore`], implementations are synchronous and may block.
+pub trait ChannelStore: Send + Sync + Debug {
+    /// Get every channel, including deleted ones.
+    ///
+    /// # Errors
+    /// Returns a [`StorageError`] if the channels could not be read.
+    fn channels(&self) -> Result<Vec<StoredChannel>, StorageError>;
+
+    /// Persist a channel, replacing whatever was stored under its ID. This brings back a deleted
+    /// channel with the same ID.
+    ///
+    /// # Errors
+    /// Returns a [`StorageError`] if the channel could not be persisted.
+    fn save_channel(&self, id: ChannelId, name: &str) -> Result<(), StorageError>;
+
+    /// Change a channel's name. Returns `false` if no channel that wasn't deleted has the ID.
+    ///
+    /// # Errors
+    /// Returns a [`StorageError`] if the channel could not be updated.
+    fn rename_channel(&self, id: ChannelId, name: &str) -> Result<bool, StorageError>;
+
+    /// Mark a channel as deleted, and delete its history. Returns `false` if no channel that
+    /// wasn't deleted has the ID.
+    ///
+    /// # Errors
+    /// Returns a [`StorageError`] if the channel could not be deleted.
+    fn delete_channel(&self, id: ChannelId) -> Result<bool, StorageError>;
+
+    /// Get the channel list the config file gave when the server last started or reloaded it, so
+    /// changes made to it in the meantime can be told apart from changes made at runtime.
+    ///
+    /// # Errors
+    /// Returns a [`StorageError`] if the list could not be read.
+    fn configured_channels(&self) -> Result<Vec<ChannelInfo>, StorageError>;
+
+    /// Replace the channel list the config file last gave.
+    ///
+    /// # Errors
+    /// Returns a [`StorageError`] if the list could not be persisted.
+    fn set_configured_channels(&self, channels: &[ChannelInfo]) -> Result<(), StorageError>;
+}
+
+/// Handles to the stores opened by [`open`]. All are backed by the same storage backend.
+#[derive(Debug, Clone)]
+pub struct Stores {
+    pub messages: Arc<dyn MessageStore>,
+    pub accounts: Arc<dyn AccountStore>,
+    pub bans: Arc<dyn BanStore>,
+    pub channels: Arc<dyn ChannelStore>,
+}
+
+/// Open the message, account, ban and channel stores described by `config`.
+///
+/// # Errors
+/// Returns a [`StorageError`] if the store could not be opened or initialized.
+pub fn open(config: &StorageConfig) -> Result<Stores, StorageError> {
+    match config.backend {
+        StorageBackend::Sqlite => {
+            let path = config.path.resolved()?;
+            debug!(path = %path.display(), "Opening SQLite store");
+            let store = Arc::new(SqliteStore::open(&path)?);
+
+            Ok(Stores {
+                messages: store.clone(),
+                accounts: store.clone(),
+                bans: store.clone(),
+                channels: store,
+            })
+        }
+
+        StorageBackend::Memory => {
+            debug!("Using in-memory store");
+            let store = Arc::new(MemoryStore::new());
+
+            Ok(Stores {
+                messages: store.clone(),
+                accounts: store.clone(),
+                bans: store.clone(),
+                channels: store,
+            })
+        }
+    }
+}
@@ -0,0 +1,843 @@
+use std::{
+    fs::create_dir_all,
+    net::IpAddr,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use network_protocol::{ChannelId, ChannelInfo, MessageId, ReceiveDestination, Role, UserId};
+use rusqlite::{Connection, Row, params};
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use super::{
+    Account, AccountStore, Ban, BanStore, BanTarget, ChannelStore, Conversation, MessageStore,
+    StorageError, StoredChannel, StoredMessage,
+};
+
+/// Schema migrations, in order. Migration `i` upgrades the database from version `i` to version
+/// `i + 1`, as tracked by the `user_version` pragma. Never edit a migration once it has been
+/// released; add a new one instead.
+const MIGRATIONS: &[&str] = &[
+    "
+    CREATE TABLE channel_messages (
+        id BLOB PRIMARY KEY NOT NULL,
+        channel_id INTEGER NOT NULL,
+        sender_id BLOB NOT NULL,
+        contents TEXT NOT NULL,
+        timestamp_ms INTEGER NOT NULL
+    ) WITHOUT ROWID;
+
+    CREATE INDEX channel_messages_by_channel ON channel_messages (channel_id, id);
+    ",
+    // `user_a` and `user_b` are the two participants, ordered as in `Conversation::direct`.
+    "
+    CREATE TABLE direct_messages (
+        id BLOB PRIMARY KEY NOT NULL,
+        user_a BLOB NOT NULL,
+        user_b BLOB NOT NULL,
+        sender_id BLOB NOT NULL,
+        recipient_id BLOB NOT NULL,
+        contents TEXT NOT NULL,
+        timestamp_ms INTEGER NOT NULL
+    ) WITHOUT ROWID;
+
+    CREATE INDEX direct_messages_by_participants ON direct_messages (user_a, user_b, id);
+    ",
+    "
+    ALTER TABLE channel_messages ADD COLUMN edited_at_ms INTEGER;
+    ALTER TABLE direct_messages ADD COLUMN edited_at_ms INTEGER;
+    ",
+    "
+    CREATE TABLE accounts (
+        id BLOB PRIMARY KEY NOT NULL,
+        username TEXT NOT NULL,
+        normalized_username TEXT NOT NULL UNIQUE,
+        password_hash TEXT NOT NULL,
+        created_at_ms INTEGER NOT NULL
+    ) WITHOUT ROWID;
+    ",
+    // Accounts created from a client certificate have no password. Their `password_hash` is
+    // empty, since SQLite can't drop the `NOT NULL` constraint in place.
+    "
+    ALTER TABLE accounts ADD COLUMN certificate_subject TEXT;
+
+    CREATE UNIQUE INDEX accounts_by_certificate_subject ON accounts (certificate_subject);
+    ",
+    // Each ban targets either an account or an address, never both.
+    "
+    ALTER TABLE accounts ADD COLUMN role TEXT NOT NULL DEFAULT 'member';
+
+    CREATE TABLE bans (
+        account_id BLOB UNIQUE,
+        address TEXT UNIQUE,
+        reason TEXT NOT NULL,
+        banned_by BLOB,
+        created_at_ms INTEGER NOT NULL,
+        expires_at_ms INTEGER,
+        CHECK ((account_id IS NULL) != (address IS NULL))
+    );
+    ",
+    // `configured_channels` is the channel list the config file last gave, in its order.
+    "
+    CREATE TABLE channels (
+        id INTEGER PRIMARY KEY NOT NULL,
+        name TEXT NOT NULL,
+        deleted INTEGER NOT NULL DEFAULT 0
+    );
+
+    CREATE TABLE configured_channels (
+        position INTEGER PRIMARY KEY NOT NULL,
+        id INTEGER NOT NULL,
+        name TEXT NOT NULL
+    );
+    ",
+];
+
+/// A [`MessageStore`], [`AccountStore`], [`BanStore`] and [`ChannelStore`] backed by an `SQLite`
+/// database file.
+#[derive(Debug)]
+pub struct SqliteStore {
+    // `rusqlite::Connection` is not `Sync`. Storage calls are already made from blocking tasks, so
+    // serializing them behind a mutex is simple and good enough.
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    /// Open (or create) the database at `path` and bring its schema up to date.
+    ///
+    /// # Errors
+    /// Returns a [`StorageError`] if the file could not be opened, or if a migration failed.
+    pub fn open(path: &Path) -> Result<Self, StorageError> {
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+
+        let mut connection = Connection::open(path)?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.pragma_update(None, "synchronous", "NORMAL")?;
+
+        Self::migrate(&mut connection)?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Apply any pending schema migrations.
+    fn migrate(connection: &mut Connection) -> Result<(), StorageError> {
+        let version: usize =
+            connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+        if version > MIGRATIONS.len() {
+            return Err(StorageError::Corrupt(format!(
+                "database schema version {version} is newer than this server supports ({})",
+                MIGRATIONS.len()
+            )));
+        }
+
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            debug!(from = i, to = i + 1, "Migrating message store schema");
+
+            let transaction = connection.transaction()?;
+            transaction.execute_batch(migration)?;
+            transaction.pragma_update(None, "user_version", i + 1)?;
+            transaction.commit()?;
+        }
+
+        if version < MIGRATIONS.len() {
+            info!(
+                version = MIGRATIONS.len(),
+                "Message store schema up to date"
+            );
+        }
+
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.connection
+            .lock()
+            .expect("Message store mutex poisoned")
+    }
+
+    /// Convert a row selected as `id, sender_id, contents, timestamp_ms, edited_at_ms, channel_id`
+    /// from `channel_messages` into a [`StoredMessage`].
+    fn channel_message_from_row(row: &Row<'_>) -> Result<StoredMessage, StorageError> {
+        let channel_id = channel_id_from_column(row.get(5)?)?;
+
+        Self::message_from_row(row, ReceiveDestination::Channel(channel_id))
+    }
+
+    /// Convert a row selected as
+    /// `id, sender_id, contents, timestamp_ms, edited_at_ms, recipient_id` from `direct_messages`
+    /// into a [`StoredMessage`].
+    fn direct_message_from_row(row: &Row<'_>) -> Result<StoredMessage, StorageError> {
+        let recipient_id: Vec<u8> = row.get(5)?;
+        let recipient_id = UserId(uuid_from_blob(&recipient_id)?);
+
+        Self::message_from_row(row, ReceiveDestination::User(recipient_id))
+    }
+
+    /// Convert the columns shared by both message tables into a [`StoredMessage`] with the given
+    /// destination.
+    fn message_from_row(
+        row: &Row<'_>,
+        destination: ReceiveDestination,
+    ) -> Result<StoredMessage, StorageError> {
+        let id: Vec<u8> = row.get(0)?;
+        let sender_id: Vec<u8> = row.get(1)?;
+        let timestamp_ms: i64 = row.get(3)?;
+        let edited_at_ms: Option<i64> = row.get(4)?;
+
+        Ok(StoredMessage {
+            id: MessageId(uuid_from_blob(&id)?),
+            sender_id: UserId(uuid_from_blob(&sender_id)?),
+            destination,
+            contents: row.get(2)?,
+            timestamp: time_from_millis(timestamp_ms)?,
+            edited_at: edited_at_ms.map(time_from_millis).transpose()?,
+        })
+    }
+
+    /// Convert a row selected as
+    /// `id, username, password_hash, created_at_ms, certificate_subject, role` from `accounts`
+    /// into an [`Account`].
+    fn account_from_row(row: &Row<'_>) -> Result<Account, StorageError> {
+        let id: Vec<u8> = row.get(0)?;
+        let password_hash: String = row.get(2)?;
+        let created_at_ms: i64 = row.get(3)?;
+        let role: String = row.get(5)?;
+
+        Ok(Account {
+            id: UserId(uuid_from_blob(&id)?),
+            username: row.get(1)?,
+            password_hash: (!password_hash.is_empty()).then_some(password_hash),
+            created_at: time_from_millis(created_at_ms)?,
+            certificate_subject: row.get(4)?,
+            role: role
+                .parse()
+                .map_err(|()| StorageError::Corrupt(format!("unknown role '{role}'")))?,
+        })
+    }
+
+    /// Convert a row selected as
+    /// `account_id, address, reason, banned_by, created_at_ms, expires_at_ms` from `bans` into a
+    /// [`Ban`].
+    fn ban_from_row(row: &Row<'_>) -> Result<Ban, StorageError> {
+        let account_id: Option<Vec<u8>> = row.get(0)?;
+        let address: Option<String> = row.get(1)?;
+        let banned_by: Option<Vec<u8>> = row.get(3)?;
+        let created_at_ms: i64 = row.get(4)?;
+        let expires_at_ms: Option<i64> = row.get(5)?;
+
+        let target = match (account_id, address) {
+            (Some(id), None) => BanTarget::Account(UserId(uuid_from_blob(&id)?)),
+            (None, Some(address)) => BanTarget::Address(
+                address
+                    .parse::<IpAddr>()
+                    .map_err(|e| StorageError::Corrupt(e.to_string()))?,
+            ),
+            _ => {
+                return Err(StorageError::Corrupt(
+                    "ban targets neither exactly one account nor one address".to_owned(),
+                ));
+            }
+        };
+
+        Ok(Ban {
+            target,
+            reason: row.get(2)?,
+            banned_by: banned_by
+                .map(|id| uuid_from_blob(&id).map(UserId))
+                .transpose()?,
+            created_at: time_from_millis(created_at_ms)?,
+            expires_at: expires_at_ms.map(time_from_millis).transpose()?,
+        })
+    }
+
+    /// Convert a row selected as `id, name, deleted` from `channels` into a [`StoredChannel`].
+    fn channel_from_row(row: &Row<'_>) -> Result<StoredChannel, StorageError> {
+        Ok(StoredChannel {
+            id: channel_id_from_column(row.get(0)?)?,
+            name: row.get(1)?,
+            deleted: row.get(2)?,
+        })
+    }
+
+    /// Get up to `limit` messages sent to a channel, newest first.
+    fn channel_history(
+        connection: &Connection,
+        channel_id: ChannelId,
+        before: Option<MessageId>,
+        limit: i64,
+    ) -> Result<Vec<StoredMessage>, StorageError> {
+        let mut statement = connection.prepare_cached(
+            "SELECT id, sender_id, contents, timestamp_ms, edited_at_ms, channel_id
+             FROM channel_messages
+             WHERE channel_id = ?1 AND (?2 IS NULL OR id < ?2)
+             ORDER BY id DESC
+             LIMIT ?3",
+        )?;
+
+        let mut rows = statement.query(params![
+            u64::from(channel_id).cast_signed(),
+            before.map(|id| id.0.into_bytes()),
+            limit,
+        ])?;
+
+        let mut messages = Vec::new();
+        while let Some(row) = rows.next()? {
+            messages.push(Self::channel_message_from_row(row)?);
+        }
+
+        Ok(messages)
+    }
+
+    /// Get up to `limit` direct messages between two users, newest first. `user_a` and `user_b`
+    /// must be ordered as in [`Conversation::direct`].
+    fn direct_history(
+        connection: &Connection,
+        user_a: UserId,
+        user_b: UserId,
+        before: Option<MessageId>,
+        limit: i64,
+    ) -> Result<Vec<StoredMessage>, StorageError> {
+        let mut statement = connection.prepare_cached(
+            "SELECT id, sender_id, contents, timestamp_ms, edited_at_ms, recipient_id
+             FROM direct_messages
+             WHERE user_a = ?1 AND user_b = ?2 AND (?3 IS NULL OR id < ?3)
+             ORDER BY id DESC
+             LIMIT ?4",
+        )?;
+
+        let mut rows = statement.query(params![
+            user_a.0.as_bytes(),
+            user_b.0.as_bytes(),
+            before.map(|id| id.0.into_bytes()),
+            limit,
+        ])?;
+
+        let mut messages = Vec::new();
+        while let Some(row) = rows.next()? {
+            messages.push(Self::direct_message_from_row(row)?);
+        }
+
+        Ok(messages)
+    }
+}
+
+impl MessageStore for SqliteStore {
+    fn append(&self, message: &StoredMessage) -> Result<(), StorageError> {
+        let timestamp_ms = millis_from_time(message.timestamp)?;
+
+        match (message.destination, message.conversation()) {
+            (ReceiveDestination::Channel(channel_id), _) => {
+                self.lock().execute(
+                    "INSERT INTO channel_messages (id, channel_id, sender_id, contents, timestamp_ms)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        message.id.0.as_bytes(),
+                        u64::from(channel_id).cast_signed(),
+                        message.sender_id.0.as_bytes(),
+                        message.contents,
+                        timestamp_ms,
+                    ],
+                )?;
+            }
+
+            (ReceiveDestination::User(recipient_id), Conversation::Direct(user_a, user_b)) => {
+                self.lock().execute(
+                    "INSERT INTO direct_messages
+                     (id, user_a, user_b, sender_id, recipient_id, contents, timestamp_ms)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![
+                        message.id.0.as_bytes(),
+                        user_a.0.as_bytes(),
+                        user_b.0.as_bytes(),
+                        message.sender_id.0.as_bytes(),
+                        recipient_id.0.as_bytes(),
+                        message.contents,
+                        timestamp_ms,
+                    ],
+                )?;
+            }
+
+            (ReceiveDestination::User(_), Conversation::Channel(_)) => {
+                unreachable!("Direct messages always belong to a direct conversation")
+            }
+        }
+
+        Ok(())
+    }
+
+    fn history(
+        &self,
+        conversation: Conversation,
+        before: Option<MessageId>,
+        limit: usize,
+    ) -> Result<Vec<StoredMessage>, StorageError> {
+        let connection = self.lock();
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+
+        // UUIDv7s sort chronologically when compared bytewise, which is exactly how SQLite
+        // compares BLOBs. The newest `limit` rows are selected, then reversed into chronological
+        // order.
+        let mut messages = match conversation {
+            Conversation::Channel(channel_id) => {
+                Self::channel_history(&connection, channel_id, before, limit)?
+            }
+
+            Conversation::Direct(user_a, user_b) => {
+                Self::direct_history(&connection, user_a, user_b, before, limit)?
+            }
+        };
+
+        messages.reverse();
+        Ok(messages)
+    }
+
+    fn get(&self, id: MessageId) -> Result<Option<StoredMessage>, StorageError> {
+        let connection = self.lock();
+
+        let mut statement = connection.prepare_cached(
+            "SELECT id, sender_id, contents, timestamp_ms, edited_at_ms, channel_id
+             FROM channel_messages WHERE id = ?1",
+        )?;
+        if let Some(row) = statement.query(params![id.0.as_bytes()])?.next()? {
+            return Ok(Some(Self::channel_message_from_row(row)?));
+        }
+
+        let mut statement = connection.prepare_cached(
+            "SELECT id, sender_id, contents, timestamp_ms, edited_at_ms, recipient_id
+             FROM direct_messages WHERE id = ?1",
+        )?;
+        if let Some(row) = statement.query(params![id.0.as_bytes()])?.next()? {
+            return Ok(Some(Self::direct_message_from_row(row)?));
+        }
+
+        Ok(None)
+    }
+
+    fn edit(
+        &self,
+        id: MessageId,
+        contents: &str,
+        edited_at: SystemTime,
+    ) -> Result<bool, StorageError> {
+        let connection = self.lock();
+        let edited_at_ms = millis_from_time(edited_at)?;
+
+        // A message lives in exactly one of the tables, so at most one of these updates matches.
+        let mut updated = 0;
+        for table in ["channel_messages", "direct_messages"] {
+            updated += connection.execute(
+                &format!("UPDATE {table} SET contents = ?2, edited_at_ms = ?3 WHERE id = ?1"),
+                params![id.0.as_bytes(), contents, edited_at_ms],
+            )?;
+        }
+
+        Ok(updated > 0)
+    }
+
+    fn delete(&self, id: MessageId) -> Result<bool, StorageError> {
+        let connection = self.lock();
+
+        let mut deleted = 0;
+        for table in ["channel_messages", "direct_messages"] {
+            deleted += connection.execute(
+                &format!("DELETE FROM {table} WHERE id = ?1"),
+                params![id.0.as_bytes()],
+            )?;
+        }
+
+        Ok(deleted > 0)
+    }
+
+    fn max_channel_id(&self) -> Result<Option<ChannelId>, StorageError> {
+        let connection = self.lock();
+
+        let max: Option<i64> =
+            connection.query_row("SELECT MAX(channel_id) FROM channel_messages", [], |row| {
+                row.get(0)
+            })?;
+
+        max.map(channel_id_from_column).transpose()
+    }
+}
+
+impl AccountStore for SqliteStore {
+    fn create_account(
+        &self,
+        account: &Account,
+        normalized_username: &str,
+    ) -> Result<bool, StorageError> {
+        let created_at_ms = millis_from_time(account.created_at)?;
+
+        // Both the normalized username and the certificate subject are unique, and a conflict on
+        // either skips the insert.
+        let inserted = self.lock().execute(
+            "INSERT INTO accounts
+             (id, username, normalized_username, password_hash, created_at_ms, certificate_subject,
+              role)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT DO NOTHING",
+            params![
+                account.id.0.as_bytes(),
+                account.username,
+                normalized_username,
+                account.password_hash.as_deref().unwrap_or_default(),
+                created_at_ms,
+                account.certificate_subject,
+                account.role.as_str(),
+            ],
+        )?;
+
+        Ok(inserted > 0)
+    }
+
+    fn account_by_name(&self, normalized_username: &str) -> Result<Option<Account>, StorageError> {
+        let connection = self.lock();
+
+        let mut statement = connection.prepare_cached(
+            "SELECT id, username, password_hash, created_at_ms, certificate_subject, role
+             FROM accounts WHERE normalized_username = ?1",
+        )?;
+
+        let mut rows = statement.query(params![normalized_username])?;
+        rows.next()?.map(Self::account_from_row).transpose()
+    }
+
+    fn account_by_certificate(&self, subject: &str) -> Result<Option<Account>, StorageError> {
+        let connection = self.lock();
+
+        let mut statement = connection.prepare_cached(
+            "SELECT id, username, password_hash, created_at_ms, certificate_subject, role
+             FROM accounts WHERE certificate_subject = ?1",
+        )?;
+
+        let mut rows = statement.query(params![subject])?;
+        rows.next()?.map(Self::account_from_row).transpose()
+    }
+
+    fn rename_account(
+        &self,
+        id: UserId,
+        username: &str,
+        normalized_username: &str,
+    ) -> Result<bool, StorageError> {
+        // `OR IGNORE` skips the update, rather than failing, if another account already has the
+        // new normalized username.
+        let updated = self.lock().execute(
+            "UPDATE OR IGNORE accounts SET username = ?2, normalized_username = ?3 WHERE id = ?1",
+            params![id.0.as_bytes(), username, normalized_username],
+        )?;
+
+        Ok(updated > 0)
+    }
+
+    fn set_role(&self, id: UserId, role: Role) -> Result<bool, StorageError> {
+        let updated = self.lock().execute(
+            "UPDATE accounts SET role = ?2 WHERE id = ?1",
+            params![id.0.as_bytes(), role.as_str()],
+        )?;
+
+        Ok(updated > 0)
+    }
+}
+
+impl BanStore for SqliteStore {
+    fn add_ban(&self, ban: &Ban) -> Result<(), StorageError> {
+        let (account_id, address) = match ban.target {
+            BanTarget::Account(id) => (Some(id.0.into_bytes()), None),
+            BanTarget::Address(ip) => (None, Some(ip.to_string())),
+        };
+
+        // `OR REPLACE` drops the target's old ban, since the target columns are unique.
+        self.lock().execute(
+            "INSERT OR REPLACE INTO bans
+             (account_id, address, reason, banned_by, created_at_ms, expires_at_ms)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                account_id,
+                address,
+                ban.reason,
+                ban.banned_by.map(|id| id.0.into_bytes()),
+                millis_from_time(ban.created_at)?,
+                ban.expires_at.map(millis_from_time).transpose()?,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    fn remove_ban(&self, target: BanTarget) -> Result<bool, StorageError> {
+        let removed = match target {
+            BanTarget::Account(id) => self.lock().execute(
+                "DELETE FROM bans WHERE account_id = ?1",
+                params![id.0.as_bytes()],
+            )?,
+
+            BanTarget::Address(ip) => self.lock().execute(
+                "DELETE FROM bans WHERE address = ?1",
+                params![ip.to_string()],
+            )?,
+        };
+
+        Ok(removed > 0)
+    }
+
+    fn remove_expired_bans(&self, now: SystemTime) -> Result<usize, StorageError> {
+        Ok(self.lock().execute(
+            "DELETE FROM bans WHERE expires_at_ms <= ?1",
+            params![millis_from_time(now)?],
+        )?)
+    }
+
+    fn bans(&self) -> Result<Vec<Ban>, StorageError> {
+        let connection = self.lock();
+
+        let mut statement = connection.prepare_cached(
+            "SELECT account_id, address, reason, banned_by, created_at_ms, expires_at_ms
+             FROM bans",
+        )?;
+
+        let mut rows = statement.query([])?;
+
+        let mut bans = Vec::new();
+        while let Some(row) = rows.next()? {
+            bans.push(Self::ban_from_row(row)?);
+        }
+
+        Ok(bans)
+    }
+}
+
+impl ChannelStore for SqliteStore {
+    fn channels(&self) -> Result<Vec<StoredChannel>, StorageError> {
+        let connection = self.lock();
+
+        let mut statement =
+            connection.prepare_cached("SELECT id, name, deleted FROM channels ORDER BY id")?;
+        let mut rows = statement.query([])?;
+
+        let mut channels = Vec::new();
+        while let Some(row) = rows.next()? {
+            channels.push(Self::channel_from_row(row)?);
+        }
+
+        Ok(channels)
+    }
+
+    fn save_channel(&self, id: ChannelId, name: &str) -> Result<(), StorageError> {
+        self.lock().execute(
+            "INSERT OR REPLACE INTO channels (id, name, deleted) VALUES (?1, ?2, 0)",
+            params![u64::from(id).cast_signed(), name],
+        )?;
+
+        Ok(())
+    }
+
+    fn rename_channel(&self, id: ChannelId, name: &str) -> Result<bool, StorageError> {
+        let updated = self.lock().execute(
+            "UPDATE channels SET name = ?2 WHERE id = ?1 AND NOT deleted",
+            params![u64::from(id).cast_signed(), name],
+        )?;
+
+        Ok(updated > 0)
+    }
+
+    fn delete_channel(&self, id: ChannelId) -> Result<bool, StorageError> {
+        let mut connection = self.lock();
+        let id = u64::from(id).cast_signed();
+
+        let transaction = connection.transaction()?;
+        let deleted = transaction.execute(
+            "UPDATE channels SET deleted = 1 WHERE id = ?1 AND NOT deleted",
+            params![id],
+        )?;
+        transaction.execute(
+            "DELETE FROM channel_messages WHERE channel_id = ?1",
+            params![id],
+        )?;
+        transaction.commit()?;
+
+        Ok(deleted > 0)
+    }
+
+    fn configured_channels(&self) -> Result<Vec<ChannelInfo>, StorageError> {
+        let connection = self.lock();
+
+        let mut statement = connection
+            .prepare_cached("SELECT id, name FROM configured_channels ORDER BY position")?;
+        let mut rows = statement.query([])?;
+
+        let mut channels = Vec::new();
+        while let Some(row) = rows.next()? {
+            channels.push(ChannelInfo {
+                id: channel_id_from_column(row.get(0)?)?,
+                name: row.get(1)?,
+            });
+        }
+
+        Ok(channels)
+    }
+
+    fn set_configured_channels(&self, channels: &[ChannelInfo]) -> Result<(), StorageError> {
+        let mut connection = self.lock();
+
+        let transaction = connection.transaction()?;
+        transaction.execute("DELETE FROM configured_channels", [])?;
+        for (position, channel) in channels.iter().enumerate() {
+            transaction.execute(
+                "INSERT INTO configured_channels (position, id, name) VALUES (?1, ?2, ?3)",
+                params![position, u64::from(channel.id).cast_signed(), channel.name],
+            )?;
+        }
+        transaction.commit()?;
+
+        Ok(())
+    }
+}
+
+fn channel_id_from_column(id: i64) -> Result<ChannelId, StorageError> {
+    ChannelId::try_from(id.cast_unsigned()).map_err(|e| StorageError::Corrupt(e.to_string()))
+}
+
+fn uuid_from_blob(blob: &[u8]) -> Result<Uuid, StorageError> {
+    Uuid::from_slice(blob).map_err(|e| StorageError::Corrupt(e.to_string()))
+}
+
+fn millis_from_time(time: SystemTime) -> Result<i64, StorageError> {
+    let millis = time
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| StorageError::Corrupt(e.to_string()))?
+        .as_millis();
+
+    i64::try_from(millis).map_err(|e| StorageError::Corrupt(e.to_string()))
+}
+
+fn time_from_millis(millis: i64) -> Result<SystemTime, StorageError> {
+    let millis = u64::try_from(millis).map_err(|e| StorageError::Corrupt(e.to_string()))?;
+
+    Ok(UNIX_EPOCH + Duration::from_millis(millis))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::storage::MemoryStore;
+
+    fn user_version(connection: &Connection) -> usize {
+        connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap()
+    }
+
+    /// A message with an ID that sorts by `n`, so messages can be made in a known order.
+    fn message(n: u128, sender: UserId, destination: ReceiveDestination) -> StoredMessage {
+        StoredMessage {
+            id: MessageId(Uuid::from_u128(n)),
+            sender_id: sender,
+            destination,
+            contents: format!("message {n}"),
+            timestamp: UNIX_EPOCH + Duration::from_millis(u64::try_from(n).unwrap()),
+            edited_at: None,
+        }
+    }
+
+    fn ids(messages: &[StoredMessage]) -> Vec<MessageId> {
+        messages.iter().map(|message| message.id).collect()
+    }
+
+    #[test]
+    fn migrates_fresh_database() {
+        let mut connection = Connection::open_in_memory().unwrap();
+
+        SqliteStore::migrate(&mut connection).unwrap();
+        assert_eq!(user_version(&connection), MIGRATIONS.len());
+
+        // Migrating again changes nothing.
+        SqliteStore::migrate(&mut connection).unwrap();
+        assert_eq!(user_version(&connection), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn migrates_from_every_version() {
+        for version in 0..MIGRATIONS.len() {
+            let mut connection = Connection::open_in_memory().unwrap();
+            for migration in &MIGRATIONS[..version] {
+                connection.execute_batch(migration).unwrap();
+            }
+            connection
+                .pragma_update(None, "user_version", version)
+                .unwrap();
+
+            SqliteStore::migrate(&mut connection).unwrap();
+            assert_eq!(user_version(&connection), MIGRATIONS.len());
+        }
+    }
+
+    #[test]
+    fn rejects_newer_schema() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        connection
+            .pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .unwrap();
+
+        let result = SqliteStore::migrate(&mut connection);
+        assert!(matches!(result, Err(StorageError::Corrupt(_))));
+    }
+
+    #[test]
+    fn history_matches_memory_store() {
+        let directory = tempfile::tempdir().unwrap();
+        let sqlite = SqliteStore::open(&directory.path().join("history.sqlite3")).unwrap();
+        let memory = MemoryStore::new();
+
+        let alice = UserId(Uuid::from_u128(1));
+        let bob = UserId(Uuid::from_u128(2));
+        let channel = ChannelId::try_from(1).unwrap();
+
+        // Interleave two conversations, appended out of order.
+        for n in [3, 1, 5, 2, 4, 7, 6] {
+            let destination = if n % 2 == 0 {
+                ReceiveDestination::User(bob)
+            } else {
+                ReceiveDestination::Channel(channel)
+            };
+            let message = message(n, alice, destination);
+            sqlite.append(&message).unwrap();
+            memory.append(&message).unwrap();
+        }
+
+        let conversations = [
+            Conversation::Channel(channel),
+            Conversation::direct(bob, alice),
+        ];
+        let cursors = [None, Some(1), Some(2), Some(5), Some(6), Some(100)]
+            .map(|n| n.map(|n| MessageId(Uuid::from_u128(n))));
+
+        for conversation in conversations {
+            for before in cursors {
+                for limit in 0..5 {
+                    let from_sqlite = sqlite.history(conversation, before, limit).unwrap();
+                    let from_memory = memory.history(conversation, before, limit).unwrap();
+
+                    assert_eq!(
+                        ids(&from_sqlite),
+                        ids(&from_memory),
+                        "{conversation:?} before {before:?}, limit {limit}"
+                    );
+                    assert!(from_sqlite.is_sorted_by_key(|message| message.id));
+                }
+            }
+        }
+
+        let newest = sqlite
+            .history(Conversation::Channel(channel), None, 2)
+            .unwrap();
+        assert_eq!(ids(&newest), [5, 7].map(|n| MessageId(Uuid::from_u128(n))));
+    }
+}
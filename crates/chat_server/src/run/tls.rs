@@ -0,0 +1,89 @@
+//! The server's TLS certificate, which may be replaced while the server runs.
+
+use std::sync::{Arc, RwLock};
+
+use anyhow::Context;
+use rustls::{
+    crypto::CryptoProvider,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+};
+use shared_utils::files::TildeRelativePathBuf;
+use tracing::debug;
+
+/// Hands the current certificate and key to every new TLS handshake. Reloading swaps them for
+/// later handshakes, while established connections keep going.
+#[derive(Debug)]
+pub struct ReloadableCertificate {
+    provider: Arc<CryptoProvider>,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ReloadableCertificate {
+    /// Load the certificate chain and private key from PEM files.
+    pub fn load(
+        provider: Arc<CryptoProvider>,
+        cert_path: &TildeRelativePathBuf,
+        key_path: &TildeRelativePathBuf,
+    ) -> anyhow::Result<Self> {
+        let current = load_certified_key(&provider, cert_path, key_path)?;
+
+        Ok(Self {
+            provider,
+            current: RwLock::new(Arc::new(current)),
+        })
+    }
+
+    /// Load the certificate chain and private key again, possibly from new paths. If loading
+    /// fails, the current certificate stays in use.
+    pub fn reload(
+        &self,
+        cert_path: &TildeRelativePathBuf,
+        key_path: &TildeRelativePathBuf,
+    ) -> anyhow::Result<()> {
+        let new = load_certified_key(&self.provider, cert_path, key_path)?;
+        *self.current.write().expect("TLS certificate lock poisoned") = Arc::new(new);
+
+        Ok(())
+    }
+}
+
+impl ResolvesServerCert for ReloadableCertificate {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(
+            self.current
+                .read()
+                .expect("TLS certificate lock poisoned")
+                .clone(),
+        )
+    }
+}
+
+fn load_certified_key(
+    provider: &CryptoProvider,
+    cert_path: &TildeRelativePathBuf,
+    key_path: &TildeRelativePathBuf,
+) -> anyhow::Result<CertifiedKey> {
+    let cert_path_err_display = cert_path.original().display();
+    let tls_cert_path = &cert_path
+        .resolved()
+        .context("Resolving TLS certificate path")?;
+    let certs = CertificateDer::pem_file_iter(tls_cert_path)
+        .with_context(|| format!("Opening TLS certificate file '{cert_path_err_display}'"))?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Reading TLS certificate file '{cert_path_err_display}'"))?;
+
+    let key_path_err_display = key_path.original().display();
+    let tls_key_path = &key_path.resolved().context("Resolving TLS key path")?;
+    let key = PrivateKeyDer::from_pem_file(tls_key_path)
+        .with_context(|| format!("Reading TLS key file '{key_path_err_display}'"))?;
+
+    debug!(
+        cert_path = %tls_cert_path.display(),
+        key_path = %tls_key_path.display(),
+        "Loaded TLS keypair"
+    );
+
+    CertifiedKey::from_der(certs, key, provider).context("Configuring TLS: bad certificate or key")
+}
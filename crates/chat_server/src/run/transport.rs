@@ -0,0 +1,268 @@
+//! The transports clients connect over: length-prefixed frames straight over the stream, or one
+//! frame per WebSocket message. Either may be wrapped in TLS.
+
+use std::{
+    fmt::{self, Debug, Display},
+    io,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use anyhow::Context as _;
+use futures::{Sink, Stream};
+use network_protocol::{CommandRequest, NetworkEvent, codecs::ServerCodec};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::{TlsAcceptor, server::TlsStream};
+use tokio_tungstenite::{
+    WebSocketStream,
+    tungstenite::{self, Message, protocol::WebSocketConfig},
+};
+use tokio_util::codec::Framed;
+use tracing::{debug, warn};
+
+use super::client_auth::CertificateIdentity;
+
+/// A client's commands and events, whatever transport carries them.
+pub trait ClientStream:
+    Stream<Item = io::Result<CommandRequest>>
+    + Sink<NetworkEvent, Error = io::Error>
+    + Debug
+    + Send
+    + Unpin
+{
+}
+
+impl<T> ClientStream for T where
+    T: Stream<Item = io::Result<CommandRequest>>
+        + Sink<NetworkEvent, Error = io::Error>
+        + Debug
+        + Send
+        + Unpin
+{
+}
+
+/// Where a client connected from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientAddr {
+    /// A TCP connection, from this address.
+    Tcp(SocketAddr),
+
+    /// A Unix domain socket connection, from a process running as this user ID, if it could be
+    /// found out.
+    Unix { uid: Option<u32> },
+}
+
+impl ClientAddr {
+    /// The IP address the client connected from. `None` for local connections.
+    pub fn ip(self) -> Option<IpAddr> {
+        match self {
+            Self::Tcp(addr) => Some(addr.ip()),
+            Self::Unix { .. } => None,
+        }
+    }
+}
+
+impl Display for ClientAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "{addr}"),
+            Self::Unix { uid: Some(uid) } => write!(f, "unix:uid={uid}"),
+            Self::Unix { uid: None } => write!(f, "unix"),
+        }
+    }
+}
+
+/// How frames are laid out on a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// Length-prefixed frames, straight over the stream.
+    Raw,
+
+    /// One frame per binary WebSocket message, without the length prefix.
+    WebSocket,
+}
+
+/// How a listener's clients connect: the protocol they speak, and whether it's wrapped in TLS.
+#[derive(Clone)]
+pub struct Transport {
+    pub protocol: Protocol,
+
+    /// Wrapper around a [`ServerConfig`](rustls::ServerConfig) for TLS handshakes. `None` if
+    /// connections aren't encrypted, e.g. because a reverse proxy in front of the server already
+    /// terminates TLS.
+    pub tls_acceptor: Option<TlsAcceptor>,
+}
+
+/// A connection that completed the transport's handshakes.
+pub struct Accepted {
+    pub stream: Box<dyn ClientStream>,
+
+    /// Identity from the certificate the client presented in the TLS handshake, if any.
+    pub certificate: Option<CertificateIdentity>,
+}
+
+impl Transport {
+    /// Perform the transport's handshakes on a new connection: TLS if it's enabled, then the
+    /// WebSocket upgrade if the protocol needs one.
+    ///
+    /// # Errors
+    /// Returns an error if either handshake fails.
+    pub async fn accept<S>(&self, stream: S, max_frame_length: usize) -> anyhow::Result<Accepted>
+    where
+        S: AsyncRead + AsyncWrite + Debug + Send + Unpin + 'static,
+    {
+        let Some(tls_acceptor) = &self.tls_acceptor else {
+            return Ok(Accepted {
+                stream: self.protocol.wrap(stream, max_frame_length).await?,
+                certificate: None,
+            });
+        };
+
+        let stream = tls_acceptor
+            .accept(stream)
+            .await
+            .context("TLS handshake failed")?;
+        debug!("Client completed TLS handshake");
+
+        let certificate = peer_certificate(&stream);
+
+        Ok(Accepted {
+            stream: self.protocol.wrap(stream, max_frame_length).await?,
+            certificate,
+        })
+    }
+}
+
+impl Protocol {
+    /// Start speaking this protocol on an established stream.
+    async fn wrap<S>(
+        self,
+        stream: S,
+        max_frame_length: usize,
+    ) -> anyhow::Result<Box<dyn ClientStream>>
+    where
+        S: AsyncRead + AsyncWrite + Debug + Send + Unpin + 'static,
+    {
+        let codec = ServerCodec::new(max_frame_length);
+
+        match self {
+            Self::Raw => Ok(Box::new(Framed::new(stream, codec))),
+
+            Self::WebSocket => {
+                let config = WebSocketConfig::default()
+                    .max_message_size(Some(max_frame_length))
+                    .max_frame_size(Some(max_frame_length));
+
+                let inner = tokio_tungstenite::accept_async_with_config(stream, Some(config))
+                    .await
+                    .context("WebSocket handshake failed")?;
+                debug!("Client completed WebSocket handshake");
+
+                Ok(Box::new(WebSocketClientStream { inner, codec }))
+            }
+        }
+    }
+}
+
+/// Identify the client by the certificate it presented, if any. The TLS layer already verified
+/// the certificate against the client CA, if there is one.
+fn peer_certificate<S>(stream: &TlsStream<S>) -> Option<CertificateIdentity> {
+    match stream.get_ref().1.peer_certificates() {
+        Some([cert, ..]) => match CertificateIdentity::from_der(cert) {
+            Ok(identity) => {
+                debug!(subject = %identity.subject, "Client presented a certificate");
+                Some(identity)
+            }
+
+            Err(e) => {
+                warn!(error = %e, "Client presented an unusable certificate");
+                None
+            }
+        },
+
+        _ => None,
+    }
+}
+
+/// A WebSocket connection carrying one frame per binary message.
+#[derive(Debug)]
+struct WebSocketClientStream<S> {
+    inner: WebSocketStream<S>,
+    codec: ServerCodec,
+}
+
+impl<S> Stream for WebSocketClientStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    type Item = io::Result<CommandRequest>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let message = match ready!(Pin::new(&mut self.inner).poll_next(cx)) {
+                Some(Ok(message)) => message,
+                Some(Err(e)) => return Poll::Ready(Some(Err(into_io_error(e)))),
+                None => return Poll::Ready(None),
+            };
+
+            match message {
+                // Each message holds a whole frame, so a bad one doesn't throw off the ones after
+                // it, unlike on a raw stream.
+                Message::Binary(data) => {
+                    return Poll::Ready(Some(self.codec.decode_message(&data)));
+                }
+
+                Message::Text(_) => {
+                    return Poll::Ready(Some(Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "expected a binary message, got a text message",
+                    ))));
+                }
+
+                // Pings are answered by the WebSocket layer, and the stream ends after a close.
+                Message::Ping(_) | Message::Pong(_) | Message::Close(_) | Message::Frame(_) => {}
+            }
+        }
+    }
+}
+
+impl<S> Sink<NetworkEvent> for WebSocketClientStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    type Error = io::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner)
+            .poll_ready(cx)
+            .map_err(into_io_error)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: NetworkEvent) -> Result<(), Self::Error> {
+        let data = self.codec.encode_message(item)?;
+
+        Pin::new(&mut self.inner)
+            .start_send(Message::Binary(data.into()))
+            .map_err(into_io_error)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner)
+            .poll_flush(cx)
+            .map_err(into_io_error)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner)
+            .poll_close(cx)
+            .map_err(into_io_error)
+    }
+}
+
+fn into_io_error(error: tungstenite::Error) -> io::Error {
+    match error {
+        tungstenite::Error::Io(e) => e,
+        e => io::Error::other(e),
+    }
+}
@@ -0,0 +1,150 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::{debug, info, instrument, warn};
+
+use crate::run::ServerState;
+
+use super::{
+    client_auth::CertificateIdentity,
+    connection::Connection,
+    transport::{ClientAddr, Protocol, Transport},
+    unix_socket,
+};
+
+/// A local user whose connections over the Unix socket are trusted to log in without a password
+/// or certificate.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrustedUser {
+    /// The user's ID on the machine the server runs on.
+    pub uid: u32,
+
+    /// Username of the account the user logs into. The account is created the first time.
+    pub username: String,
+}
+
+/// A task struct listening for clients on a Unix domain socket. Clients speak the same protocol as
+/// on the main listener, without TLS. Who may connect is up to the socket's file permissions.
+pub struct UnixSocketListener {
+    /// Server state - users, channels, etc.
+    server_state: Arc<ServerState>,
+
+    /// Cancellation token for the main task to signal for shutdown. Passed on to connections.
+    cancellation_token: CancellationToken,
+
+    /// Cancelled when the server starts shutting down, to stop accepting new connections while the
+    /// existing ones drain.
+    drain_token: CancellationToken,
+
+    /// Task tracker for the main task to join all connections on shutdown.
+    task_tracker: TaskTracker,
+
+    /// Path of the socket.
+    path: PathBuf,
+
+    /// Permissions of the socket file.
+    mode: u32,
+
+    /// Local users who log in as if they presented a client certificate.
+    trusted_users: Vec<TrustedUser>,
+}
+
+impl UnixSocketListener {
+    /// Create a new `UnixSocketListener`.
+    pub fn new(
+        server_state: Arc<ServerState>,
+        cancellation_token: CancellationToken,
+        drain_token: CancellationToken,
+        task_tracker: TaskTracker,
+        path: PathBuf,
+        mode: u32,
+        trusted_users: Vec<TrustedUser>,
+    ) -> Self {
+        Self {
+            server_state,
+            cancellation_token,
+            drain_token,
+            task_tracker,
+            path,
+            mode,
+            trusted_users,
+        }
+    }
+
+    /// Start an initialized `UnixSocketListener`. This should be spawned as a [`tokio`] task.
+    #[instrument(skip_all, fields(path = %self.path.display()), parent = None, err)]
+    pub async fn start(self) -> io::Result<()> {
+        let listener = unix_socket::bind(&self.path, self.mode).await?;
+
+        info!(
+            mode = format!("{:o}", self.mode),
+            "Unix socket listener bound and accepting connections"
+        );
+
+        let transport = Transport {
+            protocol: Protocol::Raw,
+            tls_acceptor: None,
+        };
+
+        loop {
+            tokio::select! {
+                conn = listener.accept() => match conn {
+                    Ok((stream, _addr)) => {
+                        let uid = match stream.peer_cred() {
+                            Ok(cred) => {
+                                debug!(uid = cred.uid(), pid = ?cred.pid(), "Accepted incoming Unix socket connection");
+                                Some(cred.uid())
+                            }
+
+                            Err(e) => {
+                                warn!(error = %e, "Accepted incoming Unix socket connection, but couldn't tell who from");
+                                None
+                            }
+                        };
+
+                        self.task_tracker.spawn(Connection::start(
+                            self.server_state.clone(),
+                            transport.clone(),
+                            stream,
+                            ClientAddr::Unix { uid },
+                            uid.and_then(|uid| self.trusted_identity(uid)),
+                            self.cancellation_token.clone(),
+                        ));
+                    }
+
+                    Err(e) => {
+                        warn!(error = %e, "Failed to accept incoming Unix socket connection");
+                    }
+                },
+
+                // Dropping the listener refuses any further connections.
+                () = self.drain_token.cancelled() => {
+                    info!("Unix socket listener received cancellation signal, shutting down...");
+                    break;
+                }
+            }
+        }
+
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!(error = %e, "Failed to remove Unix socket");
+        }
+
+        Ok(())
+    }
+
+    /// The identity a local user logs in with, if they're trusted.
+    fn trusted_identity(&self, uid: u32) -> Option<CertificateIdentity> {
+        let user = self.trusted_users.iter().find(|user| user.uid == uid)?;
+        debug!(uid, username = %user.username, "Trusting local user");
+
+        Some(CertificateIdentity {
+            subject: format!("unix:uid={uid}"),
+            common_name: user.username.clone(),
+        })
+    }
+}
@@ -0,0 +1,79 @@
+//! Binding Unix domain sockets, for the admin socket and the Unix socket listener.
+
+use std::fs::{self, DirBuilder, Permissions};
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::path::Path;
+use std::process;
+
+use tokio::net::{UnixListener, UnixStream};
+use tracing::debug;
+
+/// Bind a Unix socket at `path`, with the permissions in `mode`.
+///
+/// The socket is bound inside a directory only the user the server runs as may enter, given its
+/// permissions there, then moved to `path`. Binding at `path` directly would leave the socket open
+/// to anyone the umask allows until its permissions are changed.
+///
+/// A socket left behind by a server that didn't shut down cleanly is replaced, but one that another
+/// server still listens on is not.
+///
+/// # Errors
+/// Returns an error of kind [`io::ErrorKind::AddrInUse`] if another server listens on `path`, or
+/// [`io::ErrorKind::AlreadyExists`] if something other than a socket is there.
+pub async fn bind(path: &Path, mode: u32) -> io::Result<UnixListener> {
+    remove_stale(path).await?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let file_name = path.file_name().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "socket path has no file name")
+    })?;
+
+    let mut staging_name = file_name.to_os_string();
+    staging_name.push(format!(".{}", process::id()));
+    let staging_dir = path.with_file_name(staging_name);
+    let staged_path = staging_dir.join(file_name);
+
+    DirBuilder::new().mode(0o700).create(&staging_dir)?;
+
+    let bound = UnixListener::bind(&staged_path).and_then(|listener| {
+        fs::set_permissions(&staged_path, Permissions::from_mode(mode))?;
+        fs::rename(&staged_path, path)?;
+        Ok(listener)
+    });
+
+    // The socket is still in the directory if anything failed.
+    if bound.is_err() {
+        let _: Result<_, _> = fs::remove_file(&staged_path);
+    }
+
+    fs::remove_dir(&staging_dir)?;
+    bound
+}
+
+/// Remove the socket at `path`, if nothing listens on it anymore.
+async fn remove_stale(path: &Path) -> io::Result<()> {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+
+    if !metadata.file_type().is_socket() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "something other than a socket is in the way",
+        ));
+    }
+
+    if UnixStream::connect(path).await.is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            "another server is listening on the socket",
+        ));
+    }
+
+    debug!("Removing stale socket");
+    fs::remove_file(path)
+}
@@ -3,66 +3,97 @@
 use std::io;
 
 use prost::Message;
-use prost::bytes::Buf;
+use prost::bytes::{Buf, BytesMut};
 use tokio_util::codec::{Decoder, Encoder};
 
 use crate::proto::{CommandFrame, EventFrame};
-use crate::{NetworkCommand, NetworkEvent};
+use crate::{CommandRequest, NetworkCommand, NetworkEvent};
+
+/// The default maximum length of a single frame, in bytes, not counting the length prefix.
+pub const DEFAULT_MAX_FRAME_LENGTH: usize = 1024 * 1024;
 
 /// A codec for a client-side `Framed` `TcpStream`. Internally, this uses protobuf with a varint
 /// length prefix.
 ///
-/// This codec sends `NetworkCommand`s and receives `NetworkEvent`s.
-#[derive(Debug)]
-pub struct ClientCodec;
+/// This codec sends `NetworkCommand`s (or `CommandRequest`s, to tag them with a request ID) and
+/// receives `NetworkEvent`s. Frames longer than the maximum frame length are rejected in both
+/// directions.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientCodec {
+    max_frame_length: usize,
+}
 
-impl Encoder<NetworkCommand> for ClientCodec {
+impl ClientCodec {
+    /// Create a codec that rejects frames longer than `max_frame_length` bytes.
+    #[must_use]
+    pub const fn new(max_frame_length: usize) -> Self {
+        Self { max_frame_length }
+    }
+
+    /// The maximum length of a single frame, in bytes.
+    #[must_use]
+    pub const fn max_frame_length(&self) -> usize {
+        self.max_frame_length
+    }
+
+    /// Encode a command as a single frame without the length prefix, for transports that delimit
+    /// messages themselves, like WebSocket.
+    ///
+    /// # Errors
+    /// Returns an error if the frame is longer than the maximum frame length.
+    pub fn encode_message(&self, item: CommandRequest) -> io::Result<Vec<u8>> {
+        let frame = CommandFrame::from(item);
+        check_frame_length(frame.encoded_len(), self.max_frame_length)?;
+        Ok(frame.encode_to_vec())
+    }
+
+    /// Decode an event from a single frame without the length prefix, for transports that delimit
+    /// messages themselves, like WebSocket.
+    ///
+    /// # Errors
+    /// Returns an error if the frame is longer than the maximum frame length, or isn't a valid
+    /// event.
+    pub fn decode_message(&self, src: &[u8]) -> io::Result<NetworkEvent> {
+        check_frame_length(src.len(), self.max_frame_length)?;
+        let frame = EventFrame::decode(src)?;
+
+        NetworkEvent::try_from(frame)
+    }
+}
+
+impl Default for ClientCodec {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_FRAME_LENGTH)
+    }
+}
+
+impl Encoder<CommandRequest> for ClientCodec {
     type Error = io::Error;
 
-    fn encode(
-        &mut self,
-        item: NetworkCommand,
-        dst: &mut prost::bytes::BytesMut,
-    ) -> Result<(), Self::Error> {
+    fn encode(&mut self, item: CommandRequest, dst: &mut BytesMut) -> Result<(), Self::Error> {
         let frame = CommandFrame::from(item);
+        check_frame_length(frame.encoded_len(), self.max_frame_length)?;
         frame.encode_length_delimited(dst)?;
         Ok(())
     }
 }
 
-impl Decoder for ClientCodec {
-    type Item = NetworkEvent;
+impl Encoder<NetworkCommand> for ClientCodec {
     type Error = io::Error;
 
-    fn decode(
-        &mut self,
-        src: &mut prost::bytes::BytesMut,
-    ) -> Result<Option<Self::Item>, Self::Error> {
-        let mut peek_slice = src.as_ref();
-
-        // Quoting prost's documentation:
-        // An error may be returned in two cases:
-        //
-        // * If the supplied buffer contains fewer than 10 bytes, then an error indicates that more
-        //   input is required to decode the full delimiter.
-        // * If the supplied buffer contains 10 bytes or more, then the buffer contains an invalid
-        //   delimiter, and typically the buffer should be considered corrupt.
-        let len = match prost::decode_length_delimiter(&mut peek_slice) {
-            Ok(len) => len,
-            Err(_) if src.len() < 10 => return Ok(None),
-            Err(e) => return Err(e.into()),
-        };
+    fn encode(&mut self, item: NetworkCommand, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode(CommandRequest::from(item), dst)
+    }
+}
 
-        let delimiter_width = src.len() - peek_slice.len();
+impl Decoder for ClientCodec {
+    type Item = NetworkEvent;
+    type Error = io::Error;
 
-        if src.len() < delimiter_width + len {
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let Some(chunk) = split_frame(src, self.max_frame_length)? else {
             return Ok(None);
-        }
-
-        // Small optimization: avoid the redundant length delimiter calaculations that
-        // decode_length_delimited() would invoke.
-        src.advance(delimiter_width);
-        let chunk = src.split_to(len);
+        };
 
         let frame = EventFrame::decode(chunk)?;
 
@@ -72,64 +103,220 @@ impl Decoder for ClientCodec {
     }
 }
 
-/// A codec for a client-side `Framed` `TcpStream`. Internally, this uses protobuf with a varint
+/// A codec for a server-side `Framed` `TcpStream`. Internally, this uses protobuf with a varint
 /// length prefix.
 ///
-/// This codec sends `NetworkEvent`s and receives `NetworkCommand`s.
-#[derive(Debug)]
-pub struct ServerCodec;
+/// This codec sends `NetworkEvent`s and receives `CommandRequest`s. Frames longer than the maximum
+/// frame length are rejected in both directions.
+#[derive(Debug, Clone, Copy)]
+pub struct ServerCodec {
+    max_frame_length: usize,
+}
+
+impl ServerCodec {
+    /// Create a codec that rejects frames longer than `max_frame_length` bytes.
+    #[must_use]
+    pub const fn new(max_frame_length: usize) -> Self {
+        Self { max_frame_length }
+    }
+
+    /// The maximum length of a single frame, in bytes.
+    #[must_use]
+    pub const fn max_frame_length(&self) -> usize {
+        self.max_frame_length
+    }
+
+    /// Encode an event as a single frame without the length prefix, for transports that delimit
+    /// messages themselves, like WebSocket.
+    ///
+    /// # Errors
+    /// Returns an error if the frame is longer than the maximum frame length.
+    pub fn encode_message(&self, item: NetworkEvent) -> io::Result<Vec<u8>> {
+        let frame = EventFrame::from(item);
+        check_frame_length(frame.encoded_len(), self.max_frame_length)?;
+        Ok(frame.encode_to_vec())
+    }
+
+    /// Decode a command from a single frame without the length prefix, for transports that delimit
+    /// messages themselves, like WebSocket.
+    ///
+    /// # Errors
+    /// Returns an error if the frame is longer than the maximum frame length, or isn't a valid
+    /// command.
+    pub fn decode_message(&self, src: &[u8]) -> io::Result<CommandRequest> {
+        check_frame_length(src.len(), self.max_frame_length)?;
+        let frame = CommandFrame::decode(src)?;
+
+        CommandRequest::try_from(frame)
+    }
+}
+
+impl Default for ServerCodec {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_FRAME_LENGTH)
+    }
+}
 
 impl Encoder<NetworkEvent> for ServerCodec {
     type Error = io::Error;
 
-    fn encode(
-        &mut self,
-        item: NetworkEvent,
-        dst: &mut prost::bytes::BytesMut,
-    ) -> Result<(), Self::Error> {
+    fn encode(&mut self, item: NetworkEvent, dst: &mut BytesMut) -> Result<(), Self::Error> {
         let frame = EventFrame::from(item);
+        check_frame_length(frame.encoded_len(), self.max_frame_length)?;
         frame.encode_length_delimited(dst)?;
         Ok(())
     }
 }
 
 impl Decoder for ServerCodec {
-    type Item = NetworkCommand;
+    type Item = CommandRequest;
     type Error = io::Error;
 
-    fn decode(
-        &mut self,
-        src: &mut prost::bytes::BytesMut,
-    ) -> Result<Option<Self::Item>, Self::Error> {
-        let mut peek_slice = src.as_ref();
-
-        // Quoting prost's documentation:
-        // An error may be returned in two cases:
-        //
-        // * If the supplied buffer contains fewer than 10 bytes, then an error indicates that more
-        //   input is required to decode the full delimiter.
-        // * If the supplied buffer contains 10 bytes or more, then the buffer contains an invalid
-        //   delimiter, and typically the buffer should be considered corrupt.
-        let len = match prost::decode_length_delimiter(&mut peek_slice) {
-            Ok(len) => len,
-            Err(_) if src.len() < 10 => return Ok(None),
-            Err(e) => return Err(e.into()),
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let Some(chunk) = split_frame(src, self.max_frame_length)? else {
+            return Ok(None);
         };
 
-        let delimiter_width = src.len() - peek_slice.len();
+        let frame = CommandFrame::decode(chunk)?;
 
-        if src.len() < delimiter_width + len {
-            return Ok(None);
-        }
+        let request = CommandRequest::try_from(frame)?;
+        Ok(Some(request))
+    }
+}
 
-        // Small optimization: avoid the redundant length delimiter calaculations that
-        // decode_length_delimited() would invoke.
-        src.advance(delimiter_width);
-        let chunk = src.split_to(len);
+/// Split the next complete frame off of `src`, without its length prefix.
+///
+/// Returns `Ok(None)` if more input is needed. The announced length is checked against
+/// `max_frame_length` as soon as the prefix is readable, so an oversized frame is rejected before
+/// any of it is buffered.
+fn split_frame(src: &mut BytesMut, max_frame_length: usize) -> io::Result<Option<BytesMut>> {
+    let mut peek_slice = src.as_ref();
 
-        let frame = CommandFrame::decode(chunk)?;
+    // Quoting prost's documentation:
+    // An error may be returned in two cases:
+    //
+    // * If the supplied buffer contains fewer than 10 bytes, then an error indicates that more
+    //   input is required to decode the full delimiter.
+    // * If the supplied buffer contains 10 bytes or more, then the buffer contains an invalid
+    //   delimiter, and typically the buffer should be considered corrupt.
+    let len = match prost::decode_length_delimiter(&mut peek_slice) {
+        Ok(len) => len,
+        Err(_) if src.len() < 10 => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    check_frame_length(len, max_frame_length)?;
+
+    let delimiter_width = src.len() - peek_slice.len();
+
+    if src.len() < delimiter_width + len {
+        return Ok(None);
+    }
+
+    // Small optimization: avoid the redundant length delimiter calaculations that
+    // decode_length_delimited() would invoke.
+    src.advance(delimiter_width);
+    Ok(Some(src.split_to(len)))
+}
+
+fn check_frame_length(len: usize, max_frame_length: usize) -> io::Result<()> {
+    if len > max_frame_length {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame of {len} bytes exceeds the maximum of {max_frame_length} bytes"),
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{Ping, SendDestination, SendMessage, UserId};
+
+    /// A command that encodes to more than `len` bytes.
+    fn long_command(len: usize) -> NetworkCommand {
+        NetworkCommand::SendMessage(SendMessage {
+            contents: "a".repeat(len),
+            destination: SendDestination::User(UserId(Uuid::nil())),
+        })
+    }
+
+    #[test]
+    fn round_trips_frames() {
+        let mut encoded = BytesMut::new();
+        ServerCodec::new(64)
+            .encode(NetworkEvent::Ping(Ping { nonce: 7 }), &mut encoded)
+            .unwrap();
+
+        // Nothing is decoded until the whole frame has arrived.
+        let mut client = ClientCodec::new(64);
+        let mut src = BytesMut::from(&encoded[..encoded.len() - 1]);
+        assert!(client.decode(&mut src).unwrap().is_none());
+
+        src.extend_from_slice(&encoded[encoded.len() - 1..]);
+        let event = client.decode(&mut src).unwrap();
+        assert!(matches!(event, Some(NetworkEvent::Ping(Ping { nonce: 7 }))));
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn rejects_long_frame_from_prefix() {
+        let mut src = BytesMut::new();
+        prost::encode_length_delimiter(100, &mut src).unwrap();
+
+        let error = split_frame(&mut src, 10).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn accepts_frame_at_limit() {
+        let mut src = BytesMut::new();
+        prost::encode_length_delimiter(10, &mut src).unwrap();
+        src.extend_from_slice(&[0; 10]);
+
+        let frame = split_frame(&mut src, 10).unwrap().unwrap();
+        assert_eq!(frame.len(), 10);
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn encoder_rejects_long_frame() {
+        let mut dst = BytesMut::new();
+        let result = ClientCodec::new(16).encode(long_command(16), &mut dst);
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(dst.is_empty());
+    }
+
+    #[test]
+    fn round_trips_messages() {
+        let encoded = ClientCodec::new(64)
+            .encode_message(NetworkCommand::Ping(Ping { nonce: 7 }).into())
+            .unwrap();
+        let request = ServerCodec::new(64).decode_message(&encoded).unwrap();
+
+        assert!(matches!(
+            request,
+            CommandRequest {
+                request_id: None,
+                command: NetworkCommand::Ping(Ping { nonce: 7 }),
+            }
+        ));
+    }
+
+    #[test]
+    fn rejects_long_messages() {
+        let result = ClientCodec::new(16).encode_message(long_command(16).into());
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let result = ServerCodec::new(16).decode_message(&[0; 17]);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
 
-        let command = NetworkCommand::try_from(frame)?;
-        Ok(Some(command))
+        let result = ClientCodec::new(16).decode_message(&[0; 17]);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
     }
 }
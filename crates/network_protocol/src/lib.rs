@@ -1,3 +1,4 @@
+#[allow(clippy::doc_markdown, clippy::trivially_copy_pass_by_ref)]
 mod proto {
     include!(concat!(env!("OUT_DIR"), "/network_protocol.items.rs"));
 }
@@ -7,19 +8,25 @@ mod network_command;
 mod network_event;
 
 pub use network_command::{
-    ClientHello, FetchChannels, FetchUsers, NetworkCommand, SendDestination, SendMessage,
-    UpdateInfo,
+    Authentication, BanUser, ClientHello, CommandRequest, CreateChannel, Credentials,
+    DeleteChannel, DeleteMessage, EditMessage, FetchChannels, FetchHistory, FetchUsers,
+    HistoryDestination, JoinChannel, KickUser, LeaveChannel, MuteUser, NetworkCommand,
+    RenameChannel, SendDestination, SendMessage, UnmuteUser, UpdateInfo,
 };
 
 pub use network_event::{
-    ChannelInfo, ChannelSync, ErrorEvent, ErrorKind, NetworkEvent, ReceiveDestination,
-    ReceivedMessage, ServerHello, UserInfo, UserSync,
+    ChannelInfo, ChannelMember, ChannelMembers, ChannelSync, CommandAck, ErrorEvent, ErrorKind,
+    HistoryPage, MessageDeleted, MessageEdited, NetworkEvent, ReceiveDestination, ReceivedMessage,
+    Resync, ServerHello, ServerNotice, ServerShuttingDown, UserBanned, UserInfo, UserKicked,
+    UserSync,
 };
 
+use std::collections::BTreeSet;
 use std::fmt::{self, Display, Formatter};
 use std::io;
 use std::num::ParseIntError;
 use std::str::FromStr;
+use std::time::{Duration, SystemTime};
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
@@ -28,6 +35,19 @@ use uuid::Uuid;
 /// Default port the server listens to for new connections.
 pub const DEFAULT_LISTENER_PORT: u16 = 12345;
 
+/// Version of the protocol implemented by this crate. Bumped on every change to the wire format
+/// that older peers can't handle.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Oldest protocol version this crate can still talk to.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Check whether a peer speaking the given protocol version can talk to this crate.
+#[must_use]
+pub fn is_compatible_version(version: u32) -> bool {
+    (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version)
+}
+
 impl TryFrom<proto::Uuid> for Uuid {
     type Error = io::Error;
 
@@ -84,8 +104,41 @@ impl Display for UserId {
     }
 }
 
+/// Type to uniquely identify chat messages. IDs are assigned by the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MessageId(pub Uuid);
+
+impl TryFrom<proto::Uuid> for MessageId {
+    type Error = io::Error;
+
+    fn try_from(value: proto::Uuid) -> Result<Self, Self::Error> {
+        Ok(Self(value.try_into()?))
+    }
+}
+
+impl From<MessageId> for proto::Uuid {
+    fn from(value: MessageId) -> Self {
+        value.0.into()
+    }
+}
+
+impl FromStr for MessageId {
+    type Err = uuid::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(Uuid::from_str(s)?))
+    }
+}
+
+impl Display for MessageId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "MessageId({})", self.0)
+    }
+}
+
 /// Type to uniquely identify channels.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ChannelId(u64);
 
@@ -119,6 +172,305 @@ impl Display for ChannelId {
     }
 }
 
+/// Client-chosen ID to correlate a command with the server's reply to it. IDs only need to be
+/// unique among the commands a client has in flight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RequestId(pub u64);
+
+// Infallible, but TryFrom for consistency with the other wire -> domain conversions.
+impl TryFrom<u64> for RequestId {
+    type Error = io::Error;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        Ok(Self(value))
+    }
+}
+
+impl From<RequestId> for u64 {
+    fn from(value: RequestId) -> Self {
+        value.0
+    }
+}
+
+impl Display for RequestId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "RequestId({})", self.0)
+    }
+}
+
+/// Opaque token to resume a session after the connection drops. Tokens are secrets, so they are
+/// never printed.
+#[derive(Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ResumeToken(pub Vec<u8>);
+
+impl fmt::Debug for ResumeToken {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "ResumeToken(<redacted>)")
+    }
+}
+
+impl TryFrom<Vec<u8>> for ResumeToken {
+    type Error = io::Error;
+
+    fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
+        Ok(Self(value))
+    }
+}
+
+impl From<ResumeToken> for Vec<u8> {
+    fn from(value: ResumeToken) -> Self {
+        value.0
+    }
+}
+
+/// Keepalive probe, sent by either side. The other side answers with a [`Pong`] carrying the same
+/// nonce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Ping {
+    /// Arbitrary value to match the [`Pong`] to this `Ping`.
+    pub nonce: u64,
+}
+
+impl TryFrom<proto::Ping> for Ping {
+    type Error = io::Error;
+
+    fn try_from(value: proto::Ping) -> Result<Self, Self::Error> {
+        Ok(Self { nonce: value.nonce })
+    }
+}
+
+impl From<Ping> for proto::Ping {
+    fn from(value: Ping) -> Self {
+        Self { nonce: value.nonce }
+    }
+}
+
+/// Answer to a [`Ping`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Pong {
+    /// The nonce of the [`Ping`] this answers.
+    pub nonce: u64,
+}
+
+impl TryFrom<proto::Pong> for Pong {
+    type Error = io::Error;
+
+    fn try_from(value: proto::Pong) -> Result<Self, Self::Error> {
+        Ok(Self { nonce: value.nonce })
+    }
+}
+
+impl From<Pong> for proto::Pong {
+    fn from(value: Pong) -> Self {
+        Self { nonce: value.nonce }
+    }
+}
+
+/// What a user may do on a server. Roles are ordered by privilege, so `Member < Moderator <
+/// Owner`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Role {
+    /// A regular user. Guests are always members.
+    #[default]
+    Member,
+
+    /// May create, rename and delete channels, and kick, mute and ban members.
+    Moderator,
+
+    /// May create, rename and delete channels, and kick, mute and ban anyone but other owners.
+    Owner,
+}
+
+impl Role {
+    /// Every role, from least to most privileged.
+    pub const ALL: [Self; 3] = [Self::Member, Self::Moderator, Self::Owner];
+
+    /// Get the name of the role, as used in configuration and storage.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Member => "member",
+            Self::Moderator => "moderator",
+            Self::Owner => "owner",
+        }
+    }
+
+    /// Whether a user with this role may kick, mute or ban a user with the `target` role. Only
+    /// moderators and owners may moderate, and only users below them.
+    #[must_use]
+    pub fn can_moderate(self, target: Self) -> bool {
+        self >= Self::Moderator && self > target
+    }
+}
+
+impl TryFrom<i32> for Role {
+    type Error = io::Error;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Member),
+            1 => Ok(Self::Moderator),
+            2 => Ok(Self::Owner),
+            _ => Err(io_err_invalid_data()),
+        }
+    }
+}
+
+impl From<Role> for i32 {
+    fn from(value: Role) -> Self {
+        match value {
+            Role::Member => 0,
+            Role::Moderator => 1,
+            Role::Owner => 2,
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|role| role.as_str().eq_ignore_ascii_case(s))
+            .ok_or(())
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An optional feature of the protocol. Peers advertise the capabilities they support in their
+/// hellos, and only use features both of them support.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Capability {
+    /// Fetching pages of message history.
+    History,
+
+    /// Editing and deleting sent messages.
+    MessageEditing,
+
+    /// Creating, renaming and deleting channels at runtime.
+    ChannelManagement,
+
+    /// Registering and logging into accounts.
+    Accounts,
+
+    /// Logging in with a client certificate.
+    CertificateLogin,
+
+    /// Resuming a session after the connection drops.
+    SessionResumption,
+
+    /// Exchanging [`Ping`]s and [`Pong`]s to detect dead connections.
+    Keepalive,
+
+    /// Acknowledging commands that carry a [`RequestId`] with a [`CommandAck`].
+    CommandAcks,
+
+    /// Sending a [`Resync`] instead of disconnecting clients that fall behind.
+    Resync,
+
+    /// Kicking, muting and banning users.
+    Moderation,
+}
+
+impl Capability {
+    /// Every capability this crate knows about.
+    pub const ALL: [Self; 10] = [
+        Self::History,
+        Self::MessageEditing,
+        Self::ChannelManagement,
+        Self::Accounts,
+        Self::CertificateLogin,
+        Self::SessionResumption,
+        Self::Keepalive,
+        Self::CommandAcks,
+        Self::Resync,
+        Self::Moderation,
+    ];
+
+    /// Get the name of the capability on the wire.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::History => "history",
+            Self::MessageEditing => "message_editing",
+            Self::ChannelManagement => "channel_management",
+            Self::Accounts => "accounts",
+            Self::CertificateLogin => "certificate_login",
+            Self::SessionResumption => "session_resumption",
+            Self::Keepalive => "keepalive",
+            Self::CommandAcks => "command_acks",
+            Self::Resync => "resync",
+            Self::Moderation => "moderation",
+        }
+    }
+}
+
+impl FromStr for Capability {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|capability| capability.as_str() == s)
+            .ok_or(())
+    }
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// Newer peers may know capabilities we don't, so unknown ones are skipped rather than rejected.
+fn capabilities_from_proto(value: Vec<String>) -> BTreeSet<Capability> {
+    value.iter().filter_map(|name| name.parse().ok()).collect()
+}
+
+fn capabilities_to_proto(value: BTreeSet<Capability>) -> Vec<String> {
+    value
+        .into_iter()
+        .map(|capability| capability.as_str().to_owned())
+        .collect()
+}
+
 fn io_err_invalid_data() -> io::Error {
     io::Error::from(io::ErrorKind::InvalidData)
 }
+
+fn time_from_proto(value: prost_types::Timestamp) -> Result<SystemTime, io::Error> {
+    value.try_into().map_err(|_| io_err_invalid_data())
+}
+
+fn duration_from_proto(value: prost_types::Duration) -> Result<Duration, io::Error> {
+    value.try_into().map_err(|_| io_err_invalid_data())
+}
+
+// Only durations of hundreds of billions of years don't fit, so saturating is fine.
+fn duration_to_proto(value: Duration) -> prost_types::Duration {
+    value.try_into().unwrap_or(prost_types::Duration {
+        seconds: i64::MAX,
+        nanos: 999_999_999,
+    })
+}
+
+// Limits past `u32::MAX` are effectively unlimited, so saturating is fine.
+fn saturating_u32(value: usize) -> u32 {
+    u32::try_from(value).unwrap_or(u32::MAX)
+}
+
+fn usize_from_u32(value: u32) -> usize {
+    usize::try_from(value).unwrap_or(usize::MAX)
+}
@@ -1,29 +1,54 @@
-use std::io;
+use std::{collections::BTreeSet, fmt, io, time::Duration};
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    ChannelId, UserId, io_err_invalid_data,
-    proto::{self, CommandFrame, command_frame, send_message},
+    Capability, ChannelId, MessageId, Ping, Pong, RequestId, ResumeToken, UserId,
+    capabilities_from_proto, capabilities_to_proto, duration_from_proto, duration_to_proto,
+    io_err_invalid_data,
+    proto::{self, CommandFrame, client_hello, command_frame, fetch_history, send_message},
 };
 
+type ProtoAuthentication = client_hello::Authentication;
 type ProtoSendDestination = send_message::Destination;
+type ProtoFetchHistoryDestination = fetch_history::Destination;
 
-/// First message from the client to the server, indicating a desire to connect and requesting the
-/// given username.
+/// First message from the client to the server, indicating a desire to connect and how the user
+/// wishes to identify themselves.
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ClientHello {
-    pub requested_name: String,
+    pub authentication: Authentication,
+
+    /// Token from a previous session's [`ServerHello`](crate::ServerHello). If the server still
+    /// holds that session, it is resumed and `authentication` is ignored.
+    pub resume_token: Option<ResumeToken>,
+
+    /// Version of the protocol the client speaks. Usually
+    /// [`PROTOCOL_VERSION`](crate::PROTOCOL_VERSION).
+    pub protocol_version: u32,
+
+    /// Optional features the client supports.
+    pub capabilities: BTreeSet<Capability>,
 }
 
 impl TryFrom<proto::ClientHello> for ClientHello {
     type Error = io::Error;
 
     fn try_from(value: proto::ClientHello) -> Result<Self, Self::Error> {
+        let authentication = value
+            .authentication
+            .ok_or_else(io_err_invalid_data)?
+            .try_into()?;
+
+        let resume_token = value.resume_token.map(TryInto::try_into).transpose()?;
+
         Ok(Self {
-            requested_name: value.requested_name,
+            authentication,
+            resume_token,
+            protocol_version: value.protocol_version,
+            capabilities: capabilities_from_proto(value.capabilities),
         })
     }
 }
@@ -31,7 +56,90 @@ impl TryFrom<proto::ClientHello> for ClientHello {
 impl From<ClientHello> for proto::ClientHello {
     fn from(value: ClientHello) -> Self {
         Self {
-            requested_name: value.requested_name,
+            authentication: Some(value.authentication.into()),
+            resume_token: value.resume_token.map(Into::into),
+            protocol_version: value.protocol_version,
+            capabilities: capabilities_to_proto(value.capabilities),
+        }
+    }
+}
+
+/// How a user identifies themselves when connecting.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Authentication {
+    /// Join as an anonymous guest. Guests get a new [`UserId`] every time they connect.
+    Guest { requested_name: String },
+
+    /// Create a new account with the given credentials, then log into it.
+    Register(Credentials),
+
+    /// Log into an existing account. Accounts keep the same [`UserId`] across sessions.
+    Login(Credentials),
+
+    /// Log in as the identity in the client certificate presented during the TLS handshake. The
+    /// server creates an account for new certificates, named after their common name.
+    Certificate,
+}
+
+impl TryFrom<ProtoAuthentication> for Authentication {
+    type Error = io::Error;
+
+    fn try_from(value: ProtoAuthentication) -> Result<Self, Self::Error> {
+        Ok(match value {
+            ProtoAuthentication::GuestName(requested_name) => Self::Guest { requested_name },
+            ProtoAuthentication::Register(credentials) => Self::Register(credentials.try_into()?),
+            ProtoAuthentication::Login(credentials) => Self::Login(credentials.try_into()?),
+            ProtoAuthentication::Certificate(proto::CertificateLogin {}) => Self::Certificate,
+        })
+    }
+}
+
+impl From<Authentication> for ProtoAuthentication {
+    fn from(value: Authentication) -> Self {
+        match value {
+            Authentication::Guest { requested_name } => Self::GuestName(requested_name),
+            Authentication::Register(credentials) => Self::Register(credentials.into()),
+            Authentication::Login(credentials) => Self::Login(credentials.into()),
+            Authentication::Certificate => Self::Certificate(proto::CertificateLogin {}),
+        }
+    }
+}
+
+/// Username and password for an account.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+// Hand-written so the password never ends up in logs.
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+impl TryFrom<proto::Credentials> for Credentials {
+    type Error = io::Error;
+
+    fn try_from(value: proto::Credentials) -> Result<Self, Self::Error> {
+        Ok(Self {
+            username: value.username,
+            password: value.password,
+        })
+    }
+}
+
+impl From<Credentials> for proto::Credentials {
+    fn from(value: Credentials) -> Self {
+        Self {
+            username: value.username,
+            password: value.password,
         }
     }
 }
@@ -75,7 +183,7 @@ impl From<FetchUsers> for proto::FetchUsers {
 }
 
 /// Where to send a chat message.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum SendDestination {
     /// Send to a channel with the given ID.
@@ -166,6 +274,417 @@ impl From<UpdateInfo> for proto::UpdateInfo {
     }
 }
 
+/// Which conversation a page of message history belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum HistoryDestination {
+    /// The history of a channel with the given ID.
+    Channel(ChannelId),
+
+    /// The history of your direct conversation with the user with the given ID.
+    User(UserId),
+}
+
+impl TryFrom<ProtoFetchHistoryDestination> for HistoryDestination {
+    type Error = io::Error;
+
+    fn try_from(value: ProtoFetchHistoryDestination) -> Result<Self, Self::Error> {
+        Ok(match value {
+            ProtoFetchHistoryDestination::ChannelId(id) => Self::Channel(id.try_into()?),
+            ProtoFetchHistoryDestination::UserId(id) => Self::User(id.try_into()?),
+        })
+    }
+}
+
+impl From<HistoryDestination> for ProtoFetchHistoryDestination {
+    fn from(value: HistoryDestination) -> Self {
+        match value {
+            HistoryDestination::Channel(id) => Self::ChannelId(id.into()),
+            HistoryDestination::User(id) => Self::UserId(id.into()),
+        }
+    }
+}
+
+/// A request to fetch a page of message history.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FetchHistory {
+    /// The conversation to fetch history from.
+    pub destination: HistoryDestination,
+
+    /// Only fetch messages older than this message. If `None`, the newest messages are fetched.
+    pub before: Option<MessageId>,
+
+    /// Maximum number of messages to fetch. The server may return fewer, and may cap this value.
+    /// Zero requests the server's maximum page size.
+    pub limit: u32,
+}
+
+impl TryFrom<proto::FetchHistory> for FetchHistory {
+    type Error = io::Error;
+
+    fn try_from(value: proto::FetchHistory) -> Result<Self, Self::Error> {
+        let destination: HistoryDestination = value
+            .destination
+            .ok_or_else(io_err_invalid_data)?
+            .try_into()?;
+
+        let before = value.before.map(TryInto::try_into).transpose()?;
+
+        Ok(Self {
+            destination,
+            before,
+            limit: value.limit,
+        })
+    }
+}
+
+impl From<FetchHistory> for proto::FetchHistory {
+    fn from(value: FetchHistory) -> Self {
+        Self {
+            destination: Some(value.destination.into()),
+            before: value.before.map(Into::into),
+            limit: value.limit,
+        }
+    }
+}
+
+/// A request to replace the contents of a message you sent.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EditMessage {
+    /// ID of the message to edit.
+    pub message_id: MessageId,
+
+    /// The message's new content.
+    pub contents: String,
+}
+
+impl TryFrom<proto::EditMessage> for EditMessage {
+    type Error = io::Error;
+
+    fn try_from(value: proto::EditMessage) -> Result<Self, Self::Error> {
+        let message_id: MessageId = value
+            .message_id
+            .ok_or_else(io_err_invalid_data)?
+            .try_into()?;
+
+        Ok(Self {
+            message_id,
+            contents: value.contents,
+        })
+    }
+}
+
+impl From<EditMessage> for proto::EditMessage {
+    fn from(value: EditMessage) -> Self {
+        Self {
+            message_id: Some(value.message_id.into()),
+            contents: value.contents,
+        }
+    }
+}
+
+/// A request to delete a message you sent.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DeleteMessage {
+    /// ID of the message to delete.
+    pub message_id: MessageId,
+}
+
+impl TryFrom<proto::DeleteMessage> for DeleteMessage {
+    type Error = io::Error;
+
+    fn try_from(value: proto::DeleteMessage) -> Result<Self, Self::Error> {
+        let message_id: MessageId = value
+            .message_id
+            .ok_or_else(io_err_invalid_data)?
+            .try_into()?;
+
+        Ok(Self { message_id })
+    }
+}
+
+impl From<DeleteMessage> for proto::DeleteMessage {
+    fn from(value: DeleteMessage) -> Self {
+        Self {
+            message_id: Some(value.message_id.into()),
+        }
+    }
+}
+
+/// A request to create a new channel. The server assigns the channel's ID.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CreateChannel {
+    /// The new channel's display name.
+    pub name: String,
+}
+
+impl TryFrom<proto::CreateChannel> for CreateChannel {
+    type Error = io::Error;
+
+    fn try_from(value: proto::CreateChannel) -> Result<Self, Self::Error> {
+        Ok(Self { name: value.name })
+    }
+}
+
+impl From<CreateChannel> for proto::CreateChannel {
+    fn from(value: CreateChannel) -> Self {
+        Self { name: value.name }
+    }
+}
+
+/// A request to change a channel's display name.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RenameChannel {
+    /// ID of the channel to rename.
+    pub channel_id: ChannelId,
+
+    /// The channel's new display name.
+    pub new_name: String,
+}
+
+impl TryFrom<proto::RenameChannel> for RenameChannel {
+    type Error = io::Error;
+
+    fn try_from(value: proto::RenameChannel) -> Result<Self, Self::Error> {
+        Ok(Self {
+            channel_id: value.channel_id.try_into()?,
+            new_name: value.new_name,
+        })
+    }
+}
+
+impl From<RenameChannel> for proto::RenameChannel {
+    fn from(value: RenameChannel) -> Self {
+        Self {
+            channel_id: value.channel_id.into(),
+            new_name: value.new_name,
+        }
+    }
+}
+
+/// A request to delete a channel.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DeleteChannel {
+    /// ID of the channel to delete.
+    pub channel_id: ChannelId,
+}
+
+impl TryFrom<proto::DeleteChannel> for DeleteChannel {
+    type Error = io::Error;
+
+    fn try_from(value: proto::DeleteChannel) -> Result<Self, Self::Error> {
+        Ok(Self {
+            channel_id: value.channel_id.try_into()?,
+        })
+    }
+}
+
+impl From<DeleteChannel> for proto::DeleteChannel {
+    fn from(value: DeleteChannel) -> Self {
+        Self {
+            channel_id: value.channel_id.into(),
+        }
+    }
+}
+
+/// A request to join a channel.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct JoinChannel {
+    /// ID of the channel to join.
+    pub channel_id: ChannelId,
+}
+
+impl TryFrom<proto::JoinChannel> for JoinChannel {
+    type Error = io::Error;
+
+    fn try_from(value: proto::JoinChannel) -> Result<Self, Self::Error> {
+        Ok(Self {
+            channel_id: value.channel_id.try_into()?,
+        })
+    }
+}
+
+impl From<JoinChannel> for proto::JoinChannel {
+    fn from(value: JoinChannel) -> Self {
+        Self {
+            channel_id: value.channel_id.into(),
+        }
+    }
+}
+
+/// A request to leave a channel.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LeaveChannel {
+    /// ID of the channel to leave.
+    pub channel_id: ChannelId,
+}
+
+impl TryFrom<proto::LeaveChannel> for LeaveChannel {
+    type Error = io::Error;
+
+    fn try_from(value: proto::LeaveChannel) -> Result<Self, Self::Error> {
+        Ok(Self {
+            channel_id: value.channel_id.try_into()?,
+        })
+    }
+}
+
+impl From<LeaveChannel> for proto::LeaveChannel {
+    fn from(value: LeaveChannel) -> Self {
+        Self {
+            channel_id: value.channel_id.into(),
+        }
+    }
+}
+
+/// A request to disconnect a user from the server.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct KickUser {
+    /// ID of the user to kick.
+    pub user_id: UserId,
+
+    /// Why the user is kicked. Shown to everyone.
+    pub reason: String,
+}
+
+impl TryFrom<proto::KickUser> for KickUser {
+    type Error = io::Error;
+
+    fn try_from(value: proto::KickUser) -> Result<Self, Self::Error> {
+        Ok(Self {
+            user_id: value.user_id.ok_or_else(io_err_invalid_data)?.try_into()?,
+            reason: value.reason,
+        })
+    }
+}
+
+impl From<KickUser> for proto::KickUser {
+    fn from(value: KickUser) -> Self {
+        Self {
+            user_id: Some(value.user_id.into()),
+            reason: value.reason,
+        }
+    }
+}
+
+/// A request to stop a user from sending messages to a channel.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MuteUser {
+    /// ID of the channel to mute the user in.
+    pub channel_id: ChannelId,
+
+    /// ID of the user to mute.
+    pub user_id: UserId,
+
+    /// How long the mute lasts. If `None`, it lasts until the user is unmuted.
+    pub duration: Option<Duration>,
+}
+
+impl TryFrom<proto::MuteUser> for MuteUser {
+    type Error = io::Error;
+
+    fn try_from(value: proto::MuteUser) -> Result<Self, Self::Error> {
+        Ok(Self {
+            channel_id: value.channel_id.try_into()?,
+            user_id: value.user_id.ok_or_else(io_err_invalid_data)?.try_into()?,
+            duration: value.duration.map(duration_from_proto).transpose()?,
+        })
+    }
+}
+
+impl From<MuteUser> for proto::MuteUser {
+    fn from(value: MuteUser) -> Self {
+        Self {
+            channel_id: value.channel_id.into(),
+            user_id: Some(value.user_id.into()),
+            duration: value.duration.map(duration_to_proto),
+        }
+    }
+}
+
+/// A request to lift a user's mute in a channel.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct UnmuteUser {
+    /// ID of the channel the user is muted in.
+    pub channel_id: ChannelId,
+
+    /// ID of the user to unmute.
+    pub user_id: UserId,
+}
+
+impl TryFrom<proto::UnmuteUser> for UnmuteUser {
+    type Error = io::Error;
+
+    fn try_from(value: proto::UnmuteUser) -> Result<Self, Self::Error> {
+        Ok(Self {
+            channel_id: value.channel_id.try_into()?,
+            user_id: value.user_id.ok_or_else(io_err_invalid_data)?.try_into()?,
+        })
+    }
+}
+
+impl From<UnmuteUser> for proto::UnmuteUser {
+    fn from(value: UnmuteUser) -> Self {
+        Self {
+            channel_id: value.channel_id.into(),
+            user_id: Some(value.user_id.into()),
+        }
+    }
+}
+
+/// A request to disconnect a user and keep them from connecting again.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BanUser {
+    /// ID of the user to ban.
+    pub user_id: UserId,
+
+    /// Why the user is banned. Shown to everyone.
+    pub reason: String,
+
+    /// How long the ban lasts. If `None`, the ban is permanent.
+    pub duration: Option<Duration>,
+
+    /// Ban the address the user is connected from, instead of their account. Guests can only be
+    /// banned this way.
+    pub by_address: bool,
+}
+
+impl TryFrom<proto::BanUser> for BanUser {
+    type Error = io::Error;
+
+    fn try_from(value: proto::BanUser) -> Result<Self, Self::Error> {
+        Ok(Self {
+            user_id: value.user_id.ok_or_else(io_err_invalid_data)?.try_into()?,
+            reason: value.reason,
+            duration: value.duration.map(duration_from_proto).transpose()?,
+            by_address: value.by_address,
+        })
+    }
+}
+
+impl From<BanUser> for proto::BanUser {
+    fn from(value: BanUser) -> Self {
+        Self {
+            user_id: Some(value.user_id.into()),
+            reason: value.reason,
+            duration: value.duration.map(duration_to_proto),
+            by_address: value.by_address,
+        }
+    }
+}
+
 /// A command sent from the client backend to the server.
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -184,6 +703,49 @@ pub enum NetworkCommand {
 
     /// Update your info.
     UpdateInfo(UpdateInfo),
+
+    /// Fetch a page of message history.
+    FetchHistory(FetchHistory),
+
+    /// Edit a message you sent.
+    EditMessage(EditMessage),
+
+    /// Delete a message you sent.
+    DeleteMessage(DeleteMessage),
+
+    /// Create a new channel.
+    CreateChannel(CreateChannel),
+
+    /// Rename a channel.
+    RenameChannel(RenameChannel),
+
+    /// Delete a channel.
+    DeleteChannel(DeleteChannel),
+
+    /// Join a channel.
+    JoinChannel(JoinChannel),
+
+    /// Leave a channel.
+    LeaveChannel(LeaveChannel),
+
+    /// Check that the server is still there. The server answers with a
+    /// [`NetworkEvent::Pong`](crate::NetworkEvent::Pong).
+    Ping(Ping),
+
+    /// Answer a [`NetworkEvent::Ping`](crate::NetworkEvent::Ping) from the server.
+    Pong(Pong),
+
+    /// Kick a user off the server.
+    KickUser(KickUser),
+
+    /// Mute a user in a channel.
+    MuteUser(MuteUser),
+
+    /// Unmute a user in a channel.
+    UnmuteUser(UnmuteUser),
+
+    /// Ban a user from the server.
+    BanUser(BanUser),
 }
 
 impl NetworkCommand {
@@ -196,6 +758,20 @@ impl NetworkCommand {
             Self::FetchUsers(_) => "FetchUsers",
             Self::SendMessage(_) => "SendMessage",
             Self::UpdateInfo(_) => "UpdateInfo",
+            Self::FetchHistory(_) => "FetchHistory",
+            Self::EditMessage(_) => "EditMessage",
+            Self::DeleteMessage(_) => "DeleteMessage",
+            Self::CreateChannel(_) => "CreateChannel",
+            Self::RenameChannel(_) => "RenameChannel",
+            Self::DeleteChannel(_) => "DeleteChannel",
+            Self::JoinChannel(_) => "JoinChannel",
+            Self::LeaveChannel(_) => "LeaveChannel",
+            Self::Ping(_) => "Ping",
+            Self::Pong(_) => "Pong",
+            Self::KickUser(_) => "KickUser",
+            Self::MuteUser(_) => "MuteUser",
+            Self::UnmuteUser(_) => "UnmuteUser",
+            Self::BanUser(_) => "BanUser",
         }
     }
 }
@@ -216,6 +792,34 @@ impl TryFrom<CommandFrame> for NetworkCommand {
             Variant::SendMessage(message) => Ok(NetworkCommand::SendMessage(message.try_into()?)),
 
             Variant::UpdateInfo(info) => Ok(NetworkCommand::UpdateInfo(info.try_into()?)),
+
+            Variant::FetchHistory(fetch) => Ok(NetworkCommand::FetchHistory(fetch.try_into()?)),
+
+            Variant::EditMessage(edit) => Ok(NetworkCommand::EditMessage(edit.try_into()?)),
+
+            Variant::DeleteMessage(delete) => Ok(NetworkCommand::DeleteMessage(delete.try_into()?)),
+
+            Variant::CreateChannel(create) => Ok(NetworkCommand::CreateChannel(create.try_into()?)),
+
+            Variant::RenameChannel(rename) => Ok(NetworkCommand::RenameChannel(rename.try_into()?)),
+
+            Variant::DeleteChannel(delete) => Ok(NetworkCommand::DeleteChannel(delete.try_into()?)),
+
+            Variant::JoinChannel(join) => Ok(NetworkCommand::JoinChannel(join.try_into()?)),
+
+            Variant::LeaveChannel(leave) => Ok(NetworkCommand::LeaveChannel(leave.try_into()?)),
+
+            Variant::Ping(ping) => Ok(NetworkCommand::Ping(ping.try_into()?)),
+
+            Variant::Pong(pong) => Ok(NetworkCommand::Pong(pong.try_into()?)),
+
+            Variant::KickUser(kick) => Ok(NetworkCommand::KickUser(kick.try_into()?)),
+
+            Variant::MuteUser(mute) => Ok(NetworkCommand::MuteUser(mute.try_into()?)),
+
+            Variant::UnmuteUser(unmute) => Ok(NetworkCommand::UnmuteUser(unmute.try_into()?)),
+
+            Variant::BanUser(ban) => Ok(NetworkCommand::BanUser(ban.try_into()?)),
         }
     }
 }
@@ -224,26 +828,91 @@ impl From<NetworkCommand> for CommandFrame {
     fn from(value: NetworkCommand) -> Self {
         use command_frame::Variant;
 
-        match value {
-            NetworkCommand::ClientHello(hello) => CommandFrame {
-                variant: Some(Variant::ClientHello(hello.into())),
-            },
+        let variant = match value {
+            NetworkCommand::ClientHello(hello) => Variant::ClientHello(hello.into()),
+
+            NetworkCommand::FetchChannels(fetch) => Variant::FetchChannels(fetch.into()),
+
+            NetworkCommand::FetchUsers(fetch) => Variant::FetchUsers(fetch.into()),
+
+            NetworkCommand::SendMessage(message) => Variant::SendMessage(message.into()),
+
+            NetworkCommand::UpdateInfo(info) => Variant::UpdateInfo(info.into()),
+
+            NetworkCommand::FetchHistory(fetch) => Variant::FetchHistory(fetch.into()),
+
+            NetworkCommand::EditMessage(edit) => Variant::EditMessage(edit.into()),
+
+            NetworkCommand::DeleteMessage(delete) => Variant::DeleteMessage(delete.into()),
 
-            NetworkCommand::FetchChannels(fetch) => CommandFrame {
-                variant: Some(Variant::FetchChannels(fetch.into())),
-            },
+            NetworkCommand::CreateChannel(create) => Variant::CreateChannel(create.into()),
 
-            NetworkCommand::FetchUsers(fetch) => CommandFrame {
-                variant: Some(Variant::FetchUsers(fetch.into())),
-            },
+            NetworkCommand::RenameChannel(rename) => Variant::RenameChannel(rename.into()),
 
-            NetworkCommand::SendMessage(message) => CommandFrame {
-                variant: Some(Variant::SendMessage(message.into())),
-            },
+            NetworkCommand::DeleteChannel(delete) => Variant::DeleteChannel(delete.into()),
 
-            NetworkCommand::UpdateInfo(info) => CommandFrame {
-                variant: Some(Variant::UpdateInfo(info.into())),
-            },
+            NetworkCommand::JoinChannel(join) => Variant::JoinChannel(join.into()),
+
+            NetworkCommand::LeaveChannel(leave) => Variant::LeaveChannel(leave.into()),
+
+            NetworkCommand::Ping(ping) => Variant::Ping(ping.into()),
+
+            NetworkCommand::Pong(pong) => Variant::Pong(pong.into()),
+
+            NetworkCommand::KickUser(kick) => Variant::KickUser(kick.into()),
+
+            NetworkCommand::MuteUser(mute) => Variant::MuteUser(mute.into()),
+
+            NetworkCommand::UnmuteUser(unmute) => Variant::UnmuteUser(unmute.into()),
+
+            NetworkCommand::BanUser(ban) => Variant::BanUser(ban.into()),
+        };
+
+        CommandFrame {
+            variant: Some(variant),
+            request_id: None,
+        }
+    }
+}
+
+/// A [`NetworkCommand`], optionally tagged with a [`RequestId`] so that the server's reply can be
+/// matched to it.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CommandRequest {
+    /// If set, the server answers with a [`CommandAck`](crate::CommandAck) or an
+    /// [`ErrorEvent`](crate::ErrorEvent) carrying this ID.
+    pub request_id: Option<RequestId>,
+    pub command: NetworkCommand,
+}
+
+impl From<NetworkCommand> for CommandRequest {
+    fn from(value: NetworkCommand) -> Self {
+        Self {
+            request_id: None,
+            command: value,
+        }
+    }
+}
+
+impl TryFrom<CommandFrame> for CommandRequest {
+    type Error = io::Error;
+
+    fn try_from(value: CommandFrame) -> Result<Self, Self::Error> {
+        let request_id = value.request_id.map(RequestId::try_from).transpose()?;
+
+        Ok(Self {
+            request_id,
+            command: value.try_into()?,
+        })
+    }
+}
+
+impl From<CommandRequest> for CommandFrame {
+    fn from(value: CommandRequest) -> Self {
+        Self {
+            request_id: value.request_id.map(Into::into),
+            ..value.command.into()
         }
     }
 }
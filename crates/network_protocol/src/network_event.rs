@@ -1,17 +1,30 @@
-use std::{error, fmt, io};
+use std::{
+    collections::BTreeSet,
+    error, fmt, io,
+    time::{Duration, SystemTime},
+};
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    ChannelId, UserId, io_err_invalid_data,
-    proto::{self, EventFrame, event_frame, received_message},
+    Capability, ChannelId, HistoryDestination, MessageId, Ping, Pong, RequestId, ResumeToken, Role,
+    UserId, capabilities_from_proto, capabilities_to_proto, duration_from_proto, duration_to_proto,
+    io_err_invalid_data,
+    proto::{
+        self, EventFrame, event_frame, history_page, message_deleted, message_edited,
+        received_message,
+    },
+    saturating_u32, time_from_proto, usize_from_u32,
 };
 
 type ProtoReceiveDestination = received_message::Destination;
+type ProtoHistoryPageDestination = history_page::Destination;
+type ProtoMessageEditedDestination = message_edited::Destination;
+type ProtoMessageDeletedDestination = message_deleted::Destination;
 
 /// Details about where a chat message is sent to.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ReceiveDestination {
     /// Message is sent directly to the client.
@@ -45,6 +58,16 @@ impl From<ReceiveDestination> for ProtoReceiveDestination {
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ReceivedMessage {
+    /// Server-assigned ID of the message. IDs are unique, and sort in the order the server
+    /// received the messages.
+    pub id: MessageId,
+
+    /// Server time at which the message was received.
+    pub timestamp: SystemTime,
+
+    /// Server time at which the message was last edited, if it was ever edited.
+    pub edited_at: Option<SystemTime>,
+
     /// The message's content.
     pub contents: String,
 
@@ -69,7 +92,15 @@ impl TryFrom<proto::ReceivedMessage> for ReceivedMessage {
             .ok_or_else(io_err_invalid_data)?
             .try_into()?;
 
+        let id: MessageId = value.id.ok_or_else(io_err_invalid_data)?.try_into()?;
+
+        let timestamp = time_from_proto(value.timestamp.ok_or_else(io_err_invalid_data)?)?;
+        let edited_at = value.edited_at.map(time_from_proto).transpose()?;
+
         Ok(ReceivedMessage {
+            id,
+            timestamp,
+            edited_at,
             contents: value.contents,
             sender_id,
             destination,
@@ -80,9 +111,236 @@ impl TryFrom<proto::ReceivedMessage> for ReceivedMessage {
 impl From<ReceivedMessage> for proto::ReceivedMessage {
     fn from(value: ReceivedMessage) -> Self {
         Self {
+            id: Some(value.id.into()),
             contents: value.contents,
             sender_id: Some(value.sender_id.into()),
             destination: Some(value.destination.into()),
+            timestamp: Some(value.timestamp.into()),
+            edited_at: value.edited_at.map(Into::into),
+        }
+    }
+}
+
+impl TryFrom<ProtoMessageEditedDestination> for ReceiveDestination {
+    type Error = io::Error;
+
+    fn try_from(value: ProtoMessageEditedDestination) -> Result<Self, Self::Error> {
+        Ok(match value {
+            ProtoMessageEditedDestination::UserId(id) => Self::User(id.try_into()?),
+            ProtoMessageEditedDestination::ChannelId(id) => Self::Channel(id.try_into()?),
+        })
+    }
+}
+
+impl From<ReceiveDestination> for ProtoMessageEditedDestination {
+    fn from(value: ReceiveDestination) -> Self {
+        match value {
+            ReceiveDestination::Channel(id) => Self::ChannelId(id.into()),
+            ReceiveDestination::User(id) => Self::UserId(id.into()),
+        }
+    }
+}
+
+/// A message's contents were edited by its sender.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MessageEdited {
+    /// ID of the edited message.
+    pub message_id: MessageId,
+
+    /// The message's sender, who edited it.
+    pub sender_id: UserId,
+
+    /// Where the message was originally sent to.
+    pub destination: ReceiveDestination,
+
+    /// The message's new content.
+    pub contents: String,
+
+    /// Server time at which the message was edited.
+    pub edited_at: SystemTime,
+}
+
+impl TryFrom<proto::MessageEdited> for MessageEdited {
+    type Error = io::Error;
+
+    fn try_from(value: proto::MessageEdited) -> Result<Self, Self::Error> {
+        let message_id: MessageId = value
+            .message_id
+            .ok_or_else(io_err_invalid_data)?
+            .try_into()?;
+
+        let sender_id: UserId = value
+            .sender_id
+            .ok_or_else(io_err_invalid_data)?
+            .try_into()?;
+
+        let destination: ReceiveDestination = value
+            .destination
+            .ok_or_else(io_err_invalid_data)?
+            .try_into()?;
+
+        let edited_at = time_from_proto(value.edited_at.ok_or_else(io_err_invalid_data)?)?;
+
+        Ok(Self {
+            message_id,
+            sender_id,
+            destination,
+            contents: value.contents,
+            edited_at,
+        })
+    }
+}
+
+impl From<MessageEdited> for proto::MessageEdited {
+    fn from(value: MessageEdited) -> Self {
+        Self {
+            message_id: Some(value.message_id.into()),
+            sender_id: Some(value.sender_id.into()),
+            destination: Some(value.destination.into()),
+            contents: value.contents,
+            edited_at: Some(value.edited_at.into()),
+        }
+    }
+}
+
+impl TryFrom<ProtoMessageDeletedDestination> for ReceiveDestination {
+    type Error = io::Error;
+
+    fn try_from(value: ProtoMessageDeletedDestination) -> Result<Self, Self::Error> {
+        Ok(match value {
+            ProtoMessageDeletedDestination::UserId(id) => Self::User(id.try_into()?),
+            ProtoMessageDeletedDestination::ChannelId(id) => Self::Channel(id.try_into()?),
+        })
+    }
+}
+
+impl From<ReceiveDestination> for ProtoMessageDeletedDestination {
+    fn from(value: ReceiveDestination) -> Self {
+        match value {
+            ReceiveDestination::Channel(id) => Self::ChannelId(id.into()),
+            ReceiveDestination::User(id) => Self::UserId(id.into()),
+        }
+    }
+}
+
+/// A message was deleted by its sender.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MessageDeleted {
+    /// ID of the deleted message.
+    pub message_id: MessageId,
+
+    /// The message's sender, who deleted it.
+    pub sender_id: UserId,
+
+    /// Where the message was originally sent to.
+    pub destination: ReceiveDestination,
+}
+
+impl TryFrom<proto::MessageDeleted> for MessageDeleted {
+    type Error = io::Error;
+
+    fn try_from(value: proto::MessageDeleted) -> Result<Self, Self::Error> {
+        let message_id: MessageId = value
+            .message_id
+            .ok_or_else(io_err_invalid_data)?
+            .try_into()?;
+
+        let sender_id: UserId = value
+            .sender_id
+            .ok_or_else(io_err_invalid_data)?
+            .try_into()?;
+
+        let destination: ReceiveDestination = value
+            .destination
+            .ok_or_else(io_err_invalid_data)?
+            .try_into()?;
+
+        Ok(Self {
+            message_id,
+            sender_id,
+            destination,
+        })
+    }
+}
+
+impl From<MessageDeleted> for proto::MessageDeleted {
+    fn from(value: MessageDeleted) -> Self {
+        Self {
+            message_id: Some(value.message_id.into()),
+            sender_id: Some(value.sender_id.into()),
+            destination: Some(value.destination.into()),
+        }
+    }
+}
+
+impl TryFrom<ProtoHistoryPageDestination> for HistoryDestination {
+    type Error = io::Error;
+
+    fn try_from(value: ProtoHistoryPageDestination) -> Result<Self, Self::Error> {
+        Ok(match value {
+            ProtoHistoryPageDestination::ChannelId(id) => Self::Channel(id.try_into()?),
+            ProtoHistoryPageDestination::UserId(id) => Self::User(id.try_into()?),
+        })
+    }
+}
+
+impl From<HistoryDestination> for ProtoHistoryPageDestination {
+    fn from(value: HistoryDestination) -> Self {
+        match value {
+            HistoryDestination::Channel(id) => Self::ChannelId(id.into()),
+            HistoryDestination::User(id) => Self::UserId(id.into()),
+        }
+    }
+}
+
+/// A page of message history, sent in response to a
+/// [`FetchHistory`](crate::FetchHistory) command.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct HistoryPage {
+    /// The conversation this page belongs to.
+    pub destination: HistoryDestination,
+
+    /// The messages in this page, in chronological order.
+    pub messages: Vec<ReceivedMessage>,
+
+    /// Cursor to fetch the next (older) page with. `None` if there is no older history.
+    pub next_before: Option<MessageId>,
+}
+
+impl TryFrom<proto::HistoryPage> for HistoryPage {
+    type Error = io::Error;
+
+    fn try_from(value: proto::HistoryPage) -> Result<Self, Self::Error> {
+        let destination: HistoryDestination = value
+            .destination
+            .ok_or_else(io_err_invalid_data)?
+            .try_into()?;
+
+        let messages: Vec<ReceivedMessage> = value
+            .messages
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect::<Result<_, _>>()?;
+
+        let next_before = value.next_before.map(TryInto::try_into).transpose()?;
+
+        Ok(Self {
+            destination,
+            messages,
+            next_before,
+        })
+    }
+}
+
+impl From<HistoryPage> for proto::HistoryPage {
+    fn from(value: HistoryPage) -> Self {
+        Self {
+            destination: Some(value.destination.into()),
+            messages: value.messages.into_iter().map(Into::into).collect(),
+            next_before: value.next_before.map(Into::into),
         }
     }
 }
@@ -92,6 +350,28 @@ impl From<ReceivedMessage> for proto::ReceivedMessage {
 pub struct ServerHello {
     pub your_id: UserId,
     pub default_channel_id: Option<ChannelId>,
+
+    /// Token to resume this session with if the connection drops. `None` if the server doesn't
+    /// hold sessions.
+    pub resume_token: Option<ResumeToken>,
+
+    /// How long the server holds the session after the connection drops.
+    pub resume_grace_period: Duration,
+
+    /// Whether this connection resumed a previous session.
+    pub resumed: bool,
+
+    /// Version of the protocol the server speaks.
+    pub protocol_version: u32,
+
+    /// Optional features both the server and the client support.
+    pub capabilities: BTreeSet<Capability>,
+
+    /// Longest message contents the server accepts, in characters. `None` if the server didn't say.
+    pub max_message_length: Option<usize>,
+
+    /// Longest frame the server accepts, in bytes. `None` if the server didn't say.
+    pub max_frame_length: Option<usize>,
 }
 
 impl TryFrom<proto::ServerHello> for ServerHello {
@@ -105,9 +385,24 @@ impl TryFrom<proto::ServerHello> for ServerHello {
             .map(TryInto::try_into)
             .transpose()?;
 
+        let resume_token = value.resume_token.map(TryInto::try_into).transpose()?;
+
+        let resume_grace_period = value
+            .resume_grace_period
+            .map(duration_from_proto)
+            .transpose()?
+            .unwrap_or_default();
+
         Ok(Self {
             your_id,
             default_channel_id,
+            resume_token,
+            resume_grace_period,
+            resumed: value.resumed,
+            protocol_version: value.protocol_version,
+            capabilities: capabilities_from_proto(value.capabilities),
+            max_message_length: value.max_message_length.map(usize_from_u32),
+            max_frame_length: value.max_frame_length.map(usize_from_u32),
         })
     }
 }
@@ -117,6 +412,13 @@ impl From<ServerHello> for proto::ServerHello {
         Self {
             your_id: Some(value.your_id.into()),
             default_channel_id: value.default_channel_id.map(Into::into),
+            resume_token: value.resume_token.map(Into::into),
+            resume_grace_period: Some(duration_to_proto(value.resume_grace_period)),
+            resumed: value.resumed,
+            protocol_version: value.protocol_version,
+            capabilities: capabilities_to_proto(value.capabilities),
+            max_message_length: value.max_message_length.map(saturating_u32),
+            max_frame_length: value.max_frame_length.map(saturating_u32),
         }
     }
 }
@@ -153,6 +455,7 @@ impl From<ChannelInfo> for proto::ChannelInfo {
 pub struct UserInfo {
     pub id: UserId,
     pub name: String,
+    pub role: Role,
 }
 
 impl TryFrom<proto::UserInfo> for UserInfo {
@@ -164,6 +467,7 @@ impl TryFrom<proto::UserInfo> for UserInfo {
         Ok(Self {
             id,
             name: value.name,
+            role: value.role.try_into()?,
         })
     }
 }
@@ -173,6 +477,7 @@ impl From<UserInfo> for proto::UserInfo {
         Self {
             id: Some(value.id.into()),
             name: value.name,
+            role: value.role.into(),
         }
     }
 }
@@ -180,7 +485,11 @@ impl From<UserInfo> for proto::UserInfo {
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ChannelSync {
+    /// Every channel on the server, whether you joined it or not.
     pub channels: Vec<ChannelInfo>,
+
+    /// The channels you joined.
+    pub joined: Vec<ChannelId>,
 }
 
 impl TryFrom<proto::ChannelSync> for ChannelSync {
@@ -193,7 +502,13 @@ impl TryFrom<proto::ChannelSync> for ChannelSync {
             .map(TryInto::try_into)
             .collect::<Result<_, _>>()?;
 
-        Ok(Self { channels })
+        let joined: Vec<ChannelId> = value
+            .joined_channel_ids
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { channels, joined })
     }
 }
 
@@ -202,7 +517,76 @@ impl From<ChannelSync> for proto::ChannelSync {
         let channels: Vec<proto::ChannelInfo> =
             value.channels.into_iter().map(Into::into).collect();
 
-        Self { channels }
+        let joined_channel_ids: Vec<u64> = value.joined.into_iter().map(Into::into).collect();
+
+        Self {
+            channels,
+            joined_channel_ids,
+        }
+    }
+}
+
+/// The full member list of a channel.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ChannelMembers {
+    pub channel_id: ChannelId,
+    pub members: Vec<UserId>,
+}
+
+impl TryFrom<proto::ChannelMembers> for ChannelMembers {
+    type Error = io::Error;
+
+    fn try_from(value: proto::ChannelMembers) -> Result<Self, Self::Error> {
+        let members: Vec<UserId> = value
+            .member_ids
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            channel_id: value.channel_id.try_into()?,
+            members,
+        })
+    }
+}
+
+impl From<ChannelMembers> for proto::ChannelMembers {
+    fn from(value: ChannelMembers) -> Self {
+        Self {
+            channel_id: value.channel_id.into(),
+            member_ids: value.members.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// A user that joined or left a channel.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ChannelMember {
+    pub channel_id: ChannelId,
+    pub user_id: UserId,
+}
+
+impl TryFrom<proto::ChannelMember> for ChannelMember {
+    type Error = io::Error;
+
+    fn try_from(value: proto::ChannelMember) -> Result<Self, Self::Error> {
+        let user_id: UserId = value.user_id.ok_or_else(io_err_invalid_data)?.try_into()?;
+
+        Ok(Self {
+            channel_id: value.channel_id.try_into()?,
+            user_id,
+        })
+    }
+}
+
+impl From<ChannelMember> for proto::ChannelMember {
+    fn from(value: ChannelMember) -> Self {
+        Self {
+            channel_id: value.channel_id.into(),
+            user_id: Some(value.user_id.into()),
+        }
     }
 }
 
@@ -244,6 +628,13 @@ pub enum ErrorKind {
     InvalidName,
     TargetNotFound,
     ServerError,
+    PermissionDenied,
+    InvalidChannelName,
+    AuthenticationFailed,
+    IncompatibleVersion,
+    RateLimited,
+    MessageTooLong,
+    Banned,
 }
 
 impl TryFrom<i32> for ErrorKind {
@@ -256,6 +647,13 @@ impl TryFrom<i32> for ErrorKind {
             2 => Ok(Self::InvalidName),
             3 => Ok(Self::TargetNotFound),
             4 => Ok(Self::ServerError),
+            5 => Ok(Self::PermissionDenied),
+            6 => Ok(Self::InvalidChannelName),
+            7 => Ok(Self::AuthenticationFailed),
+            8 => Ok(Self::IncompatibleVersion),
+            9 => Ok(Self::RateLimited),
+            10 => Ok(Self::MessageTooLong),
+            11 => Ok(Self::Banned),
             _ => Err(()),
         }
     }
@@ -269,6 +667,13 @@ impl From<ErrorKind> for i32 {
             ErrorKind::InvalidName => 2,
             ErrorKind::TargetNotFound => 3,
             ErrorKind::ServerError => 4,
+            ErrorKind::PermissionDenied => 5,
+            ErrorKind::InvalidChannelName => 6,
+            ErrorKind::AuthenticationFailed => 7,
+            ErrorKind::IncompatibleVersion => 8,
+            ErrorKind::RateLimited => 9,
+            ErrorKind::MessageTooLong => 10,
+            ErrorKind::Banned => 11,
         }
     }
 }
@@ -284,6 +689,13 @@ impl fmt::Display for ErrorKind {
                 ErrorKind::InvalidName => "invalid username",
                 ErrorKind::TargetNotFound => "target not found",
                 ErrorKind::ServerError => "fatal server error",
+                ErrorKind::PermissionDenied => "permission denied",
+                ErrorKind::InvalidChannelName => "invalid channel name",
+                ErrorKind::AuthenticationFailed => "authentication failed",
+                ErrorKind::IncompatibleVersion => "incompatible protocol version",
+                ErrorKind::RateLimited => "rate limited",
+                ErrorKind::MessageTooLong => "message too long",
+                ErrorKind::Banned => "banned",
             }
         )
     }
@@ -295,6 +707,25 @@ impl fmt::Display for ErrorKind {
 pub struct ErrorEvent {
     pub kind: ErrorKind,
     pub message: String,
+
+    /// The request ID of the command that failed, if this is a reply to one.
+    pub request_id: Option<RequestId>,
+
+    /// For [`ErrorKind::RateLimited`] errors, how long to wait before trying again.
+    pub retry_after: Option<Duration>,
+}
+
+impl ErrorEvent {
+    /// Create an error that isn't a reply to any particular request.
+    #[must_use]
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            request_id: None,
+            retry_after: None,
+        }
+    }
 }
 
 impl TryFrom<proto::ErrorEvent> for ErrorEvent {
@@ -306,6 +737,8 @@ impl TryFrom<proto::ErrorEvent> for ErrorEvent {
         Ok(Self {
             kind,
             message: value.message,
+            request_id: value.request_id.map(RequestId::try_from).transpose()?,
+            retry_after: value.retry_after.map(duration_from_proto).transpose()?,
         })
     }
 }
@@ -315,6 +748,8 @@ impl From<ErrorEvent> for proto::ErrorEvent {
         Self {
             code: value.kind.into(),
             message: value.message,
+            request_id: value.request_id.map(Into::into),
+            retry_after: value.retry_after.map(duration_to_proto),
         }
     }
 }
@@ -327,6 +762,200 @@ impl fmt::Display for ErrorEvent {
 
 impl error::Error for ErrorEvent {}
 
+/// Confirmation that a command carrying a [`RequestId`] succeeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CommandAck {
+    pub request_id: RequestId,
+}
+
+impl TryFrom<proto::CommandAck> for CommandAck {
+    type Error = io::Error;
+
+    fn try_from(value: proto::CommandAck) -> Result<Self, Self::Error> {
+        Ok(Self {
+            request_id: value.request_id.try_into()?,
+        })
+    }
+}
+
+impl From<CommandAck> for proto::CommandAck {
+    fn from(value: CommandAck) -> Self {
+        Self {
+            request_id: value.request_id.into(),
+        }
+    }
+}
+
+/// The client fell behind, and the server dropped events it couldn't keep up with. The client
+/// should refetch whatever the lost events would have changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Resync {
+    /// Whether server-wide events, like users and channels coming and going, were lost.
+    pub global: bool,
+
+    /// Channels that lost messages.
+    pub channel_ids: Vec<ChannelId>,
+}
+
+impl TryFrom<proto::Resync> for Resync {
+    type Error = io::Error;
+
+    fn try_from(value: proto::Resync) -> Result<Self, Self::Error> {
+        let channel_ids: Vec<ChannelId> = value
+            .channel_ids
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            global: value.global,
+            channel_ids,
+        })
+    }
+}
+
+impl From<Resync> for proto::Resync {
+    fn from(value: Resync) -> Self {
+        Self {
+            global: value.global,
+            channel_ids: value.channel_ids.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// An announcement from the server's operators to every user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ServerNotice {
+    pub message: String,
+}
+
+impl From<proto::ServerNotice> for ServerNotice {
+    fn from(value: proto::ServerNotice) -> Self {
+        Self {
+            message: value.message,
+        }
+    }
+}
+
+impl From<ServerNotice> for proto::ServerNotice {
+    fn from(value: ServerNotice) -> Self {
+        Self {
+            message: value.message,
+        }
+    }
+}
+
+/// The server is about to shut down, most likely to restart. It stops accepting connections, and
+/// closes the remaining ones once the grace period is over.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ServerShuttingDown {
+    pub reason: String,
+
+    /// How long until the server closes the remaining connections.
+    pub grace_period: Duration,
+}
+
+impl TryFrom<proto::ServerShuttingDown> for ServerShuttingDown {
+    type Error = io::Error;
+
+    fn try_from(value: proto::ServerShuttingDown) -> Result<Self, Self::Error> {
+        Ok(Self {
+            reason: value.reason,
+            grace_period: value
+                .grace_period
+                .map(duration_from_proto)
+                .transpose()?
+                .unwrap_or_default(),
+        })
+    }
+}
+
+impl From<ServerShuttingDown> for proto::ServerShuttingDown {
+    fn from(value: ServerShuttingDown) -> Self {
+        Self {
+            reason: value.reason,
+            grace_period: Some(duration_to_proto(value.grace_period)),
+        }
+    }
+}
+
+/// A user was kicked off the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct UserKicked {
+    pub user_id: UserId,
+
+    /// The moderator who kicked the user. `None` if the server's operators did.
+    pub by: Option<UserId>,
+
+    pub reason: String,
+}
+
+impl TryFrom<proto::UserKicked> for UserKicked {
+    type Error = io::Error;
+
+    fn try_from(value: proto::UserKicked) -> Result<Self, Self::Error> {
+        Ok(Self {
+            user_id: value.user_id.ok_or_else(io_err_invalid_data)?.try_into()?,
+            by: value.by.map(TryInto::try_into).transpose()?,
+            reason: value.reason,
+        })
+    }
+}
+
+impl From<UserKicked> for proto::UserKicked {
+    fn from(value: UserKicked) -> Self {
+        Self {
+            user_id: Some(value.user_id.into()),
+            by: value.by.map(Into::into),
+            reason: value.reason,
+        }
+    }
+}
+
+/// A user was banned from the server, and disconnected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct UserBanned {
+    pub user_id: UserId,
+
+    /// The moderator who banned the user. `None` if the server's operators did.
+    pub by: Option<UserId>,
+
+    pub reason: String,
+
+    /// When the ban ends. `None` if the ban is permanent.
+    pub expires_at: Option<SystemTime>,
+}
+
+impl TryFrom<proto::UserBanned> for UserBanned {
+    type Error = io::Error;
+
+    fn try_from(value: proto::UserBanned) -> Result<Self, Self::Error> {
+        Ok(Self {
+            user_id: value.user_id.ok_or_else(io_err_invalid_data)?.try_into()?,
+            by: value.by.map(TryInto::try_into).transpose()?,
+            reason: value.reason,
+            expires_at: value.expires_at.map(time_from_proto).transpose()?,
+        })
+    }
+}
+
+impl From<UserBanned> for proto::UserBanned {
+    fn from(value: UserBanned) -> Self {
+        Self {
+            user_id: Some(value.user_id.into()),
+            by: value.by.map(Into::into),
+            reason: value.reason,
+            expires_at: value.expires_at.map(Into::into),
+        }
+    }
+}
+
 /// An event sent from the server to the client backend.
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -349,10 +978,62 @@ pub enum NetworkEvent {
     /// A user's information changed.
     UserInfoUpdated(UserInfo),
 
+    /// A channel was created.
+    ChannelAdded(ChannelInfo),
+
+    /// A channel was deleted.
+    ChannelRemoved(ChannelId),
+
+    /// A channel's name changed.
+    ChannelChangedName(ChannelInfo),
+
     /// Received a message from some other connected client.
     ReceivedMessage(ReceivedMessage),
 
     ErrorEvent(ErrorEvent),
+
+    /// A page of message history, in response to a fetch.
+    HistoryPage(HistoryPage),
+
+    /// A message was edited.
+    MessageEdited(MessageEdited),
+
+    /// A message was deleted.
+    MessageDeleted(MessageDeleted),
+
+    /// The full member list of a channel you joined.
+    ChannelMembers(ChannelMembers),
+
+    /// A user joined a channel you are a member of.
+    ChannelMemberJoined(ChannelMember),
+
+    /// A user left a channel you are a member of.
+    ChannelMemberLeft(ChannelMember),
+
+    /// Check that the client is still there. The client answers with a
+    /// [`NetworkCommand::Pong`](crate::NetworkCommand::Pong).
+    Ping(Ping),
+
+    /// Answer to a [`NetworkCommand::Ping`](crate::NetworkCommand::Ping) from the client.
+    Pong(Pong),
+
+    /// A command carrying a request ID succeeded.
+    CommandAck(CommandAck),
+
+    /// The client fell behind, and events were dropped.
+    Resync(Resync),
+
+    /// An announcement from the server's operators.
+    ServerNotice(ServerNotice),
+
+    /// A user was kicked off the server.
+    UserKicked(UserKicked),
+
+    /// A user was banned from the server.
+    UserBanned(UserBanned),
+
+    /// The server is about to shut down.
+    ServerShuttingDown(ServerShuttingDown),
 }
 
 impl NetworkEvent {
@@ -366,8 +1047,25 @@ impl NetworkEvent {
             Self::UserJoined(_) => "UserJoined",
             Self::UserLeft(_) => "UserLeft",
             Self::UserInfoUpdated(_) => "UserInfoUpdated",
+            Self::ChannelAdded(_) => "ChannelAdded",
+            Self::ChannelRemoved(_) => "ChannelRemoved",
+            Self::ChannelChangedName(_) => "ChannelChangedName",
             Self::ReceivedMessage(_) => "ReceivedMessage",
             Self::ErrorEvent(_) => "ErrorEvent",
+            Self::HistoryPage(_) => "HistoryPage",
+            Self::MessageEdited(_) => "MessageEdited",
+            Self::MessageDeleted(_) => "MessageDeleted",
+            Self::ChannelMembers(_) => "ChannelMembers",
+            Self::ChannelMemberJoined(_) => "ChannelMemberJoined",
+            Self::ChannelMemberLeft(_) => "ChannelMemberLeft",
+            Self::Ping(_) => "Ping",
+            Self::Pong(_) => "Pong",
+            Self::CommandAck(_) => "CommandAck",
+            Self::Resync(_) => "Resync",
+            Self::ServerNotice(_) => "ServerNotice",
+            Self::UserKicked(_) => "UserKicked",
+            Self::UserBanned(_) => "UserBanned",
+            Self::ServerShuttingDown(_) => "ServerShuttingDown",
         }
     }
 }
@@ -391,11 +1089,57 @@ impl TryFrom<EventFrame> for NetworkEvent {
 
             Variant::UserInfoUpdated(user_info) => Ok(Self::UserInfoUpdated(user_info.try_into()?)),
 
+            Variant::ChannelAdded(channel_info) => Ok(Self::ChannelAdded(channel_info.try_into()?)),
+
+            Variant::ChannelRemoved(channel_id) => Ok(Self::ChannelRemoved(channel_id.try_into()?)),
+
+            Variant::ChannelChangedName(channel_info) => {
+                Ok(Self::ChannelChangedName(channel_info.try_into()?))
+            }
+
             Variant::ReceivedMessage(message) => {
                 Ok(NetworkEvent::ReceivedMessage(message.try_into()?))
             }
 
             Variant::ErrorEvent(error) => Ok(NetworkEvent::ErrorEvent(error.try_into()?)),
+
+            Variant::HistoryPage(page) => Ok(NetworkEvent::HistoryPage(page.try_into()?)),
+
+            Variant::MessageEdited(edited) => Ok(NetworkEvent::MessageEdited(edited.try_into()?)),
+
+            Variant::MessageDeleted(deleted) => {
+                Ok(NetworkEvent::MessageDeleted(deleted.try_into()?))
+            }
+
+            Variant::ChannelMembers(members) => {
+                Ok(NetworkEvent::ChannelMembers(members.try_into()?))
+            }
+
+            Variant::ChannelMemberJoined(member) => {
+                Ok(NetworkEvent::ChannelMemberJoined(member.try_into()?))
+            }
+
+            Variant::ChannelMemberLeft(member) => {
+                Ok(NetworkEvent::ChannelMemberLeft(member.try_into()?))
+            }
+
+            Variant::Ping(ping) => Ok(NetworkEvent::Ping(ping.try_into()?)),
+
+            Variant::Pong(pong) => Ok(NetworkEvent::Pong(pong.try_into()?)),
+
+            Variant::CommandAck(ack) => Ok(NetworkEvent::CommandAck(ack.try_into()?)),
+
+            Variant::Resync(resync) => Ok(NetworkEvent::Resync(resync.try_into()?)),
+
+            Variant::ServerNotice(notice) => Ok(NetworkEvent::ServerNotice(notice.into())),
+
+            Variant::UserKicked(kicked) => Ok(NetworkEvent::UserKicked(kicked.try_into()?)),
+
+            Variant::UserBanned(banned) => Ok(NetworkEvent::UserBanned(banned.try_into()?)),
+
+            Variant::ServerShuttingDown(shutdown) => {
+                Ok(NetworkEvent::ServerShuttingDown(shutdown.try_into()?))
+            }
         }
     }
 }
@@ -429,6 +1173,18 @@ impl From<NetworkEvent> for EventFrame {
                 variant: Some(Variant::UserInfoUpdated(user_info.into())),
             },
 
+            NetworkEvent::ChannelAdded(channel_info) => Self {
+                variant: Some(Variant::ChannelAdded(channel_info.into())),
+            },
+
+            NetworkEvent::ChannelRemoved(channel_id) => Self {
+                variant: Some(Variant::ChannelRemoved(channel_id.into())),
+            },
+
+            NetworkEvent::ChannelChangedName(channel_info) => Self {
+                variant: Some(Variant::ChannelChangedName(channel_info.into())),
+            },
+
             NetworkEvent::ReceivedMessage(message) => Self {
                 variant: Some(Variant::ReceivedMessage(message.into())),
             },
@@ -436,6 +1192,62 @@ impl From<NetworkEvent> for EventFrame {
             NetworkEvent::ErrorEvent(error) => Self {
                 variant: Some(Variant::ErrorEvent(error.into())),
             },
+
+            NetworkEvent::HistoryPage(page) => Self {
+                variant: Some(Variant::HistoryPage(page.into())),
+            },
+
+            NetworkEvent::MessageEdited(edited) => Self {
+                variant: Some(Variant::MessageEdited(edited.into())),
+            },
+
+            NetworkEvent::MessageDeleted(deleted) => Self {
+                variant: Some(Variant::MessageDeleted(deleted.into())),
+            },
+
+            NetworkEvent::ChannelMembers(members) => Self {
+                variant: Some(Variant::ChannelMembers(members.into())),
+            },
+
+            NetworkEvent::ChannelMemberJoined(member) => Self {
+                variant: Some(Variant::ChannelMemberJoined(member.into())),
+            },
+
+            NetworkEvent::ChannelMemberLeft(member) => Self {
+                variant: Some(Variant::ChannelMemberLeft(member.into())),
+            },
+
+            NetworkEvent::Ping(ping) => Self {
+                variant: Some(Variant::Ping(ping.into())),
+            },
+
+            NetworkEvent::Pong(pong) => Self {
+                variant: Some(Variant::Pong(pong.into())),
+            },
+
+            NetworkEvent::CommandAck(ack) => Self {
+                variant: Some(Variant::CommandAck(ack.into())),
+            },
+
+            NetworkEvent::Resync(resync) => Self {
+                variant: Some(Variant::Resync(resync.into())),
+            },
+
+            NetworkEvent::ServerNotice(notice) => Self {
+                variant: Some(Variant::ServerNotice(notice.into())),
+            },
+
+            NetworkEvent::UserKicked(kicked) => Self {
+                variant: Some(Variant::UserKicked(kicked.into())),
+            },
+
+            NetworkEvent::UserBanned(banned) => Self {
+                variant: Some(Variant::UserBanned(banned.into())),
+            },
+
+            NetworkEvent::ServerShuttingDown(shutdown) => Self {
+                variant: Some(Variant::ServerShuttingDown(shutdown.into())),
+            },
         }
     }
 }
@@ -1,9 +1,14 @@
-use std::collections::HashMap;
-use std::net::SocketAddr;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::time::Duration;
 
 use chat_backend::{
     client_event::{ClientEvent, InitialSync},
-    network_protocol::{ChannelId, ReceiveDestination, ReceivedMessage, UserId, UserInfo},
+    network_protocol::{
+        Capability, ChannelId, ChannelMember, ChannelMembers, ChannelSync, HistoryDestination,
+        HistoryPage, MessageDeleted, MessageEdited, MessageId, ReceiveDestination, ReceivedMessage,
+        RequestId, Role, UserId, UserInfo,
+    },
+    transport::ServerAddress,
 };
 
 const CHANNEL_INIT_CAPACITY: usize = 64;
@@ -17,6 +22,49 @@ pub enum MessageContext {
     User(UserId),
 }
 
+impl MessageContext {
+    /// The history destination to fetch this context's older messages from.
+    pub fn history_destination(&self) -> HistoryDestination {
+        match self {
+            Self::Channel(id) => HistoryDestination::Channel(*id),
+            Self::User(id) => HistoryDestination::User(*id),
+        }
+    }
+}
+
+impl From<HistoryDestination> for MessageContext {
+    fn from(value: HistoryDestination) -> Self {
+        match value {
+            HistoryDestination::Channel(id) => Self::Channel(id),
+            HistoryDestination::User(id) => Self::User(id),
+        }
+    }
+}
+
+/// How much of a message context's history has been loaded from the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryCursor {
+    /// A page of history has been requested, but hasn't arrived yet.
+    Pending,
+
+    /// There are older messages before this one that haven't been loaded yet.
+    More(MessageId),
+
+    /// All history has been loaded.
+    Exhausted,
+}
+
+/// A message sent with a request ID, which the server hasn't confirmed yet.
+#[derive(Debug, Clone)]
+pub struct PendingMessage {
+    pub request_id: RequestId,
+    pub context: MessageContext,
+    pub contents: String,
+
+    /// Why the message could not be sent. `None` while still waiting for the server.
+    pub failure: Option<String>,
+}
+
 /// State struct holding information about the current connection, such as the address of the
 /// server, a list of channels and users, the message history, etc.
 ///
@@ -27,7 +75,21 @@ pub struct ConnectionState {
     pub your_id: UserId,
 
     /// The address of the server you're currently connected to.
-    pub connected_addr: SocketAddr,
+    pub connected_addr: ServerAddress,
+
+    /// If the connection dropped, the reconnect attempt the backend is on. The state is kept
+    /// until the backend reconnects or gives up.
+    pub reconnect_attempt: Option<u32>,
+
+    /// Round-trip time of the latest ping to the server. `None` until the first answer arrives,
+    /// or if the heartbeat is off.
+    pub latency: Option<Duration>,
+
+    /// Optional features the server supports. Features outside this set are hidden.
+    pub capabilities: BTreeSet<Capability>,
+
+    /// Longest message the server accepts, in characters. `None` if the server didn't say.
+    pub max_message_length: Option<usize>,
 
     /// The current message context. This determines what messages will be displayed. If `None`,
     /// there is no current context.
@@ -36,17 +98,38 @@ pub struct ConnectionState {
     /// List of channels in the current server.
     pub channels: HashMap<ChannelId, String>,
 
-    /// Order in which channels are rendered.
+    /// Order in which channels are rendered. Joined channels come first.
     pub channel_render_order: Vec<ChannelId>,
 
+    /// Channels you joined, with the IDs of their members. Channels you didn't join are absent.
+    pub joined_channels: HashMap<ChannelId, HashSet<UserId>>,
+
     /// List of users in the current server.
     pub users: HashMap<UserId, String>,
 
+    /// Roles of the users in the current server.
+    pub roles: HashMap<UserId, Role>,
+
     /// Order in which users are rendered.
     pub user_render_order: Vec<UserId>,
 
     /// Message history in the current server.
     pub messages: HashMap<MessageContext, Vec<ReceivedMessage>>,
+
+    /// How far back each message context's history has been loaded. Contexts whose history was
+    /// never requested are absent.
+    pub history_cursors: HashMap<MessageContext, HistoryCursor>,
+
+    /// Messages that were sent, but not confirmed by the server, in the order they were sent.
+    /// Confirmed messages are removed, since they arrive as regular messages.
+    pub pending_messages: Vec<PendingMessage>,
+
+    /// History requests the server hasn't confirmed yet, with the context each is for and the
+    /// cursor the context had before. A failed request puts the cursor back, so it can be retried.
+    pending_history: HashMap<RequestId, (MessageContext, Option<HistoryCursor>)>,
+
+    /// Request ID to give the next tracked request.
+    next_request_id: u64,
 }
 
 impl ConnectionState {
@@ -57,17 +140,30 @@ impl ConnectionState {
             your_id,
             default_channel_id,
             server_addr,
+            resumed: _,
+            capabilities,
+            max_message_length,
         } = initial_sync;
 
         Self {
             your_id,
             connected_addr: server_addr,
+            reconnect_attempt: None,
+            latency: None,
+            capabilities,
+            max_message_length,
             message_context: default_channel_id.map(MessageContext::Channel),
             channels: HashMap::with_capacity(CHANNEL_INIT_CAPACITY),
             channel_render_order: Vec::with_capacity(CHANNEL_INIT_CAPACITY),
+            joined_channels: HashMap::with_capacity(CHANNEL_INIT_CAPACITY),
             users: HashMap::with_capacity(USER_INIT_CAPACITY),
+            roles: HashMap::with_capacity(USER_INIT_CAPACITY),
             user_render_order: Vec::with_capacity(USER_INIT_CAPACITY),
             messages: HashMap::with_capacity(MESSAGE_INIT_CAPACITY),
+            history_cursors: HashMap::with_capacity(MESSAGE_INIT_CAPACITY),
+            pending_messages: Vec::new(),
+            pending_history: HashMap::new(),
+            next_request_id: 0,
         }
     }
 
@@ -82,19 +178,20 @@ impl ConnectionState {
     pub fn update_from_event(&mut self, event: ClientEvent) {
         match event {
             ClientEvent::UserSync(sync) => {
-                self.users
-                    .extend(sync.users.into_iter().map(|user| (user.id, user.name)));
+                // A sync is the full list, so users we missed leaving go away.
+                self.roles = sync.users.iter().map(|user| (user.id, user.role)).collect();
+                self.users = sync
+                    .users
+                    .into_iter()
+                    .map(|user| (user.id, user.name))
+                    .collect();
                 self.rebuild_user_cache();
             }
 
-            ClientEvent::ChannelSync(sync) => {
-                self.channels
-                    .extend(sync.channels.into_iter().map(|user| (user.id, user.name)));
-                self.rebuild_channel_cache();
-            }
+            ClientEvent::ChannelSync(sync) => self.sync_channels(sync),
 
             ClientEvent::UserJoined(user_info) => {
-                self.users.insert(user_info.id, user_info.name);
+                self.update_info(user_info);
                 self.rebuild_user_cache();
             }
 
@@ -105,7 +202,12 @@ impl ConnectionState {
                     self.message_context = None;
                 }
 
+                for members in self.joined_channels.values_mut() {
+                    members.remove(&user_id);
+                }
+
                 self.users.remove(&user_id);
+                self.roles.remove(&user_id);
                 self.rebuild_user_cache();
             }
 
@@ -114,12 +216,69 @@ impl ConnectionState {
                 self.rebuild_user_cache();
             }
 
+            ClientEvent::ChannelAdded(info) | ClientEvent::ChannelChangedName(info) => {
+                self.channels.insert(info.id, info.name);
+                self.rebuild_channel_cache();
+            }
+
+            ClientEvent::ChannelRemoved(channel_id) => {
+                let context = MessageContext::Channel(channel_id);
+
+                if self.message_context.as_ref() == Some(&context) {
+                    self.message_context = None;
+                }
+
+                self.messages.remove(&context);
+                self.history_cursors.remove(&context);
+                self.pending_messages
+                    .retain(|pending| pending.context != context);
+                self.joined_channels.remove(&channel_id);
+                self.channels.remove(&channel_id);
+                self.rebuild_channel_cache();
+            }
+
             ClientEvent::ReceivedMessage(message) => self.push_message(message),
 
+            ClientEvent::HistoryPage(page) => self.prepend_history(page),
+
+            ClientEvent::MessageEdited(edited) => self.edit_message(edited),
+
+            ClientEvent::MessageDeleted(deleted) => self.delete_message(deleted),
+
+            ClientEvent::ChannelMembers(members) => self.set_channel_members(members),
+
+            ClientEvent::ChannelMemberJoined(member) => self.add_channel_member(member),
+
+            ClientEvent::ChannelMemberLeft(member) => self.remove_channel_member(member),
+
+            ClientEvent::Reconnecting { attempt } => self.reconnect_attempt = Some(attempt),
+
+            // The InitialSync that follows replaces this state entirely.
+            ClientEvent::Reconnected => {}
+
+            ClientEvent::Latency(latency) => self.latency = Some(latency),
+
+            // The backend refetches what was lost, which arrives as regular syncs and history
+            // pages.
+            ClientEvent::Resync(_) => {}
+
+            ClientEvent::CommandAck(ack) => {
+                self.pending_messages
+                    .retain(|pending| pending.request_id != ack.request_id);
+                self.pending_history.remove(&ack.request_id);
+            }
+
             // Currently, no server errors demand a ConnectionState update. Because this may change
             // in the future, we make this a NOP instead of an error.
             ClientEvent::ErrorEvent(_) => {}
 
+            // Notices are shown as popups, and don't change any state. Removed users leave as
+            // usual afterwards.
+            ClientEvent::ServerNotice(_)
+            | ClientEvent::ServerShuttingDown(_)
+            | ClientEvent::UserKicked(_)
+            | ClientEvent::UserBanned(_) => {}
+
             // ==== INVALID EVENTS ====
             ClientEvent::InitialSync(_) => unreachable!(
                 "Initial sync should result in the creation of a ConnectionState, not be routed to it"
@@ -131,30 +290,243 @@ impl ConnectionState {
         }
     }
 
+    /// Track a message about to be sent, until the server confirms it. Returns the request ID to
+    /// send it with.
+    pub fn add_pending_message(&mut self, context: MessageContext, contents: String) -> RequestId {
+        let request_id = self.next_request_id();
+
+        self.pending_messages.push(PendingMessage {
+            request_id,
+            context,
+            contents,
+            failure: None,
+        });
+
+        request_id
+    }
+
+    /// Mark a context's history as loading, until a page arrives or the request fails. Returns the
+    /// request ID to send the request with.
+    pub fn add_pending_history(&mut self, context: MessageContext) -> RequestId {
+        let request_id = self.next_request_id();
+
+        let cursor = self
+            .history_cursors
+            .insert(context.clone(), HistoryCursor::Pending);
+        self.pending_history.insert(request_id, (context, cursor));
+
+        request_id
+    }
+
+    /// Get a request ID no other tracked request has.
+    fn next_request_id(&mut self) -> RequestId {
+        let request_id = RequestId(self.next_request_id);
+        self.next_request_id += 1;
+        request_id
+    }
+
+    /// Mark the pending message sent with the given request ID as failed, or put back the history
+    /// cursor of a failed history request. Returns `false` if no such message is pending, including
+    /// for history requests, whose failures aren't shown anywhere else.
+    pub fn fail_request(&mut self, request_id: RequestId, reason: String) -> bool {
+        if let Some((context, cursor)) = self.pending_history.remove(&request_id) {
+            match cursor {
+                Some(cursor) => self.history_cursors.insert(context, cursor),
+                None => self.history_cursors.remove(&context),
+            };
+
+            return false;
+        }
+
+        match self
+            .pending_messages
+            .iter_mut()
+            .find(|pending| pending.request_id == request_id)
+        {
+            Some(pending) => {
+                pending.failure = Some(reason);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Get the pending messages in a message context, oldest first.
+    pub fn pending_messages_in<'a>(
+        &'a self,
+        context: &'a MessageContext,
+    ) -> impl Iterator<Item = &'a PendingMessage> {
+        self.pending_messages
+            .iter()
+            .filter(move |pending| &pending.context == context)
+    }
+
     /// Add a new message to a message list.
     fn push_message(&mut self, message: ReceivedMessage) {
-        let context = match message.destination {
+        let context = self.context_of(message.destination, message.sender_id);
+        self.insert_message(context, message);
+    }
+
+    /// Get the message context a message belongs to.
+    fn context_of(&self, destination: ReceiveDestination, sender_id: UserId) -> MessageContext {
+        match destination {
             // If we sent the message, its context is the destination.
-            ReceiveDestination::User(id) if message.sender_id == self.your_id => {
-                MessageContext::User(id)
-            }
+            ReceiveDestination::User(id) if sender_id == self.your_id => MessageContext::User(id),
 
             // Otherwise, the context is the sender.
-            ReceiveDestination::User(_) => MessageContext::User(message.sender_id),
+            ReceiveDestination::User(_) => MessageContext::User(sender_id),
 
             // Of course, the context of a channel is just the channel.
             ReceiveDestination::Channel(id) => MessageContext::Channel(id),
-        };
+        }
+    }
 
+    /// Find a message by its ID in a message list. Returns `None` if it isn't loaded.
+    fn find_message_mut(
+        &mut self,
+        context: &MessageContext,
+        id: MessageId,
+    ) -> Option<&mut ReceivedMessage> {
+        let messages = self.messages.get_mut(context)?;
+        let index = messages
+            .binary_search_by_key(&id, |message| message.id)
+            .ok()?;
+
+        Some(&mut messages[index])
+    }
+
+    /// Update a message's contents in place.
+    fn edit_message(&mut self, edited: MessageEdited) {
+        let context = self.context_of(edited.destination, edited.sender_id);
+
+        // If the message isn't loaded, there's nothing to update; it will arrive edited if its
+        // history page is ever fetched.
+        if let Some(message) = self.find_message_mut(&context, edited.message_id) {
+            message.contents = edited.contents;
+            message.edited_at = Some(edited.edited_at);
+        }
+    }
+
+    /// Remove a message from its message list.
+    fn delete_message(&mut self, deleted: MessageDeleted) {
+        let context = self.context_of(deleted.destination, deleted.sender_id);
+
+        if let Some(messages) = self.messages.get_mut(&context)
+            && let Ok(index) =
+                messages.binary_search_by_key(&deleted.message_id, |message| message.id)
+        {
+            messages.remove(index);
+        }
+    }
+
+    /// Get a loaded message in the current message context by its ID.
+    pub fn get_message(&self, id: MessageId) -> Option<&ReceivedMessage> {
+        let messages = self.messages.get(self.message_context.as_ref()?)?;
+        let index = messages
+            .binary_search_by_key(&id, |message| message.id)
+            .ok()?;
+
+        Some(&messages[index])
+    }
+
+    /// Insert a message into a message list, keeping the list ordered by message ID. Messages
+    /// already in the list are skipped, so history pages may safely overlap live messages.
+    fn insert_message(&mut self, context: MessageContext, message: ReceivedMessage) {
         // Default vector capacity of 128 is only a reasonable default, not a significant value
-        self.messages
+        let messages = self
+            .messages
             .entry(context)
-            .or_insert(Vec::with_capacity(128))
-            .push(message);
+            .or_insert(Vec::with_capacity(128));
+
+        // Nearly every message is newer than everything we have, so check the end first.
+        if messages.last().is_none_or(|last| last.id < message.id) {
+            messages.push(message);
+            return;
+        }
+
+        if let Err(index) = messages.binary_search_by_key(&message.id, |existing| existing.id) {
+            messages.insert(index, message);
+        }
+    }
+
+    /// Add a page of older messages to the front of a message list.
+    fn prepend_history(&mut self, page: HistoryPage) {
+        let context = MessageContext::from(page.destination);
+
+        // Pages of recent messages, like the ones refetched after a resync, must not undo loading
+        // older ones.
+        let cursor = match (self.history_cursors.get(&context), page.next_before) {
+            (Some(HistoryCursor::Exhausted), _) => HistoryCursor::Exhausted,
+            (Some(HistoryCursor::More(loaded)), Some(id)) if *loaded < id => {
+                HistoryCursor::More(*loaded)
+            }
+            (_, Some(id)) => HistoryCursor::More(id),
+            (_, None) => HistoryCursor::Exhausted,
+        };
+
+        self.history_cursors.insert(context.clone(), cursor);
+
+        for message in page.messages {
+            self.insert_message(context.clone(), message);
+        }
+    }
+
+    /// Replace the channel list and the set of joined channels.
+    fn sync_channels(&mut self, sync: ChannelSync) {
+        self.channels = sync
+            .channels
+            .into_iter()
+            .map(|channel| (channel.id, channel.name))
+            .collect();
+
+        // Member lists of channels we stay in are kept, since the sync doesn't carry them.
+        self.joined_channels
+            .retain(|id, _| sync.joined.contains(id));
+        for id in sync.joined {
+            self.joined_channels.entry(id).or_default();
+        }
+
+        self.rebuild_channel_cache();
+    }
+
+    /// Set the full member list of a channel we joined.
+    fn set_channel_members(&mut self, members: ChannelMembers) {
+        self.joined_channels
+            .insert(members.channel_id, members.members.into_iter().collect());
+        self.rebuild_channel_cache();
+    }
+
+    /// Add a member to a channel. If the member is us, we joined the channel.
+    fn add_channel_member(&mut self, member: ChannelMember) {
+        if member.user_id == self.your_id {
+            self.joined_channels
+                .entry(member.channel_id)
+                .or_default()
+                .insert(member.user_id);
+            self.rebuild_channel_cache();
+        } else if let Some(members) = self.joined_channels.get_mut(&member.channel_id) {
+            members.insert(member.user_id);
+        }
+    }
+
+    /// Remove a member from a channel. If the member is us, we left the channel.
+    fn remove_channel_member(&mut self, member: ChannelMember) {
+        if member.user_id == self.your_id {
+            self.joined_channels.remove(&member.channel_id);
+            self.rebuild_channel_cache();
+        } else if let Some(members) = self.joined_channels.get_mut(&member.channel_id) {
+            members.remove(&member.user_id);
+        }
+    }
+
+    /// Whether we joined the channel with the given ID.
+    pub fn is_joined(&self, id: ChannelId) -> bool {
+        self.joined_channels.contains_key(&id)
     }
 
     /// Update a user's info.
     fn update_info(&mut self, new_info: UserInfo) {
+        self.roles.insert(new_info.id, new_info.role);
         self.users.insert(new_info.id, new_info.name);
     }
 
@@ -168,6 +540,11 @@ impl ConnectionState {
         self.users.get(&id).map(String::as_str)
     }
 
+    /// Get the role of a user with the given ID. Unknown users are treated as members.
+    pub fn get_user_role(&self, id: UserId) -> Role {
+        self.roles.get(&id).copied().unwrap_or_default()
+    }
+
     /// Rebuild [`Self::user_render_order`].
     fn rebuild_user_cache(&mut self) {
         // TODO: Optimize
@@ -196,10 +573,12 @@ impl ConnectionState {
         let mut channels: Vec<ChannelId> = self.channels.keys().copied().collect();
 
         channels.sort_by_key(|id| {
-            self.channels
+            let name = self.channels
                 .get(id)
                 .expect("We just got the ID list from the hashmap keys, and nothing else could have changed the map in between")
-                .to_lowercase()
+                .to_lowercase();
+
+            (!self.is_joined(*id), name)
         });
 
         self.channel_render_order.clear();
@@ -1,14 +1,18 @@
 mod connection_state;
 mod ui;
 
-use std::{borrow::Cow, io, path::PathBuf};
+use std::{borrow::Cow, io, path::PathBuf, time::SystemTime};
 
 use anyhow::{Context, bail};
 use chat_backend::{
     ChatBackend,
     client_command::ClientCommand,
     client_event::{self, ClientEvent},
-    network_protocol::{ErrorEvent, NetworkCommand, SendDestination, SendMessage},
+    network_protocol::{
+        Capability, CreateChannel, DeleteChannel, DeleteMessage, EditMessage, ErrorEvent,
+        FetchHistory, JoinChannel, LeaveChannel, MessageId, NetworkCommand, RenameChannel,
+        SendDestination, SendMessage, UserId,
+    },
 };
 use clap::Parser;
 use crossterm::event::{Event, EventStream, KeyCode, KeyEvent, KeyEventKind};
@@ -28,7 +32,7 @@ use tracing::{debug, error, info, instrument, warn};
 use tracing_appender::{non_blocking::WorkerGuard, rolling};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use connection_state::{ConnectionState, MessageContext};
+use connection_state::{ConnectionState, HistoryCursor, MessageContext};
 use ui::{
     Action, KeyHandler,
     main_panel::MainPanel,
@@ -41,6 +45,9 @@ use ui::{
 
 const DEFAULT_CONFIG: &str = include_str!("../data/config.toml");
 
+/// Number of messages to request per page of history.
+const HISTORY_PAGE_SIZE: u32 = 50;
+
 #[derive(Debug)]
 struct DefaultPaths {
     config: PathBuf,
@@ -158,7 +165,7 @@ impl App {
 
                 event = self.backend_receiver.recv() => {
                     match event {
-                        Some(Ok(evt)) => self.handle_client_event(evt),
+                        Some(Ok(evt)) => self.handle_client_event(evt).await,
 
                         Some(Err(e)) => self.handle_client_event_error(e),
 
@@ -225,19 +232,23 @@ impl App {
 
     /// Handle a `ClientEvent` coming from the backend.
     #[instrument(skip_all, fields(event = %event.name()))]
-    fn handle_client_event(&mut self, event: ClientEvent) {
+    async fn handle_client_event(&mut self, event: ClientEvent) {
         debug!("UI received event from backend");
 
         match event {
             ClientEvent::InitialSync(sync) => {
                 info!(addr = %sync.server_addr, "Connected to server, initialized UI state");
                 self.connection_state = Some(ConnectionState::new(sync));
+                self.request_history(false).await;
             }
 
             ClientEvent::Disconnected => {
                 info!("Disconnected from server, dropping UI state");
-                self.notify("Disconnected", NoticeLevel::Notification);
-                self.connection_state = None;
+
+                // After being kicked or banned, the removal notice already says enough.
+                if self.connection_state.take().is_some() {
+                    self.notify("Disconnected", NoticeLevel::Notification);
+                }
             }
 
             ClientEvent::ServerShutDown => {
@@ -248,6 +259,49 @@ impl App {
 
             ClientEvent::ErrorEvent(error_event) => self.handle_error_event(error_event),
 
+            ClientEvent::ServerNotice(notice) => {
+                info!(message = %notice.message, "Server sent a notice");
+                self.notify(
+                    format!("Server notice: {}", notice.message),
+                    NoticeLevel::Notification,
+                );
+            }
+
+            ClientEvent::ServerShuttingDown(shutdown) => {
+                info!(reason = %shutdown.reason, "Server is shutting down");
+
+                let reason = match shutdown.reason.trim_end_matches('.') {
+                    "" => "The server is shutting down",
+                    reason => reason,
+                };
+                let message = match shutdown.grace_period.as_secs() {
+                    0 => format!("{reason}."),
+                    secs => format!("{reason}. You will be disconnected in {secs} seconds."),
+                };
+
+                self.notify(message, NoticeLevel::Warning);
+            }
+
+            ClientEvent::UserKicked(kicked) => {
+                self.handle_removal(kicked.user_id, kicked.by, "kicked", &kicked.reason);
+            }
+
+            ClientEvent::UserBanned(banned) => {
+                let action = match banned.expires_at {
+                    Some(expires_at) => {
+                        let minutes = expires_at
+                            .duration_since(SystemTime::now())
+                            .unwrap_or_default()
+                            .as_secs()
+                            .div_ceil(60);
+                        format!("banned for {minutes} minutes")
+                    }
+                    None => "banned".to_owned(),
+                };
+
+                self.handle_removal(banned.user_id, banned.by, &action, &banned.reason);
+            }
+
             // Remaining events should all be auto-routable to the ConnectionState instance. If not,
             // we failed to handle a special case in this match statement. If there is no
             // connection, we treat it as a NOP.
@@ -261,12 +315,68 @@ impl App {
 
     /// Handle an [`ErrorEvent`](chat_backend::network_protocol::ErrorEvent).
     fn handle_error_event(&mut self, error_event: ErrorEvent) {
+        // Failed messages are marked in place, so they don't need a popup.
+        if let Some(request_id) = error_event.request_id
+            && let Some(state) = &mut self.connection_state
+            && state.fail_request(request_id, error_event.to_string())
+        {
+            return;
+        }
+
         self.notify(error_event.to_string(), NoticeLevel::Error);
     }
 
+    /// Tell the user that someone was kicked or banned. If it was us, the connection is over, and
+    /// the UI state is dropped.
+    fn handle_removal(&mut self, user_id: UserId, by: Option<UserId>, action: &str, reason: &str) {
+        let Some(state) = &self.connection_state else {
+            return;
+        };
+
+        let by = by.map_or("the server's administrators", |id| {
+            state.get_user_name(id).unwrap_or("a moderator")
+        });
+        let ours = user_id == state.your_id;
+
+        let mut message = if ours {
+            format!("You were {action} by {by}")
+        } else {
+            let name = state.get_user_name(user_id).unwrap_or("Someone");
+            format!("{name} was {action} by {by}")
+        };
+
+        if !reason.is_empty() {
+            message = format!("{message}: {reason}");
+        }
+
+        info!(%message, "User was removed from the server");
+
+        if ours {
+            self.notify(message, NoticeLevel::Warning);
+            self.connection_state = None;
+        } else {
+            self.notify(message, NoticeLevel::Notification);
+        }
+    }
+
     /// Handle a `client_event::Error` coming from the backend.
     #[instrument(skip(self))]
     fn handle_client_event_error(&mut self, error: client_event::Error) {
+        // Only the one request failed, so the connection may well be fine.
+        if let client_event::Error::RequestNotSent { request_id, source } = &error {
+            warn!("Request could not be sent");
+
+            let marked = self
+                .connection_state
+                .as_mut()
+                .is_some_and(|state| state.fail_request(*request_id, source.to_string()));
+
+            if !marked {
+                self.notify(error.to_string(), NoticeLevel::Error);
+            }
+            return;
+        }
+
         warn!("Received error from client backend. Assuming the connection is dead.");
         let message = error.to_string();
         self.notify(message, NoticeLevel::Error);
@@ -318,7 +428,7 @@ impl App {
             }
 
             Action::SendMessage(message) => {
-                let Some(state) = &self.connection_state else {
+                let Some(state) = &mut self.connection_state else {
                     self.notify(
                         "Cannot send message: not connected to a server",
                         NoticeLevel::Error,
@@ -326,27 +436,45 @@ impl App {
                     return;
                 };
 
-                let destination = match &state.message_context {
-                    Some(MessageContext::Channel(id)) => SendDestination::Channel(*id),
-                    Some(MessageContext::User(id)) => SendDestination::User(*id),
-                    None => {
-                        self.notify(
-                            "Cannot send message: no user or channel is selected.",
-                            NoticeLevel::Error,
-                        );
-                        return;
-                    }
+                let Some(context) = state.message_context.clone() else {
+                    self.notify(
+                        "Cannot send message: no user or channel is selected.",
+                        NoticeLevel::Error,
+                    );
+                    return;
                 };
 
-                let message = SendMessage {
+                let destination = match context {
+                    MessageContext::Channel(id) => SendDestination::Channel(id),
+                    MessageContext::User(id) => SendDestination::User(id),
+                };
+
+                // If the server confirms commands, the message is shown as pending until it does,
+                // and marked if it fails.
+                let request_id = state
+                    .capabilities
+                    .contains(&Capability::CommandAcks)
+                    .then(|| state.add_pending_message(context, message.clone()));
+
+                let command = NetworkCommand::SendMessage(SendMessage {
                     contents: message,
                     destination,
+                });
+
+                let command = match request_id {
+                    Some(request_id) => ClientCommand::Request {
+                        request_id,
+                        command,
+                    },
+                    None => ClientCommand::NetworkCommand(command),
                 };
 
-                let command = NetworkCommand::SendMessage(message);
-
-                self.send_to_backend(ClientCommand::NetworkCommand(command))
-                    .await;
+                if !self.send_to_backend(command).await
+                    && let Some(request_id) = request_id
+                    && let Some(state) = &mut self.connection_state
+                {
+                    state.fail_request(request_id, "the backend did not respond".to_owned());
+                }
             }
 
             Action::UpdateInfo(info) => {
@@ -368,6 +496,16 @@ impl App {
                 };
 
                 state.message_context = Some(MessageContext::Channel(id));
+                let joined = state.is_joined(id);
+
+                // Opening a channel joins it, so its new messages show up.
+                if !joined {
+                    let command = NetworkCommand::JoinChannel(JoinChannel { channel_id: id });
+                    self.send_to_backend(ClientCommand::NetworkCommand(command))
+                        .await;
+                }
+
+                self.request_history(false).await;
             }
 
             Action::SelectUser(id) => {
@@ -377,7 +515,183 @@ impl App {
                 };
 
                 state.message_context = Some(MessageContext::User(id));
+                self.request_history(false).await;
+            }
+
+            Action::LoadOlderMessages => self.request_history(true).await,
+
+            Action::BeginEditMessage(id) => {
+                if !self.server_supports(Capability::MessageEditing, "editing messages") {
+                    return;
+                }
+
+                if let Some(contents) = self.own_message_contents(id, "edit") {
+                    self.main_panel.begin_edit(id, &contents);
+                }
+            }
+
+            Action::EditMessage(id, contents) => {
+                let command = NetworkCommand::EditMessage(EditMessage {
+                    message_id: id,
+                    contents,
+                });
+
+                self.send_to_backend(ClientCommand::NetworkCommand(command))
+                    .await;
+            }
+
+            Action::DeleteMessage(id) => {
+                self.popups.pop();
+
+                if !self.server_supports(Capability::MessageEditing, "deleting messages")
+                    || self.own_message_contents(id, "delete").is_none()
+                {
+                    return;
+                }
+
+                let command = NetworkCommand::DeleteMessage(DeleteMessage { message_id: id });
+                self.send_to_backend(ClientCommand::NetworkCommand(command))
+                    .await;
+            }
+
+            Action::CreateChannel(name) => {
+                self.popups.clear();
+
+                if !self.server_supports(Capability::ChannelManagement, "creating channels") {
+                    return;
+                }
+
+                let command = NetworkCommand::CreateChannel(CreateChannel { name });
+                self.send_to_backend(ClientCommand::NetworkCommand(command))
+                    .await;
+            }
+
+            Action::RenameChannel(channel_id, new_name) => {
+                self.popups.clear();
+
+                if !self.server_supports(Capability::ChannelManagement, "renaming channels") {
+                    return;
+                }
+
+                let command = NetworkCommand::RenameChannel(RenameChannel {
+                    channel_id,
+                    new_name,
+                });
+                self.send_to_backend(ClientCommand::NetworkCommand(command))
+                    .await;
+            }
+
+            Action::LeaveChannel(channel_id) => {
+                let command = NetworkCommand::LeaveChannel(LeaveChannel { channel_id });
+                self.send_to_backend(ClientCommand::NetworkCommand(command))
+                    .await;
             }
+
+            Action::DeleteChannel(channel_id) => {
+                self.popups.clear();
+
+                if !self.server_supports(Capability::ChannelManagement, "deleting channels") {
+                    return;
+                }
+
+                let command = NetworkCommand::DeleteChannel(DeleteChannel { channel_id });
+                self.send_to_backend(ClientCommand::NetworkCommand(command))
+                    .await;
+            }
+        }
+    }
+
+    /// Check whether the connected server supports a capability. If it doesn't, notify the user
+    /// that `feature` is unavailable. If not connected, returns `true`, so the request fails the
+    /// usual way.
+    fn server_supports(&mut self, capability: Capability, feature: &str) -> bool {
+        let Some(state) = &self.connection_state else {
+            return true;
+        };
+
+        if state.capabilities.contains(&capability) {
+            return true;
+        }
+
+        self.notify(
+            format!("This server does not support {feature}."),
+            NoticeLevel::Error,
+        );
+        false
+    }
+
+    /// Get the contents of a message in the current message context, if we sent it. Otherwise,
+    /// notify the user that they can't `verb` it, and return `None`.
+    fn own_message_contents(&mut self, id: MessageId, verb: &str) -> Option<String> {
+        let state = self.connection_state.as_ref()?;
+        let message = state.get_message(id)?;
+
+        if message.sender_id != state.your_id {
+            self.notify(
+                format!("Cannot {verb} message: you can only {verb} your own messages."),
+                NoticeLevel::Error,
+            );
+            return None;
+        }
+
+        Some(message.contents.clone())
+    }
+
+    /// Request a page of history for the current message context. If `older` is false, this only
+    /// fetches the newest page, and only if no history was requested for the context yet. If
+    /// `older` is true, this also fetches the next older page, if there is one.
+    async fn request_history(&mut self, older: bool) {
+        let Some(state) = &mut self.connection_state else {
+            return;
+        };
+
+        // Without history, there is never a cursor, so no hint to load older messages shows up.
+        if !state.capabilities.contains(&Capability::History) {
+            return;
+        }
+
+        let Some(context) = state.message_context.clone() else {
+            return;
+        };
+
+        let before = match state.history_cursors.get(&context) {
+            None => None,
+            Some(HistoryCursor::More(id)) if older => Some(*id),
+
+            // Either a page is already on its way, or there is nothing left to fetch.
+            Some(_) => return,
+        };
+
+        // If the server confirms commands, a failed request puts the cursor back, so the history
+        // can be requested again. Otherwise, the cursor stays pending until a page arrives.
+        let request_id = if state.capabilities.contains(&Capability::CommandAcks) {
+            Some(state.add_pending_history(context.clone()))
+        } else {
+            state
+                .history_cursors
+                .insert(context.clone(), HistoryCursor::Pending);
+            None
+        };
+
+        let command = NetworkCommand::FetchHistory(FetchHistory {
+            destination: context.history_destination(),
+            before,
+            limit: HISTORY_PAGE_SIZE,
+        });
+
+        let command = match request_id {
+            Some(request_id) => ClientCommand::Request {
+                request_id,
+                command,
+            },
+            None => ClientCommand::NetworkCommand(command),
+        };
+
+        if !self.send_to_backend(command).await
+            && let Some(request_id) = request_id
+            && let Some(state) = &mut self.connection_state
+        {
+            state.fail_request(request_id, "the backend did not respond".to_owned());
         }
     }
 
@@ -394,9 +708,9 @@ impl App {
         self.send_to_backend(ClientCommand::Quit).await;
     }
 
-    /// Send a `ClientCommand` to the backend.
+    /// Send a `ClientCommand` to the backend. Returns `false` if the backend didn't take it.
     #[instrument(skip_all, fields(command = %command.name()))]
-    async fn send_to_backend(&mut self, command: ClientCommand) {
+    async fn send_to_backend(&mut self, command: ClientCommand) -> bool {
         debug!("Sending command to backend");
 
         // We report if the channel is full for too long instead of waiting indefinitely to avoid
@@ -408,7 +722,10 @@ impl App {
                 format!("Sending command '{command_name}' failed, backend may be slow or dead"),
                 NoticeLevel::Warning,
             );
+            return false;
         }
+
+        true
     }
 }
 
@@ -1,37 +1,74 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, time::SystemTime};
 
-use chat_backend::{client_event::ReceivedMessage, network_protocol::UserId};
+use chat_backend::{
+    client_event::ReceivedMessage,
+    network_protocol::{MessageId, UserId},
+};
+use chrono::{DateTime, Local};
+use crossterm::event::{KeyCode, KeyEvent};
 use ratatui::{
     buffer::Buffer,
     layout::Rect,
     style::Style,
-    text::{Line, Text},
+    text::{Line, Span, Text},
     widgets::{Block, List, ListItem, ListState, StatefulWidget, Widget},
 };
 
-use crate::connection_state::{ConnectionState, MessageContext};
+use crate::{
+    connection_state::{ConnectionState, HistoryCursor, MessageContext, PendingMessage},
+    ui::{Action, KeyHandler, popups::delete_message::DeleteMessagePopup},
+};
 
+/// Widget that displays the messages in the current message context. While focused, a message can
+/// be selected to edit or delete it.
 #[derive(Debug)]
 pub struct Messages {
     list_state: ListState,
+    rendered_order: Vec<MessageId>,
 }
 
 impl Messages {
     pub fn new() -> Self {
         Self {
             list_state: ListState::default(),
+            rendered_order: Vec::new(),
         }
     }
 
-    pub fn render(&mut self, area: Rect, buf: &mut Buffer, state: Option<&ConnectionState>) {
+    /// Start selecting messages, beginning with the newest one.
+    pub fn focus(&mut self) {
+        self.list_state.select_last();
+    }
+
+    /// Stop selecting messages.
+    pub fn unfocus(&mut self) {
+        self.list_state.select(None);
+    }
+
+    fn selected(&self) -> Option<MessageId> {
+        self.list_state
+            .selected()
+            .and_then(|i| self.rendered_order.get(i).copied())
+    }
+
+    pub fn render(
+        &mut self,
+        area: Rect,
+        buf: &mut Buffer,
+        state: Option<&ConnectionState>,
+        focused: bool,
+    ) {
         let title = match state.and_then(|state| state.message_context.as_ref()) {
             Some(MessageContext::Channel(id)) => {
-                let name = state
-                    .expect("If this arm triggers, state is always Some")
-                    .get_channel_name(*id)
-                    .unwrap_or("Unknown");
+                let state = state.expect("If this arm triggers, state is always Some");
+                let name = state.get_channel_name(*id).unwrap_or("Unknown");
 
-                Cow::Owned(format!(" Channel: {name} "))
+                match state.joined_channels.get(id) {
+                    Some(members) => {
+                        Cow::Owned(format!(" Channel: {name} ({} members) ", members.len()))
+                    }
+                    None => Cow::Owned(format!(" Channel: {name} (not joined) ")),
+                }
             }
 
             Some(MessageContext::User(id)) => {
@@ -46,14 +83,41 @@ impl Messages {
             None => Cow::Borrowed(" Messages "),
         };
 
-        let block = Block::bordered().title(title);
+        let history_hint = if let Some(state) = state
+            && let Some(context) = &state.message_context
+        {
+            match state.history_cursors.get(context) {
+                Some(HistoryCursor::More(_)) => Some(" o: load older messages "),
+                Some(HistoryCursor::Pending) => Some(" Loading... "),
+                Some(HistoryCursor::Exhausted) | None => None,
+            }
+        } else {
+            None
+        };
+
+        let border_style = if focused {
+            Style::default().green()
+        } else {
+            Style::default()
+        };
+
+        let mut block = Block::bordered().title(title).border_style(border_style);
+        if let Some(hint) = history_hint {
+            block = block.title_bottom(hint);
+        }
         let inner_area = block.inner(area);
         block.render(area, buf);
 
+        self.rendered_order.clear();
+
         if let Some(state) = state
             && let Some(context) = &state.message_context
-            && let Some(messages) = state.messages.get(context)
         {
+            let messages = state.messages.get(context).map_or(&[][..], Vec::as_slice);
+
+            self.rendered_order
+                .extend(messages.iter().map(|message| message.id));
+
             let mut items: Vec<ListItem> = Vec::with_capacity(messages.len());
             let mut previous_sender: Option<UserId> = None;
             let mut is_first = true;
@@ -73,6 +137,22 @@ impl Messages {
                 is_first = false;
             }
 
+            // Messages the server hasn't confirmed yet are the newest, so they go last.
+            for pending in state.pending_messages_in(context) {
+                let is_continuation = previous_sender == Some(state.your_id);
+
+                items.push(self.build_pending_item(
+                    pending,
+                    state,
+                    inner_area.width,
+                    is_continuation,
+                    is_first,
+                ));
+
+                previous_sender = Some(state.your_id);
+                is_first = false;
+            }
+
             let list = List::new(items).highlight_style(Style::new().reversed());
             StatefulWidget::render(list, inner_area, buf, &mut self.list_state);
         }
@@ -106,7 +186,14 @@ impl Messages {
                 .get_user_name(message.sender_id)
                 .unwrap_or("Unknown user");
 
-            lines.push(Line::styled(sender_name, header_style));
+            lines.push(Line::from(vec![
+                Span::styled(sender_name, header_style),
+                Span::raw(" "),
+                Span::styled(
+                    format_timestamp(message.timestamp),
+                    Style::new().dark_gray(),
+                ),
+            ]));
         }
 
         lines.extend(
@@ -118,6 +205,95 @@ impl Messages {
                 .map(Line::from),
         );
 
+        if message.edited_at.is_some()
+            && let Some(last) = lines.last_mut()
+        {
+            last.push_span(Span::styled(" (edited)", Style::new().dark_gray()));
+        }
+
         ListItem::new(Text::from(lines))
     }
+
+    /// Build the list item for a message we sent, but the server hasn't confirmed. Pending
+    /// messages are dimmed, and failed ones say why they failed.
+    fn build_pending_item<'a>(
+        &self,
+        pending: &'a PendingMessage,
+        state: &'a ConnectionState,
+        max_width: u16,
+        is_continuation: bool,
+        is_first: bool,
+    ) -> ListItem<'a> {
+        let mut lines = Vec::with_capacity(8);
+
+        if !is_continuation {
+            if !is_first {
+                lines.push(Line::raw(""));
+            }
+
+            let sender_name = state.get_user_name(state.your_id).unwrap_or("Unknown user");
+            lines.push(Line::styled(sender_name, Style::new().green()));
+        }
+
+        lines.extend(
+            textwrap::wrap(&pending.contents, max_width as usize)
+                .into_iter()
+                .map(|line| Line::styled(line, Style::new().dark_gray())),
+        );
+
+        let status = match &pending.failure {
+            Some(reason) => Span::styled(format!(" (failed: {reason})"), Style::new().red()),
+            None => Span::styled(" (sending...)", Style::new().dark_gray()),
+        };
+
+        if let Some(last) = lines.last_mut() {
+            last.push_span(status);
+        }
+
+        ListItem::new(Text::from(lines))
+    }
+}
+
+impl KeyHandler for Messages {
+    fn handle_key(&mut self, key: KeyEvent) -> Action {
+        match key.code {
+            KeyCode::Esc => {
+                self.unfocus();
+                Action::YieldFocus
+            }
+
+            KeyCode::Char('k') | KeyCode::Up => {
+                self.list_state.select_previous();
+                Action::None
+            }
+
+            KeyCode::Char('j') | KeyCode::Down => {
+                self.list_state.select_next();
+                Action::None
+            }
+
+            KeyCode::Char('e') => match self.selected() {
+                Some(id) => Action::BeginEditMessage(id),
+                None => Action::None,
+            },
+
+            KeyCode::Char('d') => match self.selected() {
+                Some(id) => Action::PushPopup(DeleteMessagePopup::create(id)),
+                None => Action::None,
+            },
+
+            _ => Action::None,
+        }
+    }
+}
+
+/// Format a message timestamp in local time. Messages from today only show the time of day.
+fn format_timestamp(timestamp: SystemTime) -> String {
+    let timestamp: DateTime<Local> = timestamp.into();
+
+    if timestamp.date_naive() == Local::now().date_naive() {
+        timestamp.format("%H:%M").to_string()
+    } else {
+        timestamp.format("%Y-%m-%d %H:%M").to_string()
+    }
 }
@@ -1,6 +1,7 @@
 mod messages;
 mod sidebar;
 
+use chat_backend::network_protocol::MessageId;
 use crossterm::event::{KeyCode, KeyEvent};
 
 use super::{Action, KeyHandler, popups::commands::CommandsPopup};
@@ -9,9 +10,10 @@ use ratatui::{
     buffer::Buffer,
     layout::{Constraint, Direction, Layout, Rect},
     style::Style,
+    text::Line,
     widgets::{Block, Widget},
 };
-use ratatui_textarea::TextArea;
+use ratatui_textarea::{CursorMove, TextArea};
 use sidebar::Sidebar;
 
 use crate::connection_state::ConnectionState;
@@ -20,6 +22,7 @@ use crate::connection_state::ConnectionState;
 pub enum Focus {
     None,
     Input,
+    Messages,
     Sidebar,
 }
 
@@ -31,6 +34,13 @@ pub struct MainPanel {
     input: TextArea<'static>,
     messages: Messages,
     sidebar: Sidebar,
+
+    /// The message being edited in the input area, if any. If `None`, the input area composes a
+    /// new message.
+    editing: Option<MessageId>,
+
+    /// Longest message the server accepts, as of the last render. Longer input is not sent.
+    max_message_length: Option<usize>,
 }
 
 impl MainPanel {
@@ -45,12 +55,36 @@ impl MainPanel {
             input,
             messages: Messages::new(),
             sidebar: Sidebar::new(),
+            editing: None,
+            max_message_length: None,
         }
     }
 
     /// Reset the input area.
     fn reset_input(&mut self) {
         self.input.clear();
+        self.editing = None;
+    }
+
+    /// The contents of the input area, as they would be sent.
+    fn input_contents(&self) -> String {
+        self.input.lines().join("")
+    }
+
+    /// Whether the input is longer than the server accepts.
+    fn input_too_long(&self) -> bool {
+        self.max_message_length
+            .is_some_and(|max| self.input_contents().chars().count() > max)
+    }
+
+    /// Start editing a message: fill the input area with its current contents and focus it.
+    pub fn begin_edit(&mut self, id: MessageId, contents: &str) {
+        self.messages.unfocus();
+        self.input = TextArea::from(contents.lines());
+        self.input.move_cursor(CursorMove::Bottom);
+        self.input.move_cursor(CursorMove::End);
+        self.editing = Some(id);
+        self.focus = Focus::Input;
     }
 
     pub fn render(&mut self, area: Rect, buf: &mut Buffer, state: Option<&ConnectionState>) {
@@ -64,26 +98,44 @@ impl MainPanel {
             .constraints(vec![Constraint::Percentage(75), Constraint::Percentage(25)])
             .areas(message_part);
 
+        self.max_message_length = state.and_then(|state| state.max_message_length);
         self.set_widget_styles();
 
         self.sidebar.render(sidebar, buf, state);
-        self.messages.render(messages, buf, state);
+        self.messages
+            .render(messages, buf, state, self.focus == Focus::Messages);
         self.input.render(input, buf);
     }
 
     /// Helper to set the styles of widgets owned by the `MainPanel` based on the current
     /// application state.
     fn set_widget_styles(&mut self) {
-        let border_style = if self.focus == Focus::Input {
+        let too_long = self.input_too_long();
+
+        let border_style = if too_long {
+            Style::default().red()
+        } else if self.focus == Focus::Input {
             Style::default().green()
         } else {
             Style::default()
         };
-        self.input.set_block(
-            Block::bordered()
-                .title(" Input ")
-                .border_style(border_style),
-        );
+        let title = if self.editing.is_some() {
+            " Editing message (Esc to cancel) "
+        } else {
+            " Input "
+        };
+        let mut block = Block::bordered().title(title).border_style(border_style);
+
+        // Warn before sending, rather than letting the server reject the message.
+        if too_long && let Some(max) = self.max_message_length {
+            let length = self.input_contents().chars().count();
+            block = block.title_bottom(
+                Line::from(format!(" Too long to send: {length}/{max} characters "))
+                    .right_aligned(),
+            );
+        }
+
+        self.input.set_block(block);
 
         let cursor_style = if self.focus == Focus::Input {
             Style::default().reversed()
@@ -111,6 +163,14 @@ impl KeyHandler for MainPanel {
                     Action::None
                 }
 
+                KeyCode::Char('o') => Action::LoadOlderMessages,
+
+                KeyCode::Char('m') => {
+                    self.focus = Focus::Messages;
+                    self.messages.focus();
+                    Action::None
+                }
+
                 KeyCode::Esc => Action::PushPopup(CommandsPopup::create()),
 
                 KeyCode::Backspace => panic!("DEBUG remove this key"),
@@ -120,14 +180,32 @@ impl KeyHandler for MainPanel {
 
             Focus::Input => match key.code {
                 KeyCode::Esc => {
+                    // Cancelling an edit discards it, rather than leaving the old contents behind
+                    // as a new message.
+                    if self.editing.is_some() {
+                        self.reset_input();
+                    }
+
                     self.focus = Focus::None;
                     Action::None
                 }
 
+                // The warning is already showing, so just keep the input for the user to shorten.
+                KeyCode::Enter if self.input_too_long() => Action::None,
+
                 KeyCode::Enter => {
-                    let message = self.input.lines().join("");
+                    let message = self.input_contents();
+                    let editing = self.editing;
                     self.reset_input();
-                    Action::SendMessage(message)
+
+                    match editing {
+                        Some(id) => {
+                            self.focus = Focus::None;
+                            Action::EditMessage(id, message)
+                        }
+
+                        None => Action::SendMessage(message),
+                    }
                 }
 
                 _ => {
@@ -136,6 +214,15 @@ impl KeyHandler for MainPanel {
                 }
             },
 
+            Focus::Messages => {
+                let action = self.messages.handle_key(key);
+                if let Action::YieldFocus = action {
+                    self.focus = Focus::None;
+                }
+
+                action
+            }
+
             Focus::Sidebar => {
                 let action = self.sidebar.handle_key(key);
                 if let Action::YieldFocus = action {
@@ -90,7 +90,12 @@ impl ChannelList {
                     Line::from(channel_name)
                 };
 
-                ListItem::new(line)
+                // Channels we haven't joined are only listed so they can be joined.
+                if state.is_joined(*channel_id) {
+                    ListItem::new(line)
+                } else {
+                    ListItem::new(line.dark_gray())
+                }
             })
             .collect();
 
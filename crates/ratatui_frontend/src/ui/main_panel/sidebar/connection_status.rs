@@ -8,7 +8,8 @@ use ratatui::{
 use crate::connection_state::ConnectionState;
 
 /// Widget that displays the status of the current connection: whether you're connected to a server,
-/// and if so, the address of that server.
+/// and if so, the address of that server and the latency to it, or that the client is reconnecting
+/// to it.
 #[derive(Debug)]
 pub struct ConnectionStatus;
 
@@ -19,7 +20,22 @@ impl ConnectionStatus {
 
     pub fn render(&mut self, area: Rect, buf: &mut Buffer, state: Option<&ConnectionState>) {
         let connection_text = if let Some(state) = state {
-            Paragraph::new(state.connected_addr.to_string()).green()
+            match state.reconnect_attempt {
+                Some(attempt) => Paragraph::new(format!(
+                    "{} (reconnecting, attempt {attempt})",
+                    state.connected_addr
+                ))
+                .yellow(),
+                None => match state.latency {
+                    Some(latency) => Paragraph::new(format!(
+                        "{} ({} ms)",
+                        state.connected_addr,
+                        latency.as_millis()
+                    ))
+                    .green(),
+                    None => Paragraph::new(state.connected_addr.to_string()).green(),
+                },
+            }
         } else {
             Paragraph::new("Not connected").red()
         };
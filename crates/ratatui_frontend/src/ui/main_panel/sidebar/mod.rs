@@ -15,7 +15,10 @@ use user_list::UserList;
 
 use crate::{
     connection_state::ConnectionState,
-    ui::{Action, KeyHandler},
+    ui::{
+        Action, KeyHandler,
+        popups::{channel_name::ChannelNamePopup, delete_channel::DeleteChannelPopup},
+    },
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -109,6 +112,23 @@ impl KeyHandler for Sidebar {
                     Action::SelectChannel(id)
                 }
 
+                KeyCode::Char('n') => Action::PushPopup(ChannelNamePopup::create_channel()),
+
+                KeyCode::Char('r') => match self.channel_list.select() {
+                    Some(id) => Action::PushPopup(ChannelNamePopup::rename_channel(id)),
+                    None => Action::None,
+                },
+
+                KeyCode::Char('d') => match self.channel_list.select() {
+                    Some(id) => Action::PushPopup(DeleteChannelPopup::create(id)),
+                    None => Action::None,
+                },
+
+                KeyCode::Char('l') => match self.channel_list.select() {
+                    Some(id) => Action::LeaveChannel(id),
+                    None => Action::None,
+                },
+
                 _ => Action::None,
             },
 
@@ -1,4 +1,4 @@
-use chat_backend::network_protocol::UserId;
+use chat_backend::network_protocol::{Role, UserId};
 use ratatui::{
     buffer::Buffer,
     layout::{Alignment, Rect},
@@ -83,11 +83,12 @@ impl UserList {
         };
 
         let your_name = state.get_user_name(state.your_id).unwrap_or("YOU");
+        let your_marker = role_marker(state.get_user_role(state.your_id));
 
         // Special style to set our ID apart
         let your_id_line = Line::from_iter([
             your_id_prefix.into(),
-            format!("{} ", your_name).blue(),
+            format!("{your_marker}{} ", your_name).blue(),
             "(you)".into(),
         ]);
 
@@ -96,13 +97,14 @@ impl UserList {
             .iter()
             .map(|user_id| {
                 let user_name = state.get_user_name(*user_id).unwrap_or("Unknown user");
+                let marker = role_marker(state.get_user_role(*user_id));
 
                 let line = if user_id == &state.your_id {
                     your_id_line.clone()
                 } else if Some(user_id) == selected_user_id {
-                    Line::from(format!("◉ {user_name}"))
+                    Line::from(format!("◉ {marker}{user_name}"))
                 } else {
-                    Line::from(user_name)
+                    Line::from(format!("{marker}{user_name}"))
                 };
 
                 ListItem::new(line)
@@ -120,3 +122,12 @@ impl UserList {
         StatefulWidget::render(users_list, area, buf, &mut self.list_state);
     }
 }
+
+/// Marker shown before a user's name to set moderators and owners apart, like on IRC.
+fn role_marker(role: Role) -> &'static str {
+    match role {
+        Role::Owner => "~",
+        Role::Moderator => "@",
+        Role::Member => "",
+    }
+}
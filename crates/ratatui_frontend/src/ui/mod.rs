@@ -3,7 +3,7 @@ pub mod popups;
 
 use chat_backend::{
     client_command::ConnectParams,
-    network_protocol::{ChannelId, UpdateInfo, UserId},
+    network_protocol::{ChannelId, MessageId, UpdateInfo, UserId},
 };
 use crossterm::event::KeyEvent;
 
@@ -22,6 +22,14 @@ pub enum Action {
     YieldFocus,
     SelectChannel(ChannelId),
     SelectUser(UserId),
+    LoadOlderMessages,
+    BeginEditMessage(MessageId),
+    EditMessage(MessageId, String),
+    DeleteMessage(MessageId),
+    CreateChannel(String),
+    RenameChannel(ChannelId, String),
+    DeleteChannel(ChannelId),
+    LeaveChannel(ChannelId),
 }
 
 pub trait KeyHandler {
@@ -0,0 +1,70 @@
+use chat_backend::network_protocol::ChannelId;
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    widgets::{Block, Widget},
+};
+use ratatui_textarea::TextArea;
+use shared_utils::strings::StringExt;
+
+use super::{Action, KeyHandler, Popup, SizeHint, SizeKind};
+
+/// Popup to enter a channel name, either for a new channel or to rename an existing one.
+#[derive(Debug)]
+pub struct ChannelNamePopup {
+    name_input: TextArea<'static>,
+
+    /// The channel to rename. If `None`, a new channel is created instead.
+    target: Option<ChannelId>,
+}
+
+impl ChannelNamePopup {
+    pub fn create_channel() -> Box<dyn Popup> {
+        Self::create("Enter new channel's name", None)
+    }
+
+    pub fn rename_channel(id: ChannelId) -> Box<dyn Popup> {
+        Self::create("Enter new channel name", Some(id))
+    }
+
+    fn create(title: &'static str, target: Option<ChannelId>) -> Box<dyn Popup> {
+        let mut name_input = TextArea::default();
+        let block = Block::bordered().title(title);
+        name_input.set_block(block);
+
+        Box::new(Self { name_input, target })
+    }
+}
+
+impl KeyHandler for ChannelNamePopup {
+    fn handle_key(&mut self, key: KeyEvent) -> Action {
+        match key.code {
+            KeyCode::Esc => Action::PopPopup,
+
+            KeyCode::Enter => {
+                let name = self.name_input.lines().join("").into_fast_trim();
+
+                match self.target {
+                    Some(id) => Action::RenameChannel(id, name),
+                    None => Action::CreateChannel(name),
+                }
+            }
+
+            _ => {
+                self.name_input.input(key);
+                Action::None
+            }
+        }
+    }
+}
+
+impl Popup for ChannelNamePopup {
+    fn render(&self, area: Rect, buf: &mut Buffer) {
+        self.name_input.render(area, buf);
+    }
+
+    fn hint_size(&self) -> SizeHint {
+        (SizeKind::Percentage(30), SizeKind::Exact(3))
+    }
+}
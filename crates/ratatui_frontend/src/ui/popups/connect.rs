@@ -1,4 +1,8 @@
-use chat_backend::client_command::ConnectParams;
+use chat_backend::{
+    client_command::ConnectParams,
+    network_protocol::{Authentication, Credentials},
+    transport::ServerAddress,
+};
 use crossterm::event::{KeyCode, KeyEvent};
 use ratatui::{
     buffer::Buffer,
@@ -15,14 +19,15 @@ use super::{
     notice::{NoticeLevel, NoticePopup},
 };
 
-const FIELD_COUNT: usize = 3;
+const FIELD_COUNT: usize = 4;
 
 #[repr(usize)]
 #[derive(Debug, Clone, Copy)]
 enum Focus {
     Host = 0,
     Username = 1,
-    Port = 2,
+    Password = 2,
+    Port = 3,
 }
 
 impl TryFrom<usize> for Focus {
@@ -32,7 +37,8 @@ impl TryFrom<usize> for Focus {
         match value {
             0 => Ok(Focus::Host),
             1 => Ok(Focus::Username),
-            2 => Ok(Focus::Port),
+            2 => Ok(Focus::Password),
+            3 => Ok(Focus::Port),
             _ => Err(()),
         }
     }
@@ -52,10 +58,39 @@ impl Focus {
     }
 }
 
+/// Whether to log into an existing account, register a new one, or log in with the client
+/// certificate from the backend config. Logging in with an empty password joins as a guest
+/// instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    LogIn,
+    Register,
+    Certificate,
+}
+
+impl Mode {
+    fn next(self) -> Self {
+        match self {
+            Mode::LogIn => Mode::Register,
+            Mode::Register => Mode::Certificate,
+            Mode::Certificate => Mode::LogIn,
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            Mode::LogIn => " Connect ",
+            Mode::Register => " Connect & Register ",
+            Mode::Certificate => " Connect with Certificate ",
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ConnectPopup {
     inputs: [TextArea<'static>; FIELD_COUNT],
     focus: Focus,
+    mode: Mode,
 }
 
 impl ConnectPopup {
@@ -64,15 +99,20 @@ impl ConnectPopup {
             TextArea::default(),
             TextArea::default(),
             TextArea::default(),
+            TextArea::default(),
         ];
 
-        inputs[Focus::Host as usize].set_placeholder_text("Host (IP or Domain)");
+        inputs[Focus::Host as usize].set_placeholder_text("Host (IP, domain or URL)");
         inputs[Focus::Username as usize].set_placeholder_text("Username");
+        inputs[Focus::Password as usize]
+            .set_placeholder_text("Password (empty to join as a guest)");
+        inputs[Focus::Password as usize].set_mask_char('•');
         inputs[Focus::Port as usize].set_placeholder_text("Port (optional)");
 
         let mut popup = Self {
             inputs,
             focus: Focus::Host,
+            mode: Mode::LogIn,
         };
 
         popup.apply_focus_styles();
@@ -124,6 +164,11 @@ impl KeyHandler for ConnectPopup {
                 Action::None
             }
 
+            KeyCode::F(2) => {
+                self.mode = self.mode.next();
+                Action::None
+            }
+
             KeyCode::Enter => {
                 let username = self.inputs[Focus::Username as usize]
                     .lines()
@@ -135,18 +180,28 @@ impl KeyHandler for ConnectPopup {
                     .join("")
                     .into_fast_trim();
 
+                // Passwords are taken verbatim, since whitespace may be part of them.
+                let password = self.inputs[Focus::Password as usize].lines().join("");
+
                 let port_raw = self.inputs[Focus::Port as usize]
                     .lines()
                     .join("")
                     .into_fast_trim();
 
-                if username.is_empty() {
+                if username.is_empty() && self.mode != Mode::Certificate {
                     return Action::PushPopup(NoticePopup::create(
                         "Must enter a username",
                         NoticeLevel::Error,
                     ));
                 }
 
+                if self.mode == Mode::Register && password.is_empty() {
+                    return Action::PushPopup(NoticePopup::create(
+                        "Must enter a password to register",
+                        NoticeLevel::Error,
+                    ));
+                }
+
                 let port = if port_raw.is_empty() {
                     None
                 } else {
@@ -161,10 +216,47 @@ impl KeyHandler for ConnectPopup {
                     }
                 };
 
+                let address = match host.parse::<ServerAddress>() {
+                    Ok(address) => address,
+                    Err(e) => {
+                        return Action::PushPopup(NoticePopup::create(
+                            format!("Could not parse the address '{host}': {e}"),
+                            NoticeLevel::Error,
+                        ));
+                    }
+                };
+
+                let address = match port {
+                    Some(port) => address.with_port(port),
+                    None => Ok(address),
+                };
+
+                let address = match address {
+                    Ok(address) => address,
+                    Err(e) => {
+                        return Action::PushPopup(NoticePopup::create(
+                            format!("Could not use the port with '{host}': {e}"),
+                            NoticeLevel::Error,
+                        ));
+                    }
+                };
+
+                let authentication = match self.mode {
+                    Mode::LogIn if password.is_empty() => Authentication::Guest {
+                        requested_name: username,
+                    },
+
+                    Mode::LogIn => Authentication::Login(Credentials { username, password }),
+
+                    Mode::Register => Authentication::Register(Credentials { username, password }),
+
+                    // The certificate decides who we are, so the username and password go unused.
+                    Mode::Certificate => Authentication::Certificate,
+                };
+
                 let params = ConnectParams {
-                    host,
-                    port,
-                    initial_username: username,
+                    address,
+                    authentication,
                 };
 
                 Action::Connect(params)
@@ -180,7 +272,7 @@ impl KeyHandler for ConnectPopup {
 
 impl Popup for ConnectPopup {
     fn render(&self, area: Rect, buf: &mut Buffer) {
-        let outer_block = Block::bordered();
+        let outer_block = Block::bordered().title(self.mode.title());
         let inner_area = outer_block.inner(area);
         outer_block.render(area, buf);
 
@@ -191,6 +283,7 @@ impl Popup for ConnectPopup {
                 Constraint::Length(2),
                 Constraint::Length(2),
                 Constraint::Length(2),
+                Constraint::Length(2),
             ])
             .areas(inner_area);
 
@@ -200,6 +293,8 @@ impl Popup for ConnectPopup {
             Span::styled("Next: Tab/↓", Style::default().blue()),
             Span::raw(" • "),
             Span::styled("Prev: ↑", Style::default().blue()),
+            Span::raw(" • "),
+            Span::styled("Mode: F2", Style::default().blue()),
         ])
         .alignment(Alignment::Center);
 
@@ -212,6 +307,6 @@ impl Popup for ConnectPopup {
     }
 
     fn hint_size(&self) -> SizeHint {
-        (SizeKind::Percentage(50), SizeKind::Exact(9))
+        (SizeKind::Percentage(50), SizeKind::Exact(11))
     }
 }
@@ -0,0 +1,66 @@
+use chat_backend::network_protocol::ChannelId;
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Alignment, Constraint, Flex, Layout, Rect},
+    style::Style,
+    text::{Line, Span},
+    widgets::{Block, Paragraph, Widget, Wrap},
+};
+
+use super::{Action, KeyHandler, Popup, SizeHint, SizeKind};
+
+#[derive(Debug)]
+pub struct DeleteChannelPopup {
+    channel_id: ChannelId,
+}
+
+impl DeleteChannelPopup {
+    pub fn create(channel_id: ChannelId) -> Box<dyn Popup> {
+        Box::new(Self { channel_id })
+    }
+}
+
+impl KeyHandler for DeleteChannelPopup {
+    fn handle_key(&mut self, key: KeyEvent) -> Action {
+        match key.code {
+            KeyCode::Char('y') => Action::DeleteChannel(self.channel_id),
+            KeyCode::Char('n') | KeyCode::Esc => Action::PopPopup,
+            _ => Action::None,
+        }
+    }
+}
+
+impl Popup for DeleteChannelPopup {
+    fn render(&self, area: Rect, buf: &mut Buffer) {
+        Block::bordered()
+            .title(" Confirm ")
+            .title_alignment(Alignment::Center)
+            .render(area, buf);
+
+        let text = vec![
+            Line::from("Delete this channel for everyone?").centered(),
+            Line::from(""),
+            Line::from(vec![
+                Span::styled("   (y) ", Style::default().blue()),
+                Span::raw("Yes"),
+                Span::styled("   (n) ", Style::default().blue()),
+                Span::raw("No"),
+            ])
+            .centered(),
+        ];
+
+        let [area] = Layout::vertical([Constraint::Length(text.len() as u16)])
+            .flex(Flex::Center)
+            .areas(area);
+
+        Paragraph::new(text)
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true })
+            .render(area, buf);
+    }
+
+    fn hint_size(&self) -> SizeHint {
+        (SizeKind::Percentage(30), SizeKind::Percentage(20))
+    }
+}
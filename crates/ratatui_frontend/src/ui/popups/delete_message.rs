@@ -0,0 +1,66 @@
+use chat_backend::network_protocol::MessageId;
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Alignment, Constraint, Flex, Layout, Rect},
+    style::Style,
+    text::{Line, Span},
+    widgets::{Block, Paragraph, Widget, Wrap},
+};
+
+use super::{Action, KeyHandler, Popup, SizeHint, SizeKind};
+
+#[derive(Debug)]
+pub struct DeleteMessagePopup {
+    message_id: MessageId,
+}
+
+impl DeleteMessagePopup {
+    pub fn create(message_id: MessageId) -> Box<dyn Popup> {
+        Box::new(Self { message_id })
+    }
+}
+
+impl KeyHandler for DeleteMessagePopup {
+    fn handle_key(&mut self, key: KeyEvent) -> Action {
+        match key.code {
+            KeyCode::Char('y') => Action::DeleteMessage(self.message_id),
+            KeyCode::Char('n') | KeyCode::Esc => Action::PopPopup,
+            _ => Action::None,
+        }
+    }
+}
+
+impl Popup for DeleteMessagePopup {
+    fn render(&self, area: Rect, buf: &mut Buffer) {
+        Block::bordered()
+            .title(" Confirm ")
+            .title_alignment(Alignment::Center)
+            .render(area, buf);
+
+        let text = vec![
+            Line::from("Delete this message for everyone?").centered(),
+            Line::from(""),
+            Line::from(vec![
+                Span::styled("   (y) ", Style::default().blue()),
+                Span::raw("Yes"),
+                Span::styled("   (n) ", Style::default().blue()),
+                Span::raw("No"),
+            ])
+            .centered(),
+        ];
+
+        let [area] = Layout::vertical([Constraint::Length(text.len() as u16)])
+            .flex(Flex::Center)
+            .areas(area);
+
+        Paragraph::new(text)
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true })
+            .render(area, buf);
+    }
+
+    fn hint_size(&self) -> SizeHint {
+        (SizeKind::Percentage(30), SizeKind::Percentage(20))
+    }
+}
@@ -1,5 +1,8 @@
+pub mod channel_name;
 pub mod commands;
 pub mod connect;
+pub mod delete_channel;
+pub mod delete_message;
 pub mod notice;
 pub mod quit;
 pub mod update_info;